@@ -0,0 +1,208 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+};
+
+use anyhow::{
+    Context,
+    Result,
+};
+use clap::Parser;
+use rinit_parser::parse_services;
+use rinit_service::{
+    config::Config,
+    env_dropins::add_env_dropins,
+    graph::{
+        DependencyGraph,
+        Node,
+    },
+    types::{
+        ScriptEnvironment,
+        Service,
+    },
+};
+use serde_json::{
+    json,
+    Value,
+};
+
+use crate::util::{
+    group_id,
+    read_graph,
+    user_id,
+};
+
+/// Print the definition of a service as rsvc would run it, as JSON: the node
+/// in the dependency graph, or the parsed file when it is not there, with the
+/// environment drop-ins merged and the users and groups of its scripts
+/// resolved
+#[derive(Parser)]
+pub struct CatCommand {
+    service: String,
+    /// Read the service from this dependency graph instead of the one used by
+    /// rsvc. Use - to read it from stdin
+    #[clap(long)]
+    graph: Option<PathBuf>,
+}
+
+impl CatCommand {
+    pub async fn run(
+        self,
+        config: Config,
+    ) -> Result<()> {
+        let graph_file = self
+            .graph
+            .clone()
+            .unwrap_or_else(|| config.dirs.graph_filename());
+        let graph = read_graph(&graph_file)?;
+        let definition = effective_definition(&self.service, graph.as_ref(), &config)?;
+        println!("{}", serde_json::to_string_pretty(&definition)?);
+
+        Ok(())
+    }
+}
+
+/// The node of service in graph, parsing it from the service directories
+/// when it is not there, with the computed fields added
+fn effective_definition(
+    service: &str,
+    graph: Option<&DependencyGraph>,
+    config: &Config,
+) -> Result<Value> {
+    let parsed_node;
+    let in_graph = graph.and_then(|graph| Some((graph.nodes.get(service)?, graph)));
+    let (node, enabled) = match in_graph {
+        Some((node, graph)) => (node, Some(graph.is_enabled(service))),
+        None => {
+            let system_mode = unsafe { libc::getuid() } == 0;
+            let parsed = parse_services(vec![service.to_string()], &config.dirs, system_mode)
+                .with_context(|| format!("unable to parse service {service}"))?
+                .into_iter()
+                .find(|parsed| parsed.name() == service)
+                .with_context(|| format!("service {service} not found"))?;
+            parsed_node = Node::new(parsed);
+            (&parsed_node, None)
+        }
+    };
+
+    // Same environment as the one of the scripts, minus the variables set by
+    // rsvc and the credentials
+    let environment = match &node.service {
+        Service::Bundle(_) => None,
+        Service::Longrun(longrun) => Some(longrun.environment.clone()),
+        Service::Oneshot(oneshot) => Some(oneshot.environment.clone()),
+        Service::Virtual(_) => Some(ScriptEnvironment::new()),
+    }
+    .map(|mut environment| -> Result<Vec<String>> {
+        add_env_dropins(
+            &mut environment,
+            service,
+            &[&config.dirs.configdir, &config.dirs.rundir],
+        )?;
+        Ok(environment
+            .contents
+            .into_iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect())
+    })
+    .transpose()?;
+    let mut users = BTreeMap::new();
+    let mut groups = BTreeMap::new();
+    for script in node.service.scripts() {
+        if let Some(user) = &script.user {
+            users.insert(user.clone(), user_id(user));
+        }
+        if let Some(group) = &script.group {
+            groups.insert(group.clone(), group_id(group));
+        }
+    }
+
+    let mut definition = serde_json::to_value(node)?;
+    let fields = definition
+        .as_object_mut()
+        .context("the service is not serialized as an object")?;
+    // None when the service is not in the graph
+    fields.insert("enabled".to_string(), json!(enabled));
+    if let Some(environment) = environment {
+        fields.insert("environment".to_string(), json!(environment));
+    }
+    fields.insert("uids".to_string(), json!(users));
+    fields.insert("gids".to_string(), json!(groups));
+    Ok(definition)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs,
+        process,
+    };
+
+    use rinit_service::dirs::Dirs;
+
+    use super::*;
+
+    #[test]
+    fn merge_environment_dropins() {
+        let dir = env::temp_dir().join(format!("rinit-cat-{}", process::id()));
+        let service_type = if unsafe { libc::getuid() } == 0 {
+            "system"
+        } else {
+            "user"
+        };
+        let service_dir = dir.join("config").join(service_type);
+        fs::create_dir_all(&service_dir).unwrap();
+        fs::write(
+            service_dir.join(format!("sshd.{service_type}")),
+            "name = sshd\ntype = oneshot\n[start]\nexecute = (\n    exit 0\n)\n\
+             prefix = bash\nuser = root\n[env]\nPORT = 22\nOPTS = -D\n",
+        )
+        .unwrap();
+        fs::create_dir_all(dir.join("config").join("sshd.d")).unwrap();
+        fs::write(
+            dir.join("config").join("sshd.d").join("10-port.conf"),
+            "PORT = 2222\n",
+        )
+        .unwrap();
+        fs::create_dir_all(dir.join("run").join("sshd.d")).unwrap();
+        fs::write(
+            dir.join("run").join("sshd.d").join("10-address.conf"),
+            "ADDRESS = ::1\n",
+        )
+        .unwrap();
+        let config = Config {
+            dirs: Dirs {
+                configdir: dir.join("config"),
+                rundir: dir.join("run"),
+                datadir: dir.join("data"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let definition = effective_definition("sshd", None, &config).unwrap();
+        assert_eq!(
+            definition["environment"],
+            json!(["PORT=22", "OPTS=-D", "PORT=2222", "ADDRESS=::1"])
+        );
+        assert_eq!(definition["enabled"], Value::Null);
+        assert_eq!(definition["uids"], json!({ "root": 0 }));
+        // The parsed fields are all there
+        assert_eq!(definition["Oneshot"]["start"]["user"], json!("root"));
+
+        // Enabled services are read from the graph
+        let mut graph = DependencyGraph::new();
+        let system_mode = service_type == "system";
+        let services = parse_services(vec!["sshd".to_string()], &config.dirs, system_mode).unwrap();
+        graph
+            .add_services(vec!["sshd".to_string()], services)
+            .unwrap();
+        let definition = effective_definition("sshd", Some(&graph), &config).unwrap();
+        assert_eq!(definition["enabled"], json!(true));
+        assert_eq!(definition["environment"][2], json!("PORT=2222"));
+
+        assert!(effective_definition("missing", None, &config).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
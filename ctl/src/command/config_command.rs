@@ -0,0 +1,28 @@
+use anyhow::Result;
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    Reply,
+    Request,
+};
+use rinit_service::config::Config;
+
+/// Print the configuration used by rsvc as JSON, e.g. to check which
+/// directories it uses when they differ from the ones of rctl
+#[derive(Parser)]
+pub struct ConfigCommand {}
+
+impl ConfigCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        match conn.send_request(Request::GetConfig).await?? {
+            Reply::Config(config) => println!("{}", serde_json::to_string_pretty(&config)?),
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+}
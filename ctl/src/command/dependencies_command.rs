@@ -0,0 +1,228 @@
+use std::{
+    collections::BTreeSet,
+    path::PathBuf,
+};
+
+use anyhow::{
+    ensure,
+    Context,
+    Result,
+};
+use clap::Parser;
+use rinit_service::{
+    config::Config,
+    graph::{
+        DependencyGraph,
+        Node,
+    },
+};
+
+use crate::util::read_graph;
+
+/// Print the services that a service depends on and the ones depending on it,
+/// as read from the dependency graph
+#[derive(Parser)]
+pub struct DependenciesCommand {
+    service: String,
+    /// Also print the dependencies of the dependencies and the dependents of
+    /// the dependents
+    #[clap(long)]
+    recursive: bool,
+    /// Read this dependency graph instead of the one used by rsvc. Use - to
+    /// read it from stdin
+    #[clap(long)]
+    graph: Option<PathBuf>,
+}
+
+impl DependenciesCommand {
+    pub async fn run(
+        self,
+        config: Config,
+    ) -> Result<()> {
+        let graph_file = self.graph.unwrap_or_else(|| config.dirs.graph_filename());
+        let graph = read_graph(&graph_file)?.context("the graph has not been initialized yet")?;
+        ensure!(
+            graph.nodes.contains_key(&self.service),
+            "service {} is not in the dependency graph",
+            self.service
+        );
+        print_services(
+            "Dependencies",
+            &dependencies(&graph, &self.service, self.recursive),
+        );
+        print_services(
+            "Dependents",
+            &dependents(&graph, &self.service, self.recursive),
+        );
+
+        Ok(())
+    }
+}
+
+fn print_services(
+    title: &str,
+    services: &BTreeSet<&str>,
+) {
+    println!("{title}:");
+    if services.is_empty() {
+        println!("  (none)");
+    }
+    for service in services {
+        println!("  {service}");
+    }
+}
+
+/// Services that service waits on when it is started: the ones satisfying its
+/// dependencies, aliases included, and for a sync point the ones anchored
+/// before it
+fn direct_dependencies<'a>(
+    graph: &'a DependencyGraph,
+    service: &str,
+) -> BTreeSet<&'a str> {
+    let mut dependencies: BTreeSet<&str> = graph
+        .nodes
+        .get(service)
+        .map(|node| node.service.dependencies())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|dep| graph.resolve(dep).ok().flatten())
+        .map(|index| graph.nodes[index].name())
+        .collect();
+    dependencies.extend(
+        graph
+            .nodes
+            .values()
+            .filter(|node| node.service.before().iter().any(|before| before == service))
+            .map(Node::name),
+    );
+    dependencies
+}
+
+/// Services that service depends on, directly or, when recursive is set,
+/// through other services
+pub fn dependencies<'a>(
+    graph: &'a DependencyGraph,
+    service: &str,
+    recursive: bool,
+) -> BTreeSet<&'a str> {
+    walk(service, recursive, |name| direct_dependencies(graph, name))
+}
+
+/// Services depending on service, directly or, when recursive is set, through
+/// other services. They are computed from the dependencies of every service
+pub fn dependents<'a>(
+    graph: &'a DependencyGraph,
+    service: &str,
+    recursive: bool,
+) -> BTreeSet<&'a str> {
+    walk(service, recursive, |name| {
+        graph
+            .nodes
+            .keys()
+            .map(String::as_str)
+            .filter(|other| direct_dependencies(graph, other).contains(name))
+            .collect()
+    })
+}
+
+/// The services next to service and, when recursive is set, the ones next to
+/// them until there are no new ones
+fn walk<'a>(
+    service: &str,
+    recursive: bool,
+    next: impl Fn(&str) -> BTreeSet<&'a str>,
+) -> BTreeSet<&'a str> {
+    let mut found = next(service);
+    if !recursive {
+        return found;
+    }
+    let mut to_visit: Vec<&str> = found.iter().copied().collect();
+    while let Some(name) = to_visit.pop() {
+        for other in next(name) {
+            if other != service && found.insert(other) {
+                to_visit.push(other);
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use rinit_service::{
+        test_util::{
+            new_graph,
+            ServiceBuilder,
+        },
+        types::{
+            Service,
+            Virtual,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn direct_and_transitive_dependencies() {
+        let graph = new_graph(vec![
+            ServiceBuilder::oneshot("mount").build(),
+            ServiceBuilder::oneshot("network")
+                .dependencies(&["mount"])
+                .build(),
+            ServiceBuilder::oneshot("sshd")
+                .dependencies(&["network"])
+                .build(),
+            ServiceBuilder::oneshot("nginx")
+                .dependencies(&["network", "mount"])
+                .build(),
+        ]);
+
+        assert_eq!(
+            dependencies(&graph, "sshd", false),
+            BTreeSet::from(["network"])
+        );
+        assert_eq!(
+            dependencies(&graph, "sshd", true),
+            BTreeSet::from(["mount", "network"])
+        );
+        assert_eq!(
+            dependents(&graph, "mount", false),
+            BTreeSet::from(["network", "nginx"])
+        );
+        assert_eq!(
+            dependents(&graph, "mount", true),
+            BTreeSet::from(["network", "nginx", "sshd"])
+        );
+        assert!(dependents(&graph, "sshd", true).is_empty());
+        assert!(dependencies(&graph, "mount", true).is_empty());
+    }
+
+    #[test]
+    fn dependencies_through_aliases_and_sync_points() {
+        let graph = new_graph(vec![
+            ServiceBuilder::oneshot("cronie")
+                .provides(&["cron"])
+                .build(),
+            ServiceBuilder::oneshot("mount")
+                .before(&["sysinit"])
+                .build(),
+            Service::Virtual(Virtual::well_known("sysinit").unwrap()),
+            ServiceBuilder::oneshot("backup")
+                .dependencies(&["cron", "sysinit"])
+                .build(),
+        ]);
+
+        assert_eq!(
+            dependencies(&graph, "backup", true),
+            BTreeSet::from(["cronie", "mount", "sysinit"])
+        );
+        assert_eq!(
+            dependents(&graph, "cronie", false),
+            BTreeSet::from(["backup"])
+        );
+        assert_eq!(
+            dependents(&graph, "mount", true),
+            BTreeSet::from(["backup", "sysinit"])
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::fs;
+use std::path::PathBuf;
 
 use anyhow::{
     ensure,
@@ -16,6 +16,13 @@ use rinit_service::{
     types::RunLevel,
 };
 
+use crate::util::{
+    is_stdio_graph,
+    read_graph,
+    stop_service,
+    write_graph,
+};
+
 #[derive(Parser)]
 pub struct DisableCommand {
     services: Vec<String>,
@@ -23,9 +30,52 @@ pub struct DisableCommand {
     pub atomic_changes: bool,
     #[clap(long, default_value_t)]
     runlevel: RunLevel,
+    /// Stop the services and wait for them to be down before disabling them
+    #[clap(long)]
+    stop: bool,
+    /// Also stop and disable the services that depend on the ones being disabled
+    #[clap(long, requires = "stop")]
+    recursive: bool,
+    /// Edit this dependency graph instead of the one used by rsvc, which is not
+    /// notified. Use - to read it from stdin and write it to stdout
+    #[clap(long, conflicts_with = "stop")]
+    graph: Option<PathBuf>,
+    /// Leave the services running. rsvc stops managing them and forgets them
+    /// once they are down
+    #[clap(long, conflicts_with_all = ["stop", "graph", "no_reload"])]
+    keep_running: bool,
+    /// Only write the graph, without asking rsvc to reload it. Useful to make
+    /// many changes in a script and run rctl reload once at the end
+    #[clap(long)]
+    no_reload: bool,
 }
 
 impl DisableCommand {
+    /// Whether rsvc has to be asked to reload the graph once it is written
+    fn reloads_rsvc(&self) -> bool {
+        self.graph.is_none() && !self.no_reload
+    }
+
+    /// Check that service is enabled in the runlevel, as done by EnableCommand
+    fn check_service(
+        &self,
+        graph: &DependencyGraph,
+        service: &str,
+    ) -> Result<()> {
+        ensure!(
+            graph
+                .nodes
+                .get(service)
+                .with_context(|| format!("the service {service} is not enabled"))?
+                .service
+                .runlevel()
+                == self.runlevel,
+            "service {service} must be of the runlevel {:?}",
+            self.runlevel
+        );
+        Ok(())
+    }
+
     pub async fn run(
         self,
         config: Config,
@@ -35,70 +85,81 @@ impl DisableCommand {
             !(1..self.services.len()).any(|i| self.services[i..].contains(&self.services[i - 1])),
             "duplicated service found"
         );
-        let graph_file = config.dirs.graph_filename();
-        ensure!(
-            graph_file.exists(),
-            "the graph has not been initialized yet"
-        );
-        let mut graph: DependencyGraph = serde_json::from_slice(
-            &fs::read(&graph_file)
-                .with_context(|| format!("unable to read graph from file {:?}", graph_file))?[..],
-        )
-        .context("unable to deserialize the dependency graph")?;
-        if self.atomic_changes {
-            for service in &self.services {
-                // Check runlevel of all services to disable
-                // same as EnableCommand
+        let reload = self.reloads_rsvc();
+        let graph_file = self
+            .graph
+            .clone()
+            .unwrap_or_else(|| config.dirs.graph_filename());
+        let stdio = is_stdio_graph(&graph_file);
+        let mut graph =
+            read_graph(&graph_file)?.context("the graph has not been initialized yet")?;
+        // Keep stdout clean when the graph is written there
+        let report = |message: String| {
+            if stdio {
+                eprintln!("{message}");
+            } else {
+                println!("{message}");
+            }
+        };
+        let services = if self.stop {
+            // Dependents come first, so that rsvc never has to stop a service
+            // that is still needed
+            graph
+                .stop_order(&self.services, self.recursive)
+                .context("unable to stop the services, use --recursive to stop the dependents")?
+        } else {
+            self.services.clone()
+        };
+        // Nothing is stopped when one of the services can't be disabled
+        if self.atomic_changes || self.stop {
+            services
+                .iter()
+                .try_for_each(|service| self.check_service(&graph, service))?;
+        }
+        if self.stop {
+            let mut conn = AsyncConnection::new_host_address()
+                .await
+                .context("unable to connect to rsvc")?;
+            for service in &services {
                 ensure!(
-                    graph
-                        .nodes
-                        .get(service)
-                        .with_context(|| format!("the service {service} is not enabled"))?
-                        .service
-                        .runlevel()
-                        == self.runlevel,
-                    "service {service} must be of the runlevel {:?}",
-                    self.runlevel
-                )
+                    stop_service(&mut conn, service, self.runlevel).await?,
+                    "unable to stop service {service}, it has not been disabled"
+                );
+                println!("The service {service} has been stopped.");
             }
+        }
+        let keep_running = if self.keep_running {
+            services.clone()
+        } else {
+            Vec::new()
+        };
+        if self.atomic_changes {
             graph
-                .disable_services(self.services)
+                .disable_services(services)
                 .context("unable to remove services in the dependency graph")?;
 
-            println!("All the services have been disabled.");
+            report("All the services have been disabled.".to_string());
         } else {
-            self.services
+            services
                 .into_iter()
                 .try_for_each(|service| -> Result<()> {
-                    ensure!(
-                        graph
-                            .nodes
-                            .get(&service)
-                            .with_context(|| format!("the service {service} is not enabled"))?
-                            .service
-                            .runlevel()
-                            == self.runlevel,
-                        "service {service} must be of the runlevel {:?}",
-                        self.runlevel
-                    );
+                    self.check_service(&graph, &service)?;
                     graph
                         .disable_services(vec![service.clone()])
                         .with_context(|| {
                             format!("unable to disable service {service} in the dependency graph")
                         })?;
-                    println!("The service {service} has been disabled.");
+                    report(format!("The service {service} has been disabled."));
                     Ok(())
                 })?;
         }
 
-        fs::write(
-            &graph_file,
-            serde_json::to_vec(&graph).context("unable to serialize the dependency graph")?,
-        )
-        .with_context(|| format!("unable to write the dependency graph to {:?}", graph_file))?;
+        write_graph(&graph, &graph_file)?;
 
-        if let Ok(mut conn) = AsyncConnection::new_host_address().await {
-            let request = Request::ReloadGraph;
+        if !reload {
+            // rsvc doesn't use this graph, or it is reloaded later by the caller
+        } else if let Ok(mut conn) = AsyncConnection::new_host_address().await {
+            let request = Request::ReloadGraph { keep_running };
             conn.send_request(request).await??;
         } else {
             eprintln!("unable to connect to rsvc");
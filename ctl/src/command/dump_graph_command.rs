@@ -0,0 +1,28 @@
+use anyhow::Result;
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    Reply,
+    Request,
+};
+use rinit_service::config::Config;
+
+/// Print the state of every service known by rsvc as JSON
+#[derive(Parser)]
+pub struct DumpGraphCommand {}
+
+impl DumpGraphCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        let snapshots = match conn.send_request(Request::DumpGraph).await?? {
+            Reply::GraphDump(snapshots) => snapshots,
+            _ => unreachable!(),
+        };
+        println!("{}", serde_json::to_string_pretty(&snapshots)?);
+
+        Ok(())
+    }
+}
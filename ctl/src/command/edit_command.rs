@@ -0,0 +1,63 @@
+use anyhow::{
+    ensure,
+    Context,
+    Result,
+};
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    Request,
+};
+use rinit_parser::parse_services;
+use rinit_service::config::Config;
+
+use crate::util::{
+    read_graph,
+    write_graph,
+};
+
+/// Apply the changes made to the files of services already in the dependency
+/// graph. Running services keep their old definition until they are restarted,
+/// unless only their options changed
+#[derive(Parser)]
+pub struct EditCommand {
+    #[clap(required = true)]
+    services: Vec<String>,
+}
+
+impl EditCommand {
+    pub async fn run(
+        self,
+        config: Config,
+    ) -> Result<()> {
+        let graph_file = config.dirs.graph_filename();
+        let mut graph = read_graph(&graph_file)?.unwrap_or_default();
+        for service in &self.services {
+            ensure!(
+                graph.nodes.contains_key(service),
+                "service {service} is not enabled nor needed by an enabled service"
+            );
+        }
+
+        let uid = unsafe { libc::getuid() };
+        let services = parse_services(self.services.clone(), &config.dirs, uid == 0)
+            .context("unable to parse services")?;
+        graph
+            .update_services(services)
+            .context("unable to update the services in the dependency graph")?;
+        write_graph(&graph, &graph_file)?;
+
+        // rsvc reads the graph when it starts
+        if let Ok(mut conn) = AsyncConnection::new_host_address().await {
+            for service in &self.services {
+                conn.send_request(Request::ReloadService(service.to_owned()))
+                    .await??;
+            }
+        }
+        for service in &self.services {
+            println!("Service {service} has been updated.");
+        }
+
+        Ok(())
+    }
+}
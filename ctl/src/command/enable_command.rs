@@ -1,4 +1,7 @@
-use std::fs;
+use std::path::{
+    Path,
+    PathBuf,
+};
 
 use anyhow::{
     bail,
@@ -11,14 +14,30 @@ use rinit_ipc::{
     AsyncConnection,
     Request,
 };
-use rinit_parser::parse_services;
+use rinit_parser::{
+    parse_services,
+    parse_services_in,
+    ServicesParserError,
+};
 use rinit_service::{
     config::Config,
-    graph::DependencyGraph,
-    types::RunLevel,
+    graph::{
+        DependencyGraph,
+        DependencyGraphError,
+    },
+    types::{
+        RunLevel,
+        Service,
+    },
 };
 
-use crate::util::start_service;
+use crate::util::{
+    check_users,
+    is_stdio_graph,
+    read_graph,
+    start_service,
+    write_graph,
+};
 
 #[derive(Parser)]
 pub struct EnableCommand {
@@ -32,9 +51,86 @@ pub struct EnableCommand {
     runlevel: RunLevel,
     #[clap(long)]
     stop_at_errors: bool,
+    /// Edit this dependency graph instead of the one used by rsvc, which is not
+    /// notified. Use - to read it from stdin and write it to stdout
+    #[clap(long, conflicts_with = "start")]
+    graph: Option<PathBuf>,
+    /// Don't check that the users and groups of the scripts exist, e.g. when
+    /// they are created at boot
+    #[clap(long)]
+    skip_user_check: bool,
+    /// Save the graph and reload the services already enabled even when their
+    /// definition has not changed
+    #[clap(long)]
+    force: bool,
+    /// Only write the graph, without asking rsvc to reload it. Useful to make
+    /// many changes in a script and run rctl reload once at the end
+    #[clap(long, conflicts_with = "start")]
+    no_reload: bool,
+    /// Look for the service files in this directory before the configured
+    /// ones, e.g. to try new services before installing them. Their
+    /// dependencies can be in either
+    #[clap(long)]
+    from: Option<PathBuf>,
+}
+
+/// What enabling some services did to the dependency graph
+#[derive(Debug, PartialEq, Eq)]
+enum Change {
+    Enabled,
+    /// All the services were already enabled, their definition was replaced
+    Updated,
+    /// All the services were already enabled with the same definition
+    Unchanged,
+}
+
+/// Parse services and their dependencies, from the directory given with
+/// --from first if any
+fn parse(
+    services: Vec<String>,
+    from: Option<&Path>,
+    config: &Config,
+    system_mode: bool,
+) -> Result<Vec<Service>, ServicesParserError> {
+    match from {
+        Some(dir) => parse_services_in(services, dir, &config.dirs, system_mode),
+        None => parse_services(services, &config.dirs, system_mode),
+    }
+}
+
+/// Enable services_to_enable. The ones already enabled are updated in place
+/// with their new definition from services, keeping the services depending
+/// on them, instead of being rejected
+fn enable_services(
+    graph: &mut DependencyGraph,
+    services_to_enable: &[String],
+    services: Vec<Service>,
+    force: bool,
+) -> Result<Change, DependencyGraphError> {
+    let (enabled, new): (Vec<String>, Vec<String>) = services_to_enable
+        .iter()
+        .cloned()
+        .partition(|service| graph.is_enabled(service));
+    if new.is_empty() && !force && graph.is_up_to_date(&services) {
+        return Ok(Change::Unchanged);
+    }
+    if !enabled.is_empty() {
+        // Also removes the dependencies that are not needed anymore
+        graph.update_services(services.clone())?;
+    }
+    if new.is_empty() {
+        return Ok(Change::Updated);
+    }
+    graph.add_services(new, services)?;
+    Ok(Change::Enabled)
 }
 
 impl EnableCommand {
+    /// Whether rsvc has to be asked to reload the graph once it is written
+    fn reloads_rsvc(&self) -> bool {
+        self.graph.is_none() && !self.no_reload
+    }
+
     pub async fn run(
         self,
         config: Config,
@@ -44,37 +140,49 @@ impl EnableCommand {
             !(1..self.services.len()).any(|i| self.services[i..].contains(&self.services[i - 1])),
             "duplicated service found"
         );
-        let graph_file = config.dirs.graph_filename();
-        let mut graph: DependencyGraph = if graph_file.exists() {
-            serde_json::from_slice(
-                &fs::read(&graph_file).with_context(|| format!("unable to read graph from file {:?}", graph_file)
-                )?[..],
-            )
-            .context("unable to deserialize the dependency graph")?
-        } else {
-            DependencyGraph::new()
-        };
+        if let Some(dir) = &self.from {
+            ensure!(dir.is_dir(), "directory {dir:?} does not exist");
+        }
+        let reload = self.reloads_rsvc();
+        let graph_file = self
+            .graph
+            .clone()
+            .unwrap_or_else(|| config.dirs.graph_filename());
+        let stdio = is_stdio_graph(&graph_file);
+        let mut graph = read_graph(&graph_file)?.unwrap_or_else(DependencyGraph::new);
 
         let uid = unsafe { libc::getuid() };
         let system_mode = uid == 0;
 
-        let save_graph = |graph: &DependencyGraph| -> Result<()> {
-            fs::create_dir_all(graph_file.parent().unwrap()).with_context(|| {
-                format!("unable to create parent directory of file {:?}", graph_file)
-            })?;
-            fs::write(
-                &graph_file,
-                serde_json::to_vec(&graph).context("unable to serialize the dependency graph")?,
-            )
-            .with_context(|| format!("unable to write the dependency graph to {:?}", graph_file))?;
-
-            Ok(())
+        // stdout is only written once, at the end
+        let save_graph = |graph: &DependencyGraph| {
+            if stdio {
+                Ok(())
+            } else {
+                write_graph(graph, &graph_file)
+            }
+        };
+        // Keep stdout clean when the graph is written there
+        let report = |message: String| {
+            if stdio {
+                eprintln!("{message}");
+            } else {
+                println!("{message}");
+            }
         };
 
         let mut success = true;
         if self.atomic_changes {
-            let services = parse_services(self.services.clone(), &config.dirs, system_mode)
-                .context("unable to parse services")?;
+            let services = parse(
+                self.services.clone(),
+                self.from.as_deref(),
+                &config,
+                system_mode,
+            )
+            .context("unable to parse services")?;
+            if !self.skip_user_check {
+                check_users(&services)?;
+            }
             // The dependency graph ensure that all the dependencies have the same runlevel
             // So we just check that we the services passed on the command line are the
             // same runlevel requested
@@ -92,21 +200,30 @@ impl EnableCommand {
                     .name(),
                 self.runlevel
             );
-            graph
-                .add_services(self.services.clone(), services)
+            let change = enable_services(&mut graph, &self.services, services, self.force)
                 .context("unable to add the parsed services to the dependency graph")?;
-            save_graph(&graph)?;
-            println!("All the services have been enabled.");
+            if change == Change::Unchanged {
+                report("All the services are already enabled and up to date.".to_string());
+            } else {
+                save_graph(&graph)?;
+                report("All the services have been enabled.".to_string());
+            }
             // In this case we have enabled all services at once
             // Ask for a graph reload
-            if let Ok(mut conn) = AsyncConnection::new_host_address().await {
-                let request = Request::ReloadGraph;
-                conn.send_request(request).await??;
+            if !reload {
+                // rsvc doesn't use this graph, or it is reloaded later by the caller
+            } else if let Ok(mut conn) = AsyncConnection::new_host_address().await {
+                if change != Change::Unchanged {
+                    let request = Request::ReloadGraph {
+                        keep_running: Vec::new(),
+                    };
+                    conn.send_request(request).await??;
+                }
 
                 // If the user asked us to start the services, try to start them one by one
                 if self.start {
                     for service in &self.services {
-                        if start_service(&mut conn, service, self.runlevel).await? {
+                        if start_service(&mut conn, service, self.runlevel, false).await? {
                             println!("Service {service} started successfully.");
                         } else {
                             println!("Service {service} failed to start.");
@@ -124,7 +241,9 @@ impl EnableCommand {
                 )
             }
         } else {
-            let mut conn = if let Ok(conn) = AsyncConnection::new_host_address().await {
+            let mut conn = if !reload {
+                None
+            } else if let Ok(conn) = AsyncConnection::new_host_address().await {
                 Some(conn)
             } else {
                 if self.start {
@@ -136,11 +255,19 @@ impl EnableCommand {
                 None
             };
 
-            let add_service = |service: &str, graph: &mut DependencyGraph| -> Result<()> {
-                let services = parse_services(vec![service.to_owned()], &config.dirs, system_mode)
-                    .with_context(|| {
-                        format!("unable to parse service {service} and its dependencies")
-                    })?;
+            let add_service = |service: &str, graph: &mut DependencyGraph| -> Result<Change> {
+                let services = parse(
+                    vec![service.to_owned()],
+                    self.from.as_deref(),
+                    &config,
+                    system_mode,
+                )
+                .with_context(|| {
+                    format!("unable to parse service {service} and its dependencies")
+                })?;
+                if !self.skip_user_check {
+                    check_users(&services)?;
+                }
                 ensure!(
                     services
                         .iter()
@@ -151,38 +278,51 @@ impl EnableCommand {
                     "service {service} must be of the runlevel {:?}",
                     self.runlevel
                 );
-                graph
-                    .add_services(vec![service.to_owned()], services)
+                enable_services(graph, &[service.to_owned()], services, self.force)
                     .with_context(|| {
                         format!(
                             "unable to add service {service} and its dependencies to the \
                              dependency graph"
                         )
-                    })?;
-
-                Ok(())
+                    })
             };
             for service in self.services {
                 let res = add_service(&service, &mut graph)
                     .with_context(|| format!("Could not enable service {service}"));
-                if let Err(err) = res {
-                    if self.stop_at_errors {
-                        bail!(err);
-                    } else {
-                        eprintln!("err");
-                        success = false;
+                let change = match res {
+                    Ok(change) => change,
+                    Err(err) => {
+                        if self.stop_at_errors {
+                            bail!(err);
+                        } else {
+                            eprintln!("{err:?}");
+                            success = false;
+                            continue;
+                        }
+                    }
+                };
+                match change {
+                    Change::Enabled => report(format!("Service {service} has been enabled")),
+                    Change::Updated => report(format!("Service {service} has been updated")),
+                    Change::Unchanged => {
+                        report(format!("Service {service} is already enabled and up to date"))
                     }
                 }
-                // Always save the graph. We save after each service, so that in case of any
-                // error, we have already it saved to disk and we can exit this function
-                save_graph(&graph)?;
-                println!("Service {service} has been enabled");
+                // Save the graph after each changed service, so that in case of any error,
+                // we have already it saved to disk and we can exit this function
+                if change != Change::Unchanged {
+                    save_graph(&graph)?;
+                }
                 if let Some(conn) = &mut conn {
-                    let request = Request::ReloadGraph;
-                    conn.send_request(request).await??;
+                    if change != Change::Unchanged {
+                        let request = Request::ReloadGraph {
+                            keep_running: Vec::new(),
+                        };
+                        conn.send_request(request).await??;
+                    }
 
                     if self.start {
-                        let res = start_service(conn, &service, self.runlevel)
+                        let res = start_service(conn, &service, self.runlevel, false)
                             .await
                             .with_context(|| format!("Could not start service {service}"));
                         if let Err(err) = res {
@@ -200,8 +340,329 @@ impl EnableCommand {
             }
         }
 
+        if stdio {
+            write_graph(&graph, &graph_file)?;
+        }
+
         ensure!(success, "Could not complete the operation successfully");
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashSet,
+        env,
+        fs,
+        process,
+        thread,
+        time::Duration,
+    };
+
+    use rinit_service::dirs::Dirs;
+
+    use super::*;
+    use crate::command::DisableCommand;
+
+    #[tokio::test]
+    async fn edit_graph_file() {
+        let dir = env::temp_dir().join(format!("rinit-graph-option-{}", process::id()));
+        let service_type = if unsafe { libc::getuid() } == 0 {
+            "system"
+        } else {
+            "user"
+        };
+        let service_dir = dir.join("config").join(service_type);
+        fs::create_dir_all(&service_dir).unwrap();
+        for (name, options) in [
+            ("foo", "[options]\ndependencies = [ bar ]\n"),
+            ("bar", ""),
+            ("baz", ""),
+        ] {
+            fs::write(
+                service_dir.join(format!("{name}.{service_type}")),
+                format!(
+                    "name = {name}\ntype = oneshot\n[start]\nexecute = (\n    exit 0\n)\n\
+                     prefix = bash\n{options}"
+                ),
+            )
+            .unwrap();
+        }
+        let new_config = || Config {
+            dirs: Dirs {
+                configdir: dir.join("config"),
+                datadir: dir.join("data"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let graph_file = dir.join("graph.json");
+        let graph_arg = graph_file.to_str().unwrap();
+
+        // Each command reads the graph written by the previous one
+        EnableCommand::parse_from(["enable", "--graph", graph_arg, "foo"])
+            .run(new_config())
+            .await
+            .unwrap();
+        EnableCommand::parse_from(["enable", "--graph", graph_arg, "baz"])
+            .run(new_config())
+            .await
+            .unwrap();
+        DisableCommand::parse_from(["disable", "--graph", graph_arg, "foo"])
+            .run(new_config())
+            .await
+            .unwrap();
+
+        let graph = read_graph(&graph_file).unwrap().unwrap();
+        assert_eq!(graph.nodes.keys().collect::<Vec<_>>(), vec!["baz"]);
+        // The graph used by rsvc has not been touched
+        assert!(!new_config().dirs.graph_filename().exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reject_nonexistent_user() {
+        let dir = env::temp_dir().join(format!("rinit-check-users-{}", process::id()));
+        let service_type = if unsafe { libc::getuid() } == 0 {
+            "system"
+        } else {
+            "user"
+        };
+        let service_dir = dir.join("config").join(service_type);
+        fs::create_dir_all(&service_dir).unwrap();
+        fs::write(
+            service_dir.join(format!("sshd.{service_type}")),
+            "name = sshd\ntype = oneshot\n[start]\nexecute = (\n    exit 0\n)\n\
+             prefix = bash\nuser = rinit-no-such-user\n",
+        )
+        .unwrap();
+        let new_config = || Config {
+            dirs: Dirs {
+                configdir: dir.join("config"),
+                datadir: dir.join("data"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let graph_file = dir.join("graph.json");
+        let graph_arg = graph_file.to_str().unwrap();
+
+        for atomic_changes in [false, true] {
+            let mut args = vec!["enable", "--graph", graph_arg, "--stop-at-errors", "sshd"];
+            if atomic_changes {
+                args.push("--atomic-changes");
+            }
+            let err = EnableCommand::parse_from(args)
+                .run(new_config())
+                .await
+                .unwrap_err();
+            assert!(format!("{err:?}").contains("user rinit-no-such-user does not exist"));
+            assert!(!graph_file.exists());
+        }
+
+        // The user could be created at boot
+        EnableCommand::parse_from(["enable", "--graph", graph_arg, "--skip-user-check", "sshd"])
+            .run(new_config())
+            .await
+            .unwrap();
+        let graph = read_graph(&graph_file).unwrap().unwrap();
+        assert_eq!(graph.nodes.keys().collect::<Vec<_>>(), vec!["sshd"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[tokio::test]
+    async fn reenable_changed_service() {
+        let dir = env::temp_dir().join(format!("rinit-reenable-{}", process::id()));
+        let service_type = if unsafe { libc::getuid() } == 0 {
+            "system"
+        } else {
+            "user"
+        };
+        let service_dir = dir.join("config").join(service_type);
+        fs::create_dir_all(&service_dir).unwrap();
+        let write_service = |name: &str, options: &str| {
+            fs::write(
+                service_dir.join(format!("{name}.{service_type}")),
+                format!(
+                    "name = {name}\ntype = oneshot\n[start]\nexecute = (\n    exit 0\n)\n\
+                     prefix = bash\n{options}"
+                ),
+            )
+            .unwrap();
+        };
+        write_service("foo", "[options]\ndependencies = [ bar ]\n");
+        write_service("bar", "");
+        write_service("baz", "");
+        write_service("qux", "[options]\ndependencies = [ foo ]\n");
+        let new_config = || Config {
+            dirs: Dirs {
+                configdir: dir.join("config"),
+                datadir: dir.join("data"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let graph_file = dir.join("graph.json");
+        let graph_arg = graph_file.to_str().unwrap();
+        let enable = |args: &[&str]| {
+            let mut command = vec!["enable", "--graph", graph_arg, "--stop-at-errors"];
+            command.extend(args);
+            EnableCommand::parse_from(command).run(new_config())
+        };
+
+        enable(&["foo", "qux"]).await.unwrap();
+        let modified = || fs::metadata(&graph_file).unwrap().modified().unwrap();
+        let written = modified();
+        // Unchanged, the graph is not even written again
+        enable(&["foo"]).await.unwrap();
+        assert_eq!(modified(), written);
+        thread::sleep(Duration::from_millis(10));
+        enable(&["--force", "foo"]).await.unwrap();
+        assert_ne!(modified(), written);
+
+        // foo now depends on baz instead of bar
+        write_service("foo", "[options]\ndependencies = [ baz ]\n");
+        for atomic_changes in [false, true] {
+            let mut args = vec!["foo"];
+            if atomic_changes {
+                args.push("--atomic-changes");
+                write_service("foo", "[options]\ndependencies = [ bar ]\n");
+            }
+            enable(&args).await.unwrap();
+            let graph = read_graph(&graph_file).unwrap().unwrap();
+            let (dependency, removed) = if atomic_changes {
+                ("bar", "baz")
+            } else {
+                ("baz", "bar")
+            };
+            assert!(!graph.nodes.contains_key(removed));
+            assert_eq!(
+                graph.nodes[dependency].dependents,
+                HashSet::from(["foo".to_string()])
+            );
+            // qux still depends on foo
+            assert_eq!(graph.nodes["foo"].dependents, HashSet::from(["qux".to_string()]));
+            assert!(graph.is_enabled("foo"));
+            assert!(graph.is_enabled("qux"));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn enable_from_staging_directory() {
+        let dir = env::temp_dir().join(format!("rinit-enable-from-{}", process::id()));
+        let service_type = if unsafe { libc::getuid() } == 0 {
+            "system"
+        } else {
+            "user"
+        };
+        let service_dir = dir.join("config").join(service_type);
+        let staging_dir = dir.join("staging");
+        for (directory, name, options) in [
+            (&service_dir, "db", ""),
+            (&staging_dir, "web", "[options]\ndependencies = [ db ]\n"),
+        ] {
+            fs::create_dir_all(directory).unwrap();
+            fs::write(
+                directory.join(format!("{name}.{service_type}")),
+                format!(
+                    "name = {name}\ntype = oneshot\n[start]\nexecute = (\n    exit 0\n)\n\
+                     prefix = bash\n{options}"
+                ),
+            )
+            .unwrap();
+        }
+        let new_config = || Config {
+            dirs: Dirs {
+                configdir: dir.join("config"),
+                datadir: dir.join("data"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let graph_file = dir.join("graph.json");
+        let graph_arg = graph_file.to_str().unwrap();
+        let staging_arg = staging_dir.to_str().unwrap();
+        let enable = |args: &[&str]| {
+            let mut command = vec!["enable", "--graph", graph_arg, "--stop-at-errors"];
+            command.extend(args);
+            EnableCommand::parse_from(command).run(new_config())
+        };
+
+        // web is not installed yet
+        assert!(enable(&["web"]).await.is_err());
+        assert!(enable(&["--from", "/nonexistent", "web"]).await.is_err());
+        for atomic_changes in [false, true] {
+            let mut args = vec!["--from", staging_arg, "--force", "web"];
+            if atomic_changes {
+                args.push("--atomic-changes");
+            }
+            enable(&args).await.unwrap();
+            let graph = read_graph(&graph_file).unwrap().unwrap();
+            assert!(graph.is_enabled("web"));
+            // Its dependency has been found in the configured directories
+            assert_eq!(graph.nodes["db"].dependents, HashSet::from(["web".to_string()]));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn enable_and_disable_without_reload() {
+        let dir = env::temp_dir().join(format!("rinit-no-reload-{}", process::id()));
+        let service_type = if unsafe { libc::getuid() } == 0 {
+            "system"
+        } else {
+            "user"
+        };
+        let service_dir = dir.join("config").join(service_type);
+        fs::create_dir_all(&service_dir).unwrap();
+        fs::write(
+            service_dir.join(format!("foo.{service_type}")),
+            "name = foo\ntype = oneshot\n[start]\nexecute = (\n    exit 0\n)\nprefix = bash\n",
+        )
+        .unwrap();
+        let new_config = || Config {
+            dirs: Dirs {
+                configdir: dir.join("config"),
+                datadir: dir.join("data"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let graph_file = new_config().dirs.graph_filename();
+
+        assert!(EnableCommand::parse_from(["enable", "foo"]).reloads_rsvc());
+        assert!(!EnableCommand::parse_from(["enable", "--graph", "-", "foo"]).reloads_rsvc());
+        // Starting the service needs the new graph in rsvc
+        assert!(
+            EnableCommand::try_parse_from(["enable", "--no-reload", "--start", "foo"]).is_err()
+        );
+        // The graph of rsvc is written, without connecting to it
+        for atomic_changes in [false, true] {
+            let mut args = vec!["enable", "--no-reload", "foo"];
+            if atomic_changes {
+                args.push("--atomic-changes");
+            }
+            let command = EnableCommand::parse_from(args);
+            assert!(!command.reloads_rsvc());
+            command.run(new_config()).await.unwrap();
+            assert!(read_graph(&graph_file).unwrap().unwrap().is_enabled("foo"));
+
+            DisableCommand::parse_from(["disable", "--no-reload", "foo"])
+                .run(new_config())
+                .await
+                .unwrap();
+            assert!(!read_graph(&graph_file).unwrap().unwrap().is_enabled("foo"));
+        }
+        // The services kept running are only known by rsvc once it reloads
+        assert!(DisableCommand::try_parse_from([
+            "disable",
+            "--no-reload",
+            "--keep-running",
+            "foo"
+        ])
+        .is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
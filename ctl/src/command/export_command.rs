@@ -0,0 +1,154 @@
+use std::{
+    fmt::Write,
+    path::PathBuf,
+};
+
+use anyhow::{
+    ensure,
+    Context,
+    Result,
+};
+use clap::Parser;
+use rinit_service::{
+    config::Config,
+    graph::DependencyGraph,
+    types::{
+        RunLevel,
+        Service,
+    },
+};
+
+use crate::util::read_graph;
+
+/// Print the dependency graph in another format, e.g. to render it
+#[derive(Parser)]
+pub struct ExportCommand {
+    /// Use the Graphviz DOT language, e.g. rctl export --dot | dot -Tsvg
+    #[clap(long)]
+    dot: bool,
+    /// Export this dependency graph instead of the one used by rsvc. Use - to
+    /// read it from stdin
+    #[clap(long)]
+    graph: Option<PathBuf>,
+}
+
+impl ExportCommand {
+    pub async fn run(
+        self,
+        config: Config,
+    ) -> Result<()> {
+        ensure!(self.dot, "no format has been chosen, use --dot");
+        let graph_file = self.graph.unwrap_or_else(|| config.dirs.graph_filename());
+        let graph = read_graph(&graph_file)?.context("the graph has not been initialized yet")?;
+        print!("{}", graph_to_dot(&graph));
+
+        Ok(())
+    }
+}
+
+/// Render the graph in the Graphviz DOT language. Each service points to its
+/// dependencies, the fill color tells its type and the border its runlevel.
+/// The services enabled explicitly have a bold border
+pub fn graph_to_dot(graph: &DependencyGraph) -> String {
+    let mut dot = String::from("digraph rinit {\n");
+    for (index, (name, node)) in graph.nodes.iter().enumerate() {
+        let style = if graph.enabled_services.contains(&index) {
+            "filled,bold"
+        } else {
+            "filled"
+        };
+        writeln!(
+            dot,
+            "    {name:?} [fillcolor={:?}, color={:?}, style={style:?}];",
+            type_color(&node.service),
+            runlevel_color(&node.service),
+        )
+        .unwrap();
+    }
+    for (name, node) in &graph.nodes {
+        let mut dependents: Vec<_> = node.dependents.iter().collect();
+        dependents.sort();
+        for dependent in dependents {
+            writeln!(dot, "    {dependent:?} -> {name:?};").unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn type_color(service: &Service) -> &'static str {
+    match service {
+        Service::Bundle(_) => "lightgrey",
+        Service::Longrun(_) => "lightblue",
+        Service::Oneshot(_) => "palegreen",
+        Service::Virtual(_) => "white",
+    }
+}
+
+fn runlevel_color(service: &Service) -> &'static str {
+    match service.runlevel() {
+        RunLevel::Boot => "red",
+        RunLevel::Default => "black",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rinit_service::types::{
+        Longrun,
+        Oneshot,
+        Script,
+        ScriptEnvironment,
+        ScriptPrefix,
+        ServiceOptions,
+    };
+
+    use super::*;
+
+    #[test]
+    fn render_small_graph() {
+        let script = Script::new(ScriptPrefix::Bash, "exit 0".to_string());
+        let mut mount_options = ServiceOptions::new();
+        mount_options.runlevel = RunLevel::Boot;
+        let mount = Service::Oneshot(Oneshot {
+            name: "mount".to_string(),
+            start: script.clone(),
+            stop: None,
+            up_check: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
+            options: mount_options,
+            environment: ScriptEnvironment::new(),
+        });
+        let mut sshd_options = ServiceOptions::new();
+        sshd_options.runlevel = RunLevel::Boot;
+        sshd_options.dependencies = vec!["mount".to_string()];
+        let sshd = Service::Longrun(Longrun {
+            name: "sshd".to_string(),
+            run: script,
+            finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
+            options: sshd_options,
+            environment: ScriptEnvironment::new(),
+        });
+        let mut graph = DependencyGraph::new();
+        graph
+            .add_services(vec!["sshd".to_string()], vec![mount, sshd])
+            .unwrap();
+
+        let dot = graph_to_dot(&graph);
+        assert!(dot.starts_with("digraph rinit {\n"));
+        assert!(dot.contains(
+            "\"mount\" [fillcolor=\"palegreen\", color=\"red\", style=\"filled\"];"
+        ));
+        assert!(dot.contains(
+            "\"sshd\" [fillcolor=\"lightblue\", color=\"red\", style=\"filled,bold\"];"
+        ));
+        assert!(dot.contains("\"sshd\" -> \"mount\";"));
+        assert!(!dot.contains("\"mount\" -> \"sshd\";"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
@@ -0,0 +1,68 @@
+use std::str::FromStr;
+
+use anyhow::{
+    Context,
+    Result,
+};
+use clap::Parser;
+use nix::sys::signal::Signal;
+use rinit_ipc::{
+    AsyncConnection,
+    Request,
+};
+use rinit_service::config::Config;
+
+/// Send a signal to the process group of a running longrun, e.g. to make it
+/// reload its configuration. rsvc doesn't change the status of the service,
+/// it notices if the process exits
+#[derive(Parser)]
+pub struct KillCommand {
+    service: String,
+    /// Name, with or without the SIG prefix, or number of the signal
+    #[clap(value_parser = parse_signal)]
+    signal: i32,
+}
+
+impl KillCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        conn.send_request(Request::Signal {
+            service: self.service,
+            signal: self.signal,
+        })
+        .await??;
+
+        Ok(())
+    }
+}
+
+/// Numbers are passed as they are, rsvc checks that they are valid signals
+fn parse_signal(signal: &str) -> Result<i32> {
+    if let Ok(number) = signal.parse() {
+        return Ok(number);
+    }
+    let name = signal.to_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{name}")
+    };
+    Ok(Signal::from_str(&name).with_context(|| format!("{signal} is not a valid signal"))? as i32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_signal_names_and_numbers() {
+        assert_eq!(parse_signal("SIGUSR1").unwrap(), libc::SIGUSR1);
+        assert_eq!(parse_signal("usr1").unwrap(), libc::SIGUSR1);
+        assert_eq!(parse_signal("HUP").unwrap(), libc::SIGHUP);
+        assert_eq!(parse_signal("10").unwrap(), 10);
+        assert!(parse_signal("SIGFOO").is_err());
+    }
+}
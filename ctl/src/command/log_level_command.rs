@@ -0,0 +1,26 @@
+use anyhow::Result;
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    Request,
+};
+use rinit_service::config::Config;
+
+/// Change the filter of the rsvc log without restarting it
+#[derive(Parser)]
+pub struct LogLevelCommand {
+    /// e.g. debug or warn,rsvc::live_service=debug
+    level: String,
+}
+
+impl LogLevelCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        conn.send_request(Request::SetLogLevel(self.level)).await??;
+
+        Ok(())
+    }
+}
@@ -1,13 +1,47 @@
+mod cat_command;
+mod config_command;
+mod dependencies_command;
 mod disable_command;
+mod dump_graph_command;
+mod edit_command;
 mod enable_command;
+mod export_command;
+mod kill_command;
+mod log_level_command;
+mod ping_command;
 mod reload_command;
+mod reload_config_command;
+mod reexec_command;
+mod reparse_command;
+mod runlevel_command;
+mod shutdown_command;
 mod start_command;
 mod status_command;
 mod stop_command;
+mod top_command;
+mod verify_command;
+mod watch_command;
 
+pub use cat_command::CatCommand;
+pub use config_command::ConfigCommand;
+pub use dependencies_command::DependenciesCommand;
 pub use disable_command::DisableCommand;
+pub use dump_graph_command::DumpGraphCommand;
+pub use edit_command::EditCommand;
 pub use enable_command::EnableCommand;
+pub use export_command::ExportCommand;
+pub use kill_command::KillCommand;
+pub use log_level_command::LogLevelCommand;
+pub use ping_command::PingCommand;
 pub use reload_command::ReloadCommand;
+pub use reload_config_command::ReloadConfigCommand;
+pub use reexec_command::ReexecCommand;
+pub use reparse_command::ReparseCommand;
+pub use runlevel_command::RunLevelCommand;
+pub use shutdown_command::ShutdownCommand;
 pub use start_command::StartCommand;
 pub use status_command::StatusCommand;
 pub use stop_command::StopCommand;
+pub use top_command::TopCommand;
+pub use verify_command::VerifyCommand;
+pub use watch_command::WatchCommand;
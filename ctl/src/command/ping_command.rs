@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    Reply,
+    Request,
+};
+use rinit_service::config::Config;
+use tokio::time::Instant;
+
+/// Check that rsvc is alive and print how long it took to reply
+#[derive(Parser)]
+pub struct PingCommand {}
+
+impl PingCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        let start = Instant::now();
+        let reply = conn.send_request(Request::Ping).await??;
+        let latency = start.elapsed();
+        match reply {
+            Reply::Pong { version, uptime } => {
+                println!(
+                    "rsvc {version}, up {}: reply in {:.3}ms",
+                    humantime::format_duration(Duration::from_secs(uptime.as_secs())),
+                    latency.as_secs_f64() * 1000.0
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+}
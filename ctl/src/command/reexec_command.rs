@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use anyhow::{
+    Context,
+    Result,
+};
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    ConnectionError,
+    Reply,
+    Request,
+};
+use rinit_service::config::Config;
+use tokio::time::Instant;
+
+/// Execute rsvc again, e.g. after upgrading it, without restarting the
+/// running services
+#[derive(Parser)]
+pub struct ReexecCommand {
+    #[clap(
+        long = "wait-timeout",
+        default_value_t = 10,
+        help = "Wait up to this many seconds for the new rsvc to accept connections"
+    )]
+    wait_timeout: u64,
+}
+
+impl ReexecCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        // rsvc only replies if it failed, the connection is closed on exec
+        match conn.send_request(Request::Reexec).await {
+            Err(ConnectionError::NoReplyReceived { .. }) => {}
+            Err(err) if err.is_disconnect() => {}
+            res => {
+                res??;
+            }
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(self.wait_timeout);
+        let mut conn = AsyncConnection::connect_with_backoff(deadline)
+            .await
+            .context("rsvc is not accepting connections after executing it again")?;
+        if let Reply::Pong { version, .. } = conn.send_request(Request::Ping).await?? {
+            println!("rsvc {version} is running again.");
+        }
+
+        Ok(())
+    }
+}
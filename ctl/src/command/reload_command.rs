@@ -6,8 +6,14 @@ use rinit_ipc::{
 };
 use rinit_service::config::Config;
 
+/// Read the dependency graph again or, when a service is given, make its
+/// process reload its configuration
 #[derive(Parser)]
-pub struct ReloadCommand {}
+pub struct ReloadCommand {
+    /// Send its reload_signal to this running longrun. With notify_reload,
+    /// wait until it has reported that the reload is done
+    service: Option<String>,
+}
 
 impl ReloadCommand {
     pub async fn run(
@@ -15,7 +21,15 @@ impl ReloadCommand {
         _config: Config,
     ) -> Result<()> {
         let mut conn = AsyncConnection::new_host_address().await?;
-        conn.send_request(Request::ReloadGraph).await??;
+        let request = match self.service {
+            Some(service) => Request::ReloadProcess { service },
+            None => {
+                Request::ReloadGraph {
+                    keep_running: Vec::new(),
+                }
+            }
+        };
+        conn.send_request(request).await??;
 
         Ok(())
     }
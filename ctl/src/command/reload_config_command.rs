@@ -0,0 +1,22 @@
+use anyhow::Result;
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    Request,
+};
+use rinit_service::config::Config;
+
+#[derive(Parser)]
+pub struct ReloadConfigCommand {}
+
+impl ReloadConfigCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        conn.send_request(Request::ReloadConfig).await??;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use anyhow::Result;
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    Request,
+};
+use rinit_service::config::Config;
+
+/// Have rsvc parse the files of the services again and apply them, restarting
+/// the services whose new definition needs it
+#[derive(Parser)]
+pub struct ReparseCommand {
+    #[clap(required = true)]
+    services: Vec<String>,
+}
+
+impl ReparseCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        for service in self.services {
+            conn.send_request(Request::ReparseService {
+                service: service.clone(),
+            })
+            .await??;
+            println!("Service {service} has been updated.");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+use anyhow::Result;
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    Request,
+};
+use rinit_service::{
+    config::Config,
+    types::RunLevel,
+};
+
+#[derive(Parser)]
+pub struct RunLevelCommand {
+    runlevel: RunLevel,
+}
+
+impl RunLevelCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        conn.send_request(Request::SetRunLevel(self.runlevel))
+            .await??;
+
+        Ok(())
+    }
+}
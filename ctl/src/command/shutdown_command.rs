@@ -0,0 +1,34 @@
+use anyhow::Result;
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    ConnectionError,
+    Request,
+    ShutdownAction,
+};
+use rinit_service::config::Config;
+
+/// Stop every service, dependents first, then power off, reboot or halt the
+/// system. Used by the poweroff, reboot and halt commands
+#[derive(Parser)]
+pub struct ShutdownCommand {}
+
+impl ShutdownCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+        action: ShutdownAction,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        // rsvc only replies if it failed, the system goes down otherwise
+        match conn.send_request(Request::Shutdown { action }).await {
+            Err(ConnectionError::NoReplyReceived { .. }) => {}
+            Err(err) if err.is_disconnect() => {}
+            res => {
+                res??;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use anyhow::{
     ensure,
     Result,
@@ -8,13 +10,33 @@ use rinit_service::{
     config::Config,
     types::RunLevel,
 };
+use tokio::time::Instant;
 
-use crate::util::start_service;
+use crate::util::{
+    start_service,
+    start_service_with_timeout,
+};
 
 #[derive(Parser)]
 pub struct StartCommand {
     #[clap(long, default_value_t)]
     runlevel: RunLevel,
+    #[clap(
+        long = "connect-timeout",
+        help = "Keep trying to connect to rsvc for up to this many seconds"
+    )]
+    connect_timeout: Option<u64>,
+    #[clap(
+        long = "stop-conflicts",
+        help = "Stop the running services conflicting with the ones being started"
+    )]
+    stop_conflicts: bool,
+    #[clap(
+        long = "wait-timeout",
+        help = "Stop waiting for a service to be up after this many seconds and fail, rsvc keeps \
+                starting it"
+    )]
+    wait_timeout: Option<u64>,
     services: Vec<String>,
 }
 
@@ -29,10 +51,44 @@ impl StartCommand {
             "duplicated service found"
         );
 
-        let mut conn = AsyncConnection::new_host_address().await?;
+        let mut conn = if let Some(connect_timeout) = self.connect_timeout {
+            AsyncConnection::connect_with_backoff(
+                Instant::now() + Duration::from_secs(connect_timeout),
+            )
+            .await?
+        } else {
+            AsyncConnection::new_host_address().await?
+        };
         let mut error = false;
         for service in self.services {
-            if start_service(&mut conn, &service, self.runlevel).await? {
+            let started = match self.wait_timeout {
+                Some(wait_timeout) => {
+                    let wait_timeout = Duration::from_secs(wait_timeout);
+                    let Some(started) = start_service_with_timeout(
+                        &mut conn,
+                        &service,
+                        self.runlevel,
+                        self.stop_conflicts,
+                        wait_timeout,
+                    )
+                    .await?
+                    else {
+                        println!(
+                            "Service {service} is not up after {}, rsvc is still starting it.",
+                            humantime::format_duration(wait_timeout)
+                        );
+                        // The start is still holding the connection
+                        conn = AsyncConnection::new_host_address().await?;
+                        error = true;
+                        continue;
+                    };
+                    started
+                }
+                None => {
+                    start_service(&mut conn, &service, self.runlevel, self.stop_conflicts).await?
+                }
+            };
+            if started {
                 println!("Service {service} started successfully.");
             } else {
                 println!("Service {service} failed to start.");
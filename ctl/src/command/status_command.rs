@@ -1,32 +1,242 @@
 use std::{
-    cell::RefCell,
-    rc::Rc,
+    io::{
+        self,
+        Write,
+    },
+    process,
+    time::{
+        Duration,
+        SystemTime,
+    },
 };
 
 use anyhow::{
     ensure,
+    Context,
     Result,
 };
-use clap::Parser;
-use futures::stream::StreamExt;
+use clap::{
+    Parser,
+    ValueEnum,
+};
 use itertools::Itertools;
 use rinit_ipc::{
     AsyncConnection,
+    ErrorKind,
     Reply,
     Request,
     RequestError,
+    ServiceStatus,
 };
-use rinit_service::config::Config;
+use rinit_service::{
+    config::Config,
+    graph::DependencyGraph,
+    service_state::{
+        IdleServiceState,
+        ServiceState,
+    },
+    types::RunLevel,
+};
+use tokio::time::sleep;
+
+use crate::{
+    exit_code::{
+        exit_code,
+        SERVICE_NOT_UP,
+    },
+    util::read_graph,
+};
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
 
 #[derive(Parser)]
 pub struct StatusCommand {
     services: Vec<String>,
+    #[clap(long, value_enum, default_value_t)]
+    format: OutputFormat,
+    /// Only show the services of this runlevel
+    #[clap(long)]
+    runlevel: Option<RunLevel>,
+    /// Query the status again every SECONDS and redraw it, highlighting what
+    /// changed since the previous query
+    #[clap(long, value_name = "SECONDS", conflicts_with = "format")]
+    watch: Option<u64>,
+}
+
+/// Names of the enabled services in runlevel. When services is not empty, only
+/// the ones listed there are kept
+fn services_in_runlevel(
+    graph: &DependencyGraph,
+    services: &[String],
+    runlevel: RunLevel,
+) -> Vec<String> {
+    graph
+        .nodes
+        .values()
+        .map(|node| &node.service)
+        .filter(|service| service.runlevel() == runlevel)
+        .map(|service| service.name().to_string())
+        .filter(|name| services.is_empty() || services.contains(name))
+        .collect()
+}
+
+/// Return a non-zero code if any of the services is not up, i.e. down, failed
+/// or skipped, or if the status of some services could not be retrieved
+fn status_exit_code(
+    statuses: &[ServiceStatus],
+    all_found: bool,
+) -> u8 {
+    if !all_found {
+        exit_code(ErrorKind::ServiceNotFound)
+    } else if statuses
+        .iter()
+        .any(|status| matches!(status.status, ServiceState::Idle(state) if state.is_down()))
+    {
+        SERVICE_NOT_UP
+    } else {
+        0
+    }
+}
+
+/// Format the status as "name: up (up 3h 2m, 2 restarts, last exit 1)"
+fn format_status(
+    status: &ServiceStatus,
+    now: SystemTime,
+) -> String {
+    let mut details = Vec::new();
+    if status.status == ServiceState::Idle(IdleServiceState::Up) {
+        if let Some(uptime) = status
+            .up_since
+            .and_then(|up_since| now.duration_since(up_since).ok())
+        {
+            details.push(format!(
+                "up {}",
+                humantime::format_duration(Duration::from_secs(uptime.as_secs()))
+            ));
+        }
+    }
+    if status.restarts > 0 {
+        details.push(format!(
+            "{} restart{}",
+            status.restarts,
+            if status.restarts == 1 { "" } else { "s" }
+        ));
+    }
+    if let Some(last_exit_code) = status.last_exit_code {
+        details.push(format!("last exit {last_exit_code}"));
+    }
+    if !status.waiting_on.is_empty() {
+        details.push(format!("waiting on: {}", status.waiting_on.join(", ")));
+    }
+    if let Some(next_run) = status
+        .next_run
+        .and_then(|next_run| next_run.duration_since(now).ok())
+    {
+        details.push(format!(
+            "next run in {}",
+            humantime::format_duration(Duration::from_secs(next_run.as_secs()))
+        ));
+    }
+    if details.is_empty() {
+        format!("{}: {}", status.name, status.status)
+    } else {
+        format!("{}: {} ({})", status.name, status.status, details.join(", "))
+    }
+}
+
+/// Ask rsvc for the status of services, or of all the services when None
+/// Return the statuses sorted by name and whether all of them were found
+async fn query_statuses(
+    conn: &mut AsyncConnection,
+    services: Option<&[String]>,
+) -> Result<(Vec<ServiceStatus>, bool)> {
+    let mut all_found = true;
+    let statuses: Vec<ServiceStatus> = if let Some(services) = services {
+        // Send all the requests at once instead of waiting for each status
+        let requests = services
+            .iter()
+            .cloned()
+            .map(Request::ServiceStatus)
+            .collect();
+        let statuses: Vec<ServiceStatus> = conn
+            .send_requests(requests)
+            .await?
+            .into_iter()
+            .filter_map(|res| {
+                match res {
+                    Ok(Reply::ServiceStatus(status)) => Some(status),
+                    Ok(_) => unreachable!(),
+                    Err(err) => {
+                        eprintln!("{err}");
+                        None
+                    }
+                }
+            })
+            .collect();
+        if !services.is_empty() && statuses.len() != services.len() {
+            all_found = false;
+        }
+        statuses
+    } else {
+        let request = Request::ServicesStatus;
+        let res: Result<Reply, RequestError> = conn.send_request(request).await?;
+        match res {
+            Ok(reply) => {
+                match reply {
+                    Reply::ServicesStatus(statuses) => statuses,
+                    _ => unreachable!(),
+                }
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                all_found = false;
+                Vec::new()
+            }
+        }
+    };
+    let statuses = statuses
+        .into_iter()
+        .sorted_by(|a, b| Ord::cmp(&a.name, &b.name))
+        .collect();
+    Ok((statuses, all_found))
+}
+
+/// Query the statuses, clear the screen and print them. The ones that changed
+/// since the previous query, if any, are printed in bold
+async fn poll_statuses(
+    conn: &mut AsyncConnection,
+    services: Option<&[String]>,
+    previous: &mut Option<Vec<ServiceStatus>>,
+    out: &mut impl Write,
+) -> Result<()> {
+    let (statuses, _) = query_statuses(conn, services).await?;
+    let now = SystemTime::now();
+    write!(out, "\x1b[2J\x1b[H")?;
+    for status in &statuses {
+        let line = format_status(status, now);
+        if previous
+            .as_ref()
+            .is_none_or(|previous| previous.contains(status))
+        {
+            writeln!(out, "{line}")?;
+        } else {
+            writeln!(out, "\x1b[1m{line}\x1b[0m")?;
+        }
+    }
+    out.flush()?;
+    *previous = Some(statuses);
+    Ok(())
 }
 
 impl StatusCommand {
     pub async fn run(
         self,
-        _config: Config,
+        config: Config,
     ) -> Result<()> {
         // TODO: Print duplicated service
         ensure!(
@@ -34,64 +244,261 @@ impl StatusCommand {
             "duplicated service found"
         );
 
-        let states = if self.services.is_empty() {
-            let mut conn = AsyncConnection::new_host_address().await?;
-            let request = Request::ServicesStatus;
-            let res: Result<Reply, RequestError> = conn.send_request(request).await?;
-            match res {
-                Ok(reply) => {
-                    match reply {
-                        Reply::ServicesStates(states) => states,
-                        _ => unreachable!(),
-                    }
-                }
-                Err(err) => {
-                    eprintln!("{err}");
-                    Vec::new()
-                }
+        // Only ask rsvc for the services in the runlevel, the statuses don't
+        // carry it. None means all the services
+        let services = match self.runlevel {
+            Some(runlevel) => {
+                let graph = read_graph(&config.dirs.graph_filename())?
+                    .context("the graph has not been initialized yet")?;
+                Some(services_in_runlevel(&graph, &self.services, runlevel))
             }
-        } else {
-            let conn = Rc::new(RefCell::new(AsyncConnection::new_host_address().await?));
-            futures::stream::iter(
-                self.services
-                    .into_iter()
-                    .map(|service| (service, conn.clone())),
-            )
-            .filter_map(async move |(service, conn)| {
-                let request = Request::ServiceStatus(service);
-                match conn.borrow_mut().send_request(request).await {
-                    Ok(res) => {
-                        match res {
-                            Ok(reply) => Some(reply),
-                            Err(err) => {
-                                eprintln!("{err}");
-                                None
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        eprintln!("{err}");
-                        None
-                    }
-                }
-            })
-            .map(|reply| {
-                match reply {
-                    Reply::ServiceState(service, state) => (service, state),
-                    _ => unreachable!(),
-                }
-            })
-            .collect()
-            .await
+            None if self.services.is_empty() => None,
+            None => Some(self.services),
         };
-        states
-            .iter()
-            .sorted_by(|a, b| Ord::cmp(&a.0, &b.0))
-            .for_each(|state| {
-                // TODO: Add better formatting
-                println!("{}: {}", state.0, state.1);
-            });
+        let mut conn = AsyncConnection::new_host_address().await?;
+        if let Some(seconds) = self.watch {
+            let mut previous = None;
+            loop {
+                poll_statuses(
+                    &mut conn,
+                    services.as_deref(),
+                    &mut previous,
+                    &mut io::stdout(),
+                )
+                .await?;
+                sleep(Duration::from_secs(seconds)).await;
+            }
+        }
+        let (statuses, all_found) = query_statuses(&mut conn, services.as_deref()).await?;
+        match self.format {
+            OutputFormat::Text => {
+                let now = SystemTime::now();
+                statuses.iter().for_each(|status| {
+                    println!("{}", format_status(status, now));
+                });
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&statuses)?),
+        }
+
+        let code = status_exit_code(&statuses, all_found);
+        if code != 0 {
+            io::stdout().flush()?;
+            process::exit(code.into());
+        }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use rinit_service::{
+        service_state::TransitioningServiceState,
+        types::{
+            Oneshot,
+            Script,
+            ScriptEnvironment,
+            ScriptPrefix,
+            Service,
+            ServiceOptions,
+        },
+    };
+
+    use std::{
+        env,
+        fs,
+    };
+
+    use remoc::rch;
+    use rinit_ipc::{
+        Envelope,
+        ReplyEnvelope,
+    };
+    use tokio::{
+        net::UnixListener,
+        task,
+    };
+
+    use super::*;
+
+    fn new_status(
+        name: &str,
+        state: ServiceState,
+    ) -> ServiceStatus {
+        ServiceStatus::new(name.to_string(), state)
+    }
+
+    #[test]
+    fn exit_code_mixed_states() {
+        let up = new_status("up", ServiceState::Idle(IdleServiceState::Up));
+        let down = new_status("down", ServiceState::Idle(IdleServiceState::Down));
+        let starting = new_status(
+            "starting",
+            ServiceState::Transitioning(TransitioningServiceState::Starting),
+        );
+
+        assert_eq!(status_exit_code(&[up.clone(), starting.clone()], true), 0);
+        assert_eq!(
+            status_exit_code(&[up.clone(), down.clone(), starting], true),
+            SERVICE_NOT_UP
+        );
+        assert_eq!(
+            status_exit_code(&[up.clone(), down], false),
+            exit_code(ErrorKind::ServiceNotFound)
+        );
+        let failed = new_status("failed", ServiceState::Idle(IdleServiceState::Failed));
+        assert_eq!(
+            status_exit_code(&[up.clone(), failed], true),
+            SERVICE_NOT_UP
+        );
+        // The condition of the service was not met, it is not running either
+        let skipped = new_status("skipped", ServiceState::Idle(IdleServiceState::Skipped));
+        assert_eq!(status_exit_code(&[up, skipped], true), SERVICE_NOT_UP);
+        assert_eq!(status_exit_code(&[], true), 0);
+    }
+
+    #[test]
+    fn filter_mixed_runlevels() {
+        let new_oneshot = |name: &str, runlevel| {
+            let mut options = ServiceOptions::new();
+            options.runlevel = runlevel;
+            Service::Oneshot(Oneshot {
+                name: name.to_string(),
+                start: Script::new(ScriptPrefix::Bash, "exit 0".to_string()),
+                stop: None,
+                up_check: None,
+                condition: None,
+                pre_start: None,
+                post_stop: None,
+                options,
+                environment: ScriptEnvironment::new(),
+            })
+        };
+        let mut graph = DependencyGraph::new();
+        graph
+            .add_services(
+                vec!["udev".to_string(), "sshd".to_string(), "cronie".to_string()],
+                vec![
+                    new_oneshot("udev", RunLevel::Boot),
+                    new_oneshot("sshd", RunLevel::Default),
+                    new_oneshot("cronie", RunLevel::Default),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(
+            services_in_runlevel(&graph, &[], RunLevel::Boot),
+            vec!["udev".to_string()]
+        );
+        assert_eq!(
+            services_in_runlevel(&graph, &[], RunLevel::Default)
+                .into_iter()
+                .sorted()
+                .collect::<Vec<_>>(),
+            vec!["cronie".to_string(), "sshd".to_string()]
+        );
+        assert_eq!(
+            services_in_runlevel(
+                &graph,
+                &["udev".to_string(), "sshd".to_string()],
+                RunLevel::Default
+            ),
+            vec!["sshd".to_string()]
+        );
+    }
+
+    #[test]
+    fn format_uptime_and_restarts() {
+        let now = SystemTime::now();
+        let status = ServiceStatus {
+            up_since: Some(now - Duration::from_secs(3 * 3600)),
+            restarts: 2,
+            last_exit_code: Some(0),
+            ..new_status("foo", ServiceState::Idle(IdleServiceState::Up))
+        };
+        assert_eq!(
+            format_status(&status, now),
+            "foo: up (up 3h, 2 restarts, last exit 0)"
+        );
+        assert_eq!(
+            format_status(
+                &new_status("bar", ServiceState::Idle(IdleServiceState::Down)),
+                now
+            ),
+            "bar: down"
+        );
+        let status = ServiceStatus {
+            waiting_on: vec!["db".to_string(), "network".to_string()],
+            ..new_status(
+                "web",
+                ServiceState::Transitioning(TransitioningServiceState::Starting),
+            )
+        };
+        assert_eq!(
+            format_status(&status, now),
+            "web: starting (waiting on: db, network)"
+        );
+        let status = ServiceStatus {
+            next_run: Some(now + Duration::from_secs(300)),
+            ..new_status("backup", ServiceState::Idle(IdleServiceState::Up))
+        };
+        assert_eq!(format_status(&status, now), "backup: up (next run in 5m)");
+    }
+
+    #[tokio::test]
+    async fn watch_highlights_changes() {
+        let socket = env::temp_dir().join(format!("rinit-status-watch-{}.socket", process::id()));
+        let _ = fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        // Acts as rsvc, sshd comes up between the two polls
+        task::spawn(async move {
+            let (stream, _addr) = listener.accept().await.unwrap();
+            let (socket_rx, socket_tx) = stream.into_split();
+            let (conn, mut tx, mut rx): (
+                _,
+                rch::base::Sender<ReplyEnvelope>,
+                rch::base::Receiver<Envelope<Request>>,
+            ) = remoc::Connect::io(remoc::Cfg::default(), socket_rx, socket_tx)
+                .await
+                .unwrap();
+            task::spawn(conn);
+            for sshd in [
+                ServiceState::Transitioning(TransitioningServiceState::Starting),
+                ServiceState::Idle(IdleServiceState::Up),
+            ] {
+                let request = rx.recv().await.unwrap().unwrap();
+                assert!(matches!(request.payload, Request::ServicesStatus));
+                let statuses = vec![
+                    new_status("udev", ServiceState::Idle(IdleServiceState::Up)),
+                    new_status("sshd", sshd),
+                ];
+                tx.send(Envelope {
+                    id: request.id,
+                    payload: Ok(Reply::ServicesStatus(statuses)),
+                })
+                .await
+                .unwrap();
+            }
+        });
+
+        let mut conn = AsyncConnection::new(socket.to_str().unwrap()).await.unwrap();
+        let mut previous = None;
+        let mut out = Vec::new();
+        poll_statuses(&mut conn, None, &mut previous, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[2J\x1b[Hsshd: starting\nudev: up\n"
+        );
+        let mut out = Vec::new();
+        poll_statuses(&mut conn, None, &mut previous, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[2J\x1b[H\x1b[1msshd: up\x1b[0m\nudev: up\n"
+        );
+        fs::remove_file(&socket).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    io::{
+        self,
+        Write,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use clap::Parser;
+use itertools::Itertools;
+use rinit_ipc::{
+    AsyncConnection,
+    Reply,
+    Request,
+    ServiceUsage,
+};
+use rinit_service::config::Config;
+use tokio::time::{
+    sleep,
+    Instant,
+};
+
+/// Show the memory and CPU usage of the running longruns, refreshed
+/// periodically
+#[derive(Parser)]
+pub struct TopCommand {
+    /// Seconds between two refreshes
+    #[clap(long, value_name = "SECONDS", default_value_t = 2)]
+    interval: u64,
+    /// Print the usage once and exit, without clearing the screen
+    #[clap(long)]
+    once: bool,
+}
+
+/// Format bytes with a binary unit, e.g. 4.5M
+fn format_memory(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1}{}", UNITS[unit])
+}
+
+/// Percentage of a CPU used by the service since the previous refresh, None
+/// on the first one
+fn cpu_percent(
+    usage: &ServiceUsage,
+    previous: &HashMap<String, Duration>,
+    elapsed: Duration,
+) -> Option<f64> {
+    let previous = previous.get(&usage.name)?;
+    if elapsed.is_zero() {
+        return None;
+    }
+    // The service could have been restarted in the meantime
+    let spent = usage.cpu_time.saturating_sub(*previous);
+    Some(spent.as_secs_f64() / elapsed.as_secs_f64() * 100.0)
+}
+
+/// One line per service, the ones using the most memory first. Services
+/// that are not in a cgroup are marked with a *, only their main process is
+/// accounted for
+fn format_usage(
+    usage: &[ServiceUsage],
+    previous: &HashMap<String, Duration>,
+    elapsed: Duration,
+) -> Vec<String> {
+    let width = usage
+        .iter()
+        .map(|usage| usage.name.len() + 1)
+        .chain([7])
+        .max()
+        .unwrap_or_default();
+    let mut lines = vec![format!(
+        "{:<width$} {:>8} {:>12} {:>6}",
+        "SERVICE", "MEMORY", "CPU TIME", "CPU%"
+    )];
+    lines.extend(
+        usage
+            .iter()
+            .sorted_by(|a, b| b.memory.cmp(&a.memory).then_with(|| a.name.cmp(&b.name)))
+            .map(|usage| {
+                let name = if usage.from_cgroup {
+                    usage.name.clone()
+                } else {
+                    format!("{}*", usage.name)
+                };
+                let percent = cpu_percent(usage, previous, elapsed)
+                    .map(|percent| format!("{percent:.1}"))
+                    .unwrap_or_else(|| "-".to_string());
+                let cpu_time = format!("{:.2}s", usage.cpu_time.as_secs_f64());
+                format!(
+                    "{name:<width$} {:>8} {cpu_time:>12} {percent:>6}",
+                    format_memory(usage.memory),
+                )
+            }),
+    );
+    lines
+}
+
+impl TopCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        let mut previous = HashMap::new();
+        let mut last_query = Instant::now();
+        loop {
+            let usage = match conn.send_request(Request::Usage).await?? {
+                Reply::Usage(usage) => usage,
+                _ => unreachable!(),
+            };
+            let now = Instant::now();
+            let lines = format_usage(&usage, &previous, now - last_query);
+            let mut out = io::stdout();
+            if !self.once {
+                write!(out, "\x1b[2J\x1b[H")?;
+            }
+            for line in lines {
+                writeln!(out, "{line}")?;
+            }
+            out.flush()?;
+            if self.once {
+                return Ok(());
+            }
+            previous = usage
+                .into_iter()
+                .map(|usage| (usage.name, usage.cpu_time))
+                .collect();
+            last_query = now;
+            sleep(Duration::from_secs(self.interval)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_usage_table() {
+        let usage = vec![
+            ServiceUsage {
+                name: "cronie".to_string(),
+                memory: 900,
+                cpu_time: Duration::from_millis(500),
+                from_cgroup: true,
+            },
+            ServiceUsage {
+                name: "sshd".to_string(),
+                memory: 5 * 1024 * 1024 + 512 * 1024,
+                cpu_time: Duration::from_secs(3),
+                from_cgroup: false,
+            },
+        ];
+        let previous = HashMap::from([("sshd".to_string(), Duration::from_secs(2))]);
+        assert_eq!(
+            format_usage(&usage, &previous, Duration::from_secs(2)),
+            vec![
+                "SERVICE   MEMORY     CPU TIME   CPU%",
+                "sshd*       5.5M        3.00s   50.0",
+                "cronie      900B        0.50s      -",
+            ]
+        );
+    }
+}
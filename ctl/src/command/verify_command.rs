@@ -0,0 +1,98 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    path::PathBuf,
+};
+
+use anyhow::{
+    ensure,
+    Result,
+};
+use clap::Parser;
+use rinit_parser::parse_service;
+use rinit_service::{
+    config::Config,
+    graph::{
+        DependencyGraph,
+        DependencyGraphError,
+    },
+};
+
+/// Check all the services available for consistency, without enabling them
+#[derive(Parser)]
+pub struct VerifyCommand {}
+
+impl VerifyCommand {
+    pub async fn run(
+        self,
+        config: Config,
+    ) -> Result<()> {
+        let uid = unsafe { libc::getuid() };
+        let extension = if uid == 0 { "system" } else { "user" };
+
+        // The first directory containing a service takes precedence, like when
+        // enabling it
+        let mut files: BTreeMap<String, PathBuf> = BTreeMap::new();
+        for dir in config.dirs.service_directories() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+                if path.extension().is_none_or(|ext| ext != extension) {
+                    continue;
+                }
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    files.entry(name.to_owned()).or_insert(path);
+                }
+            }
+        }
+
+        let mut problems = 0;
+        let mut services = Vec::new();
+        for (name, file) in &files {
+            match parse_service(file) {
+                Ok(service) if service.name() != name => {
+                    eprintln!(
+                        "{}: the service name {} is different than the file name",
+                        file.display(),
+                        service.name()
+                    );
+                    problems += 1;
+                }
+                Ok(service) => services.push(service),
+                Err(err) => {
+                    // Print the whole chain, the innermost error tells what is wrong
+                    let mut message = err.to_string();
+                    let mut source = err.source();
+                    while let Some(err) = source {
+                        message.push_str(&format!(": {err}"));
+                        source = err.source();
+                    }
+                    eprintln!("{message}");
+                    problems += 1;
+                }
+            }
+        }
+
+        for err in DependencyGraph::verify(services) {
+            let service = match &err {
+                DependencyGraphError::DependenciesMustHaveSameRunLevel { service, .. }
+                | DependencyGraphError::DependenciesUnfulfilledError { service, .. } => {
+                    Some(service)
+                }
+                _ => None,
+            };
+            match service.and_then(|service| files.get(service)) {
+                Some(file) => eprintln!("{}: {err}", file.display()),
+                None => eprintln!("{err}"),
+            }
+            problems += 1;
+        }
+
+        ensure!(problems == 0, "found {problems} problems in the services");
+        println!("All the {} services are valid.", files.len());
+
+        Ok(())
+    }
+}
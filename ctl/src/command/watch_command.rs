@@ -0,0 +1,124 @@
+use std::io::{
+    self,
+    Write,
+};
+
+use anyhow::{
+    bail,
+    Result,
+};
+use clap::Parser;
+use rinit_ipc::{
+    AsyncConnection,
+    Reply,
+    Request,
+    StatusChanged,
+};
+use rinit_service::config::Config;
+
+#[derive(Parser)]
+pub struct WatchCommand {
+    /// Print each event as a JSON object on its own line (JSON Lines)
+    #[clap(long)]
+    json: bool,
+}
+
+impl WatchCommand {
+    pub async fn run(
+        self,
+        _config: Config,
+    ) -> Result<()> {
+        let mut conn = AsyncConnection::new_host_address().await?;
+        conn.send_request(Request::Subscribe).await??;
+        let mut out = io::stdout();
+        while let Some(reply) = conn.recv_reply().await? {
+            let event = match reply? {
+                Reply::StatusChanged(event) => event,
+                // Only the events are sent once subscribed, it is a bug in rsvc
+                reply => bail!("unexpected reply {reply:?} while watching the events"),
+            };
+            match write_event(&mut out, &event, self.json) {
+                // The reader went away, e.g. rctl watch | head
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => break,
+                res => res?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Write the event on its own line and flush it, so that it reaches the
+/// reader right away
+fn write_event(
+    out: &mut impl Write,
+    event: &StatusChanged,
+    json: bool,
+) -> io::Result<()> {
+    if json {
+        serde_json::to_writer(&mut *out, event)?;
+        writeln!(out)?;
+    } else {
+        writeln!(
+            out,
+            "[{}] {}: {} -> {}",
+            humantime::format_rfc3339_seconds(event.at),
+            event.service,
+            event.from,
+            event.to
+        )?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{
+        Duration,
+        SystemTime,
+    };
+
+    use rinit_service::service_state::{
+        IdleServiceState,
+        ServiceState,
+        TransitioningServiceState,
+    };
+
+    use super::*;
+
+    #[test]
+    fn json_lines() {
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let events = [
+            StatusChanged {
+                service: "sshd".to_string(),
+                from: ServiceState::Idle(IdleServiceState::Down),
+                to: ServiceState::Transitioning(TransitioningServiceState::Starting),
+                at,
+            },
+            StatusChanged {
+                service: "sshd".to_string(),
+                from: ServiceState::Transitioning(TransitioningServiceState::Starting),
+                to: ServiceState::Idle(IdleServiceState::Up),
+                at: at + Duration::from_millis(20),
+            },
+        ];
+        let mut out = Vec::new();
+        for event in &events {
+            write_event(&mut out, event, true).unwrap();
+        }
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), events.len());
+        for (line, event) in lines.iter().zip(&events) {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(value["service"], "sshd");
+            assert_eq!(value["to"], event.to.to_string());
+            assert_eq!(
+                &serde_json::from_value::<StatusChanged>(value).unwrap(),
+                event
+            );
+        }
+    }
+}
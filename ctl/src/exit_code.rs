@@ -0,0 +1,127 @@
+use std::error::Error;
+
+use rinit_ipc::{
+    request_error::LogicError,
+    ConnectionError,
+    Envelope,
+    ErrorKind,
+    Request,
+    RequestError,
+};
+
+/// Exit code of any error without a kind, e.g. a service file that can't be
+/// parsed
+pub const GENERIC_FAILURE: u8 = 1;
+
+/// Exit code of rctl status when one of the services is not up, i.e. down,
+/// failed or skipped. It is not an error, the status has been retrieved
+pub const SERVICE_NOT_UP: u8 = 10;
+
+/// Exit code of rctl for each kind of error, so that scripts can tell the
+/// failures apart. 2 is left to clap for the invalid arguments
+pub fn exit_code(kind: ErrorKind) -> u8 {
+    match kind {
+        ErrorKind::Connection => 3,
+        ErrorKind::ServiceNotFound => 4,
+        ErrorKind::DependencyGraph => 5,
+        ErrorKind::ServiceFailed => 6,
+        ErrorKind::Conflict => 7,
+        ErrorKind::InvalidRequest => 8,
+        ErrorKind::System => 9,
+    }
+}
+
+/// Exit code for the first error with a kind in the chain of err
+pub fn exit_code_of(err: &anyhow::Error) -> u8 {
+    err.chain()
+        .find_map(error_kind)
+        .map_or(GENERIC_FAILURE, exit_code)
+}
+
+fn error_kind(err: &(dyn Error + 'static)) -> Option<ErrorKind> {
+    if let Some(err) = err.downcast_ref::<RequestError>() {
+        Some(err.kind())
+    } else if let Some(err) = err.downcast_ref::<LogicError>() {
+        Some(err.kind())
+    } else {
+        err.downcast_ref::<ConnectionError<Envelope<Request>>>()
+            .map(ConnectionError::kind)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use anyhow::{
+        anyhow,
+        Context,
+    };
+
+    use super::*;
+
+    fn logic_error(err: LogicError) -> anyhow::Error {
+        RequestError::LogicError { err }.into()
+    }
+
+    #[test]
+    fn exit_code_for_each_kind() {
+        let connection_refused: ConnectionError<Envelope<Request>> =
+            ConnectionError::SocketConnectionError {
+                socket: "/run/rinit.sock".to_string(),
+                source: io::Error::from(io::ErrorKind::ConnectionRefused),
+            };
+        let cases = [
+            (connection_refused.into(), 3),
+            (
+                logic_error(LogicError::ServiceNotFound {
+                    service: "foo".to_string(),
+                }),
+                4,
+            ),
+            (
+                logic_error(LogicError::DependencyGraphNotFound {
+                    path: "/var/lib/rinit".to_string(),
+                }),
+                5,
+            ),
+            (
+                logic_error(LogicError::ServiceFailedToStart {
+                    service: "foo".to_string(),
+                }),
+                6,
+            ),
+            (
+                logic_error(LogicError::ConflictingServiceRunning {
+                    service: "foo".to_string(),
+                    conflict: "bar".to_string(),
+                }),
+                7,
+            ),
+            (logic_error(LogicError::InvalidSignal { signal: 99 }), 8),
+            (
+                RequestError::SystemError {
+                    err: "unable to write the graph".to_string(),
+                }
+                .into(),
+                9,
+            ),
+            (anyhow!("duplicated service found"), GENERIC_FAILURE),
+        ];
+        for (err, code) in cases {
+            assert_eq!(exit_code_of(&err), code, "wrong exit code for {err}");
+        }
+    }
+
+    #[test]
+    fn exit_code_through_context() {
+        let err = Err::<(), _>(RequestError::LogicError {
+            err: LogicError::ServiceNotFound {
+                service: "foo".to_string(),
+            },
+        })
+        .context("unable to start foo")
+        .unwrap_err();
+        assert_eq!(exit_code_of(&err), 4);
+    }
+}
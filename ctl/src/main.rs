@@ -1,9 +1,13 @@
 #![feature(async_closure)]
 
 mod command;
+mod exit_code;
 mod util;
 
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    process::ExitCode,
+};
 
 use anyhow::Result;
 use clap::Parser;
@@ -11,10 +15,33 @@ use clap::Parser;
 enum Command {
     Enable(EnableCommand),
     Disable(DisableCommand),
+    Edit(EditCommand),
     Status(StatusCommand),
     Start(StartCommand),
     Stop(StopCommand),
     Reload(ReloadCommand),
+    ReloadConfig(ReloadConfigCommand),
+    Reparse(ReparseCommand),
+    Reexec(ReexecCommand),
+    #[clap(name = "runlevel")]
+    RunLevel(RunLevelCommand),
+    Watch(WatchCommand),
+    Verify(VerifyCommand),
+    DumpGraph(DumpGraphCommand),
+    Export(ExportCommand),
+    Ping(PingCommand),
+    Config(ConfigCommand),
+    LogLevel(LogLevelCommand),
+    Top(TopCommand),
+    Cat(CatCommand),
+    Dependencies(DependenciesCommand),
+    Kill(KillCommand),
+    /// Stop every service and power off the system
+    Poweroff(ShutdownCommand),
+    /// Stop every service and reboot the system
+    Reboot(ShutdownCommand),
+    /// Stop every service and halt the system
+    Halt(ShutdownCommand),
 }
 
 #[derive(Parser)]
@@ -25,28 +52,81 @@ struct Opts {
     subcmd: Command,
 }
 use command::{
+    CatCommand,
+    ConfigCommand,
+    DependenciesCommand,
     DisableCommand,
+    DumpGraphCommand,
+    EditCommand,
     EnableCommand,
+    ExportCommand,
+    KillCommand,
+    LogLevelCommand,
+    PingCommand,
     ReloadCommand,
+    ReloadConfigCommand,
+    ReexecCommand,
+    ReparseCommand,
+    RunLevelCommand,
+    ShutdownCommand,
     StartCommand,
     StatusCommand,
     StopCommand,
+    TopCommand,
+    VerifyCommand,
+    WatchCommand,
 };
+use exit_code::exit_code_of;
+use rinit_ipc::ShutdownAction;
 use rinit_service::config::Config;
 
 // This has to be async just for AsyncConnection
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
-    let opts = Opts::parse();
+async fn main() -> ExitCode {
+    match run(Opts::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(exit_code_of(&err))
+        }
+    }
+}
+
+async fn run(opts: Opts) -> Result<()> {
     let config = Config::new(opts.config)?;
 
     match opts.subcmd {
         Command::Enable(enable_command) => enable_command.run(config).await?,
         Command::Disable(disable_command) => disable_command.run(config).await?,
+        Command::Edit(edit_command) => edit_command.run(config).await?,
         Command::Status(status_command) => status_command.run(config).await?,
         Command::Start(start_command) => start_command.run(config).await?,
         Command::Stop(stop_command) => stop_command.run(config).await?,
         Command::Reload(reload_command) => reload_command.run(config).await?,
+        Command::ReloadConfig(reload_config_command) => reload_config_command.run(config).await?,
+        Command::Reparse(reparse_command) => reparse_command.run(config).await?,
+        Command::Reexec(reexec_command) => reexec_command.run(config).await?,
+        Command::RunLevel(runlevel_command) => runlevel_command.run(config).await?,
+        Command::Watch(watch_command) => watch_command.run(config).await?,
+        Command::Verify(verify_command) => verify_command.run(config).await?,
+        Command::DumpGraph(dump_graph_command) => dump_graph_command.run(config).await?,
+        Command::Export(export_command) => export_command.run(config).await?,
+        Command::Ping(ping_command) => ping_command.run(config).await?,
+        Command::Config(config_command) => config_command.run(config).await?,
+        Command::LogLevel(log_level_command) => log_level_command.run(config).await?,
+        Command::Top(top_command) => top_command.run(config).await?,
+        Command::Cat(cat_command) => cat_command.run(config).await?,
+        Command::Dependencies(dependencies_command) => dependencies_command.run(config).await?,
+        Command::Kill(kill_command) => kill_command.run(config).await?,
+        Command::Poweroff(shutdown_command) => {
+            shutdown_command.run(config, ShutdownAction::Poweroff).await?
+        }
+        Command::Reboot(shutdown_command) => {
+            shutdown_command.run(config, ShutdownAction::Reboot).await?
+        }
+        Command::Halt(shutdown_command) => {
+            shutdown_command.run(config, ShutdownAction::Halt).await?
+        }
     }
 
     Ok(())
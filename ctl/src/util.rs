@@ -1,22 +1,260 @@
-use anyhow::Result;
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{
+    ensure,
+    Context,
+    Result,
+};
 use rinit_ipc::{
     AsyncConnection,
     Reply,
     Request,
 };
-use rinit_service::types::RunLevel;
+use rinit_service::{
+    graph::{
+        self,
+        DependencyGraph,
+    },
+    types::{
+        RunLevel,
+        Service,
+    },
+};
+use tokio::time::timeout;
 
 pub async fn start_service(
     conn: &mut AsyncConnection,
     service: &str,
     runlevel: RunLevel,
+    stop_conflicts: bool,
 ) -> Result<bool> {
     let request = Request::StartService {
         service: service.to_owned(),
         runlevel,
+        stop_conflicts,
     };
     match conn.send_request(request).await?? {
         Reply::Success(success) => Ok(success),
         _ => unreachable!(),
     }
 }
+
+/// Same as start_service, but stop waiting for the service after wait_timeout
+/// and return None. Only the client gives up, rsvc keeps starting the service.
+/// Its reply will still arrive on conn, which can't be used anymore: rsvc
+/// replies to the requests of a connection one at a time
+pub async fn start_service_with_timeout(
+    conn: &mut AsyncConnection,
+    service: &str,
+    runlevel: RunLevel,
+    stop_conflicts: bool,
+    wait_timeout: Duration,
+) -> Result<Option<bool>> {
+    timeout(
+        wait_timeout,
+        start_service(conn, service, runlevel, stop_conflicts),
+    )
+    .await
+    .ok()
+    .transpose()
+}
+
+pub async fn stop_service(
+    conn: &mut AsyncConnection,
+    service: &str,
+    runlevel: RunLevel,
+) -> Result<bool> {
+    let request = Request::StopService {
+        service: service.to_owned(),
+        runlevel,
+    };
+    match conn.send_request(request).await?? {
+        Reply::Success(success) => Ok(success),
+        _ => unreachable!(),
+    }
+}
+
+/// Check that the users and groups running the scripts exist, so that a typo
+/// is reported when enabling the service instead of when starting it
+pub fn check_users(services: &[Service]) -> Result<()> {
+    for service in services {
+        for script in service.scripts() {
+            if let Some(user) = &script.user {
+                ensure!(
+                    user_exists(user),
+                    "service {}: user {user} does not exist",
+                    service.name()
+                );
+            }
+            if let Some(group) = &script.group {
+                ensure!(
+                    group_exists(group),
+                    "service {}: group {group} does not exist",
+                    service.name()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn user_exists(name: &str) -> bool {
+    user_id(name).is_some()
+}
+
+fn group_exists(name: &str) -> bool {
+    group_id(name).is_some()
+}
+
+/// uid of the user name, None if it doesn't exist
+pub fn user_id(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    (!passwd.is_null()).then(|| unsafe { (*passwd).pw_uid })
+}
+
+/// gid of the group name, None if it doesn't exist
+pub fn group_id(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let group = unsafe { libc::getgrnam(name.as_ptr()) };
+    (!group.is_null()).then(|| unsafe { (*group).gr_gid })
+}
+
+/// Passed as graph file, it stands for stdin when reading and stdout when writing
+pub const STDIO_GRAPH: &str = "-";
+
+pub fn is_stdio_graph(graph_file: &Path) -> bool {
+    graph_file == Path::new(STDIO_GRAPH)
+}
+
+/// Read the dependency graph, None if it has not been initialized yet, i.e.
+/// the file doesn't exist or stdin is empty
+pub fn read_graph(graph_file: &Path) -> Result<Option<DependencyGraph>> {
+    if is_stdio_graph(graph_file) {
+        return deserialize_graph(io::stdin().lock());
+    }
+    if !graph_file.exists() {
+        return Ok(None);
+    }
+    let file = File::open(graph_file)
+        .with_context(|| format!("unable to read graph from file {:?}", graph_file))?;
+    deserialize_graph(file)
+}
+
+fn deserialize_graph(mut reader: impl Read) -> Result<Option<DependencyGraph>> {
+    let mut contents = Vec::new();
+    reader
+        .read_to_end(&mut contents)
+        .context("unable to read the dependency graph")?;
+    if contents.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    serde_json::from_slice(&contents)
+        .map(Some)
+        .context("unable to deserialize the dependency graph")
+}
+
+/// Save the dependency graph without ever leaving a partially written file
+pub fn write_graph(
+    graph: &DependencyGraph,
+    graph_file: &Path,
+) -> Result<()> {
+    if is_stdio_graph(graph_file) {
+        let contents =
+            serde_json::to_vec(graph).context("unable to serialize the dependency graph")?;
+        let mut stdout = io::stdout().lock();
+        return stdout
+            .write_all(&contents)
+            .and_then(|_| writeln!(stdout))
+            .and_then(|_| stdout.flush())
+            .context("unable to write the dependency graph to stdout");
+    }
+    graph::write_graph(graph, graph_file)
+        .with_context(|| format!("unable to write the dependency graph to {:?}", graph_file))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs,
+        process,
+    };
+
+    use remoc::rch;
+    use rinit_ipc::{
+        Envelope,
+        ReplyEnvelope,
+    };
+    use tokio::{
+        net::UnixListener,
+        task,
+        time::{
+            sleep,
+            Instant,
+        },
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn give_up_waiting_for_slow_service() {
+        let socket = env::temp_dir().join(format!("rinit-wait-timeout-{}.socket", process::id()));
+        let _ = fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        // Acts as rsvc, the service takes 10 seconds to start
+        task::spawn(async move {
+            let (stream, _addr) = listener.accept().await.unwrap();
+            let (socket_rx, socket_tx) = stream.into_split();
+            let (conn, mut tx, mut rx): (
+                _,
+                rch::base::Sender<ReplyEnvelope>,
+                rch::base::Receiver<Envelope<Request>>,
+            ) = remoc::Connect::io(remoc::Cfg::default(), socket_rx, socket_tx)
+                .await
+                .unwrap();
+            task::spawn(conn);
+            let request = rx.recv().await.unwrap().unwrap();
+            sleep(Duration::from_secs(10)).await;
+            let _ = tx
+                .send(Envelope {
+                    id: request.id,
+                    payload: Ok(Reply::Success(true)),
+                })
+                .await;
+        });
+
+        let mut conn = AsyncConnection::new(socket.to_str().unwrap()).await.unwrap();
+        let start = Instant::now();
+        let res = start_service_with_timeout(
+            &mut conn,
+            "slow",
+            RunLevel::Default,
+            false,
+            Duration::from_millis(200),
+        )
+        .await;
+        assert!(res.unwrap().is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        fs::remove_file(&socket).unwrap();
+    }
+
+    #[test]
+    fn empty_input_is_uninitialized_graph() {
+        assert!(deserialize_graph(&b""[..]).unwrap().is_none());
+        assert!(deserialize_graph(&b"\n"[..]).unwrap().is_none());
+        let graph = serde_json::to_vec(&DependencyGraph::new()).unwrap();
+        assert!(deserialize_graph(&graph[..]).unwrap().is_some());
+        assert!(deserialize_graph(&b"{"[..]).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    env,
+    fs,
+    process::{
+        self,
+        Command,
+    },
+};
+
+use rinit_service::graph::DependencyGraph;
+
+#[test]
+fn pipe_graph_through_commands() {
+    let dir = env::temp_dir().join(format!("rinit-graph-pipe-{}", process::id()));
+    let service_type = if unsafe { libc::getuid() } == 0 {
+        "system"
+    } else {
+        "user"
+    };
+    let service_dir = dir.join("config").join(service_type);
+    fs::create_dir_all(&service_dir).unwrap();
+    for (name, options) in [
+        ("foo", "[options]\ndependencies = [ bar ]\n"),
+        ("bar", ""),
+        ("baz", ""),
+    ] {
+        fs::write(
+            service_dir.join(format!("{name}.{service_type}")),
+            format!(
+                "name = {name}\ntype = oneshot\n[start]\nexecute = (\n    exit 0\n)\n\
+                 prefix = bash\n{options}"
+            ),
+        )
+        .unwrap();
+    }
+    let config_file = dir.join("rinit.conf");
+    fs::write(
+        &config_file,
+        format!(
+            "configdir = \"{}\"\ndatadir = \"{}\"\n",
+            dir.join("config").display(),
+            dir.join("data").display()
+        ),
+    )
+    .unwrap();
+
+    // Every command reads the graph written by the previous one on stdin
+    let rctl = format!(
+        "{} --config {}",
+        env!("CARGO_BIN_EXE_rctl"),
+        config_file.display()
+    );
+    let output = Command::new("bash")
+        .arg("-c")
+        .arg(format!(
+            "set -o pipefail; {rctl} enable --graph - foo < /dev/null | \
+             {rctl} enable --graph - baz | {rctl} disable --graph - foo"
+        ))
+        .output()
+        .unwrap();
+    fs::remove_dir_all(dir).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // foo could only be disabled if it was read from the first command
+    let graph: DependencyGraph = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(graph.nodes.keys().collect::<Vec<_>>(), vec!["baz"]);
+}
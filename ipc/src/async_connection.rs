@@ -1,5 +1,10 @@
-use std::io;
+use std::{
+    cmp,
+    io,
+    time::Duration,
+};
 
+use rand::Rng;
 use remoc::{
     chmux::ChMuxError,
     rch,
@@ -12,19 +17,32 @@ use snafu::{
 use tokio::{
     net::UnixStream,
     task,
+    time::{
+        self,
+        Instant,
+    },
 };
 
 use crate::{
     request_error::RequestError,
+    Envelope,
+    ErrorKind,
     Reply,
+    ReplyEnvelope,
     Request,
 };
 
 pub struct AsyncConnection {
-    tx: rch::base::Sender<Request>,
-    rx: rch::base::Receiver<Result<Reply, RequestError>>,
+    tx: rch::base::Sender<Envelope<Request>>,
+    rx: rch::base::Receiver<ReplyEnvelope>,
+    // Id of the next request sent on this connection
+    next_id: u64,
+    // How long sending the requests can take before giving up
+    write_timeout: Duration,
 }
 
+type Result<T, E = ConnectionError<Envelope<Request>>> = std::result::Result<T, E>;
+
 #[derive(Snafu, Debug)]
 pub enum ConnectionError<T>
 where
@@ -47,23 +65,57 @@ where
     ReceiveError { source: rch::base::RecvError },
     #[snafu(display("error while sending a reply: {source}"), context(false))]
     SendError { source: rch::base::SendError<T> },
+    #[snafu(display("request {request:?} could not be sent within {timeout:?}"))]
+    WriteFailed { request: Request, timeout: Duration },
     #[snafu(display("no reply received for request {request:?}"))]
     NoReplyReceived { request: Request },
+    #[snafu(display("received a reply for the unknown request {id}"))]
+    UnknownReplyId { id: u64 },
     #[snafu(display("could not accept connection"))]
     ListenError { source: std::io::Error },
 }
 
+impl<T> ConnectionError<T>
+where
+    T: std::fmt::Debug + 'static,
+{
+    /// Whether the error only means that the other end went away, e.g. the
+    /// socket returned EPIPE or ECONNRESET. This is how a client normally
+    /// stops listening and should not be reported as a failure
+    pub fn is_disconnect(&self) -> bool {
+        match self {
+            Self::SendError { source } => source.is_disconnected() || source.is_closed(),
+            Self::ReceiveError {
+                source: rch::base::RecvError::Receive(err),
+            } => err.is_terminated(),
+            _ => false,
+        }
+    }
+
+    /// Every connection error means that rsvc could not be talked to
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::Connection
+    }
+}
+
 // Ideally there should be async and sync connection, but
 // remoc is async and requires to spawn a new task. This functions cannot
 // be sync, as Runtime::blowk_on would suspend any non-finished tasks upon
 // completion, including the one that remoc requires to spawn
 // remoc is an awesome crate for handling the connection and it is worth
 // to have all the application as (single-threaded) async.
+// remoc frames every message on top of the stream, so short reads and reads
+// interrupted by signals (EINTR, retried by tokio) never reach the deserializer
+// and there is no need for a custom read loop here.
 // Keep in mind that any external communication will be handled by another
 // daemon and not by svc/ctl itself, so that remoc is not enforced nor
 // required for interacting with rinit
 impl AsyncConnection {
-    pub async fn new(socket: &str) -> Result<Self, ConnectionError<Request>> {
+    const BACKOFF_INITIAL_DELAY: Duration = Duration::from_millis(50);
+    const BACKOFF_MAX_DELAY: Duration = Duration::from_secs(2);
+    pub const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub async fn new(socket: &str) -> Result<Self> {
         let stream = UnixStream::connect(socket).await.with_context(|_| {
             SocketConnectionSnafu {
                 socket: socket.to_string(),
@@ -72,26 +124,278 @@ impl AsyncConnection {
         let (socket_rx, socket_tx) = stream.into_split();
         let (conn, tx, rx): (
             _,
-            rch::base::Sender<Request>,
-            rch::base::Receiver<Result<Reply, RequestError>>,
+            rch::base::Sender<Envelope<Request>>,
+            rch::base::Receiver<ReplyEnvelope>,
         ) = remoc::Connect::io(remoc::Cfg::default(), socket_rx, socket_tx).await?;
         task::spawn(conn);
 
-        Ok(Self { tx, rx })
+        Ok(Self {
+            tx,
+            rx,
+            next_id: 0,
+            write_timeout: Self::DEFAULT_WRITE_TIMEOUT,
+        })
+    }
+
+    /// Give up sending the requests after write_timeout, e.g. when rsvc is
+    /// wedged and doesn't read them anymore. The connection must not be used
+    /// after a request failed to be sent
+    pub fn with_write_timeout(
+        mut self,
+        write_timeout: Duration,
+    ) -> Self {
+        self.write_timeout = write_timeout;
+        self
     }
 
-    pub async fn new_host_address() -> Result<Self, ConnectionError<Request>> {
+    pub async fn new_host_address() -> Result<Self> {
         Self::new(crate::get_host_address()).await
     }
 
+    /// Try connecting to socket until deadline has passed, waiting an
+    /// exponentially increasing (and randomly jittered) delay between each
+    /// attempt. Return the last connection error on failure
+    pub async fn new_with_backoff(
+        socket: &str,
+        deadline: Instant,
+    ) -> Result<Self> {
+        let mut delay = Self::BACKOFF_INITIAL_DELAY;
+        loop {
+            let err = match Self::new(socket).await {
+                Ok(conn) => return Ok(conn),
+                Err(err) => err,
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(err);
+            }
+            // Add up to 50% of jitter, so that multiple clients waiting on the same socket
+            // won't retry all at once
+            let jitter = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 2);
+            let sleep = cmp::min(delay + Duration::from_millis(jitter), deadline - now);
+            time::sleep(sleep).await;
+            delay = cmp::min(delay * 2, Self::BACKOFF_MAX_DELAY);
+        }
+    }
+
+    /// Same as new_with_backoff, but connect to the default socket address
+    /// Useful to wait for the supervisor to come up during early boot
+    pub async fn connect_with_backoff(deadline: Instant) -> Result<Self> {
+        Self::new_with_backoff(crate::get_host_address(), deadline).await
+    }
+
     pub async fn send_request(
         &mut self,
         request: Request,
-    ) -> Result<Result<Reply, RequestError>, ConnectionError<Request>> {
-        self.tx.send(request.clone()).await?;
-        self.rx
-            .recv()
-            .await?
-            .with_context(|| NoReplyReceivedSnafu { request })
+    ) -> Result<Result<Reply, RequestError>> {
+        Ok(self.send_requests(vec![request]).await?.pop().unwrap())
+    }
+
+    /// Send all the requests before waiting for any reply, to avoid a round
+    /// trip for each of them. The replies are matched to the requests by id
+    /// and returned in the same order as the requests
+    pub async fn send_requests(
+        &mut self,
+        requests: Vec<Request>,
+    ) -> Result<Vec<Result<Reply, RequestError>>> {
+        let first_id = self.next_id;
+        let deadline = Instant::now() + self.write_timeout;
+        for request in &requests {
+            let send = self.tx.send(Envelope {
+                id: self.next_id,
+                payload: request.clone(),
+            });
+            time::timeout_at(deadline, send).await.map_err(|_| {
+                WriteFailedSnafu {
+                    request: request.clone(),
+                    timeout: self.write_timeout,
+                }
+                .build()
+            })??;
+            self.next_id += 1;
+        }
+        let mut replies: Vec<Option<Result<Reply, RequestError>>> =
+            requests.iter().map(|_| None).collect();
+        for _ in 0..requests.len() {
+            let Some(reply) = self.rx.recv().await? else {
+                let missing = replies.iter().position(Option::is_none).unwrap();
+                return NoReplyReceivedSnafu {
+                    request: requests[missing].clone(),
+                }
+                .fail();
+            };
+            let slot = reply
+                .id
+                .checked_sub(first_id)
+                .and_then(|index| replies.get_mut(index as usize))
+                .filter(|slot| slot.is_none())
+                .with_context(|| UnknownReplyIdSnafu { id: reply.id })?;
+            *slot = Some(reply.payload);
+        }
+        Ok(replies.into_iter().map(Option::unwrap).collect())
+    }
+
+    /// Receive a reply that was not requested, e.g. after Request::Subscribe
+    /// Return None when the connection has been closed
+    pub async fn recv_reply(&mut self) -> Result<Option<Result<Reply, RequestError>>> {
+        Ok(self.rx.recv().await?.map(|reply| reply.payload))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use rinit_service::service_state::{
+        IdleServiceState,
+        ServiceState,
+    };
+    use tokio::net::UnixListener;
+
+    use super::*;
+    use crate::ServiceStatus;
+
+    #[tokio::test]
+    async fn connect_with_backoff_to_late_listener() {
+        let socket = env::temp_dir().join(format!("rinit-backoff-{}.socket", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let socket_clone = socket.clone();
+        let listener = task::spawn(async move {
+            time::sleep(Duration::from_millis(200)).await;
+            let listener = UnixListener::bind(&socket_clone).unwrap();
+            let (stream, _addr) = listener.accept().await.unwrap();
+            let (socket_rx, socket_tx) = stream.into_split();
+            let (conn, _tx, _rx): (
+                _,
+                rch::base::Sender<ReplyEnvelope>,
+                rch::base::Receiver<Envelope<Request>>,
+            ) = remoc::Connect::io(remoc::Cfg::default(), socket_rx, socket_tx)
+                .await
+                .unwrap();
+            task::spawn(conn);
+        });
+
+        let conn = AsyncConnection::new_with_backoff(
+            socket.to_str().unwrap(),
+            Instant::now() + Duration::from_secs(5),
+        )
+        .await;
+        assert!(conn.is_ok());
+        listener.await.unwrap();
+        std::fs::remove_file(&socket).unwrap();
+    }
+
+    #[tokio::test]
+    async fn connect_with_backoff_deadline_passed() {
+        let socket =
+            env::temp_dir().join(format!("rinit-no-listener-{}.socket", std::process::id()));
+        let start = Instant::now();
+        let conn = AsyncConnection::new_with_backoff(
+            socket.to_str().unwrap(),
+            start + Duration::from_millis(300),
+        )
+        .await;
+        assert!(matches!(
+            conn,
+            Err(ConnectionError::SocketConnectionError { .. })
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn send_times_out_when_not_read() {
+        let socket = env::temp_dir().join(format!("rinit-not-read-{}.socket", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = task::spawn(async move {
+            let (stream, _addr) = listener.accept().await.unwrap();
+            let (socket_rx, socket_tx) = stream.into_split();
+            let (conn, _tx, _rx): (
+                _,
+                rch::base::Sender<ReplyEnvelope>,
+                rch::base::Receiver<Envelope<Request>>,
+            ) = remoc::Connect::io(remoc::Cfg::default(), socket_rx, socket_tx)
+                .await
+                .unwrap();
+            task::spawn(conn);
+            // Never read the requests, as a wedged rsvc
+            let _ = stopped.await;
+        });
+
+        let mut conn = AsyncConnection::new(socket.to_str().unwrap())
+            .await
+            .unwrap()
+            .with_write_timeout(Duration::from_millis(300));
+        // Way more than the socket and the channel can buffer
+        let requests = (0..64)
+            .map(|_| Request::SetLogLevel("a".repeat(1 << 20)))
+            .collect();
+        let start = Instant::now();
+        let res = conn.send_requests(requests).await;
+        assert!(matches!(res, Err(ConnectionError::WriteFailed { .. })));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        stop.send(()).unwrap();
+        server.await.unwrap();
+        std::fs::remove_file(&socket).unwrap();
+    }
+
+    #[tokio::test]
+    async fn match_pipelined_replies_by_id() {
+        let socket = env::temp_dir().join(format!("rinit-pipeline-{}.socket", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = task::spawn(async move {
+            let (stream, _addr) = listener.accept().await.unwrap();
+            let (socket_rx, socket_tx) = stream.into_split();
+            let (conn, mut tx, mut rx): (
+                _,
+                rch::base::Sender<ReplyEnvelope>,
+                rch::base::Receiver<Envelope<Request>>,
+            ) = remoc::Connect::io(remoc::Cfg::default(), socket_rx, socket_tx)
+                .await
+                .unwrap();
+            task::spawn(conn);
+            let mut requests = Vec::new();
+            for _ in 0..3 {
+                requests.push(rx.recv().await.unwrap().unwrap());
+            }
+            // Reply in reverse order, the client has to reorder the replies
+            for request in requests.into_iter().rev() {
+                let Request::ServiceStatus(name) = request.payload else {
+                    panic!("unexpected request {:?}", request.payload);
+                };
+                let status = ServiceStatus::new(name, ServiceState::Idle(IdleServiceState::Up));
+                tx.send(Envelope {
+                    id: request.id,
+                    payload: Ok(Reply::ServiceStatus(status)),
+                })
+                .await
+                .unwrap();
+            }
+        });
+
+        let mut conn = AsyncConnection::new(socket.to_str().unwrap()).await.unwrap();
+        let replies = conn
+            .send_requests(
+                ["foo", "bar", "baz"]
+                    .into_iter()
+                    .map(|name| Request::ServiceStatus(name.to_string()))
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        let names: Vec<_> = replies
+            .into_iter()
+            .map(|reply| {
+                match reply.unwrap() {
+                    Reply::ServiceStatus(status) => status.name,
+                    reply => panic!("unexpected reply {reply:?}"),
+                }
+            })
+            .collect();
+        assert_eq!(names, vec!["foo", "bar", "baz"]);
+        server.await.unwrap();
+        std::fs::remove_file(&socket).unwrap();
     }
 }
@@ -0,0 +1,21 @@
+use std::path::PathBuf;
+
+use rinit_service::config::Config;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// The configuration used by rsvc, as returned by Request::GetConfig
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EffectiveConfig {
+    /// The file passed to rsvc with --config. When unset, the configuration
+    /// has been looked up in the default places
+    pub config_file: Option<PathBuf>,
+    /// As read at startup or by the last ReloadConfig
+    pub config: Config,
+    /// Where the service files are looked up, in order
+    pub service_directories: Vec<PathBuf>,
+    /// The IPC socket rsvc listens on
+    pub socket: String,
+}
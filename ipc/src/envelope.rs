@@ -0,0 +1,21 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    Reply,
+    RequestError,
+};
+
+/// A request or a reply sent over the socket, tagged with the id of the
+/// request. The replies carry the id of their request, so that multiple
+/// requests can be sent on the same connection without waiting for each reply
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Envelope<T> {
+    pub id: u64,
+    pub payload: T,
+}
+
+/// Envelope of the replies sent by rsvc
+pub type ReplyEnvelope = Envelope<Result<Reply, RequestError>>;
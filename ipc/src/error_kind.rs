@@ -0,0 +1,19 @@
+/// Stable kind of the errors of rinit, so that clients can tell the failures
+/// apart without matching their messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// rsvc can't be reached or the connection broke
+    Connection,
+    /// The service requested is not in the dependency graph
+    ServiceNotFound,
+    /// The dependency graph is missing or a dependency can't be resolved
+    DependencyGraph,
+    /// The service, or one of its dependencies, didn't start or stop
+    ServiceFailed,
+    /// Another service has to be stopped first
+    Conflict,
+    /// The request itself is not valid
+    InvalidRequest,
+    /// rsvc failed on its side, e.g. while writing a file
+    System,
+}
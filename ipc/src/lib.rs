@@ -1,17 +1,37 @@
 mod async_connection;
+mod effective_config;
+mod envelope;
+mod error_kind;
 mod get_host_address;
 mod reply;
 mod request;
 pub mod request_error;
+mod serialization;
+mod service_snapshot;
+mod service_status;
+mod service_usage;
+mod shutdown_action;
+mod status_changed;
 
 pub use async_connection::{
     AsyncConnection,
     ConnectionError,
 };
+pub use effective_config::EffectiveConfig;
+pub use envelope::{
+    Envelope,
+    ReplyEnvelope,
+};
+pub use error_kind::ErrorKind;
 pub use get_host_address::get_host_address;
 pub use reply::Reply;
 pub use request::Request;
 pub use request_error::RequestError;
+pub use service_snapshot::ServiceSnapshot;
+pub use service_status::ServiceStatus;
+pub use service_usage::ServiceUsage;
+pub use shutdown_action::ShutdownAction;
+pub use status_changed::StatusChanged;
 
 #[macro_use]
 extern crate lazy_static;
@@ -1,13 +1,28 @@
-use rinit_service::service_state::ServiceState;
+use std::time::Duration;
+
 use serde::{
     Deserialize,
     Serialize,
 };
 
+use crate::{
+    EffectiveConfig,
+    ServiceSnapshot,
+    ServiceStatus,
+    ServiceUsage,
+    StatusChanged,
+};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Reply {
-    ServicesStates(Vec<(String, ServiceState)>),
-    ServiceState(String, ServiceState),
+    ServicesStatus(Vec<ServiceStatus>),
+    ServiceStatus(ServiceStatus),
+    GraphDump(Vec<ServiceSnapshot>),
     Success(bool),
+    StatusChanged(StatusChanged),
+    Usage(Vec<ServiceUsage>),
+    /// Version of rsvc and how long it has been running
+    Pong { version: String, uptime: Duration },
+    Config(EffectiveConfig),
     Empty,
 }
@@ -10,16 +10,66 @@ use serde::{
     Serialize,
 };
 
+use crate::ShutdownAction;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
     UpdateServiceStatus(String, IdleServiceState),
+    /// Sent by a service being started, to start a service it wants without
+    /// waiting for it
+    StartWantedService { service: String, wanted: String },
     ServicesStatus,
     ServiceStatus(String),
-    StartService { service: String, runlevel: RunLevel },
+    /// Snapshot of every service in the live graph, replied with Reply::GraphDump
+    DumpGraph,
+    /// With stop_conflicts, the running services conflicting with it are
+    /// stopped first instead of refusing to start it
+    StartService {
+        service: String,
+        runlevel: RunLevel,
+        stop_conflicts: bool,
+    },
     StopService { service: String, runlevel: RunLevel },
     StartAllServices,
+    /// Change the runlevel started by StartAllServices and start its services
+    SetRunLevel(RunLevel),
     StopAllServices,
-    ReloadGraph,
+    /// Stop every service, like StopAllServices, then power off, reboot or
+    /// halt the system
+    Shutdown { action: ShutdownAction },
+    /// Read the dependency graph again. The services that have been removed
+    /// are stopped, except the ones in keep_running, which are left alone
+    ReloadGraph { keep_running: Vec<String> },
+    /// Only update this service from the dependency graph, e.g. after editing
+    /// its file
+    ReloadService(String),
+    /// Parse the file of this service again in rsvc and apply it, restarting
+    /// the service when the new definition needs it. Only this service is
+    /// affected, the dependency graph on disk is updated accordingly
+    ReparseService { service: String },
+    ReloadConfig,
+    /// Receive a Reply::StatusChanged for every transition until the
+    /// connection is closed
+    Subscribe,
+    /// Check that rsvc is alive and responsive, replied with Reply::Pong
+    Ping,
+    /// The configuration used by rsvc, replied with Reply::Config
+    GetConfig,
+    /// Replace the filter of the rsvc log, e.g. debug or
+    /// warn,rsvc::live_service=debug
+    SetLogLevel(String),
+    /// Memory and CPU time of the running longruns, replied with Reply::Usage
+    Usage,
+    /// Send a raw signal, by number, to the process group of a running
+    /// longrun. Its status is not changed by rsvc
+    Signal { service: String, signal: i32 },
+    /// Send its reload_signal to a running longrun. With notify_reload, only
+    /// replied to once it has reported that the reload is done
+    ReloadProcess { service: String },
+    /// Execute the binary of rsvc again, e.g. after upgrading it. The running
+    /// services are adopted by the new rsvc instead of being restarted. Only
+    /// replied to if it failed, the connection is closed otherwise
+    Reexec,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -4,6 +4,8 @@ use serde::{
 };
 use snafu::Snafu;
 
+use crate::ErrorKind;
+
 #[derive(Snafu, Debug, Serialize, Deserialize)]
 pub enum RequestError {
     #[snafu(display("{err}"))]
@@ -17,17 +19,89 @@ pub enum RequestError {
 pub enum LogicError {
     #[snafu(display("dependency {dependency} failed to start for service {service}"))]
     DependencyFailedToStart { service: String, dependency: String },
+    #[snafu(display("dependencies {dependencies:?} failed to start for service {service}"))]
+    DependenciesFailedToStart {
+        service: String,
+        dependencies: Vec<String>,
+    },
+    #[snafu(display(
+        "service {service} dependencies {dependencies:?} didn't start within the subtree timeout"
+    ))]
+    DependenciesTimedOut {
+        service: String,
+        dependencies: Vec<String>,
+    },
+    #[snafu(display(
+        "dependency chain {} is deeper than the maximum of {max_depth}",
+        path.join(" -> ")
+    ))]
+    DependencyTooDeep { path: Vec<String>, max_depth: usize },
     #[snafu(display("service {service} dependendents {dependents:?} are still running"))]
     DependentsStillRunning {
         service: String,
         dependents: Vec<String>,
     },
+    #[snafu(display("changing {option} in the configuration requires restarting rsvc"))]
+    ConfigChangeRequiresRestart { option: String },
+    #[snafu(display("service {service} depends on {dependency}, which is not enabled"))]
+    DependencyNotFound { service: String, dependency: String },
+    #[snafu(display("service {service} conflicts with {conflict}, which is running"))]
+    ConflictingServiceRunning { service: String, conflict: String },
     #[snafu(display("dependency graph not found in path {path}"))]
     DependencyGraphNotFound { path: String },
     #[snafu(display("service {service} has a different runlevel then the one requested"))]
     RunLevelMustMatch { service: String },
     #[snafu(display("service {service} failed to start"))]
     ServiceFailedToStart { service: String },
+    #[snafu(display("service {service} has been skipped, its condition is not met"))]
+    ServiceSkipped { service: String },
+    #[snafu(display("service {service} failed to stop and it's still up"))]
+    ServiceFailedToStop { service: String },
     #[snafu(display("service {service} does not exists"))]
     ServiceNotFound { service: String },
+    #[snafu(display("the file of service {service} can't be applied: {err}"))]
+    ServiceFileRejected { service: String, err: String },
+    #[snafu(display("invalid log level {level}: {err}"))]
+    InvalidLogLevel { level: String, err: String },
+    #[snafu(display("{signal} is not a valid signal"))]
+    InvalidSignal { signal: i32 },
+    #[snafu(display("service {service} has no running process"))]
+    ServiceNotRunning { service: String },
+    #[snafu(display("service {service} didn't finish reloading before its timeout"))]
+    ReloadTimedOut { service: String },
+}
+
+impl RequestError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            RequestError::SystemError { .. } => ErrorKind::System,
+            RequestError::LogicError { err } => err.kind(),
+        }
+    }
+}
+
+impl LogicError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            LogicError::ServiceNotFound { .. } => ErrorKind::ServiceNotFound,
+            LogicError::DependencyGraphNotFound { .. }
+            | LogicError::DependencyNotFound { .. }
+            | LogicError::DependencyTooDeep { .. }
+            | LogicError::ServiceFileRejected { .. } => ErrorKind::DependencyGraph,
+            LogicError::DependencyFailedToStart { .. }
+            | LogicError::DependenciesFailedToStart { .. }
+            | LogicError::DependenciesTimedOut { .. }
+            | LogicError::ServiceFailedToStart { .. }
+            | LogicError::ServiceSkipped { .. }
+            | LogicError::ServiceFailedToStop { .. }
+            | LogicError::ServiceNotRunning { .. }
+            | LogicError::ReloadTimedOut { .. } => ErrorKind::ServiceFailed,
+            LogicError::DependentsStillRunning { .. }
+            | LogicError::ConflictingServiceRunning { .. } => ErrorKind::Conflict,
+            LogicError::ConfigChangeRequiresRestart { .. }
+            | LogicError::RunLevelMustMatch { .. }
+            | LogicError::InvalidLogLevel { .. }
+            | LogicError::InvalidSignal { .. } => ErrorKind::InvalidRequest,
+        }
+    }
 }
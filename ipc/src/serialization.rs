@@ -0,0 +1,111 @@
+/// Serialize the state as its lowercase name, e.g. "up" or "starting"
+pub mod state_string {
+    use rinit_service::service_state::{
+        IdleServiceState,
+        ServiceState,
+        TransitioningServiceState,
+    };
+    use serde::{
+        de::Error,
+        Deserialize,
+        Deserializer,
+        Serializer,
+    };
+
+    pub fn serialize<S>(
+        state: &ServiceState,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(state)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<ServiceState, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let state = String::deserialize(deserializer)?;
+        Ok(match state.as_str() {
+            "up" => ServiceState::Idle(IdleServiceState::Up),
+            "down" => ServiceState::Idle(IdleServiceState::Down),
+            "failed" => ServiceState::Idle(IdleServiceState::Failed),
+            "skipped" => ServiceState::Idle(IdleServiceState::Skipped),
+            "starting" => ServiceState::Transitioning(TransitioningServiceState::Starting),
+            "stopping" => ServiceState::Transitioning(TransitioningServiceState::Stopping),
+            _ => return Err(D::Error::custom(format!("{state} is not a valid state"))),
+        })
+    }
+}
+
+/// Serialize a point in time as the seconds elapsed since the Unix epoch
+pub mod unix_seconds {
+    use std::time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    };
+
+    use serde::{
+        Deserialize,
+        Deserializer,
+        Serializer,
+    };
+
+    pub fn serialize<S>(
+        time: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match time.and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+            Some(duration) => serializer.serialize_some(&duration.as_secs()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<SystemTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Option::<u64>::deserialize(deserializer)?
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)))
+    }
+}
+
+/// Serialize a point in time as the milliseconds elapsed since the Unix epoch
+pub mod unix_millis {
+    use std::time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    };
+
+    use serde::{
+        Deserialize,
+        Deserializer,
+        Serializer,
+    };
+
+    pub fn serialize<S>(
+        time: &SystemTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let millis = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u64);
+        serializer.serialize_u64(millis)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(UNIX_EPOCH + Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
@@ -0,0 +1,20 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::ServiceStatus;
+
+/// Everything rsvc knows about a service in the live graph, as returned by
+/// Request::DumpGraph
+/// It is decoupled from the live types so that its serialization stays stable
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServiceSnapshot {
+    /// One of bundle, longrun, oneshot or virtual
+    #[serde(rename = "type")]
+    pub service_type: String,
+    pub dependencies: Vec<String>,
+    /// Sorted by name
+    pub dependents: Vec<String>,
+    pub status: ServiceStatus,
+}
@@ -0,0 +1,134 @@
+use std::time::SystemTime;
+
+use rinit_service::service_state::ServiceState;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::serialization::{
+    state_string,
+    unix_seconds,
+};
+
+/// Status of a service, as sent by rsvc and printed by rctl
+/// The serialization is kept stable because it is part of `rctl status --format json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServiceStatus {
+    pub name: String,
+    #[serde(with = "state_string")]
+    pub status: ServiceState,
+    /// Last state change, None if the service never changed state
+    /// Serialized as seconds since the Unix epoch
+    #[serde(with = "unix_seconds")]
+    pub since: Option<SystemTime>,
+    /// Pid of the supervised process, only available for running longruns
+    pub pid: Option<u32>,
+    /// Last time the service reached the up state
+    #[serde(with = "unix_seconds")]
+    pub up_since: Option<SystemTime>,
+    /// How many times the supervised process has been restarted after dying
+    pub restarts: u32,
+    /// Exit code of the last supervised process that died
+    pub last_exit_code: Option<i32>,
+    /// Dependencies not up yet, only filled while the service is starting
+    #[serde(default)]
+    pub waiting_on: Vec<String>,
+    /// Last time the schedule of a scheduled oneshot fired
+    #[serde(default, with = "unix_seconds")]
+    pub last_run: Option<SystemTime>,
+    /// Next time the schedule of a scheduled oneshot fires, only set while the
+    /// service is up
+    #[serde(default, with = "unix_seconds")]
+    pub next_run: Option<SystemTime>,
+}
+
+impl ServiceStatus {
+    pub fn new(
+        name: String,
+        status: ServiceState,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            since: None,
+            pid: None,
+            up_since: None,
+            restarts: 0,
+            last_exit_code: None,
+            waiting_on: Vec::new(),
+            last_run: None,
+            next_run: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{
+        Duration,
+        UNIX_EPOCH,
+    };
+
+    use rinit_service::service_state::{
+        IdleServiceState,
+        TransitioningServiceState,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn json_shape() {
+        let statuses = vec![
+            ServiceStatus {
+                since: Some(UNIX_EPOCH + Duration::from_secs(1700000000)),
+                pid: Some(42),
+                up_since: Some(UNIX_EPOCH + Duration::from_secs(1700000000)),
+                restarts: 2,
+                last_exit_code: Some(1),
+                waiting_on: vec!["db".to_string()],
+                next_run: Some(UNIX_EPOCH + Duration::from_secs(1700000300)),
+                ..ServiceStatus::new("foo".to_string(), ServiceState::Idle(IdleServiceState::Up))
+            },
+            ServiceStatus::new(
+                "bar".to_string(),
+                ServiceState::Transitioning(TransitioningServiceState::Stopping),
+            ),
+        ];
+        let value = serde_json::to_value(&statuses).unwrap();
+        assert_eq!(
+            value,
+            json!([
+                {
+                    "name": "foo",
+                    "status": "up",
+                    "since": 1700000000,
+                    "pid": 42,
+                    "up_since": 1700000000,
+                    "restarts": 2,
+                    "last_exit_code": 1,
+                    "waiting_on": ["db"],
+                    "last_run": null,
+                    "next_run": 1700000300,
+                },
+                {
+                    "name": "bar",
+                    "status": "stopping",
+                    "since": null,
+                    "pid": null,
+                    "up_since": null,
+                    "restarts": 0,
+                    "last_exit_code": null,
+                    "waiting_on": [],
+                    "last_run": null,
+                    "next_run": null,
+                },
+            ])
+        );
+        assert_eq!(
+            serde_json::from_value::<Vec<ServiceStatus>>(value).unwrap(),
+            statuses
+        );
+    }
+}
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// Resources used by a running longrun, as returned by Request::Usage
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServiceUsage {
+    pub name: String,
+    /// Bytes of memory in use
+    pub memory: u64,
+    /// CPU time spent in user and kernel mode since the service started
+    pub cpu_time: Duration,
+    /// Whether the values cover every process in the cgroup of the service,
+    /// otherwise they only cover the supervised process
+    pub from_cgroup: bool,
+}
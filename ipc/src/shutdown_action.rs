@@ -0,0 +1,28 @@
+use std::fmt;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// What rsvc does with the system once every service has been stopped by
+/// Request::Shutdown
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownAction {
+    Poweroff,
+    Reboot,
+    Halt,
+}
+
+impl fmt::Display for ShutdownAction {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        f.write_str(match self {
+            ShutdownAction::Poweroff => "poweroff",
+            ShutdownAction::Reboot => "reboot",
+            ShutdownAction::Halt => "halt",
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::SystemTime;
+
+use rinit_service::service_state::ServiceState;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::serialization::{
+    state_string,
+    unix_millis,
+};
+
+/// A transition of a service from one state to another
+/// The serialization is kept stable because it is part of `rctl watch --json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StatusChanged {
+    pub service: String,
+    #[serde(with = "state_string")]
+    pub from: ServiceState,
+    #[serde(with = "state_string")]
+    pub to: ServiceState,
+    /// Serialized as milliseconds since the Unix epoch
+    #[serde(with = "unix_millis")]
+    pub at: SystemTime,
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{
+        Duration,
+        UNIX_EPOCH,
+    };
+
+    use rinit_service::service_state::{
+        IdleServiceState,
+        TransitioningServiceState,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn json_shape() {
+        let event = StatusChanged {
+            service: "sshd".to_string(),
+            from: ServiceState::Transitioning(TransitioningServiceState::Starting),
+            to: ServiceState::Idle(IdleServiceState::Up),
+            at: UNIX_EPOCH + Duration::from_millis(1700000000123),
+        };
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "service": "sshd",
+                "from": "starting",
+                "to": "up",
+                "at": 1700000000123u64,
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<StatusChanged>(value).unwrap(),
+            event
+        );
+    }
+}
@@ -61,7 +61,7 @@ impl SectionBuilder for BundleOptionsBuilder {
     }
 
     fn get_fields(&self) -> &'static [&'static str] {
-        &[]
+        &["runlevel"]
     }
 
     fn get_array_fields(&self) -> &'static [&'static str] {
@@ -2,14 +2,32 @@ use std::{
     collections::HashMap,
     convert::TryInto,
     num::ParseIntError,
+    path::PathBuf,
+    str::FromStr,
 };
 
+use caps::Capability;
 use nix::sys::signal::Signal;
 use rinit_service::types::{
+    parse_milliseconds,
+    BindMount,
+    BindMountParseError,
     InvalidScriptPrefixError,
+    KillMode,
+    KillModeParseError,
+    ListenSocket,
+    ListenSocketParseError,
+    MillisecondsParseError,
     Script,
+    ScriptInput,
+    ScriptInputParseError,
+    ScriptOutput,
+    ScriptOutputParseError,
+    SyscallFilter,
+    SyscallFilterError,
 };
 use snafu::{
+    ensure,
     OptionExt,
     ResultExt,
     Snafu,
@@ -25,12 +43,46 @@ pub enum ScriptBuilderError {
     InvalidBoolean { key: String },
     #[snafu(display("failed conversion to integer for key {}", key))]
     InvalidInteger { key: String, source: ParseIntError },
+    #[snafu(display("invalid value for key {}: {}", key, source))]
+    InvalidTimeout {
+        key: String,
+        source: MillisecondsParseError,
+    },
     #[snafu(display("{}", source))]
     InvalidPrefix { source: InvalidScriptPrefixError },
     #[snafu(display("invalid signal"))]
     InvalidSignal { source: nix::Error },
+    #[snafu(display("{}", source))]
+    InvalidKillMode { source: KillModeParseError },
+    #[snafu(display("{} is not a valid capability", capability))]
+    InvalidCapability { capability: String },
     #[snafu(display("no execute found"))]
     NoExecuteFound,
+    #[snafu(display("oom_score_adj must be between -1000 and 1000, found {}", value))]
+    InvalidOomScoreAdj { value: i16 },
+    #[snafu(display("invalid value for key {}: {}", key, source))]
+    InvalidOutput {
+        key: String,
+        source: ScriptOutputParseError,
+    },
+    #[snafu(display("invalid value for key stdin: {}", source))]
+    InvalidInput { source: ScriptInputParseError },
+    #[snafu(display("the stdin file {:?} does not exist", path))]
+    StdinFileNotFound { path: PathBuf },
+    #[snafu(display("invalid value for key listen: {}", source))]
+    InvalidListen { source: ListenSocketParseError },
+    #[snafu(display("syscall_allow and syscall_deny cannot be used together"))]
+    ConflictingSyscallFilters,
+    #[snafu(display("{}", source))]
+    InvalidSyscall { source: SyscallFilterError },
+    #[snafu(display("{}", source))]
+    InvalidBindMount { source: BindMountParseError },
+    #[snafu(display("read_only_paths must be absolute, found {:?}", path))]
+    RelativeReadOnlyPath { path: PathBuf },
+    #[snafu(display("notify_reload requires notify_socket"))]
+    NotifyReloadWithoutSocket,
+    #[snafu(display("max_line_length must be greater than 0"))]
+    ZeroMaxLineLength,
 }
 
 pub struct ScriptBuilder {
@@ -64,11 +116,61 @@ where
         })
 }
 
+fn get_bool_or_default(
+    values: &mut HashMap<&'static str, String>,
+    key: &'static str,
+    default: bool,
+) -> Result<bool> {
+    values
+        .remove(key)
+        .map_or(Ok(default), |value| {
+            match value.as_str() {
+                "yes" => Ok(true),
+                "no" => Ok(false),
+                _ => Err(snafu::NoneError),
+            }
+        })
+        .with_context(|_| {
+            InvalidBooleanSnafu {
+                key: key.to_string(),
+            }
+        })
+}
+
+fn get_milliseconds_or_default(
+    values: &mut HashMap<&'static str, String>,
+    key: &'static str,
+    default: u32,
+) -> Result<u32> {
+    values
+        .remove(key)
+        .map_or(Ok(default), |value| parse_milliseconds(&value))
+        .with_context(|_| {
+            InvalidTimeoutSnafu {
+                key: key.to_string(),
+            }
+        })
+}
+
+fn get_output(
+    values: &mut HashMap<&'static str, String>,
+    key: &'static str,
+) -> Result<ScriptOutput> {
+    values
+        .remove(key)
+        .map_or(Ok(ScriptOutput::Log), |value| value.parse())
+        .with_context(|_| {
+            InvalidOutputSnafu {
+                key: key.to_string(),
+            }
+        })
+}
+
 impl SectionBuilder for ScriptBuilder {
     fn build(
         &mut self,
         values: &mut HashMap<&'static str, String>,
-        _array_values: &mut HashMap<&'static str, Vec<String>>,
+        array_values: &mut HashMap<&'static str, Vec<String>>,
         code_values: &mut HashMap<&'static str, String>,
     ) {
         let args: (&mut HashMap<&str, String>,) = (values,);
@@ -83,8 +185,8 @@ impl SectionBuilder for ScriptBuilder {
                     .remove("execute")
                     .with_context(|| NoExecuteFoundSnafu)?;
                 let timeout =
-                    get_int_or_default(values, "timeout", Script::DEFAULT_TIMEOUT)?;
-                let timeout_kill = get_int_or_default(
+                    get_milliseconds_or_default(values, "timeout", Script::DEFAULT_TIMEOUT)?;
+                let timeout_kill = get_milliseconds_or_default(
                     values,
                     "timeout_kill",
                     Script::DEFAULT_TIMEOUT_KILL,
@@ -94,10 +196,45 @@ impl SectionBuilder for ScriptBuilder {
                     "max_deaths",
                     Script::DEFAULT_MAX_DEATHS,
                 )?;
+                let restart_limit = values
+                    .remove("restart_limit")
+                    .map(|value| value.parse())
+                    .transpose()
+                    .with_context(|_| {
+                        InvalidIntegerSnafu {
+                            key: "restart_limit".to_string(),
+                        }
+                    })?;
+                let restart_window = get_milliseconds_or_default(
+                    values,
+                    "restart_window",
+                    Script::DEFAULT_RESTART_WINDOW,
+                )?;
+                let restart_delay = get_milliseconds_or_default(
+                    values,
+                    "restart_delay",
+                    Script::DEFAULT_RESTART_DELAY,
+                )?;
+                let log_buffer =
+                    get_int_or_default(values, "log_buffer", Script::DEFAULT_LOG_BUFFER)?;
+                let max_line_length = values
+                    .remove("max_line_length")
+                    .map(|value| value.parse::<usize>())
+                    .transpose()
+                    .with_context(|_| {
+                        InvalidIntegerSnafu {
+                            key: "max_line_length".to_string(),
+                        }
+                    })?;
+                ensure!(max_line_length != Some(0), ZeroMaxLineLengthSnafu);
                 let down_signal = values
                     .remove("down_signal")
                     .map_or(Ok(Script::DEFAULT_DOWN_SIGNAL), |down_signal| down_signal.parse::<Signal>().map(|sig| sig as i32))
                     .with_context(|_| InvalidSignalSnafu)?;
+                let kill_mode = values
+                    .remove("kill_mode")
+                    .map_or(Ok(KillMode::default()), |kill_mode| kill_mode.parse())
+                    .with_context(|_| InvalidKillModeSnafu)?;
 
                 let user = values.remove("user");
                 let group = values.remove("group");
@@ -111,16 +248,125 @@ impl SectionBuilder for ScriptBuilder {
                             key: "notify".to_string(),
                         }
                     })?;
+                let notify_socket = get_bool_or_default(values, "notify_socket", false)?;
+                let reload_signal = values
+                    .remove("reload_signal")
+                    .map_or(Ok(Script::DEFAULT_RELOAD_SIGNAL), |reload_signal| {
+                        reload_signal.parse::<Signal>().map(|sig| sig as i32)
+                    })
+                    .with_context(|_| InvalidSignalSnafu)?;
+                let notify_reload = get_bool_or_default(values, "notify_reload", false)?;
+                // The reload handshake is sent on the notify socket
+                ensure!(!notify_reload || notify_socket, NotifyReloadWithoutSocketSnafu);
+                let must_succeed = get_bool_or_default(values, "must_succeed", false)?;
+                let private_tmp = get_bool_or_default(values, "private_tmp", false)?;
+                let oom_score_adj = values
+                    .remove("oom_score_adj")
+                    .map(|value| value.parse::<i16>())
+                    .transpose()
+                    .with_context(|_| {
+                        InvalidIntegerSnafu {
+                            key: "oom_score_adj".to_string(),
+                        }
+                    })?;
+                if let Some(value) = oom_score_adj
+                    .filter(|value| !Script::OOM_SCORE_ADJ_RANGE.contains(value))
+                {
+                    return InvalidOomScoreAdjSnafu { value }.fail();
+                }
+                let stdin = values
+                    .remove("stdin")
+                    .map_or(Ok(ScriptInput::Null), |stdin| stdin.parse())
+                    .with_context(|_| InvalidInputSnafu)?;
+                if let ScriptInput::File(path) = &stdin {
+                    ensure!(path.exists(), StdinFileNotFoundSnafu { path: path.clone() });
+                }
+                let stdout = get_output(values, "stdout")?;
+                let stderr = get_output(values, "stderr")?;
+                let capabilities = array_values.remove("capabilities").unwrap_or_default();
+                if let Some(capability) = capabilities
+                    .iter()
+                    .find(|capability| Capability::from_str(capability).is_err())
+                {
+                    return InvalidCapabilitySnafu {
+                        capability: capability.to_owned(),
+                    }
+                    .fail();
+                }
+                let syscall_filter = match (
+                    array_values.remove("syscall_allow"),
+                    array_values.remove("syscall_deny"),
+                ) {
+                    (Some(_), Some(_)) => return ConflictingSyscallFiltersSnafu.fail(),
+                    (Some(syscalls), None) => Some(Box::new(SyscallFilter::Allow(syscalls))),
+                    (None, Some(syscalls)) => Some(Box::new(SyscallFilter::Deny(syscalls))),
+                    (None, None) => None,
+                };
+                if let Some(syscall_filter) = &syscall_filter {
+                    syscall_filter.compile().context(InvalidSyscallSnafu)?;
+                }
+                let listen = values
+                    .remove("listen")
+                    .map(|listen| listen.parse::<ListenSocket>())
+                    .transpose()
+                    .with_context(|_| InvalidListenSnafu)?;
+                let success_exit_codes = array_values
+                    .remove("success_exit_codes")
+                    .map_or(Ok(vec![0]), |codes| {
+                        codes.iter().map(|code| code.parse()).collect()
+                    })
+                    .with_context(|_| {
+                        InvalidIntegerSnafu {
+                            key: "success_exit_codes".to_string(),
+                        }
+                    })?;
+                let bind_mounts = array_values
+                    .remove("bind_mounts")
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|bind_mount| bind_mount.parse::<BindMount>())
+                    .collect::<Result<_, _>>()
+                    .with_context(|_| InvalidBindMountSnafu)?;
+                let read_only_paths: Vec<PathBuf> = array_values
+                    .remove("read_only_paths")
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .collect();
+                if let Some(path) = read_only_paths.iter().find(|path| !path.is_absolute()) {
+                    return RelativeReadOnlyPathSnafu { path: path.clone() }.fail();
+                }
                 Ok(Script {
                     prefix,
                     execute,
                     timeout,
                     timeout_kill,
                     max_deaths,
+                    restart_limit,
+                    restart_window,
+                    restart_delay,
                     down_signal,
+                    kill_mode,
                     user,
                     group,
                     notify,
+                    notify_socket,
+                    reload_signal,
+                    notify_reload,
+                    must_succeed,
+                    capabilities,
+                    log_buffer,
+                    max_line_length,
+                    oom_score_adj,
+                    stdin,
+                    stdout,
+                    stderr,
+                    syscall_filter,
+                    success_exit_codes,
+                    listen,
+                    bind_mounts,
+                    read_only_paths,
+                    private_tmp,
                 })
             },
             args,
@@ -137,15 +383,38 @@ impl SectionBuilder for ScriptBuilder {
             "timeout",
             "timeout_kill",
             "max_deaths",
+            "restart_limit",
+            "restart_window",
+            "restart_delay",
             "down_signal",
+            "kill_mode",
             "user",
             "group",
             "notify",
+            "notify_socket",
+            "reload_signal",
+            "notify_reload",
+            "must_succeed",
+            "log_buffer",
+            "max_line_length",
+            "oom_score_adj",
+            "stdin",
+            "stdout",
+            "stderr",
+            "listen",
+            "private_tmp",
         ]
     }
 
     fn get_array_fields(&self) -> &'static [&'static str] {
-        &[]
+        &[
+            "capabilities",
+            "syscall_allow",
+            "syscall_deny",
+            "success_exit_codes",
+            "bind_mounts",
+            "read_only_paths",
+        ]
     }
 
     fn get_code_fields(&self) -> &'static [&'static str] {
@@ -172,5 +441,407 @@ mod test {
         let script = builder.script.unwrap().unwrap();
         assert_eq!(script.prefix, ScriptPrefix::Bash);
         assert_eq!(script.execute, "    exit 0\n".to_string());
+        // Restarted without limits by default
+        assert_eq!(script.restart_limit, None);
+    }
+
+    #[test]
+    fn parse_script_capabilities() {
+        let mut builder = ScriptBuilder::new_for_section("run");
+        builder
+            .parse_until_next_section(&[
+                "prefix = bash",
+                "capabilities = [ CAP_NET_BIND_SERVICE ]",
+                "execute = (",
+                "    exit 0",
+                ")",
+            ])
+            .unwrap();
+
+        let script = builder.script.unwrap().unwrap();
+        assert_eq!(script.capabilities, vec!["CAP_NET_BIND_SERVICE".to_string()]);
+    }
+
+    #[test]
+    fn parse_script_oom_score_adj() {
+        let parse = |oom_score_adj: &str| {
+            let mut builder = ScriptBuilder::new_for_section("run");
+            builder
+                .parse_until_next_section(&[
+                    "prefix = bash",
+                    oom_score_adj,
+                    "execute = (",
+                    "    exit 0",
+                    ")",
+                ])
+                .unwrap();
+            builder.script.unwrap()
+        };
+
+        assert_eq!(
+            parse("oom_score_adj = -1000").unwrap().oom_score_adj,
+            Some(-1000)
+        );
+        assert!(matches!(
+            parse("oom_score_adj = 1001"),
+            Err(ScriptBuilderError::InvalidOomScoreAdj { value: 1001 })
+        ));
+        assert!(matches!(
+            parse("oom_score_adj = high"),
+            Err(ScriptBuilderError::InvalidInteger { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_script_kill_mode() {
+        let parse = |kill_mode: &str| {
+            let mut builder = ScriptBuilder::new_for_section("run");
+            builder
+                .parse_until_next_section(&[
+                    "prefix = bash",
+                    kill_mode,
+                    "execute = (",
+                    "    exit 0",
+                    ")",
+                ])
+                .unwrap();
+            builder.script.unwrap()
+        };
+
+        assert_eq!(parse("kill_mode = main").unwrap().kill_mode, KillMode::Main);
+        assert_eq!(parse("kill_mode = cgroup").unwrap().kill_mode, KillMode::Cgroup);
+        assert!(matches!(
+            parse("kill_mode = all"),
+            Err(ScriptBuilderError::InvalidKillMode { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_script_notify_reload() {
+        let parse = |lines: &[&str]| {
+            let mut builder = ScriptBuilder::new_for_section("run");
+            let mut script = vec!["prefix = bash"];
+            script.extend_from_slice(lines);
+            script.extend_from_slice(&["execute = (", "    exit 0", ")"]);
+            builder.parse_until_next_section(&script).unwrap();
+            builder.script.unwrap()
+        };
+
+        let script = parse(&[]).unwrap();
+        assert_eq!(script.reload_signal, Signal::SIGHUP as i32);
+        assert!(!script.notify_reload);
+        let script = parse(&[
+            "notify_socket = yes",
+            "notify_reload = yes",
+            "reload_signal = SIGUSR2",
+        ])
+        .unwrap();
+        assert_eq!(script.reload_signal, Signal::SIGUSR2 as i32);
+        assert!(script.notify_reload);
+        assert!(matches!(
+            parse(&["notify_reload = yes"]),
+            Err(ScriptBuilderError::NotifyReloadWithoutSocket)
+        ));
+        assert!(matches!(
+            parse(&["notify_socket = true"]),
+            Err(ScriptBuilderError::InvalidBoolean { key }) if key == "notify_socket"
+        ));
+    }
+
+    #[test]
+    fn parse_script_max_line_length() {
+        let parse = |max_line_length: &str| {
+            let mut builder = ScriptBuilder::new_for_section("run");
+            builder
+                .parse_until_next_section(&[
+                    "prefix = bash",
+                    max_line_length,
+                    "execute = (",
+                    "    exit 0",
+                    ")",
+                ])
+                .unwrap();
+            builder.script.unwrap()
+        };
+
+        assert_eq!(
+            parse("max_line_length = 1024").unwrap().max_line_length,
+            Some(1024)
+        );
+        assert!(matches!(
+            parse("max_line_length = 0"),
+            Err(ScriptBuilderError::ZeroMaxLineLength)
+        ));
+        assert!(matches!(
+            parse("max_line_length = long"),
+            Err(ScriptBuilderError::InvalidInteger { key, .. }) if key == "max_line_length"
+        ));
+    }
+
+    #[test]
+    fn parse_script_timeouts() {
+        let mut builder = ScriptBuilder::new_for_section("run");
+        builder
+            .parse_until_next_section(&[
+                "prefix = bash",
+                "timeout = 30s",
+                "timeout_kill = 1500",
+                "restart_limit = 5",
+                "restart_window = 2m",
+                "restart_delay = 1s",
+                "execute = (",
+                "    exit 0",
+                ")",
+            ])
+            .unwrap();
+
+        let script = builder.script.unwrap().unwrap();
+        assert_eq!(script.timeout, 30_000);
+        assert_eq!(script.timeout_kill, 1500);
+        assert_eq!(script.restart_limit, Some(5));
+        assert_eq!(script.restart_window, 120_000);
+        assert_eq!(script.restart_delay, 1000);
+
+        let mut builder = ScriptBuilder::new_for_section("run");
+        builder
+            .parse_until_next_section(&["prefix = bash", "timeout = soon", "execute = (", ")"])
+            .unwrap();
+        assert!(matches!(
+            builder.script.unwrap(),
+            Err(ScriptBuilderError::InvalidTimeout { key, .. }) if key == "timeout"
+        ));
+    }
+
+    #[test]
+    fn parse_script_success_exit_codes() {
+        let parse = |success_exit_codes: &str| {
+            let mut builder = ScriptBuilder::new_for_section("start");
+            builder
+                .parse_until_next_section(&[
+                    "prefix = bash",
+                    success_exit_codes,
+                    "execute = (",
+                    "    exit 0",
+                    ")",
+                ])
+                .unwrap();
+            builder.script.unwrap()
+        };
+
+        assert_eq!(
+            parse("success_exit_codes = [ 0 1 ]")
+                .unwrap()
+                .success_exit_codes,
+            vec![0, 1]
+        );
+        assert!(matches!(
+            parse("success_exit_codes = [ none ]"),
+            Err(ScriptBuilderError::InvalidInteger { key, .. }) if key == "success_exit_codes"
+        ));
+    }
+
+    #[test]
+    fn parse_script_output() {
+        let mut builder = ScriptBuilder::new_for_section("run");
+        builder
+            .parse_until_next_section(&[
+                "prefix = bash",
+                "stdout = service:foo-log",
+                "stderr = file:/var/log/foo.err",
+                "execute = (",
+                "    exit 0",
+                ")",
+            ])
+            .unwrap();
+
+        let script = builder.script.unwrap().unwrap();
+        assert_eq!(script.stdout, ScriptOutput::Service("foo-log".to_string()));
+        assert_eq!(script.stderr, ScriptOutput::File("/var/log/foo.err".into()));
+
+        let mut builder = ScriptBuilder::new_for_section("run");
+        builder
+            .parse_until_next_section(&[
+                "prefix = bash",
+                "stdout = file:",
+                "execute = (",
+                "    exit 0",
+                ")",
+            ])
+            .unwrap();
+        assert!(matches!(
+            builder.script.unwrap(),
+            Err(ScriptBuilderError::InvalidOutput { key, .. }) if key == "stdout"
+        ));
+    }
+
+    #[test]
+    fn parse_script_stdin() {
+        let parse = |stdin: &str| {
+            let mut builder = ScriptBuilder::new_for_section("run");
+            builder
+                .parse_until_next_section(&[
+                    "prefix = bash",
+                    stdin,
+                    "execute = (",
+                    "    exit 0",
+                    ")",
+                ])
+                .unwrap();
+            builder.script.unwrap()
+        };
+
+        assert_eq!(parse("stdin = null").unwrap().stdin, ScriptInput::Null);
+        assert_eq!(parse("stdin = fd:3").unwrap().stdin, ScriptInput::Fd(3));
+        let file = std::env::current_exe().unwrap();
+        assert_eq!(
+            parse(&format!("stdin = file:{}", file.display()))
+                .unwrap()
+                .stdin,
+            ScriptInput::File(file)
+        );
+        assert!(matches!(
+            parse("stdin = file:/nonexistent/rinit-stdin"),
+            Err(ScriptBuilderError::StdinFileNotFound { .. })
+        ));
+        assert!(matches!(
+            parse("stdin = fd:stdin"),
+            Err(ScriptBuilderError::InvalidInput { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_script_listen() {
+        let parse = |listen: &str| {
+            let mut builder = ScriptBuilder::new_for_section("run");
+            builder
+                .parse_until_next_section(&[
+                    "prefix = bash",
+                    listen,
+                    "execute = (",
+                    "    exit 0",
+                    ")",
+                ])
+                .unwrap();
+            builder.script.unwrap()
+        };
+
+        assert_eq!(
+            parse("listen = unix:/run/foo.sock").unwrap().listen,
+            Some(ListenSocket::Unix("/run/foo.sock".into()))
+        );
+        assert_eq!(
+            parse("listen = tcp:127.0.0.1:8080").unwrap().listen,
+            Some(ListenSocket::Tcp("127.0.0.1:8080".parse().unwrap()))
+        );
+        assert!(matches!(
+            parse("listen = tcp:localhost"),
+            Err(ScriptBuilderError::InvalidListen { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_script_mounts() {
+        let parse = |mounts: &[&str]| {
+            let mut builder = ScriptBuilder::new_for_section("run");
+            let mut lines = vec!["prefix = bash", "execute = (", "    exit 0", ")"];
+            lines.extend(mounts);
+            builder.parse_until_next_section(&lines).unwrap();
+            builder.script.unwrap()
+        };
+
+        let script = parse(&[
+            // Arrays are sorted, so a path is always mounted before the ones below it
+            "bind_mounts = [ /var/lib/foo /srv/foo:/data:ro /etc/foo:rw ]",
+            "read_only_paths = [ /usr /var ]",
+            "private_tmp = yes",
+        ])
+        .unwrap();
+        assert_eq!(
+            script.bind_mounts,
+            vec![
+                BindMount {
+                    source: "/etc/foo".into(),
+                    target: "/etc/foo".into(),
+                    read_only: false,
+                },
+                BindMount {
+                    source: "/srv/foo".into(),
+                    target: "/data".into(),
+                    read_only: true,
+                },
+                BindMount {
+                    source: "/var/lib/foo".into(),
+                    target: "/var/lib/foo".into(),
+                    read_only: false,
+                },
+            ]
+        );
+        assert_eq!(
+            script.read_only_paths,
+            vec![PathBuf::from("/usr"), PathBuf::from("/var")]
+        );
+        assert!(script.private_tmp);
+        assert!(matches!(
+            parse(&["private_tmp = true"]),
+            Err(ScriptBuilderError::InvalidBoolean { .. })
+        ));
+        for bind_mount in ["var/lib/foo", "/srv/foo:data", "/a:/b:/c", "/srv/foo:/data:rx"] {
+            assert!(matches!(
+                parse(&[&format!("bind_mounts = [ {bind_mount} ]")]),
+                Err(ScriptBuilderError::InvalidBindMount { .. })
+            ));
+        }
+        assert!(matches!(
+            parse(&["read_only_paths = [ usr ]"]),
+            Err(ScriptBuilderError::RelativeReadOnlyPath { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_script_invalid_capability() {
+        let mut builder = ScriptBuilder::new_for_section("run");
+        builder
+            .parse_until_next_section(&[
+                "prefix = bash",
+                "capabilities = [ CAP_FOO ]",
+                "execute = (",
+                "    exit 0",
+                ")",
+            ])
+            .unwrap();
+
+        assert!(matches!(
+            builder.script.unwrap(),
+            Err(ScriptBuilderError::InvalidCapability { capability }) if capability == "CAP_FOO"
+        ));
+    }
+
+    #[test]
+    fn parse_script_syscall_filter() {
+        let parse = |filters: &[&str]| {
+            let mut builder = ScriptBuilder::new_for_section("run");
+            let mut lines = vec!["prefix = bash", "execute = (", "    exit 0", ")"];
+            lines.extend(filters);
+            builder.parse_until_next_section(&lines).unwrap();
+            builder.script.unwrap()
+        };
+
+        assert_eq!(
+            parse(&["syscall_deny = [ @mount reboot ]"])
+                .unwrap()
+                .syscall_filter,
+            Some(Box::new(SyscallFilter::Deny(vec![
+                "@mount".to_string(),
+                "reboot".to_string()
+            ])))
+        );
+        assert!(matches!(
+            parse(&["syscall_allow = [ read rebot ]"]),
+            Err(ScriptBuilderError::InvalidSyscall { .. })
+        ));
+        assert!(matches!(
+            parse(&["syscall_allow = [ read ]", "syscall_deny = [ reboot ]"]),
+            Err(ScriptBuilderError::ConflictingSyscallFilters)
+        ));
     }
 }
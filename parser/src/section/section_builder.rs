@@ -35,6 +35,8 @@ pub enum SectionBuilderError {
     DuplicateField { field: String },
     #[snafu(display("{} is not a valid field", field))]
     InvalidField { field: String },
+    #[snafu(display("'{}' is not a valid key-value pair", line))]
+    InvalidLine { line: String },
 }
 
 type Result<T, E = SectionBuilderError> = std::result::Result<T, E>;
@@ -109,6 +111,8 @@ pub trait SectionBuilder: Any {
 
                 // Reset
                 code_parser = CodeParser::new();
+                // The closing token does not contain any data
+                continue;
             }
 
             let line = line.trim();
@@ -158,6 +162,11 @@ pub trait SectionBuilder: Any {
                     &mut values,
                     self.get_fields(),
                 )?;
+            } else {
+                return InvalidLineSnafu {
+                    line: line.to_owned(),
+                }
+                .fail();
             }
         }
 
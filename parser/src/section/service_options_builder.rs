@@ -1,14 +1,23 @@
 use std::{
     collections::HashMap,
+    num::ParseIntError,
+    path::PathBuf,
     str::FromStr,
 };
 
 use rinit_service::types::{
+    parse_milliseconds,
+    Credential,
+    CredentialParseError,
+    MillisecondsParseError,
     RunLevel,
     RunLevelParseError,
+    Schedule,
+    ScheduleParseError,
     ServiceOptions,
 };
 use snafu::{
+    ensure,
     ResultExt,
     Snafu,
 };
@@ -19,8 +28,21 @@ use super::SectionBuilder;
 pub enum ServiceOptionsBuilderError {
     #[snafu(display("{} must be either 'yes' or 'no'", key))]
     InvalidBoolean { key: String },
+    #[snafu(display("failed conversion to integer for key {}", key))]
+    InvalidInteger { key: String, source: ParseIntError },
+    #[snafu(display("invalid value for key {key}: {source}"))]
+    InvalidTimeout {
+        key: String,
+        source: MillisecondsParseError,
+    },
     #[snafu(display("{source}"))]
     RunLevelParseError { source: RunLevelParseError },
+    #[snafu(display("{source}"))]
+    InvalidCredential { source: CredentialParseError },
+    #[snafu(display("{source}"))]
+    InvalidSchedule { source: ScheduleParseError },
+    #[snafu(display("path must not be empty"))]
+    EmptyPath,
 }
 
 pub struct ServiceOptionsBuilder {
@@ -42,9 +64,19 @@ impl SectionBuilder for ServiceOptionsBuilder {
         array_values: &mut HashMap<&'static str, Vec<String>>,
         _code_values: &mut HashMap<&'static str, String>,
     ) {
-        let dependencies = array_values.remove("dependencies").unwrap_or_default();
+        let mut dependencies = array_values.remove("dependencies").unwrap_or_default();
+        // Being after a sync point is depending on it
+        for after in array_values.remove("after").unwrap_or_default() {
+            if !dependencies.contains(&after) {
+                dependencies.push(after);
+            }
+        }
         let requires = array_values.remove("requires").unwrap_or_default();
         let requires_one = array_values.remove("requires-one").unwrap_or_default();
+        let wants = array_values.remove("wants").unwrap_or_default();
+        let provides = array_values.remove("provides").unwrap_or_default();
+        let conflicts = array_values.remove("conflicts").unwrap_or_default();
+        let before = array_values.remove("before").unwrap_or_default();
         let autostart = values
             .remove("autostart")
             .map_or(Ok(true), |autostart| {
@@ -59,21 +91,97 @@ impl SectionBuilder for ServiceOptionsBuilder {
                     key: "autostart".to_string(),
                 }
             });
+        let clean_environment = values
+            .remove("clean_environment")
+            .map_or(Ok(false), |clean_environment| {
+                match clean_environment.as_str() {
+                    "yes" => Ok(true),
+                    "no" => Ok(false),
+                    _ => Err(snafu::NoneError),
+                }
+            })
+            .with_context(|_| {
+                InvalidBooleanSnafu {
+                    key: "clean_environment".to_string(),
+                }
+            });
         let runlevel = values
             .remove("runlevel")
             .map_or(Ok(RunLevel::default()), |s| RunLevel::from_str(&s))
             .with_context(|_| RunLevelParseSnafu);
-        self.options = Some(autostart.and_then(|autostart| {
-            runlevel.map(|runlevel| {
-                ServiceOptions {
-                    dependencies,
-                    requires,
-                    requires_one,
-                    autostart,
-                    runlevel,
+        let needs_path = array_values
+            .remove("needs_path")
+            .unwrap_or_default()
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        let needs_path_timeout = values
+            .remove("needs_path_timeout")
+            .map_or(Ok(ServiceOptions::DEFAULT_NEEDS_PATH_TIMEOUT), |timeout| {
+                parse_milliseconds(&timeout)
+            })
+            .with_context(|_| {
+                InvalidTimeoutSnafu {
+                    key: "needs_path_timeout".to_string(),
                 }
+            });
+        let down_timeout = values
+            .remove("down_timeout")
+            .map(|timeout| parse_milliseconds(&timeout))
+            .transpose()
+            .with_context(|_| {
+                InvalidTimeoutSnafu {
+                    key: "down_timeout".to_string(),
+                }
+            });
+        let priority = values
+            .remove("priority")
+            .map_or(Ok(0), |priority| priority.parse())
+            .with_context(|_| {
+                InvalidIntegerSnafu {
+                    key: "priority".to_string(),
+                }
+            });
+        let credentials = array_values
+            .remove("credentials")
+            .unwrap_or_default()
+            .iter()
+            .map(|credential| Credential::from_str(credential))
+            .collect::<Result<_, _>>()
+            .with_context(|_| InvalidCredentialSnafu);
+        let schedule = values
+            .remove("schedule")
+            .map(|schedule| Schedule::from_str(&schedule))
+            .transpose()
+            .with_context(|_| InvalidScheduleSnafu);
+        let path = values
+            .remove("path")
+            .map(|path| {
+                ensure!(!path.is_empty(), EmptyPathSnafu);
+                Ok(path)
+            })
+            .transpose();
+        self.options = Some((|| {
+            Ok(ServiceOptions {
+                dependencies,
+                requires,
+                requires_one,
+                wants,
+                provides,
+                conflicts,
+                before,
+                autostart: autostart?,
+                runlevel: runlevel?,
+                needs_path,
+                needs_path_timeout: needs_path_timeout?,
+                down_timeout: down_timeout?,
+                credentials: credentials?,
+                priority: priority?,
+                schedule: schedule?,
+                clean_environment: clean_environment?,
+                path: path?,
             })
-        }));
+        })());
     }
 
     fn section_name(&self) -> &'static str {
@@ -81,11 +189,31 @@ impl SectionBuilder for ServiceOptionsBuilder {
     }
 
     fn get_fields(&self) -> &'static [&'static str] {
-        &[]
+        &[
+            "autostart",
+            "runlevel",
+            "needs_path_timeout",
+            "down_timeout",
+            "priority",
+            "schedule",
+            "clean_environment",
+            "path",
+        ]
     }
 
     fn get_array_fields(&self) -> &'static [&'static str] {
-        &["dependencies", "requires", "requires-one"]
+        &[
+            "dependencies",
+            "requires",
+            "requires-one",
+            "wants",
+            "provides",
+            "conflicts",
+            "before",
+            "after",
+            "needs_path",
+            "credentials",
+        ]
     }
 
     fn get_code_fields(&self) -> &'static [&'static str] {
@@ -105,15 +233,76 @@ mod test {
                 .parse_until_next_section(&[
                     "dependencies = [ foo ]",
                     "requires = [ bar ]",
-                    "requires-one = [ foobar ]"
+                    "requires-one = [ foobar ]",
+                    "wants = [ avahi ]",
+                    "provides = [ cron ]",
+                    "conflicts = [ dhcpcd ]",
+                    "before = [ basic ]",
+                    "after = [ sysinit foo ]",
+                    "needs_path = [ /dev/sda ]",
+                    "needs_path_timeout = 5000",
+                    "down_timeout = 3s",
+                    "priority = -5",
+                    "schedule = */15 * * * 1-5",
+                    "credentials = [ DB_PASSWORD:/etc/rinit/db_password ]",
+                    "clean_environment = yes",
+                    "path = /opt/app/bin:/usr/bin",
                 ])
                 .unwrap()
                 .is_empty()
         );
 
         let options = builder.options.unwrap().unwrap();
-        assert_eq!(options.dependencies, vec!["foo".to_string()]);
+        assert_eq!(
+            options.dependencies,
+            vec!["foo".to_string(), "sysinit".to_string()]
+        );
         assert_eq!(options.requires, vec!["bar".to_string()]);
         assert_eq!(options.requires_one, vec!["foobar".to_string()]);
+        assert_eq!(options.wants, vec!["avahi".to_string()]);
+        assert_eq!(options.provides, vec!["cron".to_string()]);
+        assert_eq!(options.conflicts, vec!["dhcpcd".to_string()]);
+        assert_eq!(options.before, vec!["basic".to_string()]);
+        assert_eq!(options.needs_path, vec![PathBuf::from("/dev/sda")]);
+        assert_eq!(options.needs_path_timeout, 5000);
+        assert_eq!(options.down_timeout, Some(3000));
+        assert_eq!(options.priority, -5);
+        assert!(options.clean_environment);
+        assert_eq!(options.path.as_deref(), Some("/opt/app/bin:/usr/bin"));
+        assert_eq!(
+            options.schedule,
+            Some(Schedule::from_str("*/15 * * * 1-5").unwrap())
+        );
+        assert_eq!(
+            options.credentials,
+            vec![Credential {
+                name: "DB_PASSWORD".to_string(),
+                path: PathBuf::from("/etc/rinit/db_password"),
+            }]
+        );
+    }
+
+    #[test]
+    fn invalid_credential() {
+        for credential in ["DB_PASSWORD", "DB-PASSWORD:/etc/secret", "DB_PASSWORD:secret"] {
+            let mut builder = ServiceOptionsBuilder::new();
+            builder
+                .parse_until_next_section(&[&format!("credentials = [ {credential} ]")])
+                .unwrap();
+            assert!(matches!(
+                builder.options.unwrap(),
+                Err(ServiceOptionsBuilderError::InvalidCredential { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn empty_path() {
+        let mut builder = ServiceOptionsBuilder::new();
+        builder.parse_until_next_section(&["path = "]).unwrap();
+        assert!(matches!(
+            builder.options.unwrap(),
+            Err(ServiceOptionsBuilderError::EmptyPath)
+        ));
     }
 }
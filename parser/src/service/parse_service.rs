@@ -31,7 +31,7 @@ pub enum ParseServiceError {
         path: PathBuf,
         source: ServiceBuilderError,
     },
-    #[snafu(display("whiile reading file {:?}", path))]
+    #[snafu(display("while reading file {:?}", path))]
     ServiceBuild {
         path: PathBuf,
         source: Box<dyn Error>,
@@ -114,7 +114,20 @@ pub fn parse_service(path: &Path) -> Result<Service> {
                 }
             })
         }
-        // "virtual" => VirtualParser::parse(name, reader),
+        "virtual" => {
+            let mut builder = VirtualBuilder::new(name);
+            builder.parse(&lines[2..]).with_context(|_| {
+                ServiceParseSnafu {
+                    path: path.to_owned(),
+                }
+            })?;
+
+            builder.build().with_context(|_| {
+                ServiceBuildSnafu {
+                    path: path.to_owned(),
+                }
+            })
+        }
         _ => {
             TypeNotFoundSnafu {
                 path: path.to_owned(),
@@ -129,6 +142,10 @@ mod test {
     use std::path::PathBuf;
 
     use super::*;
+    use crate::section::{
+        ScriptBuilderError,
+        SectionBuilderError,
+    };
 
     #[test]
     fn parse_bundle() -> Result<(), ParseServiceError> {
@@ -168,6 +185,10 @@ mod test {
                 name: "foo".to_string(),
                 start: Script::new(ScriptPrefix::Bash, "    exit 0\n".to_string()),
                 stop: None,
+                up_check: None,
+                condition: None,
+                pre_start: None,
+                post_stop: None,
                 options: ServiceOptions::new(),
                 environment: ScriptEnvironment::new(),
             }),
@@ -188,6 +209,10 @@ mod test {
                 name: "foo".to_string(),
                 start: Script::new(ScriptPrefix::Bash, "    exit 0\n".to_string()),
                 stop: Some(Script::new(ScriptPrefix::Sh, "    exit 1\n".to_string())),
+                up_check: None,
+                condition: None,
+                pre_start: None,
+                post_stop: None,
                 options: ServiceOptions::new(),
                 environment: ScriptEnvironment::new(),
             }),
@@ -208,6 +233,9 @@ mod test {
                 name: "foo".to_string(),
                 run: Script::new(ScriptPrefix::Bash, "    loop\n".to_string()),
                 finish: None,
+                condition: None,
+                pre_start: None,
+                post_stop: None,
                 options: ServiceOptions::new(),
                 environment: ScriptEnvironment::new(),
             }),
@@ -232,4 +260,135 @@ mod test {
             .is_err()
         );
     }
+
+    fn parse_sample(sample: &str) -> Result<Service, ParseServiceError> {
+        parse_service(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test/samples")
+                .join(sample)
+                .as_path(),
+        )
+    }
+
+    #[test]
+    fn parse_longrun_with_options() -> Result<(), ParseServiceError> {
+        let Service::Longrun(longrun) = parse_sample("longrun_with_options")? else {
+            panic!("expected a longrun");
+        };
+        assert!(!longrun.options.autostart);
+        assert_eq!(RunLevel::Boot, longrun.options.runlevel);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_longrun_with_hooks() -> Result<(), ParseServiceError> {
+        let Service::Longrun(longrun) = parse_sample("longrun_with_hooks")? else {
+            panic!("expected a longrun");
+        };
+        assert_eq!(
+            Some(Script::new(ScriptPrefix::Sh, "    mkdir -p /run/foo\n".to_string())),
+            longrun.pre_start
+        );
+        assert_eq!(
+            Some(Script::new(ScriptPrefix::Sh, "    rm -rf /run/foo\n".to_string())),
+            longrun.post_stop
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_oneshot_with_condition() -> Result<(), ParseServiceError> {
+        let Service::Oneshot(oneshot) = parse_sample("oneshot_with_condition")? else {
+            panic!("expected a oneshot");
+        };
+        assert_eq!(
+            Some(Script::new(ScriptPrefix::Sh, "    test -e /dev/kvm\n".to_string())),
+            oneshot.condition
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_oneshot_no_start() {
+        let res = parse_sample("oneshot_no_start");
+        assert!(matches!(
+            res,
+            Err(ParseServiceError::ServiceBuild { ref source, .. })
+                if matches!(
+                    source.downcast_ref::<OneshotBuilderError>(),
+                    Some(OneshotBuilderError::NoStartSection)
+                )
+        ));
+    }
+
+    #[test]
+    fn parse_unknown_field() {
+        let res = parse_sample("oneshot_unknown_field");
+        assert!(matches!(
+            res,
+            Err(ParseServiceError::ServiceParse {
+                source: ServiceBuilderError::ErrorInSection {
+                    ref section,
+                    source: SectionBuilderError::InvalidField { ref field },
+                },
+                ..
+            }) if section == "start" && field == "restart"
+        ));
+    }
+
+    #[test]
+    fn parse_invalid_line() {
+        let res = parse_sample("oneshot_invalid_line");
+        assert!(matches!(
+            res,
+            Err(ParseServiceError::ServiceParse {
+                source: ServiceBuilderError::ErrorInSection {
+                    source: SectionBuilderError::InvalidLine { ref line },
+                    ..
+                },
+                ..
+            }) if line == "timeout"
+        ));
+    }
+
+    #[test]
+    fn parse_unknown_section() {
+        let res = parse_sample("longrun_unknown_section");
+        assert!(matches!(
+            res,
+            Err(ParseServiceError::ServiceParse {
+                source: ServiceBuilderError::InvalidSection { ref section },
+                ..
+            }) if section == "install"
+        ));
+    }
+
+    #[test]
+    fn parse_invalid_integer() {
+        let res = parse_sample("longrun_invalid_integer");
+        assert!(matches!(
+            res,
+            Err(ParseServiceError::ServiceBuild { ref path, ref source })
+                if path.ends_with("longrun_invalid_integer") && matches!(
+                    source.downcast_ref::<ScriptBuilderError>(),
+                    Some(ScriptBuilderError::InvalidInteger { key, .. }) if key == "max_deaths"
+                )
+        ));
+    }
+
+    #[test]
+    fn parse_invalid_timeout() {
+        let res = parse_sample("longrun_invalid_timeout");
+        assert!(matches!(
+            res,
+            Err(ParseServiceError::ServiceBuild { ref source, .. })
+                if matches!(
+                    source.downcast_ref::<ScriptBuilderError>(),
+                    Some(ScriptBuilderError::InvalidTimeout { key, .. }) if key == "timeout"
+                )
+        ));
+    }
 }
@@ -8,7 +8,10 @@ use std::{
 
 use rinit_service::{
     dirs::Dirs,
-    types::Service,
+    types::{
+        Service,
+        Virtual,
+    },
 };
 use snafu::{
     ensure,
@@ -42,36 +45,70 @@ pub fn parse_services(
     dirs: &Dirs,
     system: bool,
 ) -> Result<Vec<Service>, ServicesParserError> {
-    let service_dirs = dirs.service_directories();
+    parse_services_from_dirs(services, &dirs.service_directories(), system)
+}
+
+/// Parse services and their dependencies looking into dir first, then into
+/// the service directories, e.g. to try services before installing them
+pub fn parse_services_in(
+    services: Vec<String>,
+    dir: &Path,
+    dirs: &Dirs,
+    system: bool,
+) -> Result<Vec<Service>, ServicesParserError> {
+    let mut service_dirs = vec![dir.to_path_buf()];
+    service_dirs.extend(dirs.service_directories());
+    parse_services_from_dirs(services, &service_dirs, system)
+}
+
+fn parse_services_from_dirs(
+    services: Vec<String>,
+    service_dirs: &[PathBuf],
+    system: bool,
+) -> Result<Vec<Service>, ServicesParserError> {
     let mut services_already_parsed = services.clone().into_iter().collect::<HashSet<String>>();
     let mut results = Vec::new();
-    let mut to_parse = services
-        .into_iter()
-        .map(|service| {
+    let mut to_parse = Vec::new();
+    for service in services {
+        if let Some(file) = get_service_file(&service, service_dirs, system) {
+            to_parse.push((service, file));
+        } else if let Some(target) = Virtual::well_known(&service) {
+            results.push(Service::Virtual(target));
+        } else {
             // If we don't find the services passed as args on the system, return an error
-            if let Some(file) = get_service_file(&service, &service_dirs, system) {
-                Ok((service, file))
-            } else {
-                Err(ServicesParserError::CouldNotFindService { service })
-            }
-        })
-        .collect::<Result<Vec<(String, PathBuf)>, ServicesParserError>>()?;
+            return CouldNotFindServiceSnafu { service }.fail();
+        }
+    }
 
     while let Some((name, file)) = to_parse.pop() {
-        let service = parse_service(&file).context(ParsingServiceSnafu {})?;
+        let mut service = parse_service(&file).context(ParsingServiceSnafu {})?;
+        if let Some((template, instance)) =
+            Service::split_instance(&name).filter(|_| service.is_template())
+        {
+            ensure!(
+                service.name() == template,
+                NameNotMatchingFileSnafu { service_file: file }
+            );
+            service.instantiate(instance);
+        }
         ensure!(
             service.name() == name,
             NameNotMatchingFileSnafu { service_file: file }
         );
         // Skip services that we can't found, the dependency graph will
-        // handle the error
-        to_parse.extend(service.dependencies().iter().filter_map(|service| {
-            if services_already_parsed.insert(service.clone()) {
-                get_service_file(service, &service_dirs, system).map(|file| (service.clone(), file))
-            } else {
-                None
+        // handle the error. The wanted services are parsed as well, but they
+        // can be missing
+        for dependency in service.dependencies().iter().chain(service.wants()) {
+            if !services_already_parsed.insert(dependency.clone()) {
+                continue;
             }
-        }));
+            // A service file takes precedence over the well-known targets
+            if let Some(file) = get_service_file(dependency, service_dirs, system) {
+                to_parse.push((dependency.clone(), file));
+            } else if let Some(target) = Virtual::well_known(dependency) {
+                results.push(Service::Virtual(target));
+            }
+        }
 
         results.push(service);
     }
@@ -79,10 +116,23 @@ pub fn parse_services(
     Ok(results)
 }
 
+/// Find the file for service. Instances (e.g. getty@tty1) fall back to the file
+/// of their template (getty@) when they don't have their own
 fn get_service_file(
     service: &str,
     paths: &[PathBuf],
     system: bool,
+) -> Option<PathBuf> {
+    find_service_file(service, paths, system).or_else(|| {
+        Service::split_instance(service)
+            .and_then(|(template, _)| find_service_file(template, paths, system))
+    })
+}
+
+fn find_service_file(
+    service: &str,
+    paths: &[PathBuf],
+    system: bool,
 ) -> Option<PathBuf> {
     paths.iter().find_map(|path| {
         let service_file =
@@ -94,3 +144,118 @@ fn get_service_file(
         }
     })
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs,
+    };
+
+    use rinit_service::graph::DependencyGraph;
+
+    use super::*;
+
+    #[test]
+    fn parse_template_instances() {
+        let configdir = env::temp_dir().join(format!("rinit-template-{}", std::process::id()));
+        let system = nix::unistd::getuid().is_root();
+        let mode = if system { "system" } else { "user" };
+        let service_dir = configdir.join(mode);
+        fs::create_dir_all(&service_dir).unwrap();
+        fs::write(
+            service_dir.join(format!("getty@.{mode}")),
+            "name = getty@\ntype = oneshot\n[start]\nexecute = (\n    agetty $INSTANCE\n)\nprefix \
+             = bash\n",
+        )
+        .unwrap();
+        let dirs = Dirs {
+            configdir: configdir.clone(),
+            ..Default::default()
+        };
+
+        let instances = vec!["getty@tty1".to_string(), "getty@tty2".to_string()];
+        let services = parse_services(instances.clone(), &dirs, system).unwrap();
+        fs::remove_dir_all(configdir).unwrap();
+
+        for instance in ["tty1", "tty2"] {
+            let Some(Service::Oneshot(oneshot)) = services
+                .iter()
+                .find(|service| service.name() == format!("getty@{instance}"))
+            else {
+                panic!("instance {instance} not found");
+            };
+            assert_eq!(
+                oneshot.environment.contents,
+                vec![("INSTANCE".to_string(), instance.to_string())]
+            );
+        }
+        let mut graph = DependencyGraph::new();
+        graph.add_services(instances, services).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+    }
+
+    #[test]
+    fn depend_on_well_known_target() {
+        let configdir = env::temp_dir().join(format!("rinit-well-known-{}", std::process::id()));
+        let system = nix::unistd::getuid().is_root();
+        let mode = if system { "system" } else { "user" };
+        let service_dir = configdir.join(mode);
+        fs::create_dir_all(&service_dir).unwrap();
+        fs::write(
+            service_dir.join(format!("ntpd.{mode}")),
+            "name = ntpd\ntype = oneshot\n[start]\nexecute = (\n    ntpd -q\n)\nprefix = \
+             bash\n[options]\ndependencies = [ network-online ]\n",
+        )
+        .unwrap();
+        let dirs = Dirs {
+            configdir: configdir.clone(),
+            ..Default::default()
+        };
+
+        let services = parse_services(vec!["ntpd".to_string()], &dirs, system).unwrap();
+        fs::remove_dir_all(configdir).unwrap();
+
+        assert!(services.iter().any(|service| {
+            matches!(service, Service::Virtual(target) if target.name == "network-online")
+        }));
+        let mut graph = DependencyGraph::new();
+        graph.add_services(vec!["ntpd".to_string()], services).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+    }
+
+    #[test]
+    fn parse_wanted_services() {
+        let configdir = env::temp_dir().join(format!("rinit-wanted-{}", std::process::id()));
+        let system = nix::unistd::getuid().is_root();
+        let mode = if system { "system" } else { "user" };
+        let service_dir = configdir.join(mode);
+        fs::create_dir_all(&service_dir).unwrap();
+        fs::write(
+            service_dir.join(format!("sshd.{mode}")),
+            "name = sshd\ntype = oneshot\n[start]\nexecute = (\n    sshd\n)\nprefix = \
+             bash\n[options]\nwants = [ avahi missing ]\n",
+        )
+        .unwrap();
+        fs::write(
+            service_dir.join(format!("avahi.{mode}")),
+            "name = avahi\ntype = oneshot\n[start]\nexecute = (\n    avahi-daemon\n)\nprefix = \
+             bash\n",
+        )
+        .unwrap();
+        let dirs = Dirs {
+            configdir: configdir.clone(),
+            ..Default::default()
+        };
+
+        let services = parse_services(vec!["sshd".to_string()], &dirs, system).unwrap();
+        fs::remove_dir_all(configdir).unwrap();
+
+        let mut names: Vec<&str> = services.iter().map(Service::name).collect();
+        names.sort();
+        assert_eq!(names, vec!["avahi", "sshd"]);
+        let mut graph = DependencyGraph::new();
+        graph.add_services(vec!["sshd".to_string()], services).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+    }
+}
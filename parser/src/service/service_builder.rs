@@ -5,6 +5,7 @@ use rinit_service::types::{
     ScriptEnvironment,
     Service,
     ServiceOptions,
+    Virtual,
 };
 use snafu::{
     ensure,
@@ -109,6 +110,10 @@ pub struct OneshotBuilder {
     name: String,
     start_builder: ScriptBuilder,
     stop_builder: ScriptBuilder,
+    up_check_builder: ScriptBuilder,
+    condition_builder: ScriptBuilder,
+    pre_start_builder: ScriptBuilder,
+    post_stop_builder: ScriptBuilder,
     options_builder: ServiceOptionsBuilder,
     env_builder: ScriptEnvironmentBuilder,
 }
@@ -125,6 +130,10 @@ impl OneshotBuilder {
             name,
             start_builder: ScriptBuilder::new_for_section("start"),
             stop_builder: ScriptBuilder::new_for_section("stop"),
+            up_check_builder: ScriptBuilder::new_for_section("up_check"),
+            condition_builder: ScriptBuilder::new_for_section("condition"),
+            pre_start_builder: ScriptBuilder::new_for_section("pre_start"),
+            post_stop_builder: ScriptBuilder::new_for_section("post_stop"),
             options_builder: ServiceOptionsBuilder::new(),
             env_builder: ScriptEnvironmentBuilder::new(),
         }
@@ -135,13 +144,16 @@ pub struct LongrunBuilder {
     name: String,
     run_builder: ScriptBuilder,
     finish_builder: ScriptBuilder,
+    condition_builder: ScriptBuilder,
+    pre_start_builder: ScriptBuilder,
+    post_stop_builder: ScriptBuilder,
     options_builder: ServiceOptionsBuilder,
     env_builder: ScriptEnvironmentBuilder,
 }
 
 #[derive(Snafu, Debug)]
 pub enum LongrunBuilderError {
-    #[snafu(display("no start section found"))]
+    #[snafu(display("no run section found"))]
     NoRunSection,
 }
 
@@ -151,12 +163,31 @@ impl LongrunBuilder {
             name,
             run_builder: ScriptBuilder::new_for_section("run"),
             finish_builder: ScriptBuilder::new_for_section("finish"),
+            condition_builder: ScriptBuilder::new_for_section("condition"),
+            pre_start_builder: ScriptBuilder::new_for_section("pre_start"),
+            post_stop_builder: ScriptBuilder::new_for_section("post_stop"),
             options_builder: ServiceOptionsBuilder::new(),
             env_builder: ScriptEnvironmentBuilder::new(),
         }
     }
 }
 
+pub struct VirtualBuilder {
+    name: String,
+    condition_builder: ScriptBuilder,
+    options_builder: ServiceOptionsBuilder,
+}
+
+impl VirtualBuilder {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            condition_builder: ScriptBuilder::new_for_section("condition"),
+            options_builder: ServiceOptionsBuilder::new(),
+        }
+    }
+}
+
 impl ServiceBuilder for BundleBuilder {
     fn build(self) -> Result<Service, Box<dyn Error>> {
         Ok(Service::Bundle(Bundle {
@@ -184,6 +215,26 @@ impl ServiceBuilder for OneshotBuilder {
             } else {
                 None
             },
+            up_check: if let Some(up_check) = self.up_check_builder.script {
+                Some(Box::new(up_check?))
+            } else {
+                None
+            },
+            condition: if let Some(condition) = self.condition_builder.script {
+                Some(condition?)
+            } else {
+                None
+            },
+            pre_start: if let Some(pre_start) = self.pre_start_builder.script {
+                Some(pre_start?)
+            } else {
+                None
+            },
+            post_stop: if let Some(post_stop) = self.post_stop_builder.script {
+                Some(post_stop?)
+            } else {
+                None
+            },
             options: self
                 .options_builder
                 .options
@@ -201,6 +252,14 @@ impl ServiceBuilder for OneshotBuilder {
         self.start_builder,
         "stop",
         self.stop_builder,
+        "up_check",
+        self.up_check_builder,
+        "condition",
+        self.condition_builder,
+        "pre_start",
+        self.pre_start_builder,
+        "post_stop",
+        self.post_stop_builder,
         "options",
         self.options_builder,
         "env",
@@ -221,6 +280,21 @@ impl ServiceBuilder for LongrunBuilder {
             } else {
                 None
             },
+            condition: if let Some(condition) = self.condition_builder.script {
+                Some(condition?)
+            } else {
+                None
+            },
+            pre_start: if let Some(pre_start) = self.pre_start_builder.script {
+                Some(pre_start?)
+            } else {
+                None
+            },
+            post_stop: if let Some(post_stop) = self.post_stop_builder.script {
+                Some(post_stop?)
+            } else {
+                None
+            },
             options: self
                 .options_builder
                 .options
@@ -238,9 +312,40 @@ impl ServiceBuilder for LongrunBuilder {
         self.run_builder,
         "finish",
         self.finish_builder,
+        "condition",
+        self.condition_builder,
+        "pre_start",
+        self.pre_start_builder,
+        "post_stop",
+        self.post_stop_builder,
         "options",
         self.options_builder,
         "env",
         self.env_builder
     );
 }
+
+impl ServiceBuilder for VirtualBuilder {
+    fn build(self) -> Result<Service, Box<dyn Error>> {
+        Ok(Service::Virtual(Virtual {
+            condition: if let Some(condition) = self.condition_builder.script {
+                Some(condition?)
+            } else {
+                None
+            },
+            options: self
+                .options_builder
+                .options
+                .unwrap_or_else(|| Ok(ServiceOptions::new()))?,
+            ..Virtual::new(self.name)
+        }))
+    }
+
+    parse_sections!(
+        self,
+        "condition",
+        self.condition_builder,
+        "options",
+        self.options_builder
+    );
+}
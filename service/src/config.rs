@@ -23,17 +23,74 @@ use snafu::{
     Snafu,
 };
 
-use crate::dirs::{
-    Dirs,
-    DirsError,
+use crate::{
+    dirs::{
+        Dirs,
+        DirsError,
+    },
+    types::RunLevel,
 };
 
 const CONF_FILENAME: &str = "rinit.conf";
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct Config {
     #[serde(flatten)]
     pub dirs: Dirs,
+    /// Maximum time (in milliseconds) that a service and its whole dependency
+    /// subtree can take to start. There is no limit when unset
+    #[serde(default)]
+    pub subtree_timeout: Option<u64>,
+    /// Runlevel started at boot. The services in the boot runlevel are always
+    /// started first
+    #[serde(default)]
+    pub default_runlevel: RunLevel,
+    /// Start the services one at a time, dependencies first, so that the order
+    /// is the same at every boot. Slower, meant for debugging
+    #[serde(default)]
+    pub serial_start: bool,
+    /// How many services can run their start script at the same time, the
+    /// ones with the highest priority go first. There is no limit when unset
+    #[serde(default)]
+    pub max_parallel_starts: Option<usize>,
+    /// Longest chain of dependencies that can be started at once, counting
+    /// the service itself. Guards against huge graphs, 64 when unset
+    #[serde(default)]
+    pub max_dependency_depth: Option<usize>,
+    /// How many more times a dependency whose scripts failed is started
+    /// before giving up on its dependents, waiting twice as long before each
+    /// retry. Dependencies are not retried when unset
+    #[serde(default)]
+    pub dependency_start_retries: Option<u32>,
+    /// When the dependency graph references services that are not in it,
+    /// e.g. after editing it by hand, remove them and their dependents at
+    /// startup instead of refusing to start
+    #[serde(default)]
+    pub prune_dangling_services: bool,
+    /// cgroup (v2) in which every longrun gets its own cgroup, e.g.
+    /// /sys/fs/cgroup/rinit. Services are not placed in cgroups when unset
+    #[serde(default)]
+    pub cgroup: Option<PathBuf>,
+    /// How often (in milliseconds) the processes orphaned by the services are
+    /// reaped. Every second when unset
+    #[serde(default)]
+    pub reap_interval: Option<u64>,
+    /// Filter of the rsvc log, e.g. debug or warn,rsvc::live_service=debug.
+    /// Info when unset, -q and -v take precedence
+    #[serde(default)]
+    pub log_level: Option<String>,
+    #[serde(default)]
+    pub log_format: LogFormat,
+}
+
+/// Format of the lines written in the log of rsvc
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Plain,
+    /// One JSON object per line, for log collectors
+    Json,
 }
 
 #[derive(Debug, Snafu)]
@@ -42,6 +99,8 @@ pub enum ConfigError {
     DirectoriesError { source: DirsError },
     #[snafu(display("unable to find configuration file {:?}", config_file))]
     DirsFileNotFound { config_file: PathBuf },
+    #[snafu(display("unable to read the configuration: {source}"))]
+    ExtractError { source: Box<figment::Error> },
 }
 
 type Result<T, E = ConfigError> = std::result::Result<T, E>;
@@ -83,6 +142,6 @@ impl Config {
         // Read the configuration variables from the env
         conf = conf.merge(providers::Env::prefixed("RINIT_"));
 
-        Ok(conf.extract().unwrap())
+        conf.extract().map_err(Box::new).context(ExtractSnafu {})
     }
 }
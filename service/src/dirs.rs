@@ -13,7 +13,7 @@ use snafu::{
 };
 use xdg::BaseDirectories;
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct Dirs {
     #[serde(default)]
     pub path: PathBuf,
@@ -0,0 +1,137 @@
+use std::{
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use snafu::{
+    ensure,
+    ResultExt,
+    Snafu,
+};
+
+use crate::types::ScriptEnvironment;
+
+#[derive(Debug, Snafu)]
+pub enum EnvDropinError {
+    #[snafu(display("unable to read directory {:?}: {source}", dir))]
+    ReadDirectory { dir: PathBuf, source: io::Error },
+    #[snafu(display("unable to read environment drop-in {:?}: {source}", file))]
+    ReadDropin { file: PathBuf, source: io::Error },
+    #[snafu(display("{:?}:{}: expected KEY = value", file, line))]
+    MissingEquals { file: PathBuf, line: usize },
+    #[snafu(display("{:?}:{}: missing variable name", file, line))]
+    MissingName { file: PathBuf, line: usize },
+}
+
+type Result<T, E = EnvDropinError> = std::result::Result<T, E>;
+
+/// Add the variables of the drop-ins of service, i.e. the files
+/// <dir>/<service>.d/*.conf, after the ones already in environment so that
+/// they take precedence. The directories are read in order and the files of
+/// each directory in lexical order, the last value of a variable wins
+pub fn add_env_dropins(
+    environment: &mut ScriptEnvironment,
+    service: &str,
+    dirs: &[&Path],
+) -> Result<()> {
+    for dir in dirs {
+        for file in dropin_files(&dir.join(format!("{service}.d")))? {
+            let contents = fs::read_to_string(&file).context(ReadDropinSnafu { file: &file })?;
+            for (index, line) in contents.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let Some((key, value)) = line.split_once('=') else {
+                    return MissingEqualsSnafu {
+                        file,
+                        line: index + 1,
+                    }
+                    .fail();
+                };
+                let key = key.trim();
+                ensure!(
+                    !key.is_empty(),
+                    MissingNameSnafu {
+                        file: &file,
+                        line: index + 1,
+                    }
+                );
+                environment.add(key, value.trim().to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The *.conf files in dir sorted by name, none if dir does not exist
+fn dropin_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(source) => return Err(source).context(ReadDirectorySnafu { dir }),
+    };
+    let mut files = entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()
+        .context(ReadDirectorySnafu { dir })?;
+    files.retain(|path| path.extension().is_some_and(|ext| ext == "conf"));
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn merge_dropins_in_order() {
+        let dir = env::temp_dir().join(format!("rinit-test-env-dropins-{}", std::process::id()));
+        let configdir = dir.join("config");
+        let rundir = dir.join("run");
+        fs::create_dir_all(configdir.join("sshd.d")).unwrap();
+        fs::create_dir_all(rundir.join("sshd.d")).unwrap();
+        fs::write(
+            configdir.join("sshd.d").join("20-port.conf"),
+            "# Listen elsewhere\nPORT = 2222\n",
+        )
+        .unwrap();
+        fs::write(
+            configdir.join("sshd.d").join("10-base.conf"),
+            "PORT=22\nOPTS = -D\n",
+        )
+        .unwrap();
+        // Not a drop-in
+        fs::write(configdir.join("sshd.d").join("99-backup.conf~"), "PORT=1\n").unwrap();
+        fs::write(rundir.join("sshd.d").join("00-boot.conf"), "ADDRESS = ::1\n").unwrap();
+
+        let mut environment = ScriptEnvironment::new();
+        environment.add("PORT", "21".to_string());
+        add_env_dropins(&mut environment, "sshd", &[&configdir, &rundir]).unwrap();
+        assert_eq!(
+            environment.contents,
+            vec![
+                ("PORT".to_string(), "21".to_string()),
+                ("PORT".to_string(), "22".to_string()),
+                ("OPTS".to_string(), "-D".to_string()),
+                ("PORT".to_string(), "2222".to_string()),
+                ("ADDRESS".to_string(), "::1".to_string()),
+            ]
+        );
+
+        // Services without drop-ins are left untouched
+        let mut environment = ScriptEnvironment::new();
+        add_env_dropins(&mut environment, "cronie", &[&configdir, &rundir]).unwrap();
+        assert!(environment.is_empty());
+
+        fs::write(rundir.join("sshd.d").join("50-broken.conf"), "PORT\n").unwrap();
+        assert!(add_env_dropins(&mut ScriptEnvironment::new(), "sshd", &[&rundir]).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
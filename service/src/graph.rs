@@ -1,8 +1,14 @@
 mod dependency_graph;
 mod node;
+mod write_graph;
 
 pub use dependency_graph::{
     DependencyGraph,
     DependencyGraphError,
 };
 pub use node::Node;
+pub use write_graph::{
+    write_atomically,
+    write_graph,
+    WriteGraphError,
+};
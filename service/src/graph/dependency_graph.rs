@@ -33,12 +33,81 @@ pub enum DependencyGraphError {
     ServiceNotEnabled { service: String },
     #[snafu(display("service {service} is already enabled"))]
     ServiceAlreadyEnabled { service: String },
+    #[snafu(display("service {service} is a template, enable one of its instances instead"))]
+    TemplateCannotBeEnabled { service: String },
+    #[snafu(display(
+        "{alias} is provided by multiple services {providers:?}, depend on one of them directly \
+         or enable only one of them"
+    ))]
+    MultipleProviders {
+        alias: String,
+        providers: Vec<String>,
+    },
+    #[snafu(display("service {service} is needed by {dependents:?}"))]
+    ServiceNeededByDependents {
+        service: String,
+        dependents: Vec<String>,
+    },
+    #[snafu(display("the enabled service at index {index} is not in the graph"))]
+    EnabledServiceMissing { index: usize },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(from = "StoredDependencyGraph")]
 pub struct DependencyGraph {
     pub enabled_services: HashSet<usize>,
     pub nodes: IndexMap<String, Node>,
+    // The services providing each alias. Kept along with nodes, it is built
+    // again when loading the graph
+    #[serde(skip_serializing)]
+    aliases: HashMap<String, Vec<String>>,
+}
+
+/// DependencyGraph as it is stored on disk
+#[derive(Deserialize)]
+struct StoredDependencyGraph {
+    enabled_services: HashSet<usize>,
+    nodes: IndexMap<String, Node>,
+}
+
+impl From<StoredDependencyGraph> for DependencyGraph {
+    fn from(graph: StoredDependencyGraph) -> Self {
+        let mut aliases = HashMap::new();
+        for node in graph.nodes.values() {
+            add_aliases(&mut aliases, &node.service);
+        }
+        Self {
+            enabled_services: graph.enabled_services,
+            nodes: graph.nodes,
+            aliases,
+        }
+    }
+}
+
+fn add_aliases(
+    aliases: &mut HashMap<String, Vec<String>>,
+    service: &Service,
+) {
+    for alias in service.provides() {
+        aliases
+            .entry(alias.to_owned())
+            .or_default()
+            .push(service.name().to_owned());
+    }
+}
+
+fn remove_aliases(
+    aliases: &mut HashMap<String, Vec<String>>,
+    service: &Service,
+) {
+    for alias in service.provides() {
+        if let Some(providers) = aliases.get_mut(alias) {
+            providers.retain(|provider| provider != service.name());
+            if providers.is_empty() {
+                aliases.remove(alias);
+            }
+        }
+    }
 }
 
 enum Color {
@@ -52,6 +121,7 @@ impl DependencyGraph {
         DependencyGraph {
             enabled_services: HashSet::new(),
             nodes: IndexMap::new(),
+            aliases: HashMap::new(),
         }
     }
 }
@@ -73,6 +143,10 @@ impl DependencyGraph {
         services: Vec<Service>,
     ) -> Result<()> {
         services_to_enable.iter().try_for_each(|service| {
+            ensure!(
+                !service.ends_with('@'),
+                TemplateCannotBeEnabledSnafu { service }
+            );
             if let Some(index) = &self.nodes.get_index_of(service) {
                 ensure!(
                     !self.enabled_services.contains(index),
@@ -86,13 +160,17 @@ impl DependencyGraph {
         // One that contains services that the graph doesn't have
         // The other contains services that are already inserted in the graph
         // This way we can optimize the addition of services
-        let (new_services, existing_services) = services
+        let (new_services, existing_services): (Vec<Service>, Vec<Service>) = services
             .into_iter()
             .partition(|service| !self.nodes.contains_key(service.name()));
 
         let index = self.nodes.len();
+        // A new provider could make the aliases used by the existing services ambiguous
+        let new_aliases = new_services
+            .iter()
+            .any(|service| !service.provides().is_empty());
         self.add_nodes(new_services);
-        let starting_index = if self.replace_existing_nodes(existing_services) {
+        let starting_index = if self.replace_existing_nodes(existing_services) || new_aliases {
             0
         } else {
             index
@@ -104,13 +182,7 @@ impl DependencyGraph {
         services_to_enable.iter().for_each(|service| {
             let index = self.nodes.get_index_of(service).unwrap();
             self.enabled_services.insert(index);
-            let dependencies = self.nodes[index].service.dependencies().to_owned();
-            for dep in dependencies {
-                self.nodes
-                    .get_mut(&dep)
-                    .unwrap()
-                    .add_dependent(service.to_string());
-            }
+            self.populate_dependents(&[index]);
         });
 
         self.check_cycles(
@@ -123,6 +195,67 @@ impl DependencyGraph {
         Ok(())
     }
 
+    /// Replace the services already in the graph with their new definition,
+    /// e.g. after their files have been edited. Their new dependencies are added
+    /// and the dependencies that are not needed anymore are removed
+    pub fn update_services(
+        &mut self,
+        services: Vec<Service>,
+    ) -> Result<()> {
+        let (new_services, existing_services): (Vec<Service>, Vec<Service>) = services
+            .into_iter()
+            .partition(|service| !self.nodes.contains_key(service.name()));
+        let old_dependencies: Vec<String> = existing_services
+            .iter()
+            .flat_map(|service| {
+                let old_service = &self.nodes[service.name()].service;
+                [old_service.dependencies(), old_service.wants()].concat()
+            })
+            .filter_map(|dep| self.resolve(&dep).ok().flatten())
+            .map(|index| self.nodes[index].name().to_owned())
+            .collect();
+
+        let index = self.add_nodes(new_services);
+        self.replace_existing_nodes(existing_services);
+        self.populate_dependents(&(index..self.nodes.len()).collect::<Vec<_>>());
+        self.check_dependencies(0)?;
+        self.check_cycles(self.enabled_services.iter().copied().collect())?;
+
+        for dep in old_dependencies {
+            if let Some(index) = self.nodes.get_index_of(&dep) {
+                if !self.is_node_required(index) {
+                    self.remove_node(index);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether service has been enabled, and is not only in the graph as a
+    /// dependency of another service
+    pub fn is_enabled(
+        &self,
+        service: &str,
+    ) -> bool {
+        self.nodes
+            .get_index_of(service)
+            .is_some_and(|index| self.enabled_services.contains(&index))
+    }
+
+    /// Whether all the services are already in the graph with the same
+    /// definition, i.e. update_services would not change anything
+    pub fn is_up_to_date(
+        &self,
+        services: &[Service],
+    ) -> bool {
+        services.iter().all(|service| {
+            self.nodes
+                .get(service.name())
+                .is_some_and(|node| &node.service == service)
+        })
+    }
+
     fn add_nodes(
         &mut self,
         services: Vec<Service>,
@@ -130,6 +263,7 @@ impl DependencyGraph {
         let ret = self.nodes.len();
         self.nodes.reserve(services.len());
         for service in services {
+            add_aliases(&mut self.aliases, &service);
             self.nodes
                 .insert(service.name().to_string(), Node::new(service));
         }
@@ -155,12 +289,28 @@ impl DependencyGraph {
 
                 let name = name.clone();
                 // Remove all instances of this service from Node::dependents
+                // and Node::wanted_by
                 let dependencies = existing_service.dependencies().to_owned();
+                let wants = existing_service.wants().to_owned();
                 for dep in dependencies {
-                    self.nodes.get_mut(&dep).unwrap().remove_dependent(&name);
+                    if let Ok(Some(dep_index)) = self.resolve(&dep) {
+                        self.nodes[dep_index].remove_dependent(&name);
+                    }
+                }
+                for wanted in wants {
+                    if let Ok(Some(wanted_index)) = self.resolve(&wanted) {
+                        self.nodes[wanted_index].wanted_by.remove(&name);
+                    }
                 }
-                self.nodes
-                    .insert(new_service.name().to_string(), Node::new(new_service));
+                // The services depending on this one haven't changed
+                let dependents = std::mem::take(&mut self.nodes[service_index].dependents);
+                let wanted_by = std::mem::take(&mut self.nodes[service_index].wanted_by);
+                remove_aliases(&mut self.aliases, &self.nodes[service_index].service);
+                add_aliases(&mut self.aliases, &new_service);
+                let mut node = Node::new(new_service);
+                node.dependents = dependents;
+                node.wanted_by = wanted_by;
+                self.nodes.insert(name, node);
                 self.populate_dependents(&[service_index]);
                 true
             })
@@ -178,7 +328,19 @@ impl DependencyGraph {
                 .dependencies()
                 .to_owned()
                 .iter()
-                .for_each(|dep| self.nodes.get_mut(dep).unwrap().add_dependent(name.clone()));
+                .for_each(|dep| {
+                    // Missing dependencies are reported by check_dependencies
+                    if let Ok(Some(dep_index)) = self.resolve(dep) {
+                        self.nodes[dep_index].add_dependent(name.clone());
+                    }
+                });
+            // Missing wanted services are ignored
+            let (_, node) = self.nodes.get_index(*index).unwrap();
+            node.service.wants().to_owned().iter().for_each(|wanted| {
+                if let Ok(Some(wanted_index)) = self.resolve(wanted) {
+                    self.nodes[wanted_index].wanted_by.insert(name.clone());
+                }
+            });
         });
     }
 
@@ -186,32 +348,32 @@ impl DependencyGraph {
         &self,
         from: usize,
     ) -> Result<()> {
-        self.nodes
-            .values()
-            .skip(from)
-            .try_for_each(|node| -> Result<()> {
-                let runlevel = node.service.runlevel();
-                node.service
-                    .dependencies()
-                    .iter()
-                    .try_for_each(|dep| -> Result<()> {
-                        ensure!(
-                            self.has_service(dep),
-                            DependenciesUnfulfilledSnafu {
-                                service: node.name().to_owned(),
-                                dependency: dep.to_owned()
-                            }
-                        );
-                        ensure!(
-                            self.nodes.get(dep).unwrap().service.runlevel() == runlevel,
-                            DependenciesMustHaveSameRunLevelSnafu {
-                                service: node.name(),
-                                dependency: dep
-                            }
-                        );
-                        Ok(())
-                    })
-            })?;
+        self.nodes.values().skip(from).try_for_each(|node| {
+            node.service
+                .dependencies()
+                .iter()
+                .try_for_each(|dep| self.check_dependency(node, dep))
+        })
+    }
+
+    fn check_dependency(
+        &self,
+        node: &Node,
+        dep: &str,
+    ) -> Result<()> {
+        let dep_index = self.resolve(dep)?.with_context(|| {
+            DependenciesUnfulfilledSnafu {
+                service: node.name().to_owned(),
+                dependency: dep.to_owned(),
+            }
+        })?;
+        ensure!(
+            self.nodes[dep_index].service.runlevel() == node.service.runlevel(),
+            DependenciesMustHaveSameRunLevelSnafu {
+                service: node.name(),
+                dependency: dep
+            }
+        );
         Ok(())
     }
 
@@ -246,8 +408,8 @@ impl DependencyGraph {
             .service
             .dependencies()
             .iter()
-            .map(|dep| self.nodes.get_index_of(dep).unwrap())
             .try_for_each(|dep| -> Result<()> {
+                let dep = self.resolve(dep)?.unwrap();
                 match colors.get(&dep).unwrap() {
                     Color::White => self.visit(colors, dep),
                     Color::Gray => Err(DependencyGraphError::CycleFoundError {}),
@@ -259,6 +421,79 @@ impl DependencyGraph {
         Ok(())
     }
 
+    /// Check a graph read from disk, which could have been edited by hand or
+    /// left behind by a service whose file has been removed: every enabled
+    /// service and every dependency must be in it. Return all the problems
+    /// found
+    pub fn check_integrity(&self) -> Vec<DependencyGraphError> {
+        let mut errors: Vec<DependencyGraphError> = self
+            .enabled_services
+            .iter()
+            .filter(|index| **index >= self.nodes.len())
+            .map(|index| DependencyGraphError::EnabledServiceMissing { index: *index })
+            .collect();
+        errors.extend(self.nodes.values().flat_map(|node| {
+            node.service
+                .dependencies()
+                .iter()
+                .filter_map(|dep| self.check_dependency(node, dep).err())
+        }));
+        errors
+    }
+
+    /// Remove the services with a missing dependency, along with their
+    /// dependents, so that the rest of the graph can be used. Return the names
+    /// of the removed services
+    pub fn prune_dangling_services(&mut self) -> Vec<String> {
+        let len = self.nodes.len();
+        self.enabled_services.retain(|index| *index < len);
+        let mut removed = Vec::new();
+        // The dependents of a removed service have a missing dependency now,
+        // they are removed by the next iterations
+        while let Some(index) = self.nodes.values().position(|node| {
+            node.service
+                .dependencies()
+                .iter()
+                .any(|dep| matches!(self.resolve(dep), Ok(None)))
+        }) {
+            removed.push(self.nodes[index].name().to_owned());
+            self.enabled_services.remove(&index);
+            self.nodes[index].dependents.clear();
+            self.nodes[index].wanted_by.clear();
+            self.remove_node(index);
+        }
+        let names: HashSet<String> = self.nodes.keys().cloned().collect();
+        for node in self.nodes.values_mut() {
+            node.dependents.retain(|dependent| names.contains(dependent));
+            node.wanted_by.retain(|wanting| names.contains(wanting));
+        }
+        removed
+    }
+
+    /// Check the services as if they were all enabled, without changing any graph
+    /// Return all the problems found instead of stopping at the first one
+    pub fn verify(services: Vec<Service>) -> Vec<DependencyGraphError> {
+        let mut graph = DependencyGraph::new();
+        graph.add_nodes(services);
+        let mut errors: Vec<DependencyGraphError> = graph
+            .nodes
+            .values()
+            .flat_map(|node| {
+                node.service
+                    .dependencies()
+                    .iter()
+                    .filter_map(|dep| graph.check_dependency(node, dep).err())
+            })
+            .collect();
+        // Looking for cycles requires all the dependencies to be there
+        if errors.is_empty() {
+            if let Err(err) = graph.check_cycles((0..graph.nodes.len()).collect()) {
+                errors.push(err);
+            }
+        }
+        errors
+    }
+
     pub fn disable_services(
         &mut self,
         services: Vec<String>,
@@ -277,6 +512,58 @@ impl DependencyGraph {
         })
     }
 
+    /// Return the services in the order they have to be stopped, i.e. every
+    /// service comes after its dependents. When recursive is false, the services
+    /// can't be needed by any service not in services
+    pub fn stop_order(
+        &self,
+        services: &[String],
+        recursive: bool,
+    ) -> Result<Vec<String>> {
+        let mut order = Vec::new();
+        services.iter().try_for_each(|service| {
+            self.add_to_stop_order(service, services, recursive, &mut order)
+        })?;
+        Ok(order)
+    }
+
+    fn add_to_stop_order(
+        &self,
+        service: &str,
+        services: &[String],
+        recursive: bool,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if order.iter().any(|s| s == service) {
+            return Ok(());
+        }
+        let node = self
+            .nodes
+            .get(service)
+            .context(ServiceNotEnabledSnafu { service })?;
+        let mut dependents: Vec<&String> = node.dependents.iter().collect();
+        dependents.sort();
+        if !recursive {
+            let needed_by: Vec<String> = dependents
+                .iter()
+                .filter(|dependent| !services.contains(dependent))
+                .map(|dependent| dependent.to_string())
+                .collect();
+            ensure!(
+                needed_by.is_empty(),
+                ServiceNeededByDependentsSnafu {
+                    service,
+                    dependents: needed_by
+                }
+            );
+        }
+        dependents.iter().try_for_each(|dependent| {
+            self.add_to_stop_order(dependent, services, recursive, order)
+        })?;
+        order.push(service.to_owned());
+        Ok(())
+    }
+
     fn remove_node(
         &mut self,
         index: usize,
@@ -293,27 +580,80 @@ impl DependencyGraph {
             .to_owned()
             .iter()
             .for_each(|dep| {
-                let dep_index = self.nodes.get_index_of(dep).unwrap();
-                self.nodes[dep_index].remove_dependent(&name);
-                if !self.is_node_required(dep_index) {
-                    self.remove_node(dep_index)
+                if let Ok(Some(dep_index)) = self.resolve(dep) {
+                    self.nodes[dep_index].remove_dependent(&name);
+                    if !self.is_node_required(dep_index) {
+                        self.remove_node(dep_index)
+                    }
                 }
             });
-
-        // The node to remove is the last one
-        if index == self.nodes.len() - 1 {
-            self.nodes.pop();
+        // Removing the dependencies could have moved this node, or removed it
+        // when one of them wants it
+        let Some(index) = self.nodes.get_index_of(&name) else {
             return;
+        };
+        let last = self.nodes.len() - 1;
+        // The last node takes the place of the removed one, keep its index valid
+        let (_, node) = self.nodes.swap_remove_index(index).unwrap();
+        if index != last && self.enabled_services.remove(&last) {
+            self.enabled_services.insert(index);
         }
+        remove_aliases(&mut self.aliases, &node.service);
 
-        self.nodes[index] = self.nodes.pop().unwrap().1;
+        // Only done once the node is gone, services can want each other
+        node.service.wants().iter().for_each(|wanted| {
+            if let Ok(Some(wanted_index)) = self.resolve(wanted) {
+                self.nodes[wanted_index].wanted_by.remove(&name);
+                if !self.is_node_required(wanted_index) {
+                    self.remove_node(wanted_index)
+                }
+            }
+        });
     }
 
     fn is_node_required(
         &self,
         index: usize,
     ) -> bool {
-        self.enabled_services.contains(&index) || self.nodes[index].has_dependents()
+        self.enabled_services.contains(&index)
+            || self.nodes[index].has_dependents()
+            || !self.nodes[index].wanted_by.is_empty()
+    }
+
+    /// Find the node satisfying a dependency: the service with the same name
+    /// if any, otherwise the only service providing it as an alias
+    /// Return None when there is no such service
+    pub fn resolve(
+        &self,
+        dependency: &str,
+    ) -> Result<Option<usize>> {
+        if let Some(index) = self.nodes.get_index_of(dependency) {
+            return Ok(Some(index));
+        }
+        let providers = self.providers(dependency);
+        ensure!(
+            providers.len() <= 1,
+            MultipleProvidersSnafu {
+                alias: dependency,
+                providers,
+            }
+        );
+        Ok(providers
+            .first()
+            .and_then(|provider| self.nodes.get_index_of(provider)))
+    }
+
+    /// Names of the services providing alias
+    fn providers(
+        &self,
+        alias: &str,
+    ) -> &[String] {
+        self.aliases.get(alias).map_or(&[], Vec::as_slice)
+    }
+
+    /// The services providing each alias
+    pub fn aliases(&self) -> &HashMap<String, Vec<String>> {
+        &self.aliases
     }
 
     #[inline]
@@ -338,6 +678,10 @@ mod test {
             name: name.to_string(),
             start: Script::new(ScriptPrefix::Bash, "exit 0".to_string()),
             stop: None,
+            up_check: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
             options,
             environment: ScriptEnvironment::new(),
         })
@@ -536,4 +880,333 @@ mod test {
             }
         );
     }
+
+    fn create_provider(
+        name: &str,
+        alias: &str,
+    ) -> Service {
+        create_new_service(name, {
+            let mut options = ServiceOptions::new();
+            options.provides = vec![alias.to_string()];
+            options
+        })
+    }
+
+    fn create_cron_dependent() -> Service {
+        create_new_service("backup", {
+            let mut options = ServiceOptions::new();
+            options.dependencies = vec!["cron".to_string()];
+            options
+        })
+    }
+
+    #[test]
+    fn dependency_satisfied_by_provider() {
+        let mut graph = DependencyGraph::new();
+
+        graph
+            .add_services(
+                vec!["backup".to_string()],
+                vec![create_cron_dependent(), create_provider("cronie", "cron")],
+            )
+            .unwrap();
+        assert_eq!(graph.resolve("cron"), Ok(graph.nodes.get_index_of("cronie")));
+        assert!(graph.nodes["cronie"].dependents.contains("backup"));
+
+        // The aliases are not stored, they are built again when loading the graph
+        let mut graph: DependencyGraph =
+            serde_json::from_slice(&serde_json::to_vec(&graph).unwrap()).unwrap();
+        assert_eq!(
+            graph.resolve("cron"),
+            Ok(graph.nodes.get_index_of("cronie"))
+        );
+        // cronie is only there for backup
+        graph.disable_services(vec!["backup".to_string()]).unwrap();
+        assert_eq!(graph.resolve("cron"), Ok(None));
+    }
+
+    #[test]
+    fn dependency_with_multiple_providers() {
+        let mut graph = DependencyGraph::new();
+
+        assert_eq!(
+            graph
+                .add_services(
+                    vec!["backup".to_string()],
+                    vec![
+                        create_cron_dependent(),
+                        create_provider("cronie", "cron"),
+                        create_provider("fcron", "cron"),
+                    ],
+                )
+                .unwrap_err(),
+            DependencyGraphError::MultipleProviders {
+                alias: "cron".to_string(),
+                providers: vec!["cronie".to_string(), "fcron".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn new_provider_makes_alias_ambiguous() {
+        let mut graph = DependencyGraph::new();
+
+        graph
+            .add_services(
+                vec!["backup".to_string(), "cronie".to_string()],
+                vec![create_cron_dependent(), create_provider("cronie", "cron")],
+            )
+            .unwrap();
+        assert!(matches!(
+            graph.add_services(
+                vec!["fcron".to_string()],
+                vec![create_provider("fcron", "cron")],
+            ),
+            Err(DependencyGraphError::MultipleProviders { .. })
+        ));
+    }
+
+    #[test]
+    fn verify_services() {
+        assert!(DependencyGraph::verify(vec![
+            create_cron_dependent(),
+            create_provider("cronie", "cron"),
+        ])
+        .is_empty());
+    }
+
+    #[test]
+    fn verify_reports_all_problems() {
+        let errors = DependencyGraph::verify(vec![
+            create_new_service("foo", {
+                let mut options = ServiceOptions::new();
+                options.dependencies = vec!["missing".to_string(), "boot".to_string()];
+                options
+            }),
+            create_new_service("boot", {
+                let mut options = ServiceOptions::new();
+                options.runlevel = RunLevel::Boot;
+                options
+            }),
+            create_cron_dependent(),
+            create_provider("cronie", "cron"),
+            create_provider("fcron", "cron"),
+        ]);
+        assert_eq!(
+            errors,
+            vec![
+                DependencyGraphError::DependenciesUnfulfilledError {
+                    service: "foo".to_string(),
+                    dependency: "missing".to_string(),
+                },
+                DependencyGraphError::DependenciesMustHaveSameRunLevel {
+                    service: "foo".to_string(),
+                    dependency: "boot".to_string(),
+                },
+                DependencyGraphError::MultipleProviders {
+                    alias: "cron".to_string(),
+                    providers: vec!["cronie".to_string(), "fcron".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_cycle() {
+        let errors = DependencyGraph::verify(vec![
+            create_new_service("foo", {
+                let mut options = ServiceOptions::new();
+                options.dependencies = vec!["bar".to_string()];
+                options
+            }),
+            create_new_service("bar", {
+                let mut options = ServiceOptions::new();
+                options.dependencies = vec!["foo".to_string()];
+                options
+            }),
+        ]);
+        assert_eq!(errors, vec![DependencyGraphError::CycleFoundError]);
+    }
+
+    fn create_chain() -> DependencyGraph {
+        let mut graph = DependencyGraph::new();
+        graph
+            .add_services(
+                vec!["foo".to_string(), "bar".to_string()],
+                vec![
+                    create_new_service("foo", {
+                        let mut options = ServiceOptions::new();
+                        options.dependencies = vec!["bar".to_string()];
+                        options
+                    }),
+                    create_new_service("bar", {
+                        let mut options = ServiceOptions::new();
+                        options.dependencies = vec!["baz".to_string()];
+                        options
+                    }),
+                    create_new_service("baz", ServiceOptions::new()),
+                ],
+            )
+            .unwrap();
+        graph
+    }
+
+    #[test]
+    fn disable_keeps_other_services() {
+        let mut graph = create_chain();
+        graph
+            .add_services(
+                vec!["qux".to_string()],
+                vec![create_new_service("qux", ServiceOptions::new())],
+            )
+            .unwrap();
+        graph.disable_services(vec!["foo".to_string()]).unwrap();
+        assert_eq!(
+            graph.nodes.keys().collect::<Vec<_>>(),
+            vec!["qux", "bar", "baz"]
+        );
+        assert_eq!(graph.nodes["qux"].name(), "qux");
+        assert_eq!(graph.enabled_services, HashSet::from([0, 1]));
+        graph.disable_services(vec!["bar".to_string()]).unwrap();
+        assert_eq!(graph.nodes.keys().collect::<Vec<_>>(), vec!["qux"]);
+        assert_eq!(graph.enabled_services, HashSet::from([0]));
+    }
+
+    #[test]
+    fn update_service_definition() {
+        let mut graph = create_chain();
+        // bar now depends on qux instead of baz
+        graph
+            .update_services(vec![
+                create_new_service("bar", {
+                    let mut options = ServiceOptions::new();
+                    options.dependencies = vec!["qux".to_string()];
+                    options
+                }),
+                create_new_service("qux", ServiceOptions::new()),
+            ])
+            .unwrap();
+        assert_eq!(
+            graph.nodes.keys().collect::<Vec<_>>(),
+            vec!["foo", "bar", "qux"]
+        );
+        assert_eq!(graph.nodes["bar"].dependents, HashSet::from(["foo".to_string()]));
+        assert_eq!(graph.nodes["qux"].dependents, HashSet::from(["bar".to_string()]));
+        assert_eq!(graph.enabled_services, HashSet::from([0, 1]));
+
+        assert!(matches!(
+            graph.update_services(vec![create_new_service("bar", {
+                let mut options = ServiceOptions::new();
+                options.dependencies = vec!["missing".to_string()];
+                options
+            })]),
+            Err(DependencyGraphError::DependenciesUnfulfilledError { .. })
+        ));
+    }
+
+    #[test]
+    fn wanted_services_kept_while_wanted() {
+        let with_wants = |name: &str, wants: &[&str]| {
+            let mut options = ServiceOptions::new();
+            options.wants = wants.iter().map(|wanted| wanted.to_string()).collect();
+            create_new_service(name, options)
+        };
+        let mut graph = DependencyGraph::new();
+        // A missing wanted service is not an error
+        graph
+            .add_services(
+                vec!["foo".to_string()],
+                vec![
+                    with_wants("foo", &["bar", "missing"]),
+                    with_wants("bar", &["foo"]),
+                ],
+            )
+            .unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.nodes["bar"].wanted_by, HashSet::from(["foo".to_string()]));
+        assert!(graph.nodes["bar"].dependents.is_empty());
+        // Nothing needs bar, stopping it doesn't stop foo
+        assert_eq!(
+            graph.stop_order(&["bar".to_string()], false).unwrap(),
+            vec!["bar".to_string()]
+        );
+
+        graph.disable_services(vec!["foo".to_string()]).unwrap();
+        assert!(graph.nodes.is_empty());
+    }
+
+    #[test]
+    fn stop_dependents_first() {
+        let graph = create_chain();
+        assert_eq!(
+            graph
+                .stop_order(&["bar".to_string(), "foo".to_string()], false)
+                .unwrap(),
+            vec!["foo".to_string(), "bar".to_string()]
+        );
+        assert_eq!(
+            graph.stop_order(&["baz".to_string()], true).unwrap(),
+            vec!["foo".to_string(), "bar".to_string(), "baz".to_string()]
+        );
+    }
+
+    #[test]
+    fn stop_service_needed_by_dependents() {
+        let graph = create_chain();
+        assert_eq!(
+            graph.stop_order(&["bar".to_string()], false).unwrap_err(),
+            DependencyGraphError::ServiceNeededByDependents {
+                service: "bar".to_string(),
+                dependents: vec!["foo".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn prune_deleted_service() {
+        let with_dependencies = |name: &str, dependencies: &[&str]| {
+            let mut options = ServiceOptions::new();
+            options.dependencies = dependencies.iter().map(|dep| dep.to_string()).collect();
+            create_new_service(name, options)
+        };
+        let mut graph = DependencyGraph::new();
+        graph
+            .add_services(
+                vec!["foo".to_string(), "baz".to_string(), "qux".to_string()],
+                vec![
+                    with_dependencies("foo", &["bar", "common"]),
+                    with_dependencies("bar", &[]),
+                    with_dependencies("baz", &["foo"]),
+                    with_dependencies("qux", &["common"]),
+                    with_dependencies("common", &[]),
+                ],
+            )
+            .unwrap();
+        assert!(graph.check_integrity().is_empty());
+
+        // As if bar had been removed from the graph on disk, along with an
+        // enabled service
+        let last = graph.nodes.len();
+        graph.nodes.swap_remove("bar");
+        graph.enabled_services.insert(last);
+        assert_eq!(
+            graph.check_integrity(),
+            vec![
+                DependencyGraphError::EnabledServiceMissing { index: last },
+                DependencyGraphError::DependenciesUnfulfilledError {
+                    service: "foo".to_string(),
+                    dependency: "bar".to_string(),
+                },
+            ]
+        );
+
+        let mut removed = graph.prune_dangling_services();
+        removed.sort();
+        assert_eq!(removed, vec!["baz".to_string(), "foo".to_string()]);
+        assert!(graph.check_integrity().is_empty());
+        assert!(graph.is_enabled("qux"));
+        // Still needed by qux
+        assert_eq!(graph.nodes["common"].dependents, HashSet::from(["qux".to_string()]));
+        assert_eq!(graph.nodes.len(), 2);
+    }
 }
@@ -19,6 +19,10 @@ pub struct Node {
     pub service: Service,
     #[serde(default, skip_serializing_if = "HashSet::is_empty")]
     pub dependents: HashSet<String>,
+    // The services wanting this one, which keep it in the graph without
+    // needing it
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub wanted_by: HashSet<String>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     providers: HashMap<String, Provider>,
 }
@@ -28,6 +32,7 @@ impl Node {
         Node {
             service,
             dependents: HashSet::new(),
+            wanted_by: HashSet::new(),
             providers: HashMap::new(),
         }
     }
@@ -0,0 +1,104 @@
+use std::{
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        self,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    process,
+};
+
+use snafu::{
+    OptionExt,
+    ResultExt,
+    Snafu,
+};
+
+use crate::graph::DependencyGraph;
+
+#[derive(Debug, Snafu)]
+pub enum WriteGraphError {
+    #[snafu(display("unable to serialize the dependency graph: {source}"))]
+    SerializeGraph { source: serde_json::Error },
+    #[snafu(display("file {:?} has no parent directory", path))]
+    NoParentDirectory { path: PathBuf },
+    #[snafu(display("unable to create parent directory of file {:?}: {source}", path))]
+    CreateParentDirectory { path: PathBuf, source: io::Error },
+    #[snafu(display("unable to write file {:?}: {source}", path))]
+    WriteFile { path: PathBuf, source: io::Error },
+    #[snafu(display("unable to sync directory {:?}: {source}", dir))]
+    SyncDirectory { dir: PathBuf, source: io::Error },
+}
+
+/// Save the dependency graph without ever leaving a partially written file.
+/// Both rsvc and rctl write the graph, and either could read it meanwhile
+pub fn write_graph(
+    graph: &DependencyGraph,
+    graph_file: &Path,
+) -> Result<(), WriteGraphError> {
+    let contents = serde_json::to_vec(graph).context(SerializeGraphSnafu)?;
+    write_atomically(graph_file, |file| file.write_all(&contents))
+}
+
+/// Call write on a temporary file in the same directory of path and then rename
+/// it over path. On failure, path is left untouched
+pub fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> io::Result<()>,
+) -> Result<(), WriteGraphError> {
+    let dir = path.parent().context(NoParentDirectorySnafu { path })?;
+    fs::create_dir_all(dir).context(CreateParentDirectorySnafu { path })?;
+    let tmp_path = dir.join(format!(
+        ".{}.tmp-{}",
+        path.file_name().unwrap().to_string_lossy(),
+        process::id()
+    ));
+    let res = File::create(&tmp_path)
+        .and_then(|mut file| {
+            write(&mut file)?;
+            // The data must be on disk before the rename is
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if let Err(err) = res {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err).context(WriteFileSnafu { path: tmp_path });
+    }
+    // Persist the rename
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .context(SyncDirectorySnafu { dir })
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn interrupted_write_keeps_original() {
+        let dir = env::temp_dir().join(format!("rinit-atomic-write-{}", process::id()));
+        let file = dir.join("graph.data");
+        write_atomically(&file, |file| file.write_all(b"original")).unwrap();
+
+        let res = write_atomically(&file, |file| {
+            file.write_all(b"partial")?;
+            Err(io::Error::new(io::ErrorKind::Interrupted, "simulated crash"))
+        });
+        assert!(res.is_err());
+        assert_eq!(fs::read(&file).unwrap(), b"original");
+        // The temporary file has been cleaned up
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        write_atomically(&file, |file| file.write_all(b"updated")).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"updated");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,6 +2,9 @@
 
 pub mod config;
 pub mod dirs;
+pub mod env_dropins;
 pub mod graph;
 pub mod service_state;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 pub mod types;
@@ -20,7 +20,23 @@ pub enum ServiceState {
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum IdleServiceState {
     Up,
+    /// Stopped on request or never started
     Down,
+    /// Failed to start or exited on its own. The exit code is in the status of
+    /// the service
+    Failed,
+    /// Not started because its condition was not met, which is not a failure
+    Skipped,
+}
+
+impl IdleServiceState {
+    /// Whether the service is not running, either stopped, failed or skipped
+    pub fn is_down(&self) -> bool {
+        matches!(
+            self,
+            IdleServiceState::Down | IdleServiceState::Failed | IdleServiceState::Skipped
+        )
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
@@ -29,6 +45,39 @@ pub enum TransitioningServiceState {
     Stopping,
 }
 
+impl ServiceState {
+    /// Whether a service can go from this state to new. The services only
+    /// become up after starting and only start when they are down; a
+    /// transition breaking these rules is a bug in the supervision
+    pub fn can_transition_to(
+        &self,
+        new: ServiceState,
+    ) -> bool {
+        match (*self, new) {
+            (old, new) if old == new => true,
+            (old, ServiceState::Transitioning(new)) => {
+                match new {
+                    TransitioningServiceState::Starting => {
+                        matches!(old, ServiceState::Idle(old) if old.is_down())
+                    }
+                    // Stopping is always possible, even to abort a start
+                    TransitioningServiceState::Stopping => true,
+                }
+            }
+            (ServiceState::Transitioning(_), ServiceState::Idle(_)) => true,
+            (ServiceState::Idle(old), ServiceState::Idle(new)) => {
+                match (old, new) {
+                    // A longrun that crashed is restarted by its supervisor
+                    (IdleServiceState::Failed, IdleServiceState::Up) => true,
+                    // The process exited, or was stopped from outside
+                    (IdleServiceState::Up, _) => true,
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
 impl fmt::Display for ServiceState {
     fn fmt(
         &self,
@@ -52,6 +101,8 @@ impl fmt::Display for IdleServiceState {
             match self {
                 IdleServiceState::Up => "up",
                 IdleServiceState::Down => "down",
+                IdleServiceState::Failed => "failed",
+                IdleServiceState::Skipped => "skipped",
             }
         )
     }
@@ -75,3 +126,55 @@ impl fmt::Display for TransitioningServiceState {
 
 unsafe impl Send for ServiceState {}
 unsafe impl Sync for ServiceState {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const UP: ServiceState = ServiceState::Idle(IdleServiceState::Up);
+    const DOWN: ServiceState = ServiceState::Idle(IdleServiceState::Down);
+    const FAILED: ServiceState = ServiceState::Idle(IdleServiceState::Failed);
+    const SKIPPED: ServiceState = ServiceState::Idle(IdleServiceState::Skipped);
+    const STARTING: ServiceState = ServiceState::Transitioning(TransitioningServiceState::Starting);
+    const STOPPING: ServiceState = ServiceState::Transitioning(TransitioningServiceState::Stopping);
+
+    #[test]
+    fn validate_transitions() {
+        let legal = [
+            (DOWN, STARTING),
+            (FAILED, STARTING),
+            (STARTING, UP),
+            (STARTING, FAILED),
+            (STARTING, DOWN),
+            (STARTING, SKIPPED),
+            (SKIPPED, STARTING),
+            (SKIPPED, STOPPING),
+            (STARTING, STOPPING),
+            (UP, STOPPING),
+            (DOWN, STOPPING),
+            (FAILED, STOPPING),
+            (STOPPING, DOWN),
+            (STOPPING, UP),
+            (STOPPING, FAILED),
+            (UP, FAILED),
+            (UP, DOWN),
+            (FAILED, UP),
+            (UP, UP),
+        ];
+        for (old, new) in legal {
+            assert!(old.can_transition_to(new), "{old} -> {new} should be legal");
+        }
+        let illegal = [
+            (DOWN, UP),
+            (DOWN, FAILED),
+            (FAILED, DOWN),
+            (SKIPPED, UP),
+            (SKIPPED, FAILED),
+            (UP, STARTING),
+            (STOPPING, STARTING),
+        ];
+        for (old, new) in illegal {
+            assert!(!old.can_transition_to(new), "{old} -> {new} should be illegal");
+        }
+    }
+}
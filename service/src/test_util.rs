@@ -0,0 +1,231 @@
+use crate::{
+    graph::DependencyGraph,
+    types::{
+        Longrun,
+        Oneshot,
+        RunLevel,
+        Script,
+        ScriptEnvironment,
+        ScriptPrefix,
+        Service,
+        ServiceOptions,
+    },
+};
+
+fn bash(execute: &str) -> Script {
+    Script::new(ScriptPrefix::Bash, execute.to_string())
+}
+
+fn names(services: &[&str]) -> Vec<String> {
+    services.iter().map(|service| service.to_string()).collect()
+}
+
+/// Build the services used in the tests, every script is run by bash
+pub struct ServiceBuilder {
+    service: Service,
+}
+
+impl ServiceBuilder {
+    /// A oneshot that succeeds right away
+    pub fn oneshot(name: &str) -> Self {
+        Self {
+            service: Service::Oneshot(Oneshot {
+                name: name.to_string(),
+                start: bash("exit 0"),
+                stop: None,
+                up_check: None,
+                condition: None,
+                pre_start: None,
+                post_stop: None,
+                options: ServiceOptions::new(),
+                environment: ScriptEnvironment::new(),
+            }),
+        }
+    }
+
+    /// A longrun that keeps running for a while
+    pub fn longrun(name: &str) -> Self {
+        Self {
+            service: Service::Longrun(Longrun {
+                name: name.to_string(),
+                run: bash("sleep 10"),
+                finish: None,
+                condition: None,
+                pre_start: None,
+                post_stop: None,
+                options: ServiceOptions::new(),
+                environment: ScriptEnvironment::new(),
+            }),
+        }
+    }
+
+    fn as_oneshot(&mut self) -> &mut Oneshot {
+        match &mut self.service {
+            Service::Oneshot(oneshot) => oneshot,
+            service => panic!("{} is not a oneshot", service.name()),
+        }
+    }
+
+    /// The start script of a oneshot or the run script of a longrun
+    pub fn start(
+        self,
+        execute: &str,
+    ) -> Self {
+        self.start_script(|script| *script = bash(execute))
+    }
+
+    pub fn start_script(
+        mut self,
+        f: impl FnOnce(&mut Script),
+    ) -> Self {
+        match &mut self.service {
+            Service::Oneshot(oneshot) => f(&mut oneshot.start),
+            Service::Longrun(longrun) => f(&mut longrun.run),
+            service => panic!("{} has no start script", service.name()),
+        }
+        self
+    }
+
+    /// The stop script of a oneshot or the finish script of a longrun
+    pub fn stop(
+        mut self,
+        execute: &str,
+    ) -> Self {
+        match &mut self.service {
+            Service::Oneshot(oneshot) => oneshot.stop = Some(bash(execute)),
+            Service::Longrun(longrun) => longrun.finish = Some(bash(execute)),
+            service => panic!("{} has no stop script", service.name()),
+        }
+        self
+    }
+
+    pub fn stop_must_succeed(
+        mut self,
+        must_succeed: bool,
+    ) -> Self {
+        self.as_oneshot()
+            .stop
+            .as_mut()
+            .expect("the stop script has to be set first")
+            .must_succeed = must_succeed;
+        self
+    }
+
+    pub fn up_check(
+        mut self,
+        execute: &str,
+    ) -> Self {
+        self.as_oneshot().up_check = Some(Box::new(bash(execute)));
+        self
+    }
+
+    pub fn condition(
+        mut self,
+        execute: &str,
+    ) -> Self {
+        match &mut self.service {
+            Service::Oneshot(oneshot) => oneshot.condition = Some(bash(execute)),
+            Service::Longrun(longrun) => longrun.condition = Some(bash(execute)),
+            service => panic!("{} has no condition", service.name()),
+        }
+        self
+    }
+
+    pub fn pre_start(
+        mut self,
+        execute: &str,
+    ) -> Self {
+        match &mut self.service {
+            Service::Oneshot(oneshot) => oneshot.pre_start = Some(bash(execute)),
+            Service::Longrun(longrun) => longrun.pre_start = Some(bash(execute)),
+            service => panic!("{} has no pre_start", service.name()),
+        }
+        self
+    }
+
+    pub fn post_stop(
+        mut self,
+        execute: &str,
+    ) -> Self {
+        match &mut self.service {
+            Service::Oneshot(oneshot) => oneshot.post_stop = Some(bash(execute)),
+            Service::Longrun(longrun) => longrun.post_stop = Some(bash(execute)),
+            service => panic!("{} has no post_stop", service.name()),
+        }
+        self
+    }
+
+    /// Change any other option of the service
+    pub fn options(
+        mut self,
+        f: impl FnOnce(&mut ServiceOptions),
+    ) -> Self {
+        match &mut self.service {
+            Service::Oneshot(oneshot) => f(&mut oneshot.options),
+            Service::Longrun(longrun) => f(&mut longrun.options),
+            service => panic!("{} has no service options", service.name()),
+        }
+        self
+    }
+
+    pub fn dependencies(
+        self,
+        dependencies: &[&str],
+    ) -> Self {
+        self.options(|options| options.dependencies = names(dependencies))
+    }
+
+    pub fn wants(
+        self,
+        wants: &[&str],
+    ) -> Self {
+        self.options(|options| options.wants = names(wants))
+    }
+
+    pub fn provides(
+        self,
+        provides: &[&str],
+    ) -> Self {
+        self.options(|options| options.provides = names(provides))
+    }
+
+    pub fn conflicts(
+        self,
+        conflicts: &[&str],
+    ) -> Self {
+        self.options(|options| options.conflicts = names(conflicts))
+    }
+
+    pub fn before(
+        self,
+        before: &[&str],
+    ) -> Self {
+        self.options(|options| options.before = names(before))
+    }
+
+    pub fn runlevel(
+        self,
+        runlevel: RunLevel,
+    ) -> Self {
+        self.options(|options| options.runlevel = runlevel)
+    }
+
+    pub fn build(self) -> Service {
+        self.service
+    }
+}
+
+/// A graph where every service is enabled
+pub fn new_graph(services: Vec<Service>) -> DependencyGraph {
+    let mut graph = DependencyGraph::new();
+    graph
+        .add_services(
+            services
+                .iter()
+                .map(|service| service.name().to_string())
+                .collect(),
+            services,
+        )
+        .unwrap();
+    graph
+}
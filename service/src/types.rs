@@ -1,25 +1,43 @@
+mod bind_mount;
 mod bundle;
 mod bundle_options;
+mod credential;
+mod kill_mode;
+mod listen_socket;
 mod longrun;
+mod milliseconds;
 mod oneshot;
 mod provider;
 mod runlevel;
+mod schedule;
 mod script;
 mod script_environment;
+mod script_input;
+mod script_output;
 mod service;
 mod service_options;
+mod syscall_filter;
 mod virtual_service;
 
 pub use self::{
+    bind_mount::*,
     bundle::*,
     bundle_options::*,
+    credential::*,
+    kill_mode::*,
+    listen_socket::*,
     longrun::*,
+    milliseconds::*,
     oneshot::*,
     provider::*,
     runlevel::*,
+    schedule::*,
     script::*,
     script_environment::*,
+    script_input::*,
+    script_output::*,
     service::*,
     service_options::*,
+    syscall_filter::*,
     virtual_service::*,
 };
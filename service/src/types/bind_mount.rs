@@ -0,0 +1,55 @@
+use std::{
+    path::PathBuf,
+    str::FromStr,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use snafu::{
+    ensure,
+    Snafu,
+};
+
+/// Path mounted at target inside the mount namespace of a script, so that
+/// only the script sees it
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct BindMount {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(display(
+    "{bind_mount} is not a valid bind mount, use <absolute source>[:<absolute target>][:ro|rw]"
+))]
+pub struct BindMountParseError {
+    bind_mount: String,
+}
+
+impl FromStr for BindMount {
+    type Err = BindMountParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (paths, read_only) = match s.rsplit_once(':') {
+            Some((paths, "ro")) => (paths, true),
+            Some((paths, "rw")) => (paths, false),
+            _ => (s, false),
+        };
+        let (source, target) = paths.split_once(':').unwrap_or((paths, paths));
+        ensure!(
+            source.starts_with('/') && target.starts_with('/') && !target.contains(':'),
+            BindMountParseSnafu {
+                bind_mount: s.to_string(),
+            }
+        );
+        Ok(BindMount {
+            source: PathBuf::from(source),
+            target: PathBuf::from(target),
+            read_only,
+        })
+    }
+}
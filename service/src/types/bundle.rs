@@ -5,7 +5,7 @@ use serde::{
 
 use super::bundle_options::BundleOptions;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Bundle {
     pub name: String,
     pub options: BundleOptions,
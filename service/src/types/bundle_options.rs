@@ -6,7 +6,7 @@ use serde::{
 use super::runlevel::RunLevel;
 
 /// Store options for Longrun and Oneshot
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct BundleOptions {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub contents: Vec<String>,
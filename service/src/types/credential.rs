@@ -0,0 +1,54 @@
+use std::{
+    path::PathBuf,
+    str::FromStr,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use snafu::Snafu;
+
+/// Secret read from a file when the scripts of a service are run and passed to
+/// them as an environment variable. Only the path is stored, the contents never
+/// end up in the dependency graph
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Credential {
+    /// Name of the environment variable
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(display(
+    "{credential} is not a valid credential, use <NAME>:<absolute path to the file>"
+))]
+pub struct CredentialParseError {
+    credential: String,
+}
+
+impl FromStr for Credential {
+    type Err = CredentialParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((name, path)) if is_variable_name(name) && path.starts_with('/') => {
+                Ok(Credential {
+                    name: name.to_string(),
+                    path: PathBuf::from(path),
+                })
+            }
+            _ => CredentialParseSnafu {
+                credential: s.to_string(),
+            }
+            .fail(),
+        }
+    }
+}
+
+fn is_variable_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
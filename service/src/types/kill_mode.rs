@@ -0,0 +1,46 @@
+use std::str::FromStr;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use snafu::Snafu;
+
+/// Which processes receive down_signal when a script is stopped
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum KillMode {
+    /// Only the process executing the script, for daemons that stop their own
+    /// children
+    Main,
+    /// The process group created for the script
+    #[default]
+    ProcessGroup,
+    /// Every process in the cgroup of the service, including the ones that
+    /// left the process group
+    Cgroup,
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(display("{mode} is not a valid kill mode, use main, process-group or cgroup"))]
+pub struct KillModeParseError {
+    mode: String,
+}
+
+impl KillMode {
+    pub fn is_process_group(&self) -> bool {
+        matches!(self, KillMode::ProcessGroup)
+    }
+}
+
+impl FromStr for KillMode {
+    type Err = KillModeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "main" => Ok(KillMode::Main),
+            "process-group" => Ok(KillMode::ProcessGroup),
+            "cgroup" => Ok(KillMode::Cgroup),
+            _ => KillModeParseSnafu { mode: s.to_string() }.fail(),
+        }
+    }
+}
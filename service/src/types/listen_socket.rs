@@ -0,0 +1,46 @@
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use snafu::Snafu;
+
+/// Socket bound by rsvc for a socket activated longrun
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum ListenSocket {
+    /// Unix stream socket at this path
+    Unix(PathBuf),
+    /// TCP socket listening on this address
+    Tcp(SocketAddr),
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(display("{socket} is not a valid socket, use unix:<path> or tcp:<address>:<port>"))]
+pub struct ListenSocketParseError {
+    socket: String,
+}
+
+impl FromStr for ListenSocket {
+    type Err = ListenSocketParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:").filter(|path| !path.is_empty()) {
+            Ok(ListenSocket::Unix(PathBuf::from(path)))
+        } else if let Some(address) = s
+            .strip_prefix("tcp:")
+            .and_then(|address| address.parse().ok())
+        {
+            Ok(ListenSocket::Tcp(address))
+        } else {
+            ListenSocketParseSnafu {
+                socket: s.to_string(),
+            }
+            .fail()
+        }
+    }
+}
@@ -12,6 +12,13 @@ pub struct Longrun {
     pub name: String,
     pub run: Script,
     pub finish: Option<Script>,
+    /// Checked before starting, if it fails the service is skipped instead of
+    /// being started
+    pub condition: Option<Script>,
+    /// Run before run, the service fails to start if it fails
+    pub pre_start: Option<Script>,
+    /// Run once the supervised process and its children are gone
+    pub post_stop: Option<Script>,
     #[serde(flatten)]
     pub options: ServiceOptions,
     #[serde(flatten, default, skip_serializing_if = "ScriptEnvironment::is_empty")]
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use serde::{
+    Deserialize,
+    Deserializer,
+};
+use snafu::{
+    OptionExt,
+    ResultExt,
+    Snafu,
+};
+
+#[derive(Debug, Snafu)]
+pub enum MillisecondsParseError {
+    #[snafu(display(
+        "{value} is not a valid duration, use milliseconds or a duration like 30s, 2m or 1500ms: \
+         {source}"
+    ))]
+    InvalidDuration {
+        value: String,
+        source: humantime::DurationError,
+    },
+    #[snafu(display(
+        "{value} is too long, the maximum is {}",
+        humantime::format_duration(Duration::from_millis(u32::MAX as u64))
+    ))]
+    DurationTooLong { value: String },
+}
+
+/// Parse a timeout into milliseconds. A plain number is in milliseconds, as
+/// it has always been, anything else is a duration like "30s", "2m" or
+/// "1min 30s"
+pub fn parse_milliseconds(value: &str) -> Result<u32, MillisecondsParseError> {
+    let value = value.trim();
+    if let Ok(milliseconds) = value.parse::<u32>() {
+        return Ok(milliseconds);
+    }
+    let duration = humantime::parse_duration(value).with_context(|_| {
+        InvalidDurationSnafu {
+            value: value.to_string(),
+        }
+    })?;
+    u32::try_from(duration.as_millis()).ok().with_context(|| {
+        DurationTooLongSnafu {
+            value: value.to_string(),
+        }
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MillisecondsOrDuration {
+    Milliseconds(u32),
+    Duration(String),
+}
+
+impl MillisecondsOrDuration {
+    fn into_milliseconds<E: serde::de::Error>(self) -> Result<u32, E> {
+        match self {
+            Self::Milliseconds(milliseconds) => Ok(milliseconds),
+            Self::Duration(duration) => parse_milliseconds(&duration).map_err(E::custom),
+        }
+    }
+}
+
+/// Deserialize a timeout either from milliseconds or from a duration string
+pub fn deserialize_milliseconds<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    MillisecondsOrDuration::deserialize(deserializer)?.into_milliseconds()
+}
+
+/// Same as deserialize_milliseconds, for optional timeouts
+pub fn deserialize_optional_milliseconds<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<MillisecondsOrDuration>::deserialize(deserializer)?
+        .map(MillisecondsOrDuration::into_milliseconds)
+        .transpose()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_each_unit() {
+        assert_eq!(parse_milliseconds("1500").unwrap(), 1500);
+        assert_eq!(parse_milliseconds("1500ms").unwrap(), 1500);
+        assert_eq!(parse_milliseconds("30s").unwrap(), 30_000);
+        assert_eq!(parse_milliseconds("2m").unwrap(), 120_000);
+        assert_eq!(parse_milliseconds("1h").unwrap(), 3_600_000);
+        assert_eq!(parse_milliseconds("1d").unwrap(), 86_400_000);
+        assert_eq!(parse_milliseconds("1min 30s").unwrap(), 90_000);
+    }
+
+    #[test]
+    fn reject_garbage() {
+        for value in ["", "abc", "-5s", "10 parsecs", "5x"] {
+            assert!(
+                matches!(
+                    parse_milliseconds(value),
+                    Err(MillisecondsParseError::InvalidDuration { .. })
+                ),
+                "{value} should be rejected"
+            );
+        }
+        assert!(matches!(
+            parse_milliseconds("100d"),
+            Err(MillisecondsParseError::DurationTooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn deserialize_milliseconds_or_duration() {
+        #[derive(Deserialize)]
+        struct Timeouts {
+            #[serde(deserialize_with = "deserialize_milliseconds")]
+            timeout: u32,
+            #[serde(default, deserialize_with = "deserialize_optional_milliseconds")]
+            down_timeout: Option<u32>,
+        }
+
+        let timeouts: Timeouts = toml::from_str("timeout = 1500").unwrap();
+        assert_eq!(timeouts.timeout, 1500);
+        assert_eq!(timeouts.down_timeout, None);
+        let timeouts: Timeouts =
+            toml::from_str("timeout = \"30s\"\ndown_timeout = \"2m\"").unwrap();
+        assert_eq!(timeouts.timeout, 30_000);
+        assert_eq!(timeouts.down_timeout, Some(120_000));
+        assert!(toml::from_str::<Timeouts>("timeout = \"soon\"").is_err());
+    }
+}
@@ -7,11 +7,20 @@ use serde_with::skip_serializing_none;
 use super::*;
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Oneshot {
     pub name: String,
     pub start: Script,
     pub stop: Option<Script>,
+    /// Checked before running start, if it succeeds the service is already up
+    pub up_check: Option<Box<Script>>,
+    /// Checked before starting, if it fails the service is skipped instead of
+    /// being started
+    pub condition: Option<Script>,
+    /// Run before start, the service fails to start if it fails
+    pub pre_start: Option<Script>,
+    /// Run after stop
+    pub post_stop: Option<Script>,
     #[serde(flatten)]
     pub options: ServiceOptions,
     #[serde(flatten, default, skip_serializing_if = "ScriptEnvironment::is_empty")]
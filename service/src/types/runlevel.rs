@@ -12,8 +12,10 @@ use snafu::Snafu;
 // dbus before all the other services
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
 pub enum RunLevel {
+    #[serde(alias = "boot")]
     Boot,
     #[default]
+    #[serde(alias = "default")]
     Default,
 }
 
@@ -0,0 +1,456 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use snafu::Snafu;
+
+/// When a scheduled oneshot is run, in the crontab format
+/// "<minute> <hour> <day of month> <month> <day of week>", in local time.
+/// Every field is *, a number, a range like 1-5 or a list of them separated by
+/// commas, each optionally followed by a step like */15. Sunday is either 0
+/// or 7. The shortcuts @hourly, @daily, @weekly, @monthly and @yearly can be
+/// used instead
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct Schedule {
+    expression: String,
+    // One bit for each allowed value
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Like cron, when both the days of the month and of the week are
+    // restricted, a day matching either of them is enough
+    any_day: bool,
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(display(
+    "{schedule} is not a valid schedule, use <minute> <hour> <day of month> <month> <day of \
+     week>"
+))]
+pub struct ScheduleParseError {
+    schedule: String,
+}
+
+/// A minute in local time
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct CalendarTime {
+    pub year: i32,
+    /// From 1 to 12
+    pub month: u32,
+    /// From 1 to 31
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+}
+
+impl Schedule {
+    /// The first time after the given one when the schedule fires
+    /// None if it never does, e.g. on February 30th
+    pub fn next_run(
+        &self,
+        after: SystemTime,
+    ) -> Option<SystemTime> {
+        let mut time = CalendarTime::from_system_time(after);
+        loop {
+            time = self.next_match(time)?;
+            // When the clock goes back for daylight saving time, the same
+            // minute comes twice and the first one could have already passed
+            let next = time.to_system_time();
+            if next > after {
+                return Some(next);
+            }
+        }
+    }
+
+    /// The first minute after the given one matching the schedule
+    pub fn next_match(
+        &self,
+        after: CalendarTime,
+    ) -> Option<CalendarTime> {
+        // February 29th can be 8 years away, any later match never comes
+        let last_year = after.year + 8;
+        let mut time = after.next_minute();
+        while time.year <= last_year {
+            time = if !has(self.months, time.month) {
+                time.next_month()
+            } else if !self.matches_day(&time) {
+                time.next_day()
+            } else if !has(self.hours, time.hour) {
+                time.next_hour()
+            } else if !has(self.minutes, time.minute) {
+                time.next_minute()
+            } else {
+                return Some(time);
+            };
+        }
+        None
+    }
+
+    fn matches_day(
+        &self,
+        time: &CalendarTime,
+    ) -> bool {
+        let day = has(self.days, time.day);
+        let weekday = has(self.weekdays, time.weekday());
+        if self.any_day {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+}
+
+fn has(
+    values: u64,
+    value: u32,
+) -> bool {
+    values & (1 << value) != 0
+}
+
+/// Parse a single field of the schedule into the bits of the allowed values
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+) -> Option<u64> {
+    let mut values = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u32>().ok()?)),
+            None => (part, None),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (first.parse().ok()?, last.parse().ok()?),
+            // 5/10 means from 5 to the end, every 10
+            None if step.is_some() => (range.parse().ok()?, max),
+            None => {
+                let value = range.parse().ok()?;
+                (value, value)
+            }
+        };
+        let step = step.unwrap_or(1);
+        if first < min || last > max || first > last || step == 0 {
+            return None;
+        }
+        for value in (first..=last).step_by(step as usize) {
+            values |= 1 << value;
+        }
+    }
+    Some(values)
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" => "0 0 1 1 *",
+            expression => expression,
+        };
+        let schedule = (|| {
+            let fields: Vec<&str> = expression.split_whitespace().collect();
+            let [minutes, hours, days, months, weekdays] = fields[..] else {
+                return None;
+            };
+            let mut weekdays_values = parse_field(weekdays, 0, 7)?;
+            // 7 is Sunday too
+            if has(weekdays_values, 7) {
+                weekdays_values |= 1;
+            }
+            Some(Schedule {
+                expression: s.trim().to_string(),
+                minutes: parse_field(minutes, 0, 59)?,
+                hours: parse_field(hours, 0, 23)?,
+                days: parse_field(days, 1, 31)?,
+                months: parse_field(months, 1, 12)?,
+                weekdays: weekdays_values,
+                any_day: !days.starts_with('*') && !weekdays.starts_with('*'),
+            })
+        })();
+        schedule.ok_or_else(|| {
+            ScheduleParseError {
+                schedule: s.to_string(),
+            }
+        })
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = ScheduleParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Schedule::from_str(&s)
+    }
+}
+
+impl From<Schedule> for String {
+    fn from(schedule: Schedule) -> Self {
+        schedule.expression
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl CalendarTime {
+    /// The minute of time in the local timezone
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs()) as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe { libc::localtime_r(&secs, &mut tm) };
+        Self {
+            year: tm.tm_year + 1900,
+            month: tm.tm_mon as u32 + 1,
+            day: tm.tm_mday as u32,
+            hour: tm.tm_hour as u32,
+            minute: tm.tm_min as u32,
+        }
+    }
+
+    /// The start of this minute in the local timezone. A minute skipped when
+    /// the clock goes forward for daylight saving time is moved after it
+    pub fn to_system_time(self) -> SystemTime {
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        tm.tm_year = self.year - 1900;
+        tm.tm_mon = self.month as i32 - 1;
+        tm.tm_mday = self.day as i32;
+        tm.tm_hour = self.hour as i32;
+        tm.tm_min = self.minute as i32;
+        // Let mktime find out whether daylight saving time is in effect
+        tm.tm_isdst = -1;
+        let secs = unsafe { libc::mktime(&mut tm) };
+        UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)
+    }
+
+    /// Day of the week, 0 is Sunday
+    pub fn weekday(&self) -> u32 {
+        // Days since 1970-01-01 (a Thursday), from Howard Hinnant's
+        // days_from_civil
+        let year = if self.month <= 2 {
+            self.year - 1
+        } else {
+            self.year
+        } as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5
+            + self.day as i64
+            - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+        (days + 4).rem_euclid(7) as u32
+    }
+
+    fn days_in_month(&self) -> u32 {
+        match self.month {
+            2 if self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    fn next_minute(self) -> Self {
+        if self.minute == 59 {
+            self.next_hour()
+        } else {
+            Self {
+                minute: self.minute + 1,
+                ..self
+            }
+        }
+    }
+
+    fn next_hour(self) -> Self {
+        if self.hour == 23 {
+            self.next_day()
+        } else {
+            Self {
+                hour: self.hour + 1,
+                minute: 0,
+                ..self
+            }
+        }
+    }
+
+    fn next_day(self) -> Self {
+        if self.day >= self.days_in_month() {
+            self.next_month()
+        } else {
+            Self {
+                day: self.day + 1,
+                hour: 0,
+                minute: 0,
+                ..self
+            }
+        }
+    }
+
+    fn next_month(self) -> Self {
+        let (year, month) = if self.month == 12 {
+            (self.year + 1, 1)
+        } else {
+            (self.year, self.month + 1)
+        };
+        Self {
+            year,
+            month,
+            day: 1,
+            hour: 0,
+            minute: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn time(
+        year: i32,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+    ) -> CalendarTime {
+        CalendarTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+        }
+    }
+
+    fn next(
+        schedule: &str,
+        after: CalendarTime,
+    ) -> Option<CalendarTime> {
+        Schedule::from_str(schedule).unwrap().next_match(after)
+    }
+
+    #[test]
+    fn parse_schedule() {
+        let schedule = Schedule::from_str("*/15 8-18 * 1,6-7 1-5").unwrap();
+        assert_eq!(
+            schedule.minutes,
+            1 << 0 | 1 << 15 | 1 << 30 | 1 << 45
+        );
+        assert_eq!(schedule.hours, 0b1111111111100000000);
+        assert_eq!(schedule.months, 1 << 1 | 1 << 6 | 1 << 7);
+        assert!(!schedule.any_day);
+        assert!(Schedule::from_str("0 0 1 * 7").unwrap().any_day);
+        assert!(has(Schedule::from_str("0 0 * * 7").unwrap().weekdays, 0));
+        assert_eq!(
+            Schedule::from_str("@daily").unwrap().hours,
+            Schedule::from_str("0 0 * * *").unwrap().hours
+        );
+
+        for invalid in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+            "@often",
+        ] {
+            assert!(Schedule::from_str(invalid).is_err(), "{invalid} is valid");
+        }
+
+        // The expression is kept as written
+        let schedule = Schedule::try_from(" @weekly".to_string()).unwrap();
+        assert_eq!(String::from(schedule), "@weekly");
+    }
+
+    #[test]
+    fn compute_next_match() {
+        // 2023-05-10 was a Wednesday
+        assert_eq!(time(2023, 5, 10, 0, 0).weekday(), 3);
+        assert_eq!(time(2000, 2, 29, 0, 0).weekday(), 2);
+        assert_eq!(time(1970, 1, 1, 0, 0).weekday(), 4);
+
+        let after = time(2023, 5, 10, 12, 30);
+        // Never the given minute itself
+        assert_eq!(next("* * * * *", after), Some(time(2023, 5, 10, 12, 31)));
+        assert_eq!(next("*/15 * * * *", after), Some(time(2023, 5, 10, 12, 45)));
+        assert_eq!(next("30 12 * * *", after), Some(time(2023, 5, 11, 12, 30)));
+        assert_eq!(next("@hourly", after), Some(time(2023, 5, 10, 13, 0)));
+        assert_eq!(next("@monthly", after), Some(time(2023, 6, 1, 0, 0)));
+        assert_eq!(next("@yearly", after), Some(time(2024, 1, 1, 0, 0)));
+        // Next Sunday
+        assert_eq!(next("0 9 * * 7", after), Some(time(2023, 5, 14, 9, 0)));
+        // Weekdays only, from Friday evening to Monday
+        assert_eq!(
+            next("0 8 * * 1-5", time(2023, 5, 12, 20, 0)),
+            Some(time(2023, 5, 15, 8, 0))
+        );
+        // Either the 1st or Monday
+        assert_eq!(next("0 0 1 * 1", after), Some(time(2023, 5, 15, 0, 0)));
+        assert_eq!(
+            next("0 0 1 * 1", time(2023, 5, 29, 12, 0)),
+            Some(time(2023, 6, 1, 0, 0))
+        );
+        // Rolling over the end of the year and skipping the short months
+        assert_eq!(
+            next("59 23 31 * *", time(2023, 12, 31, 23, 59)),
+            Some(time(2024, 1, 31, 23, 59))
+        );
+        assert_eq!(
+            next("0 0 31 * *", time(2023, 1, 31, 0, 0)),
+            Some(time(2023, 3, 31, 0, 0))
+        );
+        // Leap days
+        assert_eq!(next("0 0 29 2 *", after), Some(time(2024, 2, 29, 0, 0)));
+        assert_eq!(
+            next("0 0 29 2 *", time(2096, 3, 1, 0, 0)),
+            Some(time(2104, 2, 29, 0, 0))
+        );
+        assert_eq!(next("0 0 30 2 *", after), None);
+    }
+
+    #[test]
+    fn next_run_in_local_time() {
+        let schedule = Schedule::from_str("*/5 * * * *").unwrap();
+        let now = SystemTime::now();
+        let next_run = schedule.next_run(now).unwrap();
+        assert!(next_run > now);
+        assert!(next_run <= now + Duration::from_secs(5 * 60));
+        let next = CalendarTime::from_system_time(next_run);
+        assert_eq!(next.minute % 5, 0);
+        assert_eq!(next.to_system_time(), next_run);
+        assert!(Schedule::from_str("0 0 30 2 *").unwrap().next_run(now).is_none());
+    }
+}
@@ -1,4 +1,8 @@
-use std::convert::TryFrom;
+use std::{
+    convert::TryFrom,
+    ops::RangeInclusive,
+    path::PathBuf,
+};
 
 use serde::{
     Deserialize,
@@ -7,11 +11,23 @@ use serde::{
 use serde_with::skip_serializing_none;
 use snafu::Snafu;
 
+use super::{
+    deserialize_milliseconds,
+    BindMount,
+    KillMode,
+    ListenSocket,
+    ScriptInput,
+    ScriptOutput,
+    SyscallFilter,
+};
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub enum ScriptPrefix {
     Bash,
     Path,
     Sh,
+    /// Run by execlineb, to reuse the scripts of s6 services
+    Execline,
 }
 
 #[derive(Snafu, Debug)]
@@ -27,6 +43,7 @@ impl TryFrom<String> for ScriptPrefix {
             "bash" => ScriptPrefix::Bash,
             "path" => ScriptPrefix::Path,
             "sh" => ScriptPrefix::Sh,
+            "execline" => ScriptPrefix::Execline,
             _ => {
                 InvalidScriptPrefixSnafu {
                     prefix: value.to_owned(),
@@ -47,7 +64,8 @@ pub struct Script {
     pub execute: String,
     #[serde(
         default = "Script::default_timeout",
-        skip_serializing_if = "Script::is_default_timeout"
+        skip_serializing_if = "Script::is_default_timeout",
+        deserialize_with = "deserialize_milliseconds"
     )]
     /// How long will the supervisor wait to consider this service up?
     /// Short lived scripts have to exit within timeout milliseconds
@@ -55,7 +73,8 @@ pub struct Script {
     pub timeout: u32,
     #[serde(
         default = "Script::default_timeout_kill",
-        skip_serializing_if = "Script::is_default_timeout_kill"
+        skip_serializing_if = "Script::is_default_timeout_kill",
+        deserialize_with = "deserialize_milliseconds"
     )]
     /// The time to wait until the script has exited after down_signal has been
     /// sent, in milliseconds
@@ -66,25 +85,119 @@ pub struct Script {
     )]
     /// How many times can this script dies before it is considered "down"
     pub max_deaths: u8,
+    /// Only used for long lived scripts. How many times the process can be
+    /// restarted within restart_window before the service is considered
+    /// failed, it is always restarted if not set
+    pub restart_limit: Option<u32>,
+    #[serde(
+        default = "Script::default_restart_window",
+        skip_serializing_if = "Script::is_default_restart_window",
+        deserialize_with = "deserialize_milliseconds"
+    )]
+    /// The window for restart_limit, in milliseconds
+    pub restart_window: u32,
+    #[serde(
+        default = "Script::default_restart_delay",
+        skip_serializing_if = "Script::is_default_restart_delay",
+        deserialize_with = "deserialize_milliseconds"
+    )]
+    /// Only used for long lived scripts. Milliseconds to wait after the
+    /// process died before restarting it, e.g. for its port to be released
+    pub restart_delay: u32,
     #[serde(
         default = "Script::default_down_signal",
         skip_serializing_if = "Script::is_default_down_signal"
     )]
     /// The signal to send when we want to stop/close a script/process
     pub down_signal: i32,
+    #[serde(default, skip_serializing_if = "KillMode::is_process_group")]
+    /// The processes that receive down_signal
+    pub kill_mode: KillMode,
     pub user: Option<String>,
     pub group: Option<String>,
     pub notify: Option<u8>,
+    #[serde(default)]
+    /// Wait for READY=1 on the socket in NOTIFY_SOCKET (sd_notify protocol)
+    /// instead of waiting for timeout
+    pub notify_socket: bool,
+    #[serde(
+        default = "Script::default_reload_signal",
+        skip_serializing_if = "Script::is_default_reload_signal"
+    )]
+    /// Only used for long lived scripts. The signal sent by rctl reload to
+    /// make the process reload its configuration
+    pub reload_signal: i32,
+    #[serde(default)]
+    /// Only used for long lived scripts with notify_socket. After the reload
+    /// signal, wait for RELOADING=1 and then READY=1 before the reload is done
+    pub notify_reload: bool,
+    #[serde(default)]
+    /// Only used for stop scripts. When the script fails, consider the service
+    /// still up instead of logging the failure and ignoring it
+    pub must_succeed: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Linux capabilities (e.g. CAP_NET_BIND_SERVICE) that the script keeps
+    /// after dropping to user
+    pub capabilities: Vec<String>,
+    #[serde(
+        default = "Script::default_log_buffer",
+        skip_serializing_if = "Script::is_default_log_buffer"
+    )]
+    /// How many lines of output can wait to be logged. When the logger falls
+    /// behind, the oldest lines are dropped
+    pub log_buffer: usize,
+    /// Lines of output longer than this many bytes are truncated, with a
+    /// marker telling how many bytes were dropped. Without it, long lines are
+    /// split instead
+    pub max_line_length: Option<usize>,
+    /// Value written to /proc/self/oom_score_adj, from -1000 (never killed by
+    /// the OOM killer) to 1000 (killed first)
+    pub oom_score_adj: Option<i16>,
+    #[serde(default, skip_serializing_if = "ScriptInput::is_null")]
+    pub stdin: ScriptInput,
+    #[serde(default, skip_serializing_if = "ScriptOutput::is_log")]
+    pub stdout: ScriptOutput,
+    #[serde(default, skip_serializing_if = "ScriptOutput::is_log")]
+    pub stderr: ScriptOutput,
+    /// Seccomp filter installed right before executing the script
+    pub syscall_filter: Option<Box<SyscallFilter>>,
+    /// Only used for long lived scripts. Socket bound by the supervisor, the
+    /// process is spawned on the first connection and receives it as fd 3
+    pub listen: Option<ListenSocket>,
+    #[serde(
+        default = "Script::default_success_exit_codes",
+        skip_serializing_if = "Script::is_default_success_exit_codes"
+    )]
+    /// Only used for short lived scripts. The exit codes meaning that the
+    /// script succeeded, e.g. 1 for "nothing to do"
+    pub success_exit_codes: Vec<i32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Paths mounted in a mount namespace of the script, on top of the
+    /// read_only_paths
+    pub bind_mounts: Vec<BindMount>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Paths made read-only in a mount namespace of the script, except the
+    /// bind_mounts below them
+    pub read_only_paths: Vec<PathBuf>,
+    #[serde(default)]
+    /// Mount an empty tmpfs on /tmp and /var/tmp in a mount namespace of the
+    /// script, removed with it
+    pub private_tmp: bool,
 }
 
 impl Script {
     pub const DEFAULT_TIMEOUT: u32 = 3000;
     pub const DEFAULT_TIMEOUT_KILL: u32 = 3000;
     pub const DEFAULT_MAX_DEATHS: u8 = 3;
+    pub const DEFAULT_RESTART_WINDOW: u32 = 10000;
+    pub const DEFAULT_RESTART_DELAY: u32 = 100;
     // SIGHUP is the only signal that is handled by shells and that is forwarded to
     // children as well. Sending SIGTERM would only kill the shell and leave the
     // children runnning
     pub const DEFAULT_DOWN_SIGNAL: i32 = libc::SIGHUP;
+    pub const DEFAULT_RELOAD_SIGNAL: i32 = libc::SIGHUP;
+    pub const DEFAULT_LOG_BUFFER: usize = 1024;
+    pub const OOM_SCORE_ADJ_RANGE: RangeInclusive<i16> = -1000..=1000;
 
     const fn default_timeout() -> u32 {
         Self::DEFAULT_TIMEOUT
@@ -110,6 +223,22 @@ impl Script {
         *max_deaths == Self::DEFAULT_MAX_DEATHS
     }
 
+    const fn default_restart_window() -> u32 {
+        Self::DEFAULT_RESTART_WINDOW
+    }
+
+    fn is_default_restart_window(restart_window: &u32) -> bool {
+        *restart_window == Self::DEFAULT_RESTART_WINDOW
+    }
+
+    const fn default_restart_delay() -> u32 {
+        Self::DEFAULT_RESTART_DELAY
+    }
+
+    fn is_default_restart_delay(restart_delay: &u32) -> bool {
+        *restart_delay == Self::DEFAULT_RESTART_DELAY
+    }
+
     const fn default_down_signal() -> i32 {
         Self::DEFAULT_DOWN_SIGNAL
     }
@@ -118,6 +247,30 @@ impl Script {
         *signal == Self::DEFAULT_DOWN_SIGNAL
     }
 
+    const fn default_reload_signal() -> i32 {
+        Self::DEFAULT_RELOAD_SIGNAL
+    }
+
+    const fn is_default_reload_signal(signal: &i32) -> bool {
+        *signal == Self::DEFAULT_RELOAD_SIGNAL
+    }
+
+    const fn default_log_buffer() -> usize {
+        Self::DEFAULT_LOG_BUFFER
+    }
+
+    fn is_default_log_buffer(log_buffer: &usize) -> bool {
+        *log_buffer == Self::DEFAULT_LOG_BUFFER
+    }
+
+    fn default_success_exit_codes() -> Vec<i32> {
+        vec![0]
+    }
+
+    fn is_default_success_exit_codes(success_exit_codes: &Vec<i32>) -> bool {
+        *success_exit_codes == Self::default_success_exit_codes()
+    }
+
     // This function always set the default values instead of leaving None
     // Use it everywhere the script will be read and executed
     pub fn new(
@@ -130,13 +283,43 @@ impl Script {
             timeout: Self::default_timeout(),
             timeout_kill: Self::default_timeout_kill(),
             max_deaths: Self::default_max_deaths(),
+            restart_limit: None,
+            restart_window: Self::default_restart_window(),
+            restart_delay: Self::default_restart_delay(),
             down_signal: Self::default_down_signal(),
+            kill_mode: KillMode::default(),
             user: None,
             group: None,
             notify: None,
+            notify_socket: false,
+            reload_signal: Self::default_reload_signal(),
+            notify_reload: false,
+            must_succeed: false,
+            capabilities: Vec::new(),
+            log_buffer: Self::default_log_buffer(),
+            max_line_length: None,
+            oom_score_adj: None,
+            stdin: ScriptInput::Null,
+            stdout: ScriptOutput::Log,
+            stderr: ScriptOutput::Log,
+            syscall_filter: None,
+            listen: None,
+            success_exit_codes: Self::default_success_exit_codes(),
+            bind_mounts: Vec::new(),
+            read_only_paths: Vec::new(),
+            private_tmp: false,
         }
     }
 
+    /// Return true if the script exited with one of success_exit_codes. A
+    /// script killed by a signal has no exit code and always failed
+    pub fn exited_successfully(
+        &self,
+        exit_code: Option<i32>,
+    ) -> bool {
+        exit_code.is_some_and(|code| self.success_exit_codes.contains(&code))
+    }
+
     /// get the maximum time that this service might take before being
     /// considered "up"
     pub fn get_maximum_time(&self) -> u32 {
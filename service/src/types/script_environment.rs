@@ -7,12 +7,20 @@ use serde::{
 pub struct ScriptEnvironment {
     #[serde(default)]
     pub contents: Vec<(String, String)>,
+    /// Start from a minimal environment instead of the one of rsvc
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub clean: bool,
+    /// Replaces the PATH of rsvc, e.g. for a self-contained install
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
 }
 
 impl ScriptEnvironment {
     pub fn new() -> ScriptEnvironment {
         ScriptEnvironment {
             contents: Vec::new(),
+            clean: false,
+            path: None,
         }
     }
 
@@ -0,0 +1,55 @@
+use std::{
+    os::fd::RawFd,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use snafu::Snafu;
+
+/// Where the stdin of a script comes from
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub enum ScriptInput {
+    /// Nothing, reading returns end of file right away
+    #[default]
+    Null,
+    /// The contents of the file
+    File(PathBuf),
+    /// A file descriptor inherited by rsvc, e.g. a listening socket
+    Fd(RawFd),
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(display("{input} is not a valid input, use null, file:<path> or fd:<number>"))]
+pub struct ScriptInputParseError {
+    input: String,
+}
+
+impl ScriptInput {
+    pub fn is_null(&self) -> bool {
+        matches!(self, ScriptInput::Null)
+    }
+}
+
+impl FromStr for ScriptInput {
+    type Err = ScriptInputParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "null" {
+            Ok(ScriptInput::Null)
+        } else if let Some(path) = s.strip_prefix("file:").filter(|path| !path.is_empty()) {
+            Ok(ScriptInput::File(PathBuf::from(path)))
+        } else if let Some(fd) = s.strip_prefix("fd:").and_then(|fd| fd.parse::<RawFd>().ok())
+        {
+            Ok(ScriptInput::Fd(fd))
+        } else {
+            ScriptInputParseSnafu {
+                input: s.to_string(),
+            }
+            .fail()
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::{
+    path::PathBuf,
+    str::FromStr,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use snafu::Snafu;
+
+/// Where the stdout or the stderr of a script goes
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub enum ScriptOutput {
+    /// Written line by line in the log file of the service
+    #[default]
+    Log,
+    /// Discarded
+    Null,
+    /// Appended to the file
+    File(PathBuf),
+    /// Piped into the stdin of the logger service with this name
+    Service(String),
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(display(
+    "{output} is not a valid output, use log, null, file:<path> or service:<name>"
+))]
+pub struct ScriptOutputParseError {
+    output: String,
+}
+
+impl ScriptOutput {
+    pub fn is_log(&self) -> bool {
+        matches!(self, ScriptOutput::Log)
+    }
+}
+
+impl FromStr for ScriptOutput {
+    type Err = ScriptOutputParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(ScriptOutput::Log),
+            "null" => Ok(ScriptOutput::Null),
+            _ => {
+                if let Some(path) = s.strip_prefix("file:").filter(|path| !path.is_empty()) {
+                    Ok(ScriptOutput::File(PathBuf::from(path)))
+                } else if let Some(service) =
+                    s.strip_prefix("service:").filter(|service| !service.is_empty())
+                {
+                    Ok(ScriptOutput::Service(service.to_string()))
+                } else {
+                    ScriptOutputParseSnafu {
+                        output: s.to_string(),
+                    }
+                    .fail()
+                }
+            }
+        }
+    }
+}
@@ -5,7 +5,7 @@ use serde::{
 
 use super::*;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub enum Service {
     Bundle(Bundle),
     Longrun(Longrun),
@@ -23,13 +23,100 @@ impl Service {
         }
     }
 
+    /// Lowercase name of the type of service, e.g. "longrun"
+    pub fn type_name(&self) -> &'static str {
+        match &self {
+            Self::Bundle(_) => "bundle",
+            Self::Longrun(_) => "longrun",
+            Self::Oneshot(_) => "oneshot",
+            Self::Virtual(_) => "virtual",
+        }
+    }
+
     pub fn dependencies(&self) -> &[String] {
         match &self {
             Self::Bundle(bundle) => &bundle.options.contents,
             Self::Longrun(longrun) => &longrun.options.dependencies,
             Self::Oneshot(oneshot) => &oneshot.options.dependencies,
-            // TODO: What should be done here?
-            Self::Virtual(_virtual_service) => &[],
+            Self::Virtual(virtual_service) => &virtual_service.options.dependencies,
+        }
+    }
+
+    pub fn wants(&self) -> &[String] {
+        match &self {
+            Self::Bundle(_) => &[],
+            Self::Longrun(longrun) => &longrun.options.wants,
+            Self::Oneshot(oneshot) => &oneshot.options.wants,
+            Self::Virtual(virtual_service) => &virtual_service.options.wants,
+        }
+    }
+
+    pub fn provides(&self) -> &[String] {
+        match &self {
+            Self::Bundle(_) => &[],
+            Self::Longrun(longrun) => &longrun.options.provides,
+            Self::Oneshot(oneshot) => &oneshot.options.provides,
+            Self::Virtual(virtual_service) => &virtual_service.options.provides,
+        }
+    }
+
+    pub fn conflicts(&self) -> &[String] {
+        match &self {
+            Self::Bundle(_) => &[],
+            Self::Longrun(longrun) => &longrun.options.conflicts,
+            Self::Oneshot(oneshot) => &oneshot.options.conflicts,
+            Self::Virtual(virtual_service) => &virtual_service.options.conflicts,
+        }
+    }
+
+    pub fn before(&self) -> &[String] {
+        match &self {
+            Self::Bundle(_) => &[],
+            Self::Longrun(longrun) => &longrun.options.before,
+            Self::Oneshot(oneshot) => &oneshot.options.before,
+            Self::Virtual(virtual_service) => &virtual_service.options.before,
+        }
+    }
+
+    /// Every script that can be run for the service
+    pub fn scripts(&self) -> Vec<&Script> {
+        match &self {
+            Self::Bundle(_) => Vec::new(),
+            Self::Longrun(longrun) => {
+                [
+                    Some(&longrun.run),
+                    longrun.finish.as_ref(),
+                    longrun.condition.as_ref(),
+                    longrun.pre_start.as_ref(),
+                    longrun.post_stop.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .collect()
+            }
+            Self::Oneshot(oneshot) => {
+                [
+                    Some(&oneshot.start),
+                    oneshot.stop.as_ref(),
+                    oneshot.up_check.as_deref(),
+                    oneshot.condition.as_ref(),
+                    oneshot.pre_start.as_ref(),
+                    oneshot.post_stop.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .collect()
+            }
+            Self::Virtual(virtual_service) => virtual_service.condition.iter().collect(),
+        }
+    }
+
+    pub fn down_timeout(&self) -> Option<u32> {
+        match &self {
+            Self::Bundle(_) => None,
+            Self::Longrun(longrun) => longrun.options.down_timeout,
+            Self::Oneshot(oneshot) => oneshot.options.down_timeout,
+            Self::Virtual(virtual_service) => virtual_service.options.down_timeout,
         }
     }
 
@@ -38,7 +125,58 @@ impl Service {
             Service::Bundle(_) => false,
             Service::Longrun(longrun) => longrun.options.autostart,
             Service::Oneshot(oneshot) => oneshot.options.autostart,
-            Service::Virtual(_) => false,
+            Service::Virtual(virtual_service) => virtual_service.options.autostart,
+        }
+    }
+
+    /// Split the name of an instanced service (e.g. getty@tty1) into the name
+    /// of its template (getty@) and the instance argument (tty1)
+    pub fn split_instance(name: &str) -> Option<(&str, &str)> {
+        name.find('@')
+            .map(|index| name.split_at(index + 1))
+            .filter(|(_, instance)| !instance.is_empty())
+    }
+
+    pub fn is_template(&self) -> bool {
+        self.name().ends_with('@')
+    }
+
+    /// Turn a template into the service for the instance argument, which is
+    /// available to its scripts in the INSTANCE environment variable
+    pub fn instantiate(
+        &mut self,
+        instance: &str,
+    ) {
+        match self {
+            Self::Bundle(bundle) => bundle.name.push_str(instance),
+            Self::Longrun(longrun) => {
+                longrun.name.push_str(instance);
+                longrun.environment.add("INSTANCE", instance.to_string());
+            }
+            Self::Oneshot(oneshot) => {
+                oneshot.name.push_str(instance);
+                oneshot.environment.add("INSTANCE", instance.to_string());
+            }
+            Self::Virtual(virtual_service) => virtual_service.name.push_str(instance),
+        }
+    }
+
+    /// Priority of the service among the ones starting at the same time,
+    /// bundles don't have one
+    pub fn priority(&self) -> i32 {
+        match &self {
+            Service::Bundle(_) => 0,
+            Service::Longrun(longrun) => longrun.options.priority,
+            Service::Oneshot(oneshot) => oneshot.options.priority,
+            Service::Virtual(virtual_service) => virtual_service.options.priority,
+        }
+    }
+
+    /// Schedule of a oneshot run periodically by rsvc
+    pub fn schedule(&self) -> Option<&Schedule> {
+        match &self {
+            Service::Oneshot(oneshot) => oneshot.options.schedule.as_ref(),
+            _ => None,
         }
     }
 
@@ -47,7 +185,7 @@ impl Service {
             Service::Bundle(bundle) => bundle.options.runlevel,
             Service::Longrun(longrun) => longrun.options.runlevel,
             Service::Oneshot(oneshot) => oneshot.options.runlevel,
-            Service::Virtual(_) => unimplemented!(),
+            Service::Virtual(virtual_service) => virtual_service.options.runlevel,
         }
     }
 }
@@ -1,9 +1,17 @@
+use std::path::PathBuf;
+
 use serde::{
     Deserialize,
     Serialize,
 };
 
-use super::RunLevel;
+use super::{
+    deserialize_milliseconds,
+    deserialize_optional_milliseconds,
+    Credential,
+    RunLevel,
+    Schedule,
+};
 
 /// Store options for Longrun and Oneshot
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -14,6 +22,23 @@ pub struct ServiceOptions {
     pub requires: Vec<String>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub requires_one: Vec<String>,
+    /// Services started together with this one, which starts anyway when they
+    /// fail or are not enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wants: Vec<String>,
+    /// Aliases that dependencies can use to refer to this service, e.g. a cron
+    /// implementation providing "cron"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provides: Vec<String>,
+    /// Services that must not run at the same time as this one. It goes both
+    /// ways, a service listed here conflicts with this one too
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<String>,
+    /// Sync points, like sysinit or basic, that are up only after this service
+    /// is. The services depending on a sync point start once every service
+    /// anchored before it is up
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
     #[serde(
         default = "ServiceOptions::default_autostart",
         skip_serializing_if = "ServiceOptions::is_default_autostart"
@@ -21,6 +46,43 @@ pub struct ServiceOptions {
     pub autostart: bool,
     #[serde(default, skip_serializing_if = "RunLevel::is_default")]
     pub runlevel: RunLevel,
+    /// Paths that must exist before the service is started, like device nodes
+    /// or mountpoints
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub needs_path: Vec<PathBuf>,
+    /// Milliseconds to wait for needs_path to appear
+    #[serde(
+        default = "ServiceOptions::default_needs_path_timeout",
+        skip_serializing_if = "ServiceOptions::is_default_needs_path_timeout",
+        deserialize_with = "deserialize_milliseconds"
+    )]
+    pub needs_path_timeout: u32,
+    /// Milliseconds after which a service that is still stopping is considered
+    /// down anyway. The stop is not bounded when unset
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_milliseconds"
+    )]
+    pub down_timeout: Option<u32>,
+    /// Files holding secrets, read every time a script of the service is run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub credentials: Vec<Credential>,
+    /// Services with a higher priority are started first among the ones that
+    /// could start at the same time
+    #[serde(default, skip_serializing_if = "ServiceOptions::is_default_priority")]
+    pub priority: i32,
+    /// Run the oneshot every time the schedule fires instead of once when it
+    /// is started. The other services ignore it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+    /// Only pass PATH, HOME, USER and TERM from the environment of rsvc to
+    /// the scripts, along with the variables of the service
+    #[serde(default)]
+    pub clean_environment: bool,
+    /// PATH of the scripts, instead of the one of rsvc
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
 }
 
 impl ServiceOptions {
@@ -29,11 +91,25 @@ impl ServiceOptions {
             dependencies: Vec::new(),
             requires: Vec::new(),
             requires_one: Vec::new(),
+            wants: Vec::new(),
+            provides: Vec::new(),
+            conflicts: Vec::new(),
+            before: Vec::new(),
             autostart: Self::default_autostart(),
             runlevel: RunLevel::Default,
+            needs_path: Vec::new(),
+            needs_path_timeout: Self::DEFAULT_NEEDS_PATH_TIMEOUT,
+            down_timeout: None,
+            credentials: Vec::new(),
+            priority: 0,
+            schedule: None,
+            clean_environment: false,
+            path: None,
         }
     }
 
+    pub const DEFAULT_NEEDS_PATH_TIMEOUT: u32 = 10000;
+
     const fn default_autostart() -> bool {
         true
     }
@@ -41,6 +117,18 @@ impl ServiceOptions {
     fn is_default_autostart(autostart: &bool) -> bool {
         *autostart
     }
+
+    const fn default_needs_path_timeout() -> u32 {
+        Self::DEFAULT_NEEDS_PATH_TIMEOUT
+    }
+
+    fn is_default_needs_path_timeout(needs_path_timeout: &u32) -> bool {
+        *needs_path_timeout == Self::DEFAULT_NEEDS_PATH_TIMEOUT
+    }
+
+    fn is_default_priority(priority: &i32) -> bool {
+        *priority == 0
+    }
 }
 impl Default for ServiceOptions {
     fn default() -> Self {
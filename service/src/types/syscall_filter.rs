@@ -0,0 +1,204 @@
+use std::env::consts::ARCH;
+
+use seccompiler::{
+    sock_filter,
+    BpfProgram,
+    TargetArch,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::json;
+use snafu::{
+    ResultExt,
+    Snafu,
+};
+
+/// Syscalls that a script is allowed to use or not, enforced by a seccomp
+/// filter. Names starting with @ refer to the groups in SYSCALL_GROUPS
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum SyscallFilter {
+    /// Every other syscall fails with EPERM
+    Allow(Vec<String>),
+    /// These syscalls fail with EPERM
+    Deny(Vec<String>),
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(display("invalid syscall filter: {source}"))]
+pub struct SyscallFilterError {
+    source: seccompiler::Error,
+}
+
+/// Groups of syscalls used for the same purpose, to avoid listing all of them
+pub const SYSCALL_GROUPS: &[(&str, &[&str])] = &[
+    ("@clock", &["adjtimex", "clock_adjtime", "clock_settime", "settimeofday"]),
+    (
+        "@debug",
+        &[
+            "kcmp",
+            "perf_event_open",
+            "process_vm_readv",
+            "process_vm_writev",
+            "ptrace",
+        ],
+    ),
+    ("@keyring", &["add_key", "keyctl", "request_key"]),
+    ("@module", &["delete_module", "finit_module", "init_module"]),
+    (
+        "@mount",
+        &[
+            "fsconfig",
+            "fsmount",
+            "fsopen",
+            "fspick",
+            "mount",
+            "mount_setattr",
+            "move_mount",
+            "open_tree",
+            "pivot_root",
+            "umount2",
+        ],
+    ),
+    ("@reboot", &["kexec_file_load", "kexec_load", "reboot"]),
+    ("@swap", &["swapoff", "swapon"]),
+];
+
+/// Syscalls of the groups missing from the table of seccompiler. They are
+/// denied by number, an allow list can't allow them
+const UNKNOWN_TO_SECCOMPILER: &[(&str, libc::c_long)] =
+    &[("mount_setattr", libc::SYS_mount_setattr)];
+
+impl SyscallFilter {
+    /// Syscalls needed to execute the script once the filter is installed. The
+    /// child reports the failure of execve on a pipe before exiting
+    const SPAWN_SYSCALLS: [&'static str; 3] = ["execve", "exit_group", "write"];
+
+    pub fn syscalls(&self) -> &[String] {
+        match self {
+            SyscallFilter::Allow(syscalls) | SyscallFilter::Deny(syscalls) => syscalls,
+        }
+    }
+
+    /// Names of the syscalls in the filter, with the groups expanded
+    fn names(&self) -> Vec<&str> {
+        self.syscalls()
+            .iter()
+            .flat_map(|syscall| {
+                match SYSCALL_GROUPS.iter().find(|(group, _)| group == syscall) {
+                    Some((_, group)) => group.to_vec(),
+                    None => vec![syscall.as_str()],
+                }
+            })
+            .collect()
+    }
+
+    /// Compile the seccomp-bpf program of the filter for the architecture of
+    /// rsvc. The syscalls needed to execute the script are always allowed
+    pub fn compile(&self) -> Result<BpfProgram, SyscallFilterError> {
+        let arch = TargetArch::try_from(ARCH)
+            .map_err(seccompiler::Error::Backend)
+            .context(SyscallFilterSnafu)?;
+        let deny = json!({ "errno": libc::EPERM });
+        let (mut names, unknown): (Vec<&str>, Vec<&str>) =
+            self.names().into_iter().partition(|name| {
+                !UNKNOWN_TO_SECCOMPILER
+                    .iter()
+                    .any(|(unknown, _)| unknown == name)
+            });
+        let (match_action, mismatch_action, denied_numbers) = match self {
+            SyscallFilter::Allow(_) => {
+                names.extend(Self::SPAWN_SYSCALLS);
+                (json!("allow"), deny, Vec::new())
+            }
+            SyscallFilter::Deny(_) => {
+                names.retain(|name| !Self::SPAWN_SYSCALLS.contains(name));
+                let numbers = UNKNOWN_TO_SECCOMPILER
+                    .iter()
+                    .filter(|(name, _)| unknown.contains(name))
+                    .map(|(_, number)| *number)
+                    .collect();
+                (deny, json!("allow"), numbers)
+            }
+        };
+        let filter = json!({
+            "script": {
+                "match_action": match_action,
+                "mismatch_action": mismatch_action,
+                "filter": names
+                    .iter()
+                    .map(|name| json!({ "syscall": name }))
+                    .collect::<Vec<_>>(),
+            }
+        });
+        let program = seccompiler::compile_from_json(filter.to_string().as_bytes(), arch)
+            .context(SyscallFilterSnafu)?
+            .remove("script")
+            .unwrap();
+        Ok([deny_prefix(arch, &denied_numbers), program].concat())
+    }
+}
+
+/// Instructions run before the program of seccompiler, denying what it can't
+/// match: the syscalls in numbers, and the x32 syscalls of x86_64, which have
+/// bit 30 set, since it only checks the architecture
+fn deny_prefix(
+    arch: TargetArch,
+    numbers: &[libc::c_long],
+) -> Vec<sock_filter> {
+    const X32_SYSCALL_BIT: u32 = 0x40000000;
+    const RET_DENY: u32 = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+    let jump_if = |comparison: u32, value: u32| {
+        sock_filter {
+            code: (libc::BPF_JMP | comparison | libc::BPF_K) as u16,
+            jt: 0,
+            jf: 1,
+            k: value,
+        }
+    };
+    let ret_deny = sock_filter {
+        code: (libc::BPF_RET | libc::BPF_K) as u16,
+        jt: 0,
+        jf: 0,
+        k: RET_DENY,
+    };
+    // Load seccomp_data.nr
+    let mut prefix = vec![sock_filter {
+        code: (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16,
+        jt: 0,
+        jf: 0,
+        k: 0,
+    }];
+    if arch == TargetArch::x86_64 {
+        prefix.extend([jump_if(libc::BPF_JGE, X32_SYSCALL_BIT), ret_deny.clone()]);
+    }
+    for number in numbers {
+        prefix.extend([jump_if(libc::BPF_JEQ, *number as u32), ret_deny.clone()]);
+    }
+    prefix
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compile_syscall_filter() {
+        let filter = |syscalls: &[&str]| {
+            SyscallFilter::Deny(syscalls.iter().map(|syscall| syscall.to_string()).collect())
+                .compile()
+        };
+
+        let single = filter(&["reboot"]).unwrap();
+        // The groups are expanded
+        assert!(filter(&["@mount", "reboot"]).unwrap().len() > single.len());
+        assert!(filter(&["rebot"]).is_err());
+        assert!(filter(&["@nothing"]).is_err());
+        // Denying the syscalls needed to execute the script has no effect
+        assert_eq!(filter(&["reboot", "execve"]).unwrap(), single);
+        assert!(SyscallFilter::Allow(vec!["@mount".to_string()])
+            .compile()
+            .is_ok());
+    }
+}
@@ -2,9 +2,65 @@ use serde::{
     Deserialize,
     Serialize,
 };
+use serde_with::skip_serializing_none;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+use super::*;
+
+/// Target that other services can depend on. It has no process of its own and
+/// it is up as soon as its condition is met, e.g. the network is online
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Virtual {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub providers: Vec<String>,
+    /// Run until it succeeds, for up to its timeout. Without a condition the
+    /// target is up once its dependencies are
+    pub condition: Option<Script>,
+    #[serde(flatten)]
+    pub options: ServiceOptions,
+}
+
+/// Wait up to 90 seconds for the conditions of the well-known targets
+const WELL_KNOWN_TIMEOUT: u32 = 90000;
+
+/// Succeed if there is a default IPv4 route
+const NETWORK_ONLINE_CONDITION: &str =
+    "awk 'NR > 1 && $2 == \"00000000\" { found = 1 } END { exit !found }' /proc/net/route";
+
+/// Succeed if every local filesystem in /etc/fstab is mounted
+const LOCAL_FS_CONDITION: &str = r#"[ ! -e /etc/fstab ] || awk '
+    NR == FNR { mounted[$2] = 1; next }
+    $1 ~ /^#/ || NF < 3 || $2 == "none" || $3 == "swap" { next }
+    $3 ~ /^(nfs|nfs4|cifs|smb3|sshfs|fuse\.sshfs)$/ { next }
+    $4 ~ /(^|,)(noauto|_netdev)(,|$)/ { next }
+    !mounted[$2] { exit 1 }
+' /proc/mounts /etc/fstab"#;
+
+impl Virtual {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            providers: Vec::new(),
+            condition: None,
+            options: ServiceOptions::new(),
+        }
+    }
+
+    /// Targets available without a service file: network-online, local-fs
+    /// and the sysinit and basic sync points
+    pub fn well_known(name: &str) -> Option<Self> {
+        let condition = match name {
+            "network-online" => NETWORK_ONLINE_CONDITION,
+            "local-fs" => LOCAL_FS_CONDITION,
+            "sysinit" | "basic" => return Some(Self::new(name.to_string())),
+            _ => return None,
+        };
+        let mut condition = Script::new(ScriptPrefix::Sh, condition.to_string());
+        condition.timeout = WELL_KNOWN_TIMEOUT;
+        Some(Self {
+            condition: Some(condition),
+            ..Self::new(name.to_string())
+        })
+    }
 }
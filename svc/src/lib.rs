@@ -0,0 +1,16 @@
+#![feature(async_closure)]
+
+pub mod live_service;
+pub mod live_service_graph;
+pub mod logging;
+pub mod reexec_state;
+pub mod request_handler;
+pub mod supervision;
+pub mod supervisor;
+#[cfg(test)]
+mod test_util;
+
+pub use supervisor::Supervisor;
+
+#[macro_use]
+extern crate lazy_static;
@@ -1,9 +1,21 @@
 use std::{
-    cell::RefCell,
-    path::Path,
-    time::Duration,
+    cell::{
+        Cell,
+        RefCell,
+    },
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+    rc::Rc,
+    time::{
+        Duration,
+        SystemTime,
+    },
 };
 
+use anyhow::Result;
 use flexi_logger::{
     writers::{
         FileLogWriter,
@@ -16,27 +28,51 @@ use flexi_logger::{
     WriteMode,
 };
 use futures::future::BoxFuture;
-use rinit_ipc::Request;
+use rinit_ipc::{
+    Request,
+    ServiceStatus,
+    StatusChanged,
+};
 use rinit_service::{
+    dirs::Dirs,
+    env_dropins::add_env_dropins,
     graph::Node,
     service_state::{
         IdleServiceState,
         ServiceState,
         TransitioningServiceState,
     },
-    types::Service,
+    types::{
+        Oneshot,
+        Schedule,
+        Script,
+        ScriptEnvironment,
+        Service,
+        ServiceOptions,
+    },
 };
 use tokio::{
+    select,
     sync::{
-        broadcast,
+        broadcast::{
+            self,
+            error::RecvError,
+        },
         mpsc,
         watch,
     },
     task,
-    time::timeout,
+    time::{
+        sleep,
+        timeout,
+        timeout_at,
+        Instant,
+    },
 };
 use tracing::{
+    debug,
     error,
+    info,
     instrument::WithSubscriber,
     metadata::LevelFilter,
     warn,
@@ -44,11 +80,20 @@ use tracing::{
 use tracing_subscriber::FmtSubscriber;
 
 use crate::supervision::{
+    add_credentials,
     run_short_lived_script,
     signal_wait_fun,
+    Cgroup,
+    InheritedFds,
+    ProcessInfo,
+    Reaper,
+    ServicePipes,
     Supervisor,
 };
 
+const NEEDS_PATH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const CONDITION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 // This data will be changed frequently
 // To avoid passing &mut LiveService, it is encapsulated by RefCell
 pub struct LiveService {
@@ -58,28 +103,69 @@ pub struct LiveService {
     // Keep a receiving end open so that the sender can always send data
     _rx: broadcast::Receiver<IdleServiceState>,
     pub state: RefCell<ServiceState>,
+    // When the state last changed
+    pub since: RefCell<Option<SystemTime>>,
+    // When the service last reached the up state
+    pub up_since: RefCell<Option<SystemTime>>,
+    // Every state transition is sent to the subscribers of the graph
+    events: broadcast::Sender<StatusChanged>,
     pub terminate: RefCell<Option<watch::Sender<()>>>,
+    // Human readable status sent by the service using STATUS= on the notify socket
+    pub status_message: Rc<RefCell<Option<String>>>,
+    // Updated by the supervisor of longruns
+    pub process_info: Rc<ProcessInfo>,
+    // Whether the last run of the stop script succeeded, None if it has never run
+    pub stop_success: RefCell<Option<bool>>,
+    // When the schedule of a oneshot last fired and when it fires next. Updated
+    // by its timer
+    pub last_run: Rc<Cell<Option<SystemTime>>>,
+    pub next_run: Rc<Cell<Option<SystemTime>>>,
+    // Shared by the whole graph, tracks the processes spawned by the scripts
+    reaper: Rc<Reaper>,
     pub remove: bool,
+    // Removed with keep_running: left alone by the reloads until it goes down
+    pub unmanaged: bool,
     pub new: Option<Box<LiveService>>,
 }
 
 impl LiveService {
-    pub fn new(node: Node) -> Self {
+    /// Directory of rundir with the notify sockets of the longruns
+    pub const NOTIFY_DIR: &'static str = "notify";
+    /// Directory of rundir with the markers of the oneshots that are up
+    pub const DONE_DIR: &'static str = "done";
+
+    pub fn new(
+        node: Node,
+        events: broadcast::Sender<StatusChanged>,
+        reaper: Rc<Reaper>,
+    ) -> Self {
         let (tx, rx) = broadcast::channel(1);
         Self {
             node,
             state: RefCell::new(ServiceState::Idle(IdleServiceState::Down)),
+            since: RefCell::new(None),
+            up_since: RefCell::new(None),
+            events,
             remove: false,
+            unmanaged: false,
             new: None,
             tx,
             _rx: rx,
             terminate: RefCell::new(None),
+            status_message: Rc::new(RefCell::new(None)),
+            process_info: Rc::new(ProcessInfo::default()),
+            stop_success: RefCell::new(None),
+            last_run: Rc::new(Cell::new(None)),
+            next_run: Rc::new(Cell::new(None)),
+            reaper,
         }
     }
 
+    /// How long the current transition can take at most
     pub fn get_timeout(&self) -> Duration {
         Duration::from_millis(match *self.state.borrow() {
-            ServiceState::Idle(_) => unreachable!(),
+            // Nothing to wait for
+            ServiceState::Idle(_) => 0,
             ServiceState::Transitioning(state) => {
                 match state {
                     TransitioningServiceState::Starting => {
@@ -87,9 +173,25 @@ impl LiveService {
                             Service::Bundle(_) => unreachable!(),
                             Service::Longrun(longrun) => {
                                 longrun.run.timeout * longrun.run.max_deaths as u32
+                                    + Self::needs_path_time(&longrun.options)
+                                    + Self::hook_time(&longrun.condition)
+                                    + Self::hook_time(&longrun.pre_start)
+                            }
+                            Service::Oneshot(oneshot) => {
+                                oneshot.start.get_maximum_time()
+                                    + Self::needs_path_time(&oneshot.options)
+                                    + Self::hook_time(&oneshot.condition)
+                                    + Self::hook_time(&oneshot.pre_start)
+                            }
+                            Service::Virtual(target) => {
+                                target
+                                    .condition
+                                    .as_ref()
+                                    .map_or(Script::DEFAULT_TIMEOUT, |condition| {
+                                        condition.timeout
+                                    })
+                                    + Self::needs_path_time(&target.options)
                             }
-                            Service::Oneshot(oneshot) => oneshot.start.get_maximum_time(),
-                            Service::Virtual(_) => todo!(),
                         }
                     }
                     TransitioningServiceState::Stopping => {
@@ -102,15 +204,17 @@ impl LiveService {
                                     } else {
                                         0
                                     }
+                                    + Self::hook_time(&longrun.post_stop)
                             }
                             Service::Oneshot(oneshot) => {
-                                if let Some(stop) = &oneshot.stop {
-                                    stop.get_maximum_time()
-                                } else {
-                                    0
-                                }
+                                Self::hook_time(&oneshot.post_stop)
+                                    + if let Some(stop) = &oneshot.stop {
+                                        stop.get_maximum_time()
+                                    } else {
+                                        0
+                                    }
                             }
-                            Service::Virtual(_) => todo!(),
+                            Service::Virtual(_) => 0,
                         }
                     }
                 }
@@ -118,51 +222,291 @@ impl LiveService {
         } as u64)
     }
 
+    fn needs_path_time(options: &ServiceOptions) -> u32 {
+        if options.needs_path.is_empty() {
+            0
+        } else {
+            options.needs_path_timeout
+        }
+    }
+
+    fn hook_time(hook: &Option<Script>) -> u32 {
+        hook.as_ref().map_or(0, Script::get_maximum_time)
+    }
+
+    /// Run the condition of the service, if any, once. Return false if it is
+    /// not met and the service must be skipped. The condition of a target is
+    /// different, it is waited on while starting it
+    pub async fn condition_met(
+        &self,
+        dirs: &Dirs,
+    ) -> Result<bool> {
+        let (condition, environment, options) = match &self.node.service {
+            Service::Longrun(longrun) => {
+                (&longrun.condition, &longrun.environment, &longrun.options)
+            }
+            Service::Oneshot(oneshot) => {
+                (&oneshot.condition, &oneshot.environment, &oneshot.options)
+            }
+            Service::Virtual(_) | Service::Bundle(_) => return Ok(true),
+        };
+        let Some(condition) = condition else {
+            return Ok(true);
+        };
+        let environment = self.script_environment(environment, options, dirs)?;
+        let mut condition = condition.clone();
+        // Failing is the expected outcome of a condition, don't retry it
+        condition.max_deaths = 1;
+        let met = run_short_lived_script(&condition, &environment, &self.reaper, signal_wait_fun())
+            .with_subscriber(self.logger_subscriber(&dirs.logdir).1)
+            .await?;
+        if !met {
+            info!(
+                "the condition of service {} is not met, skipping it",
+                self.node.name()
+            );
+        }
+        Ok(met)
+    }
+
+    /// Run pre_start, if any. Return false if it failed and the service must
+    /// not be started
+    async fn run_pre_start(
+        &self,
+        pre_start: &Option<Script>,
+        environment: &ScriptEnvironment,
+    ) -> bool {
+        let Some(pre_start) = pre_start else {
+            return true;
+        };
+        match run_short_lived_script(pre_start, environment, &self.reaper, signal_wait_fun()).await
+        {
+            Ok(true) => true,
+            Ok(false) => {
+                error!("pre_start script of service {} failed", self.node.name());
+                false
+            }
+            Err(err) => {
+                error!("{err:?}");
+                false
+            }
+        }
+    }
+
+    /// Run the start script of a oneshot, false if it failed or could not be
+    /// spawned
+    async fn run_start(
+        &self,
+        start: &Script,
+        environment: &ScriptEnvironment,
+    ) -> bool {
+        match run_short_lived_script(start, environment, &self.reaper, signal_wait_fun()).await {
+            Ok(success) => success,
+            Err(err) => {
+                error!("{err:?}");
+                false
+            }
+        }
+    }
+
+    /// Wait until all the paths in needs_path exist
+    /// Return false if any of them is still missing after needs_path_timeout
+    async fn wait_needed_paths(
+        &self,
+        options: &ServiceOptions,
+    ) -> bool {
+        let wait = async {
+            for path in &options.needs_path {
+                while !path.exists() {
+                    sleep(NEEDS_PATH_POLL_INTERVAL).await;
+                }
+            }
+        };
+        let res = timeout(
+            Duration::from_millis(options.needs_path_timeout as u64),
+            wait,
+        )
+        .await;
+        if res.is_err() {
+            error!(
+                "service {} timed out waiting for paths {:?}",
+                self.node.name(),
+                options.needs_path
+            );
+        }
+        res.is_ok()
+    }
+
     /// Wait until we have an idle service state, i.e. non transitioning
     /// A BoxFuture is returned so that it's independent from the live_service
+    /// The service is considered down when the transition takes longer than
+    /// its timeout
     pub fn wait_idle_state(&self) -> BoxFuture<'static, IdleServiceState> {
+        let wait = self.wait_idle_state_timeout(self.get_timeout());
+        Box::pin(async move { wait.await.unwrap_or(IdleServiceState::Down) })
+    }
+
+    /// Same as wait_idle_state, but give up after wait_timeout and return
+    /// None, the service still being in its transition
+    pub fn wait_idle_state_timeout(
+        &self,
+        wait_timeout: Duration,
+    ) -> BoxFuture<'static, Option<IdleServiceState>> {
         let state = *self.state.borrow();
         match state {
             ServiceState::Transitioning(_) => {
                 let mut rx = self.tx.subscribe();
-                let service_timeout = self.get_timeout();
+                let deadline = Instant::now() + wait_timeout;
                 Box::pin(async move {
-                    match timeout(service_timeout, rx.recv()).await {
-                        Ok(res) => {
-                            match res {
-                                Ok(state) => state,
-                                Err(_) => IdleServiceState::Down,
-                            }
+                    loop {
+                        match timeout_at(deadline, rx.recv()).await {
+                            Ok(Ok(state)) => return Some(state),
+                            // More states have been sent than the channel
+                            // holds, the next one is the latest. Wait again
+                            // instead of assuming the service is down
+                            Ok(Err(RecvError::Lagged(_))) => continue,
+                            Ok(Err(RecvError::Closed)) => return Some(IdleServiceState::Down),
+                            // the wait timed out
+                            Err(_) => return None,
                         }
-                        // the wait timed out
-                        Err(_) => IdleServiceState::Down,
                     }
                 })
             }
-            ServiceState::Idle(state) => Box::pin(async move { state }),
+            ServiceState::Idle(state) => Box::pin(async move { Some(state) }),
         }
     }
 
+    /// Change the state of the service and notify the subscribers. Illegal
+    /// transitions, e.g. from down to up without starting, are logged and
+    /// rejected; return whether the state has been changed
     pub fn update_state(
         &self,
         new: ServiceState,
-    ) {
+    ) -> bool {
+        let old = *self.state.borrow();
+        if !old.can_transition_to(new) {
+            warn!(
+                service = self.node.name(),
+                "rejected illegal transition from {old} to {new}"
+            );
+            return false;
+        }
         self.state.replace(new);
+        if old != new {
+            debug!(service = self.node.name(), from = %old, to = %new, "state changed");
+            let at = SystemTime::now();
+            self.since.replace(Some(at));
+            if new == ServiceState::Idle(IdleServiceState::Up) {
+                self.up_since.replace(Some(at));
+            }
+            // There might be no subscribers
+            let _ = self.events.send(StatusChanged {
+                service: self.node.name().to_owned(),
+                from: old,
+                to: new,
+                at,
+            });
+        }
+        true
+    }
+
+    /// Whether it is a longrun spawning its process on the first connection
+    /// to its socket
+    pub fn is_socket_activated(&self) -> bool {
+        matches!(&self.node.service, Service::Longrun(longrun) if longrun.run.listen.is_some())
+    }
+
+    /// Whether it is a socket activated longrun listening for the connection
+    /// that spawns its process
+    pub fn waits_connection(&self) -> bool {
+        self.process_info.listen_fd.get().is_some() && self.process_info.pid.get().is_none()
+    }
+
+    /// Whether the dependents of the service in state can run: it is up, or
+    /// down while waiting for the connection that activates it
+    pub fn is_available(
+        &self,
+        state: IdleServiceState,
+    ) -> bool {
+        state == IdleServiceState::Up || state == IdleServiceState::Down && self.waits_connection()
+    }
+
+    /// Whether the dependents of the service can run in its current state
+    pub fn is_available_now(&self) -> bool {
+        matches!(*self.state.borrow(), ServiceState::Idle(state) if self.is_available(state))
+    }
+
+    pub fn status(&self) -> ServiceStatus {
+        ServiceStatus {
+            since: *self.since.borrow(),
+            pid: self.process_info.pid.get(),
+            up_since: *self.up_since.borrow(),
+            restarts: self.process_info.restarts.get(),
+            last_exit_code: self.process_info.last_exit_code.get(),
+            last_run: self.last_run.get(),
+            next_run: self.next_run.get(),
+            ..ServiceStatus::new(self.node.name().to_owned(), *self.state.borrow())
+        }
     }
 
     pub async fn start_service(
         &self,
-        logdir: &Path,
+        dirs: &Dirs,
+        cgroup_parent: Option<&Path>,
+        pipes: ServicePipes,
         send: mpsc::Sender<Request>,
     ) -> bool {
+        let (logdir, rundir) = (dirs.logdir.as_path(), dirs.rundir.as_path());
         match &self.node.service {
             Service::Longrun(longrun) => {
+                if !self.wait_needed_paths(&longrun.options).await {
+                    return false;
+                }
+                let mut longrun = longrun.clone();
+                longrun.environment = match self.script_environment(
+                    &longrun.environment,
+                    &longrun.options,
+                    dirs,
+                ) {
+                    Ok(environment) => environment,
+                    Err(err) => {
+                        error!("{err:?}");
+                        return false;
+                    }
+                };
+                if !self
+                    .run_pre_start(&longrun.pre_start, &longrun.environment)
+                    .with_subscriber(self.logger_subscriber(logdir).1)
+                    .await
+                {
+                    return false;
+                }
+                let (fw_handle, logger) = self.logger_subscriber(logdir);
+                let cgroup = match cgroup_parent
+                    .map(|parent| Cgroup::create(parent, self.node.name()))
+                    .transpose()
+                {
+                    Ok(cgroup) => cgroup,
+                    Err(err) => {
+                        error!("{err:?}");
+                        return false;
+                    }
+                };
                 let (tx, rx) = watch::channel(());
                 // terminate is our channel to ask the supervisor to close the process
                 self.terminate.replace(Some(tx));
-                let (fw_handle, logger) = self.logger_subscriber(logdir);
-                let mut supervisor = Supervisor::new(longrun.clone(), rx, fw_handle);
-                async {
+                let mut supervisor = Supervisor::new(
+                    longrun,
+                    rx,
+                    fw_handle,
+                    rundir.join(Self::NOTIFY_DIR),
+                    self.status_message.clone(),
+                    self.process_info.clone(),
+                    cgroup,
+                )
+                .with_pipes(pipes)
+                .with_reaper(self.reaper.clone());
+                let success = async {
                     match supervisor.start().await {
                         Ok(res) => {
                             if res {
@@ -182,22 +526,360 @@ impl LiveService {
                     }
                 }
                 .with_subscriber(logger)
-                .await
+                .await;
+                if !success {
+                    // The supervisor is gone along with its processes, there is
+                    // nothing left to stop
+                    self.terminate.replace(None);
+                    self.process_info.pid.set(None);
+                }
+                success
             }
             Service::Oneshot(oneshot) => {
-                run_short_lived_script(&oneshot.start, &oneshot.environment, signal_wait_fun())
+                if let Some(schedule) = &oneshot.options.schedule {
+                    self.arm_timer(oneshot, schedule, dirs);
+                    return true;
+                }
+                if !self.wait_needed_paths(&oneshot.options).await {
+                    return false;
+                }
+                let environment = match self.script_environment(
+                    &oneshot.environment,
+                    &oneshot.options,
+                    dirs,
+                ) {
+                    Ok(environment) => environment,
+                    Err(err) => {
+                        error!("{err:?}");
+                        return false;
+                    }
+                };
+                let Some(up_check) = &oneshot.up_check else {
+                    return async {
+                        self.run_pre_start(&oneshot.pre_start, &environment).await
+                            && self.run_start(&oneshot.start, &environment).await
+                    }
+                    .with_subscriber(self.logger_subscriber(logdir).1)
+                    .await;
+                };
+
+                // The oneshot has already been started, e.g. before rsvc was restarted
+                let done_marker = self.done_marker(rundir);
+                if done_marker.exists() {
+                    info!("service {} is already up", self.node.name());
+                    return true;
+                }
+                let success = async {
+                    match run_short_lived_script(
+                        up_check,
+                        &environment,
+                        &self.reaper,
+                        signal_wait_fun(),
+                    )
+                    .await
+                    {
+                        Ok(true) => {
+                            info!("up_check succeeded, skipping the start script");
+                            return true;
+                        }
+                        Ok(false) => {}
+                        Err(err) => error!("{err}"),
+                    }
+                    self.run_pre_start(&oneshot.pre_start, &environment).await
+                        && self.run_start(&oneshot.start, &environment).await
+                }
+                .with_subscriber(self.logger_subscriber(logdir).1)
+                .await;
+                if success {
+                    if let Err(err) = fs::create_dir_all(done_marker.parent().unwrap())
+                        .and_then(|_| fs::write(&done_marker, ""))
+                    {
+                        warn!("unable to create {:?}: {err}", done_marker);
+                    }
+                }
+                success
+            }
+            Service::Virtual(target) => {
+                if !self.wait_needed_paths(&target.options).await {
+                    return false;
+                }
+                let Some(condition) = &target.condition else {
+                    return true;
+                };
+                let environment = match self.script_environment(
+                    &ScriptEnvironment::new(),
+                    &target.options,
+                    dirs,
+                ) {
+                    Ok(environment) => environment,
+                    Err(err) => {
+                        error!("{err:?}");
+                        return false;
+                    }
+                };
+                self.wait_condition(condition, &environment)
                     .with_subscriber(self.logger_subscriber(logdir).1)
                     .await
-                    .unwrap()
             }
-            Service::Bundle(_) | Service::Virtual(_) => todo!(),
+            Service::Bundle(_) => todo!(),
         }
     }
 
-    pub async fn stop_service(
+    /// Run the condition of a target until it succeeds
+    /// Return false if it still fails after its timeout
+    async fn wait_condition(
         &self,
-        logdir: &Path,
+        condition: &Script,
+        environment: &ScriptEnvironment,
+    ) -> bool {
+        let condition_timeout = condition.timeout;
+        let deadline = Instant::now() + Duration::from_millis(condition_timeout as u64);
+        let mut condition = condition.clone();
+        // Every failure is retried here, after waiting
+        condition.max_deaths = 1;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                error!(
+                    "the condition of target {} was not met within {condition_timeout}ms",
+                    self.node.name(),
+                );
+                return false;
+            }
+            // A single run can't go past the deadline
+            condition.timeout = remaining.as_millis() as u32;
+            match run_short_lived_script(&condition, environment, &self.reaper, signal_wait_fun())
+                .await
+            {
+                Ok(true) => return true,
+                Ok(false) => {}
+                Err(err) => warn!("{err}"),
+            }
+            sleep(CONDITION_POLL_INTERVAL.min(remaining)).await;
+        }
+    }
+
+    /// Add the variables set by rinit before the ones of the service, so that
+    /// they can be used in its values, and the drop-ins after them, so that
+    /// they override them. The credentials are read here, right before running
+    /// the script, so that they are only kept in memory
+    fn script_environment(
+        &self,
+        environment: &ScriptEnvironment,
+        options: &ServiceOptions,
+        dirs: &Dirs,
+    ) -> Result<ScriptEnvironment> {
+        Self::service_script_environment(self.node.name(), environment, options, dirs)
+    }
+
+    fn service_script_environment(
+        name: &str,
+        environment: &ScriptEnvironment,
+        options: &ServiceOptions,
+        dirs: &Dirs,
+    ) -> Result<ScriptEnvironment> {
+        let mut script_environment = ScriptEnvironment::new();
+        script_environment.clean = options.clean_environment;
+        script_environment.path = options.path.clone();
+        script_environment.add("SERVICE_NAME", name.to_owned());
+        script_environment.add("RUNDIR", dirs.rundir.to_string_lossy().into_owned());
+        add_credentials(&mut script_environment, &options.credentials)?;
+        script_environment
+            .contents
+            .extend(environment.contents.iter().cloned());
+        // The drop-ins in rundir are generated at runtime, they win over the
+        // ones written by the administrator
+        add_env_dropins(
+            &mut script_environment,
+            name,
+            &[&dirs.configdir, &dirs.rundir],
+        )?;
+        Ok(script_environment)
+    }
+
+    /// Run the start script of a scheduled oneshot every time its schedule
+    /// fires, until the service is stopped. The runs never overlap, the
+    /// ones missed while the previous one was still going are skipped
+    fn arm_timer(
+        &self,
+        oneshot: &Oneshot,
+        schedule: &Schedule,
+        dirs: &Dirs,
+    ) {
+        let (tx, mut rx) = watch::channel(());
+        // Dropped when the service is stopped
+        self.terminate.replace(Some(tx));
+        let (oneshot, schedule, dirs) = (oneshot.clone(), schedule.clone(), dirs.clone());
+        let (last_run, next_run) = (self.last_run.clone(), self.next_run.clone());
+        let reaper = self.reaper.clone();
+        task::spawn_local(async move {
+            let name = &oneshot.name;
+            let mut after = SystemTime::now();
+            'timer: loop {
+                let Some(next) = schedule.next_run(after) else {
+                    warn!("the schedule {schedule} of service {name} never fires");
+                    break;
+                };
+                next_run.set(Some(next));
+                // The sleep follows the monotonic clock, sleep again if the
+                // wall clock has been set back in the meantime
+                while let Ok(wait) = next.duration_since(SystemTime::now()) {
+                    select! {
+                        _ = sleep(wait) => {}
+                        _ = rx.changed() => break 'timer,
+                    }
+                }
+                info!("running scheduled service {name}");
+                last_run.set(Some(SystemTime::now()));
+                select! {
+                    success = Self::run_scheduled(&oneshot, &dirs, &reaper) => {
+                        if !success {
+                            warn!("scheduled run of service {name} failed");
+                        }
+                    }
+                    _ = rx.changed() => break,
+                }
+                after = SystemTime::now().max(next);
+            }
+            next_run.set(None);
+        });
+    }
+
+    /// Arm the timer of a scheduled oneshot that was up in the rsvc that
+    /// executed this one, without running it. last_run is when that rsvc last
+    /// ran it; the runs missed while re-executing are skipped
+    pub fn resume_timer(
+        &self,
+        oneshot: &Oneshot,
+        schedule: &Schedule,
+        dirs: &Dirs,
+        last_run: Option<SystemTime>,
     ) {
+        self.last_run.set(last_run);
+        self.arm_timer(oneshot, schedule, dirs);
+    }
+
+    /// Run pre_start and start of a scheduled oneshot
+    async fn run_scheduled(
+        oneshot: &Oneshot,
+        dirs: &Dirs,
+        reaper: &Reaper,
+    ) -> bool {
+        let environment = match Self::service_script_environment(
+            &oneshot.name,
+            &oneshot.environment,
+            &oneshot.options,
+            dirs,
+        ) {
+            Ok(environment) => environment,
+            Err(err) => {
+                error!("{err:?}");
+                return false;
+            }
+        };
+        async {
+            for script in oneshot.pre_start.iter().chain([&oneshot.start]) {
+                match run_short_lived_script(script, &environment, reaper, signal_wait_fun()).await
+                {
+                    Ok(true) => {}
+                    Ok(false) => return false,
+                    Err(err) => {
+                        error!("{err}");
+                        return false;
+                    }
+                }
+            }
+            true
+        }
+        .with_subscriber(Self::service_logger(&oneshot.name, &dirs.logdir).1)
+        .await
+    }
+
+    /// File marking a oneshot with an up_check as started. rundir does not
+    /// survive reboots, so it only lasts until the next boot
+    fn done_marker(
+        &self,
+        rundir: &Path,
+    ) -> PathBuf {
+        rundir.join(Self::DONE_DIR).join(self.node.name())
+    }
+
+    /// Supervise the process of a longrun that was started by the rsvc running
+    /// before it re-executed itself, instead of spawning a new one. A socket
+    /// activated longrun could be without a process. Return whether it has
+    /// been adopted
+    pub fn adopt_process(
+        &self,
+        pid: Option<u32>,
+        fds: InheritedFds,
+        dirs: &Dirs,
+        cgroup_parent: Option<&Path>,
+        pipes: ServicePipes,
+        send: mpsc::Sender<Request>,
+    ) -> bool {
+        let Service::Longrun(longrun) = &self.node.service else {
+            warn!("only the process of a longrun can be adopted");
+            return false;
+        };
+        let mut longrun = longrun.clone();
+        // Used by the finish and post_stop scripts
+        longrun.environment =
+            match self.script_environment(&longrun.environment, &longrun.options, dirs) {
+                Ok(environment) => environment,
+                Err(err) => {
+                    error!("{err:?}");
+                    return false;
+                }
+            };
+        let cgroup = match cgroup_parent
+            .map(|parent| Cgroup::create(parent, self.node.name()))
+            .transpose()
+        {
+            Ok(cgroup) => cgroup,
+            Err(err) => {
+                error!("{err:?}");
+                return false;
+            }
+        };
+        let (fw_handle, logger) = self.logger_subscriber(&dirs.logdir);
+        let (tx, rx) = watch::channel(());
+        let mut supervisor = Supervisor::new(
+            longrun,
+            rx,
+            fw_handle,
+            dirs.rundir.join(Self::NOTIFY_DIR),
+            self.status_message.clone(),
+            self.process_info.clone(),
+            cgroup,
+        )
+        .with_pipes(pipes)
+        .with_reaper(self.reaper.clone());
+        // The output of the process is logged along with the service logs
+        if let Err(err) =
+            tracing::subscriber::with_default(logger.finish(), || supervisor.adopt(pid, fds))
+        {
+            error!("{err:?}");
+            return false;
+        }
+        self.terminate.replace(Some(tx));
+        task::spawn_local(
+            async move {
+                if let Err(err) = supervisor.supervise(send).await {
+                    error!("{err}");
+                }
+            }
+            .with_subscriber(self.logger_subscriber(&dirs.logdir).1),
+        );
+        true
+    }
+
+    /// Return false if the service is still up, i.e. its stop script failed and
+    /// it must succeed
+    pub async fn stop_service(
+        &self,
+        dirs: &Dirs,
+    ) -> bool {
+        let (logdir, rundir) = (dirs.logdir.as_path(), dirs.rundir.as_path());
         match &self.node.service {
             Service::Longrun(_) => {
                 if let Some(terminate) = &*self.terminate.borrow() {
@@ -206,22 +888,98 @@ impl LiveService {
                         warn!("{err}");
                     }
                 }
+                true
             }
             Service::Oneshot(oneshot) => {
+                // Disarm the timer of a scheduled oneshot, killing its run if
+                // there is one
+                self.terminate.replace(None);
                 if let Some(stop_script) = &oneshot.stop {
-                    let res = run_short_lived_script(
-                        stop_script,
-                        &oneshot.environment,
-                        signal_wait_fun(),
-                    )
-                    .with_subscriber(self.logger_subscriber(logdir).1)
-                    .await;
-                    if let Err(err) = res {
-                        error!("{err}");
+                    let res =
+                        match self.script_environment(&oneshot.environment, &oneshot.options, dirs)
+                        {
+                            Ok(environment) => {
+                                run_short_lived_script(
+                                    stop_script,
+                                    &environment,
+                                    &self.reaper,
+                                    signal_wait_fun(),
+                                )
+                                .with_subscriber(self.logger_subscriber(logdir).1)
+                                .await
+                        }
+                        Err(err) => Err(err),
+                    };
+                    let success = match res {
+                        Ok(success) => success,
+                        Err(err) => {
+                            error!("{err}");
+                            false
+                        }
+                    };
+                    self.stop_success.replace(Some(success));
+                    if !success {
+                        if stop_script.must_succeed {
+                            error!(
+                                "stop script of service {} failed, the service is still up",
+                                self.node.name()
+                            );
+                            return false;
+                        }
+                        warn!("stop script of service {} failed", self.node.name());
                     }
                 }
+                if let Some(post_stop) = &oneshot.post_stop {
+                    let res =
+                        match self.script_environment(&oneshot.environment, &oneshot.options, dirs)
+                        {
+                            Ok(environment) => {
+                                run_short_lived_script(
+                                    post_stop,
+                                    &environment,
+                                    &self.reaper,
+                                    signal_wait_fun(),
+                                )
+                                .with_subscriber(self.logger_subscriber(logdir).1)
+                                .await
+                        }
+                        Err(err) => Err(err),
+                    };
+                    match res {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            warn!("post_stop script of service {} failed", self.node.name())
+                        }
+                        Err(err) => warn!("{err}"),
+                    }
+                }
+                let done_marker = self.done_marker(rundir);
+                if done_marker.exists() {
+                    if let Err(err) = fs::remove_file(&done_marker) {
+                        warn!("unable to remove {:?}: {err}", done_marker);
+                    }
+                }
+                true
+            }
+            // Targets have nothing to stop
+            Service::Virtual(_) => true,
+            Service::Bundle(_) => todo!(),
+        }
+    }
+
+    /// Forget what is left of a service that has been forced down
+    pub fn release(
+        &self,
+        rundir: &Path,
+    ) {
+        self.terminate.replace(None);
+        self.process_info.pid.set(None);
+        self.stop_success.replace(Some(false));
+        let done_marker = self.done_marker(rundir);
+        if done_marker.exists() {
+            if let Err(err) = fs::remove_file(&done_marker) {
+                warn!("unable to remove {:?}: {err}", done_marker);
             }
-            Service::Bundle(_) | Service::Virtual(_) => todo!(),
         }
     }
 
@@ -236,11 +994,26 @@ impl LiveService {
             LevelFilter,
             impl Fn() -> flexi_logger::writers::ArcFileLogWriter,
         >,
+    ) {
+        Self::service_logger(self.node.name(), logdir)
+    }
+
+    fn service_logger(
+        name: &str,
+        logdir: &Path,
+    ) -> (
+        FileLogWriterHandle,
+        tracing_subscriber::fmt::SubscriberBuilder<
+            tracing_subscriber::fmt::format::DefaultFields,
+            tracing_subscriber::fmt::format::Format,
+            LevelFilter,
+            impl Fn() -> flexi_logger::writers::ArcFileLogWriter,
+        >,
     ) {
         let (file_writer, fw_handle) = FileLogWriter::builder(
             FileSpec::default()
-                .directory(logdir.join(self.node.name()))
-                .basename(self.node.name().to_owned()),
+                .directory(logdir.join(name))
+                .basename(name.to_owned()),
         )
         .rotate(
             Criterion::Size(1024 * 512),
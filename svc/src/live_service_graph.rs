@@ -1,69 +1,178 @@
 use std::{
     self,
+    cell::RefCell,
+    cmp::Reverse,
     collections::{
+        hash_map::Entry,
         HashMap,
         TryReserveError,
     },
+    fmt,
     io,
+    os::fd::{
+        OwnedFd,
+        RawFd,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    rc::Rc,
+    time::Duration,
 };
 
 use async_recursion::async_recursion;
 use async_scoped_local::TokioScope;
+use futures::future::join_all;
 use indexmap::IndexMap;
+use nix::{
+    sys::signal::{
+        kill,
+        killpg,
+        Signal,
+    },
+    unistd::{
+        dup,
+        Pid,
+    },
+};
 use rinit_ipc::{
     request_error::{
+        ConfigChangeRequiresRestartSnafu,
+        ConflictingServiceRunningSnafu,
+        DependenciesFailedToStartSnafu,
+        DependenciesTimedOutSnafu,
         DependencyFailedToStartSnafu,
         DependencyGraphNotFoundSnafu,
+        DependencyNotFoundSnafu,
+        DependencyTooDeepSnafu,
         DependentsStillRunningSnafu,
+        InvalidSignalSnafu,
         LogicError,
+        ReloadTimedOutSnafu,
         RequestError,
         RunLevelMustMatchSnafu,
         ServiceFailedToStartSnafu,
+        ServiceFailedToStopSnafu,
+        ServiceFileRejectedSnafu,
         ServiceNotFoundSnafu,
+        ServiceNotRunningSnafu,
+        ServiceSkippedSnafu,
     },
     Request,
+    ServiceSnapshot,
+    ServiceStatus,
+    ServiceUsage,
+    StatusChanged,
 };
+use rinit_parser::parse_services;
 use rinit_service::{
-    config::Config,
-    graph::DependencyGraph,
+    config::{
+        Config,
+        ConfigError,
+    },
+    graph::{
+        write_graph,
+        DependencyGraph,
+        DependencyGraphError,
+        Node,
+    },
     service_state::{
         IdleServiceState,
         ServiceState,
         TransitioningServiceState,
     },
-    types::RunLevel,
+    types::{
+        RunLevel,
+        ScriptOutput,
+        Service,
+    },
 };
 use snafu::{
     ensure,
+    OptionExt,
     ResultExt,
     Snafu,
 };
-use tokio::sync::mpsc;
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc,
+        Semaphore,
+    },
+    time::{
+        sleep,
+        timeout,
+        timeout_at,
+        Instant,
+    },
+};
 use tokio_stream::StreamExt;
 use tracing::{
+    error,
     info,
+    instrument,
     trace,
     warn,
 };
 
-use crate::live_service::LiveService;
+use crate::{
+    live_service::LiveService,
+    reexec_state::{
+        ReexecState,
+        ReexecStateError,
+        SavedService,
+    },
+    supervision::{
+        process_group,
+        LoggerPipe,
+        Reaper,
+        ResourceUsage,
+        ServicePipes,
+    },
+};
 
 pub struct LiveServiceGraph {
     pub live_services: IndexMap<String, LiveService>,
     config: Config,
     send: mpsc::Sender<Request>,
+    // Used to notify the subscribers about every state transition
+    events: broadcast::Sender<StatusChanged>,
+    // Pipes of the logger services, by name. They are kept for as long as
+    // rsvc runs
+    logger_pipes: RefCell<HashMap<String, LoggerPipe>>,
+    // Bounds the start scripts running at the same time to
+    // max_parallel_starts, if set
+    start_slots: Option<Semaphore>,
+    // The services providing each alias, as resolved by the dependency graph
+    aliases: HashMap<String, Vec<String>>,
+    // Reaps the processes orphaned by the services, leaving alone the ones
+    // spawned by their scripts
+    reaper: Rc<Reaper>,
 }
 
 #[derive(Snafu, Debug)]
 pub enum SystemError {
     #[snafu(display("error reading dependency graph from disk: {source}"))]
     ReadGraphError { source: io::Error },
+    #[snafu(display("error reading the configuration: {source}"))]
+    ReadConfigError { source: ConfigError },
     #[snafu(display("error deserializing json: {source}"))]
     JsonDeserializeError { source: serde_json::Error },
+    #[snafu(display("error writing the dependency graph: {source}"))]
+    WriteGraphError {
+        source: rinit_service::graph::WriteGraphError,
+    },
     #[snafu(display("error when joining tasks: {source}"))]
     JoinError { source: tokio::task::JoinError },
     #[snafu(display("rsupervision is not in PATH"))]
     RSupervisionNotInPath,
+    #[snafu(display(
+        "the dependency graph is broken, set prune_dangling_services to remove the services \
+         with missing dependencies: {}",
+        errors.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    ))]
+    BrokenGraph { errors: Vec<DependencyGraphError> },
     #[snafu(display("error when sending a signal: {source}"))]
     SendSignalError { source: nix::Error },
     #[snafu(display("error when spawning the supervisor: {source}"))]
@@ -72,6 +181,14 @@ pub enum SystemError {
     TryReserveError { source: TryReserveError },
     #[snafu(display("error when waiting on a child: {source}"))]
     WaitError { source: io::Error },
+    #[snafu(display("error when creating the pipe of logger service {logger}: {source}"))]
+    LoggerPipeError { logger: String, source: io::Error },
+    #[snafu(display("error when saving the runlevel in {path:?}: {source}"))]
+    SaveRunLevelError { path: PathBuf, source: io::Error },
+    #[snafu(display("error when creating the directory {path:?}: {source}"))]
+    CreateRundirError { path: PathBuf, source: io::Error },
+    #[snafu(display("{source}"))]
+    SaveReexecStateError { source: ReexecStateError },
 }
 
 // Snafu doesn't work with enums of enums
@@ -112,108 +229,455 @@ impl From<LiveGraphError> for RequestError {
 
 type Result<T> = std::result::Result<T, LiveGraphError>;
 
+// Mark the service as down when its start is abandoned midway, i.e. one of
+// its dependencies failed or the start future has been dropped
+struct StartGuard<'a> {
+    live_service: Option<&'a LiveService>,
+}
+
+impl<'a> StartGuard<'a> {
+    fn new(live_service: &'a LiveService) -> Self {
+        Self {
+            live_service: Some(live_service),
+        }
+    }
+
+    fn disarm(mut self) {
+        self.live_service = None;
+    }
+}
+
+impl Drop for StartGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(live_service) = self.live_service {
+            live_service.update_state(ServiceState::Idle(IdleServiceState::Down));
+            // LiveService always keeps a receiver open, this can't fail
+            let _ = live_service.tx.send(IdleServiceState::Down);
+        }
+    }
+}
+
+/// Outcome of starting all the services of a runlevel
+#[derive(Debug, Default)]
+pub struct StartSummary {
+    pub started: Vec<String>,
+    /// Services whose condition was not met
+    pub skipped: Vec<String>,
+    /// Services that failed to start, with the reason
+    pub failed: Vec<(String, String)>,
+}
+
+impl StartSummary {
+    fn add(
+        &mut self,
+        name: &str,
+        res: Result<()>,
+    ) {
+        match res {
+            Ok(()) => self.started.push(name.to_string()),
+            Err(LiveGraphError::LogicError {
+                err: LogicError::ServiceSkipped { .. },
+            }) => self.skipped.push(name.to_string()),
+            Err(err) => self.failed.push((name.to_string(), err.to_string())),
+        }
+    }
+
+    /// Merge the outcomes of other, e.g. of another runlevel
+    pub fn extend(
+        &mut self,
+        other: StartSummary,
+    ) {
+        self.started.extend(other.started);
+        self.skipped.extend(other.skipped);
+        self.failed.extend(other.failed);
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for StartSummary {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        write!(
+            f,
+            "{} started, {} failed",
+            self.started.len(),
+            self.failed.len()
+        )?;
+        if !self.skipped.is_empty() {
+            write!(f, ", {} skipped", self.skipped.len())?;
+        }
+        for (name, reason) in &self.failed {
+            write!(f, "\n  {name}: {reason}")?;
+        }
+        Ok(())
+    }
+}
+
 impl LiveServiceGraph {
+    const EVENTS_CAPACITY: usize = 64;
+    const DEFAULT_MAX_DEPENDENCY_DEPTH: usize = 64;
+    const DEPENDENCY_RETRY_DELAY: Duration = Duration::from_millis(100);
+
     pub fn new(
-        config: Config,
+        mut config: Config,
         send: mpsc::Sender<Request>,
     ) -> Result<Self> {
+        Self::provision_rundir(&config.dirs.rundir)?;
+        // rsvc has been restarted, keep the runlevel it was in
+        if let Some(runlevel) = Self::read_runlevel(&config.dirs.rundir) {
+            config.default_runlevel = runlevel;
+        }
         let graph_file = config.dirs.graph_filename();
-        let graph: DependencyGraph = if graph_file.exists() {
+        let mut graph: DependencyGraph = if graph_file.exists() {
             serde_json::from_slice(&std::fs::read(graph_file).with_context(|_| ReadGraphSnafu)?)
                 .with_context(|_| JsonDeserializeSnafu)?
         } else {
             DependencyGraph::new()
         };
-        Ok(Self {
+        if config.prune_dangling_services && !graph.check_integrity().is_empty() {
+            let removed = graph.prune_dangling_services();
+            warn!("removed the services with missing dependencies {removed:?} from the graph");
+        }
+        let errors = graph.check_integrity();
+        ensure!(errors.is_empty(), BrokenGraphSnafu { errors });
+        Ok(Self::new_with_graph(graph, config, send))
+    }
+
+    pub fn new_with_graph(
+        graph: DependencyGraph,
+        config: Config,
+        send: mpsc::Sender<Request>,
+    ) -> Self {
+        let (events, _) = broadcast::channel(Self::EVENTS_CAPACITY);
+        let start_slots = config.max_parallel_starts.map(Semaphore::new);
+        let reaper = Rc::new(Reaper::default());
+        let aliases = graph.aliases().clone();
+        Self {
             live_services: graph
                 .nodes
                 .into_iter()
-                .map(|(name, node)| (name, LiveService::new(node)))
+                .map(|(name, node)| (name, LiveService::new(node, events.clone(), reaper.clone())))
                 .collect(),
             config,
             send,
-        })
+            events,
+            logger_pipes: RefCell::new(HashMap::new()),
+            start_slots,
+            aliases,
+            reaper,
+        }
+    }
+
+    /// Start the boot services and then the ones in the default runlevel
+    pub async fn start_default_runlevel(&self) -> StartSummary {
+        let mut summary = self.start_all_services(RunLevel::Boot).await;
+        if self.config.default_runlevel != RunLevel::Boot {
+            summary.extend(self.start_all_services(self.config.default_runlevel).await);
+        }
+        summary
+    }
+
+    /// Read the configuration again and apply it. The options that can't be
+    /// changed while running are rejected. The runlevel in use is kept,
+    /// default_runlevel only applies when rsvc starts
+    pub fn reload_config(
+        &mut self,
+        config_file: Option<PathBuf>,
+    ) -> Result<()> {
+        let mut config = Config::new(config_file).with_context(|_| ReadConfigSnafu)?;
+        // The notify sockets of the running services are in rundir
+        ensure!(
+            config.dirs.rundir == self.config.dirs.rundir,
+            ConfigChangeRequiresRestartSnafu { option: "rundir" }
+        );
+        // The live graph has been loaded from datadir
+        ensure!(
+            config.dirs.datadir == self.config.dirs.datadir,
+            ConfigChangeRequiresRestartSnafu { option: "datadir" }
+        );
+        // Set by rinit.runlevel= on the command line or by set_default_runlevel,
+        // which win over the configuration file
+        config.default_runlevel = self.config.default_runlevel;
+        self.start_slots = config.max_parallel_starts.map(Semaphore::new);
+        self.config = config;
+        Ok(())
+    }
+
+    /// Change the runlevel and save it in rundir, so that it survives a
+    /// restart of rsvc but not a reboot
+    pub fn set_default_runlevel(
+        &mut self,
+        runlevel: RunLevel,
+    ) -> Result<()> {
+        self.config.default_runlevel = runlevel;
+        let path = Self::runlevel_file(&self.config.dirs.rundir);
+        std::fs::create_dir_all(&self.config.dirs.rundir)
+            .and_then(|_| std::fs::write(&path, runlevel.to_string()))
+            .with_context(|_| SaveRunLevelSnafu { path })?;
+        Ok(())
+    }
+
+    /// Create the directories of rundir used by the services. rundir is
+    /// usually on a tmpfs, so it is empty at every boot; nothing else in it
+    /// is expected to be there
+    fn provision_rundir(rundir: &Path) -> Result<()> {
+        for dir in [
+            rundir.to_path_buf(),
+            rundir.join(LiveService::NOTIFY_DIR),
+            rundir.join(LiveService::DONE_DIR),
+        ] {
+            std::fs::create_dir_all(&dir).with_context(|_| CreateRundirSnafu { path: dir })?;
+        }
+        Ok(())
+    }
+
+    fn runlevel_file(rundir: &Path) -> PathBuf {
+        rundir.join("runlevel")
+    }
+
+    /// Runlevel saved by set_default_runlevel, if any
+    fn read_runlevel(rundir: &Path) -> Option<RunLevel> {
+        let path = Self::runlevel_file(rundir);
+        match std::fs::read_to_string(&path) {
+            Ok(runlevel) => {
+                let runlevel = runlevel.trim().parse().ok();
+                if runlevel.is_none() {
+                    warn!("ignoring invalid runlevel saved in {path:?}");
+                }
+                runlevel
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                warn!("unable to read the runlevel saved in {path:?}: {err}");
+                None
+            }
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn reaper(&self) -> Rc<Reaper> {
+        self.reaper.clone()
+    }
+
+    /// Receive a StatusChanged for every state transition from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<StatusChanged> {
+        self.events.subscribe()
     }
 
+    /// Start every service of the runlevel. A service failing to start, even
+    /// by panicking, does not stop the others; the outcomes are logged and
+    /// returned
     pub async fn start_all_services(
         &self,
         runlevel: RunLevel,
-    ) -> Vec<Result<()>> {
-        // This is unsafe because the futures may outlive the current scope
-        // We wait on them afterwards and we know that self will outlive them
-        // so it's safe to use it
-        let (_, futures) = unsafe {
-            TokioScope::scope_and_collect(|s| {
-                self.live_services.iter().for_each(|(_, live_service)| {
-                    s.spawn(async move {
-                        if live_service.node.service.should_start()
-                            && live_service.node.service.runlevel() == runlevel
-                        {
-                            // TODO: Generate an order of the services to start and use
-                            // start_service_impl
-                            self.start_service(live_service).await
-                        } else {
-                            Ok(())
-                        }
+    ) -> StartSummary {
+        let mut summary = StartSummary::default();
+        if self.config.serial_start {
+            for live_service in self.start_order(runlevel) {
+                summary.add(
+                    live_service.node.name(),
+                    self.start_service(live_service).await,
+                );
+            }
+        } else {
+            let mut live_services = self
+                .live_services
+                .values()
+                .filter(|live_service| {
+                    live_service.node.service.should_start()
+                        && live_service.node.service.runlevel() == runlevel
+                })
+                .collect::<Vec<_>>();
+            // The services spawned first reach the start slots first
+            live_services
+                .sort_by_key(|live_service| Reverse(live_service.node.service.priority()));
+            // This is unsafe because the futures may outlive the current scope
+            // We wait on them afterwards and we know that self will outlive them
+            // so it's safe to use it
+            let (_, futures) = unsafe {
+                TokioScope::scope_and_collect(|s| {
+                    live_services.iter().for_each(|live_service| {
+                        // TODO: Generate an order of the services to start and use
+                        // start_service_impl
+                        s.spawn(self.start_service(live_service));
                     });
-                });
-            })
+                })
+            }
+            .await;
+            // The futures are collected in the order they have been spawned
+            for (live_service, res) in live_services.iter().zip(futures) {
+                // A join error means that the start panicked or was cancelled
+                let res = match res {
+                    Ok(res) => res,
+                    Err(source) => Err(SystemError::JoinError { source }.into()),
+                };
+                summary.add(live_service.node.name(), res);
+            }
         }
-        .await;
-        futures
-            .into_iter()
-            .map(|res| {
-                // Here we either lose the system error or the join error
-                // let's consider the join error (which could even be a panic)
-                // more important
-                res.with_context(|_| JoinSnafu)?
+        if summary.is_success() {
+            info!("runlevel {}: {summary}", runlevel.to_string());
+        } else {
+            warn!("runlevel {}: {summary}", runlevel.to_string());
+        }
+        summary
+    }
+
+    /// Services of the runlevel to start, each one after its dependencies and
+    /// otherwise by priority and then in the order of the graph
+    fn start_order(
+        &self,
+        runlevel: RunLevel,
+    ) -> Vec<&LiveService> {
+        fn visit<'a>(
+            graph: &'a LiveServiceGraph,
+            live_service: &'a LiveService,
+            order: &mut IndexMap<&'a str, &'a LiveService>,
+        ) {
+            if order.contains_key(live_service.node.name()) {
+                return;
+            }
+            // A missing dependency is reported when starting the service
+            for dep in graph.dependencies_of(live_service) {
+                if let Ok(dep_service) = graph.get_dependency(live_service, &dep) {
+                    visit(graph, dep_service, order);
+                }
+            }
+            order.insert(live_service.node.name(), live_service);
+        }
+
+        let mut live_services: Vec<&LiveService> = self
+            .live_services
+            .values()
+            .filter(|live_service| {
+                live_service.node.service.should_start()
+                    && live_service.node.service.runlevel() == runlevel
             })
-            .collect()
+            .collect();
+        live_services.sort_by_key(|live_service| Reverse(live_service.node.service.priority()));
+        let mut order = IndexMap::new();
+        live_services
+            .into_iter()
+            .for_each(|live_service| visit(self, live_service, &mut order));
+        order.into_values().collect()
+    }
+
+    /// Services marked for removal by the reloads that are still running and
+    /// not unmanaged, in the order they have to be stopped, i.e. after their
+    /// dependents
+    pub fn removed_services(&self) -> Vec<String> {
+        fn visit<'a>(
+            graph: &'a LiveServiceGraph,
+            live_service: &'a LiveService,
+            order: &mut IndexMap<&'a str, &'a LiveService>,
+        ) {
+            if order.contains_key(live_service.node.name()) {
+                return;
+            }
+            // The other dependents are still needed, stopping it will fail
+            for dependent in graph.get_dependents(live_service) {
+                if dependent.remove && !dependent.unmanaged {
+                    visit(graph, dependent, order);
+                }
+            }
+            order.insert(live_service.node.name(), live_service);
+        }
+
+        let mut order = IndexMap::new();
+        self.live_services
+            .values()
+            .filter(|live_service| live_service.remove && !live_service.unmanaged)
+            .for_each(|live_service| visit(self, live_service, &mut order));
+        order.into_keys().map(str::to_owned).collect()
     }
 
-    #[async_recursion(?Send)]
     pub async fn start_service(
         &self,
         live_service: &LiveService,
     ) -> Result<()> {
-        let mut state = *live_service.state.borrow();
-        if state == ServiceState::Idle(IdleServiceState::Up) {
+        let deadline = self
+            .config
+            .subtree_timeout
+            .map(|subtree_timeout| Instant::now() + Duration::from_millis(subtree_timeout));
+        self.start_service_until(live_service, deadline, &[]).await
+    }
+
+    /// Start the service after its dependencies. When a deadline is set, the
+    /// whole dependency subtree has to be up before it passes. path holds the
+    /// dependents being started that led to this service
+    #[instrument(
+        name = "start_service",
+        skip_all,
+        fields(service = live_service.node.name())
+    )]
+    #[async_recursion(?Send)]
+    async fn start_service_until(
+        &self,
+        live_service: &LiveService,
+        deadline: Option<Instant>,
+        path: &[&str],
+    ) -> Result<()> {
+        if live_service.is_available_now() {
             return Ok(());
         }
+        let mut state = *live_service.state.borrow();
         if matches!(state, ServiceState::Transitioning(_)) {
             state = ServiceState::Idle(live_service.wait_idle_state().await);
         }
-        // If the service is down
-        if state == ServiceState::Idle(IdleServiceState::Down) {
+        // If the service is down or it has failed, and not listening on its
+        // socket already
+        if matches!(state, ServiceState::Idle(state) if state.is_down())
+            && !live_service.is_available_now()
+        {
+            if let Some(conflict) = self
+                .get_conflicts(live_service)
+                .into_iter()
+                .find(|conflict| Self::is_running(conflict))
+            {
+                return ConflictingServiceRunningSnafu {
+                    service: live_service.node.name(),
+                    conflict: conflict.node.name(),
+                }
+                .fail()?;
+            }
+            let path = [path, &[live_service.node.name()]].concat();
+            let max_depth = self
+                .config
+                .max_dependency_depth
+                .unwrap_or(Self::DEFAULT_MAX_DEPENDENCY_DEPTH);
+            ensure!(
+                path.len() <= max_depth,
+                DependencyTooDeepSnafu {
+                    path: path.iter().map(|name| name.to_string()).collect::<Vec<_>>(),
+                    max_depth,
+                }
+            );
             trace!("starting service {}", live_service.node.name());
-            live_service.state.replace(ServiceState::Transitioning(
+            live_service.update_state(ServiceState::Transitioning(
                 TransitioningServiceState::Starting,
             ));
-            self.start_dependencies(live_service).await?;
-            self.wait_on_deps_starting(live_service).await?;
-
-            // Call the closure and let the new subscriber collect all the tracings
-            let success = live_service
-                .start_service(&self.config.dirs.logdir, self.send.clone())
-                .await;
-            if let Err(err) = self
-                .send
-                .send(Request::UpdateServiceStatus(
-                    live_service.node.name().to_string(),
-                    if success {
-                        IdleServiceState::Up
-                    } else {
-                        IdleServiceState::Down
-                    },
-                ))
-                .await
-            {
-                warn!("Could not update service status: {err}");
-            }
+            // The wanted services don't delay the service, they are started by
+            // the supervisor at the same time and their failures are ignored
+            self.request_wanted(live_service).await;
+            self.start_after_dependencies(live_service, deadline, &path)
+                .await?;
         }
         let state = live_service.wait_idle_state().await;
         ensure!(
-            state == IdleServiceState::Up,
+            state != IdleServiceState::Skipped,
+            ServiceSkippedSnafu {
+                service: live_service.node.name().to_string(),
+            },
+        );
+        ensure!(
+            live_service.is_available(state),
             ServiceFailedToStartSnafu {
                 service: live_service.node.name().to_string(),
             },
@@ -222,66 +686,42 @@ impl LiveServiceGraph {
         Ok(())
     }
 
-    async fn start_dependencies(
+    /// Start the service once its dependencies are up and send its new state
+    async fn start_after_dependencies(
         &self,
         live_service: &LiveService,
+        deadline: Option<Instant>,
+        path: &[&str],
     ) -> Result<()> {
-        let futures: Vec<_> = live_service
-            .node
-            .service
-            .dependencies()
-            .iter()
-            .map(async move |dep| -> Result<()> {
-                let dep_service = self.live_services.get(dep).unwrap();
-                if dep_service.wait_idle_state().await == IdleServiceState::Down {
-                    // Awaiting here is safe, as starting services always mean spawning rsupervisor
-                    self.start_service(dep_service).await
+        let guard = StartGuard::new(live_service);
+        self.start_and_wait_dependencies(live_service, deadline, path).await?;
+        let state = match live_service.condition_met(&self.config.dirs).await {
+            Ok(true) => {
+                if !self.run_start(live_service).await? {
+                    IdleServiceState::Failed
+                } else if live_service.waits_connection() {
+                    // Down until a connection spawns its process
+                    IdleServiceState::Down
                 } else {
-                    Ok(())
+                    IdleServiceState::Up
                 }
-            })
-            .collect();
-        for future in futures {
-            future.await?;
-        }
-
-        Ok(())
-    }
-
-    async fn wait_on_deps_starting(
-        &self,
-        live_service: &LiveService,
-    ) -> Result<()> {
-        for dep in live_service.node.service.dependencies() {
-            let dep_service = &self.live_services[dep];
-            let state = dep_service.wait_idle_state().await;
-            ensure!(
-                state == IdleServiceState::Up,
-                DependencyFailedToStartSnafu {
-                    service: live_service.node.name().to_string(),
-                    dependency: dep.to_string(),
-                }
-            )
-        }
-
-        Ok(())
-    }
-
-    pub async fn stop_service(
-        &self,
-        live_service: &LiveService,
-    ) -> Result<()> {
-        let dependents = self.get_dependents(live_service);
-        Self::wait_on_dependents_stopping(live_service.node.name(), &dependents).await?;
-        live_service.state.replace(ServiceState::Transitioning(
-            TransitioningServiceState::Stopping,
-        ));
-        live_service.stop_service(&self.config.dirs.logdir).await;
+            }
+            // Not meeting the condition is not a failure
+            Ok(false) => IdleServiceState::Skipped,
+            Err(err) => {
+                error!(
+                    "unable to check the condition of service {}: {err:?}",
+                    live_service.node.name()
+                );
+                IdleServiceState::Failed
+            }
+        };
+        guard.disarm();
         if let Err(err) = self
             .send
             .send(Request::UpdateServiceStatus(
                 live_service.node.name().to_string(),
-                IdleServiceState::Down,
+                state,
             ))
             .await
         {
@@ -290,74 +730,694 @@ impl LiveServiceGraph {
         Ok(())
     }
 
-    pub async fn stop_all_services(
+    /// Run the start of the service, its dependencies being up
+    async fn run_start(
         &self,
-        runlevel: RunLevel,
-    ) {
-        // This is unsafe because the futures may outlive the current scope
-        // We wait on them afterwards and we know that self will outlive them
-        // so it's safe to use it
-        let (_res, futures) = unsafe {
-            TokioScope::scope_and_collect(|s| {
-                for (service, live_service) in &self.live_services {
-                    s.spawn(async move {
-                        if live_service.node.service.runlevel() == runlevel {
-                            let dependents = self.get_dependents(live_service);
-                            for dependent in dependents {
-                                // Wait until the dependent is down
-                                // TODO: Log
-                                while let Ok(IdleServiceState::Up) =
-                                    dependent.tx.subscribe().recv().await
-                                {
-                                }
-                            }
-                            self.stop_service(live_service).await.unwrap();
+        live_service: &LiveService,
+    ) -> Result<bool> {
+        let pipes = self.service_pipes(live_service)?;
+        // Only taken once the dependencies are up, they could need a slot too
+        let _slot = match &self.start_slots {
+            // The semaphore is never closed
+            Some(start_slots) => start_slots.acquire().await.ok(),
+            None => None,
+        };
 
-                            // Self::stop_service only spawn the supervisor, we don't know if the
-                            // service has stopped yet. Get the state of each one
-                            if *live_service.state.borrow()
-                                == ServiceState::Idle(IdleServiceState::Up)
-                            {
-                                if let Ok(IdleServiceState::Up) =
-                                    live_service.tx.subscribe().recv().await
-                                {
-                                    warn!("service {service} didn't exit successfully");
-                                }
-                            }
-                        }
-                    });
-                }
-            })
-        }
-        .await;
-        for future in futures {
-            future.unwrap();
+        // Call the closure and let the new subscriber collect all the tracings
+        Ok(live_service
+            .start_service(
+                &self.config.dirs,
+                self.config.cgroup.as_deref(),
+                pipes,
+                self.send.clone(),
+            )
+            .await)
+    }
+
+    /// Ask the supervisor to start the services wanted by live_service, so
+    /// that its start doesn't wait for them
+    async fn request_wanted(
+        &self,
+        live_service: &LiveService,
+    ) {
+        let wanted = self.by_priority(live_service, live_service.node.service.wants());
+        for wanted in wanted {
+            let request = Request::StartWantedService {
+                service: live_service.node.name().to_string(),
+                wanted: wanted.to_string(),
+            };
+            if let Err(err) = self.send.send(request).await {
+                warn!("Could not start wanted service {wanted}: {err}");
+            }
         }
     }
 
-    pub fn get_service(
+    /// Start the service wanted by service. It is not needed by it, so its
+    /// failure is only logged
+    pub async fn start_wanted(
         &self,
-        name: &str,
-    ) -> Result<&LiveService> {
-        if self.live_services.contains_key(name) {
-            Ok(&self.live_services[name])
-        } else {
-            ServiceNotFoundSnafu {
-                service: name.to_string(),
+        service: &str,
+        wanted: &str,
+    ) {
+        let res = async {
+            let live_service = self.get_service(service)?;
+            let wanted_service = self.get_dependency(live_service, wanted)?;
+            self.start_service(wanted_service).await
+        };
+        match res.await {
+            Ok(()) => {}
+            Err(LiveGraphError::LogicError {
+                err: LogicError::ServiceSkipped { .. },
+            }) => {
+                info!("service {service} wants {wanted}, which has been skipped");
+            }
+            Err(err) => {
+                warn!("service {service} wants {wanted}, which didn't start: {err}");
             }
-            .fail()?
         }
     }
 
-    fn get_dependents(
+    async fn start_and_wait_dependencies(
         &self,
         live_service: &LiveService,
-    ) -> Vec<&LiveService> {
-        live_service
-            .node
-            .dependents
+        deadline: Option<Instant>,
+        path: &[&str],
+    ) -> Result<()> {
+        let dependencies = async {
+            self.start_dependencies(live_service, deadline, path).await?;
+            self.wait_on_deps_starting(live_service).await
+        };
+        let Some(deadline) = deadline else {
+            return dependencies.await;
+        };
+        if let Ok(res) = timeout_at(deadline, dependencies).await {
+            return res;
+        }
+
+        let pending = self.pending_dependencies(live_service);
+        warn!(
+            "service {} failed to start, dependencies {:?} are still pending after the subtree \
+             timeout",
+            live_service.node.name(),
+            pending
+        );
+        DependenciesTimedOutSnafu {
+            service: live_service.node.name().to_string(),
+            dependencies: pending,
+        }
+        .fail()?
+    }
+
+    /// Dependencies of the service that are not up yet
+    fn pending_dependencies(
+        &self,
+        live_service: &LiveService,
+    ) -> Vec<String> {
+        self.dependencies_of(live_service)
+            .into_iter()
+            .filter(|dep| {
+                self.get_dependency(live_service, dep)
+                    .map_or(true, |dep_service| !dep_service.is_available_now())
+            })
+            .collect()
+    }
+
+    /// Dependencies of the service, along with the services anchored before
+    /// it with the before option when it is a sync point. Only the anchored
+    /// services of the same runlevel that are started with it are waited on
+    fn dependencies_of(
+        &self,
+        live_service: &LiveService,
+    ) -> Vec<String> {
+        let name = live_service.node.name();
+        let mut dependencies = live_service.node.service.dependencies().to_vec();
+        let anchored: Vec<String> = self
+            .live_services
+            .values()
+            .filter(|anchored| {
+                anchored.node.service.before().iter().any(|before| before == name)
+                    && anchored.node.service.should_start()
+                    && anchored.node.service.runlevel() == live_service.node.service.runlevel()
+            })
+            .map(|anchored| anchored.node.name().to_string())
+            .filter(|anchored| !dependencies.contains(anchored))
+            .collect();
+        dependencies.extend(anchored);
+        dependencies
+    }
+
+    /// Status of the service, including the dependencies it is waiting on when
+    /// it is starting
+    pub fn status(
+        &self,
+        live_service: &LiveService,
+    ) -> ServiceStatus {
+        let mut status = live_service.status();
+        if status.status == ServiceState::Transitioning(TransitioningServiceState::Starting) {
+            status.waiting_on = self.pending_dependencies(live_service);
+        }
+        status
+    }
+
+    /// Snapshot of every service in the graph, to get the whole state in a
+    /// single request
+    pub fn dump(&self) -> Vec<ServiceSnapshot> {
+        self.live_services
+            .values()
+            .map(|live_service| {
+                let service = &live_service.node.service;
+                let mut dependents: Vec<String> =
+                    live_service.node.dependents.iter().cloned().collect();
+                dependents.sort();
+                ServiceSnapshot {
+                    service_type: service.type_name().to_string(),
+                    dependencies: service.dependencies().to_vec(),
+                    dependents,
+                    status: self.status(live_service),
+                }
+            })
+            .collect()
+    }
+
+    /// Memory and CPU time of the longruns with a running process. They are
+    /// read from the cgroup of the service when there is one, from the
+    /// supervised process otherwise
+    pub fn usage(&self) -> Vec<ServiceUsage> {
+        self.live_services
+            .values()
+            .filter(|live_service| matches!(live_service.node.service, Service::Longrun(_)))
+            .filter_map(|live_service| {
+                let name = live_service.node.name();
+                let pid = live_service.status().pid?;
+                let cgroup = self
+                    .config
+                    .cgroup
+                    .as_ref()
+                    .map(|parent| parent.join(name))
+                    .filter(|cgroup| cgroup.exists());
+                let from_cgroup = cgroup.is_some();
+                let usage = match cgroup {
+                    Some(cgroup) => ResourceUsage::of_cgroup(&cgroup),
+                    None => ResourceUsage::of_process(pid),
+                };
+                match usage {
+                    Ok(usage) => {
+                        Some(ServiceUsage {
+                            name: name.to_string(),
+                            memory: usage.memory,
+                            cpu_time: usage.cpu_time,
+                            from_cgroup,
+                        })
+                    }
+                    Err(err) => {
+                        // The process could have exited in the meantime
+                        warn!("unable to get the resource usage of {name}: {err:?}");
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Send signal to the process group of the running process of a longrun,
+    /// created by exec_script. The state of the service is left as it is, the
+    /// supervisor of the longrun handles the process exiting
+    pub fn signal_service(
+        &self,
+        live_service: &LiveService,
+        signal: i32,
+    ) -> Result<()> {
+        let signal = Signal::try_from(signal)
+            .ok()
+            .with_context(|| InvalidSignalSnafu { signal })?;
+        let pid = live_service.process_info.pid.get().with_context(|| {
+            ServiceNotRunningSnafu {
+                service: live_service.node.name().to_string(),
+            }
+        })?;
+        let pid = Pid::from_raw(pid as i32);
+        info!(
+            service = live_service.node.name(),
+            "sending signal {signal} to {pid}"
+        );
+        match process_group(pid) {
+            Some(group) => killpg(group, signal),
+            None => kill(pid, signal),
+        }
+        .context(SendSignalSnafu)?;
+        Ok(())
+    }
+
+    /// Make a running longrun reload its configuration by sending it its
+    /// reload_signal. With notify_reload, wait until it sends READY=1 after
+    /// RELOADING=1 on the notify socket, for at most its timeout
+    pub async fn reload_process(
+        &self,
+        live_service: &LiveService,
+    ) -> Result<()> {
+        let name = live_service.node.name();
+        let Service::Longrun(longrun) = &live_service.node.service else {
+            return ServiceNotRunningSnafu { service: name }.fail()?;
+        };
+        // Waiting starts before the signal, the process could be done
+        // reloading before it is sent
+        let reloaded = live_service.process_info.reloaded.notified();
+        self.signal_service(live_service, longrun.run.reload_signal)?;
+        if longrun.run.notify_reload {
+            let reload_timeout = Duration::from_millis(longrun.run.timeout as u64);
+            timeout(reload_timeout, reloaded)
+                .await
+                .ok()
+                .with_context(|| ReloadTimedOutSnafu { service: name })?;
+            info!(service = name, "reload done");
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(service = live_service.node.name()))]
+    async fn start_dependencies(
+        &self,
+        live_service: &LiveService,
+        deadline: Option<Instant>,
+        path: &[&str],
+    ) -> Result<()> {
+        let dependencies = self.dependencies_of(live_service);
+        let futures: Vec<_> = self
+            .by_priority(live_service, &dependencies)
+            .into_iter()
+            .map(|dep| {
+                async move {
+                    let res = self
+                        .start_dependency(live_service, dep, deadline, path)
+                        .await;
+                    (dep, res)
+                }
+            })
+            .collect();
+        // Start all the dependencies together and wait for every one of them,
+        // even after one has failed: they can be shared with other services,
+        // which must not see them cancelled
+        let mut failed: Vec<_> = join_all(futures)
+            .await
+            .into_iter()
+            .filter_map(|(dep, res)| res.err().map(|err| (dep, err)))
+            .collect();
+        match failed.len() {
+            0 => Ok(()),
+            1 => Err(failed.pop().unwrap().1),
+            _ => {
+                for (dep, err) in &failed {
+                    warn!("dependency {dep} of {}: {err}", live_service.node.name());
+                }
+                Err(DependenciesFailedToStartSnafu {
+                    service: live_service.node.name(),
+                    dependencies: failed
+                        .into_iter()
+                        .map(|(dep, _)| dep.to_string())
+                        .collect::<Vec<_>>(),
+                }
+                .build()
+                .into())
+            }
+        }
+    }
+
+    /// Start dep, a dependency of live_service, unless it is already up
+    async fn start_dependency(
+        &self,
+        live_service: &LiveService,
+        dep: &str,
+        deadline: Option<Instant>,
+        path: &[&str],
+    ) -> Result<()> {
+        let dep_service = self.get_dependency(live_service, dep)?;
+        // A dependency stuck in its transition fails the service, instead of
+        // being considered down and started again
+        let state = dep_service
+            .wait_idle_state_timeout(dep_service.get_timeout())
+            .await
+            .with_context(|| {
+                DependenciesTimedOutSnafu {
+                    service: live_service.node.name(),
+                    dependencies: vec![dep.to_string()],
+                }
+            })?;
+        if !state.is_down() {
+            return Ok(());
+        }
+        // Awaiting here is safe, as starting services always mean spawning
+        // rsupervisor
+        self.start_dependency_with_retries(dep_service, deadline, path)
+            .await
+            .map_err(|err| {
+                match err {
+                    LiveGraphError::LogicError {
+                        err:
+                            LogicError::ServiceFailedToStart { .. }
+                            | LogicError::ServiceSkipped { .. },
+                    } => {
+                        DependencyFailedToStartSnafu {
+                            service: live_service.node.name(),
+                            dependency: dep,
+                        }
+                        .build()
+                        .into()
+                    }
+                    err => err,
+                }
+            })
+    }
+
+    /// Start the dependency, starting it again up to dependency_start_retries
+    /// times when its own scripts fail, which could be transient. The other
+    /// errors, e.g. a missing dependency or one of its own dependencies that
+    /// failed (already retried), are returned right away
+    async fn start_dependency_with_retries(
+        &self,
+        dep_service: &LiveService,
+        deadline: Option<Instant>,
+        path: &[&str],
+    ) -> Result<()> {
+        let max_retries = self.config.dependency_start_retries.unwrap_or(0);
+        let mut delay = Self::DEPENDENCY_RETRY_DELAY;
+        let mut retries = 0;
+        loop {
+            match self.start_service_until(dep_service, deadline, path).await {
+                Err(LiveGraphError::LogicError {
+                    err: LogicError::ServiceFailedToStart { .. },
+                }) if retries < max_retries => {
+                    retries += 1;
+                    warn!(
+                        "dependency {} failed to start, retry {retries}/{max_retries} in {delay:?}",
+                        dep_service.node.name()
+                    );
+                    // The subtree timeout, if any, cancels the wait
+                    sleep(delay).await;
+                    delay *= 2;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Dependencies sorted by the priority of their service, the highest first,
+    /// so that they are started in this order. The ones that can't be found
+    /// are kept, the error is reported when starting them
+    fn by_priority<'a>(
+        &self,
+        live_service: &LiveService,
+        dependencies: &'a [String],
+    ) -> Vec<&'a String> {
+        let mut dependencies: Vec<&String> = dependencies.iter().collect();
+        dependencies.sort_by_key(|dep| {
+            Reverse(
+                self.get_dependency(live_service, dep)
+                    .map_or(0, |dep_service| dep_service.node.service.priority()),
+            )
+        });
+        dependencies
+    }
+
+    #[instrument(name = "wait_on_deps", skip_all, fields(service = live_service.node.name()))]
+    async fn wait_on_deps_starting(
+        &self,
+        live_service: &LiveService,
+    ) -> Result<()> {
+        for dep in self.dependencies_of(live_service) {
+            let dep_service = self.get_dependency(live_service, &dep)?;
+            let state = dep_service.wait_idle_state().await;
+            ensure!(
+                dep_service.is_available(state),
+                DependencyFailedToStartSnafu {
+                    service: live_service.node.name().to_string(),
+                    dependency: dep.to_string(),
+                }
+            )
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(service = live_service.node.name()))]
+    pub async fn stop_service(
+        &self,
+        live_service: &LiveService,
+    ) -> Result<()> {
+        let dependents = self.get_dependents(live_service);
+        Self::wait_on_dependents_stopping(live_service.node.name(), &dependents).await?;
+        live_service.update_state(ServiceState::Transitioning(
+            TransitioningServiceState::Stopping,
+        ));
+        let stop = live_service.stop_service(&self.config.dirs);
+        let stopped = match live_service.node.service.down_timeout() {
+            Some(down_timeout) => {
+                // Dropping the stop kills the scripts still running
+                timeout(Duration::from_millis(down_timeout as u64), stop)
+                    .await
+                    .unwrap_or_else(|_| {
+                        warn!(
+                            "service {} did not stop within {down_timeout}ms, forcing it down",
+                            live_service.node.name()
+                        );
+                        live_service.release(&self.config.dirs.rundir);
+                        true
+                    })
+            }
+            None => stop.await,
+        };
+        if let Err(err) = self
+            .send
+            .send(Request::UpdateServiceStatus(
+                live_service.node.name().to_string(),
+                if stopped {
+                    IdleServiceState::Down
+                } else {
+                    IdleServiceState::Up
+                },
+            ))
+            .await
+        {
+            warn!("Could not update service status: {err}");
+        }
+        ensure!(
+            stopped,
+            ServiceFailedToStopSnafu {
+                service: live_service.node.name().to_string(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Stop the running services conflicting with live_service, so that it
+    /// can be started
+    pub async fn stop_conflicts(
+        &self,
+        live_service: &LiveService,
+    ) -> Result<()> {
+        for conflict in self.get_conflicts(live_service) {
+            if conflict.wait_idle_state().await == IdleServiceState::Up {
+                self.stop_service(conflict).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn stop_all_services(
+        &self,
+        runlevel: RunLevel,
+    ) {
+        // This is unsafe because the futures may outlive the current scope
+        // We wait on them afterwards and we know that self will outlive them
+        // so it's safe to use it
+        let (_res, futures) = unsafe {
+            TokioScope::scope_and_collect(|s| {
+                for (service, live_service) in &self.live_services {
+                    s.spawn(async move {
+                        if live_service.node.service.runlevel() == runlevel {
+                            let dependents = self.get_dependents(live_service);
+                            for dependent in dependents {
+                                // Wait until the stop of the dependent is over.
+                                // If it failed, stop_service refuses to stop
+                                // this service as well
+                                if Self::is_running(dependent) {
+                                    let _ = dependent.tx.subscribe().recv().await;
+                                }
+                            }
+                            // Keep stopping the other services, the system is
+                            // going down anyway
+                            if let Err(err) = self.stop_service(live_service).await {
+                                error!("unable to stop service {service}: {err:?}");
+                                return;
+                            }
+
+                            // Self::stop_service only spawn the supervisor, we don't know if the
+                            // service has stopped yet. Get the state of each one
+                            if *live_service.state.borrow()
+                                == ServiceState::Idle(IdleServiceState::Up)
+                            {
+                                if let Ok(IdleServiceState::Up) =
+                                    live_service.tx.subscribe().recv().await
+                                {
+                                    warn!("service {service} didn't exit successfully");
+                                }
+                            }
+                        }
+                    });
+                }
+            })
+        }
+        .await;
+        for future in futures {
+            if let Err(err) = future {
+                error!("unable to stop a service: {err:?}");
+            }
+        }
+    }
+
+    pub fn get_service(
+        &self,
+        name: &str,
+    ) -> Result<&LiveService> {
+        if self.live_services.contains_key(name) {
+            Ok(&self.live_services[name])
+        } else {
+            ServiceNotFoundSnafu {
+                service: name.to_string(),
+            }
+            .fail()?
+        }
+    }
+
+    /// Get the service satisfying a dependency of live_service, either by its
+    /// name or by one of the aliases in its provides option. The dependency
+    /// graph should have checked that it exists and that it's not ambiguous, but
+    /// the graph on disk could have been edited by hand
+    fn get_dependency(
+        &self,
+        live_service: &LiveService,
+        dependency: &str,
+    ) -> Result<&LiveService> {
+        if let Some(dep_service) = self.live_services.get(dependency) {
+            return Ok(dep_service);
+        }
+        let provider = self
+            .aliases
+            .get(dependency)
+            .into_iter()
+            .flatten()
+            .find_map(|provider| self.live_services.get(provider));
+        let Some(provider) = provider else {
+            warn!(
+                "service {} depends on {dependency}, which is not enabled",
+                live_service.node.name()
+            );
+            return DependencyNotFoundSnafu {
+                service: live_service.node.name(),
+                dependency,
+            }
+            .fail()?;
+        };
+        Ok(provider)
+    }
+
+    /// Get one end of the pipe of the logger service, creating the pipe the
+    /// first time
+    fn logger_pipe_end(
+        &self,
+        logger: &str,
+        end: fn(&LoggerPipe) -> io::Result<OwnedFd>,
+    ) -> Result<OwnedFd> {
+        let mut logger_pipes = self.logger_pipes.borrow_mut();
+        let pipe = match logger_pipes.entry(logger.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(LoggerPipe::new().with_context(|_| LoggerPipeSnafu { logger })?)
+            }
+        };
+        Ok(end(pipe).with_context(|_| LoggerPipeSnafu { logger })?)
+    }
+
+    /// Connect a longrun to the logger services in its stdout and stderr, and
+    /// a logger service to the longruns sending their output to it
+    fn service_pipes(
+        &self,
+        live_service: &LiveService,
+    ) -> Result<ServicePipes> {
+        let Service::Longrun(longrun) = &live_service.node.service else {
+            return Ok(ServicePipes::default());
+        };
+        let name = live_service.node.name();
+        let is_logger = self.live_services.values().any(|other| {
+            match &other.node.service {
+                Service::Longrun(other) => {
+                    [&other.run.stdout, &other.run.stderr].into_iter().any(
+                        |output| matches!(output, ScriptOutput::Service(logger) if logger == name),
+                    )
+                }
+                _ => false,
+            }
+        });
+        let output = |output: &ScriptOutput| {
+            match output {
+                ScriptOutput::Service(logger) => {
+                    self.logger_pipe_end(logger, LoggerPipe::writer).map(Some)
+                }
+                _ => Ok(None),
+            }
+        };
+        Ok(ServicePipes {
+            stdin: if is_logger {
+                Some(self.logger_pipe_end(name, LoggerPipe::reader)?)
+            } else {
+                None
+            },
+            stdout: output(&longrun.run.stdout)?,
+            stderr: output(&longrun.run.stderr)?,
+            listen: None,
+        })
+    }
+
+    /// Get the services that conflict with live_service, either because it
+    /// lists them in its conflicts option or because they list it in theirs
+    fn get_conflicts(
+        &self,
+        live_service: &LiveService,
+    ) -> Vec<&LiveService> {
+        let name = live_service.node.name();
+        let conflicts = live_service.node.service.conflicts();
+        self.live_services
+            .values()
+            .filter(|other| other.node.name() != name)
+            .filter(|other| {
+                conflicts.iter().any(|conflict| conflict == other.node.name())
+                    || other
+                        .node
+                        .service
+                        .conflicts()
+                        .iter()
+                        .any(|conflict| conflict == name)
+            })
+            .collect()
+    }
+
+    fn is_running(live_service: &LiveService) -> bool {
+        matches!(
+            *live_service.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+                | ServiceState::Transitioning(TransitioningServiceState::Starting)
+        )
+    }
+
+    fn get_dependents(
+        &self,
+        live_service: &LiveService,
+    ) -> Vec<&LiveService> {
+        // The dependents that were down have already been dropped by a reload
+        live_service
+            .node
+            .dependents
             .iter()
-            .map(|dependant| &self.live_services[dependant])
+            .filter_map(|dependant| self.live_services.get(dependant))
             .collect()
     }
 
@@ -371,11 +1431,8 @@ impl LiveServiceGraph {
             .then(async move |dependent| -> (&LiveService, IdleServiceState) {
                 (dependent, dependent.wait_idle_state().await)
             })
-            .filter_map(|(dependent, state)|
-                match state {
-                    IdleServiceState::Down => None,
-                    IdleServiceState::Up => Some(dependent),
-                })
+            // The socket activated ones can spawn their process anytime
+            .filter_map(|(dependent, state)| dependent.is_available(state).then_some(dependent))
             .map(|live_service| live_service.node.name().to_owned())
             .collect::<Vec<String>>()
             .await;
@@ -390,7 +1447,7 @@ impl LiveServiceGraph {
         Ok(())
     }
 
-    pub async fn reload_dependency_graph(&mut self) -> Result<()> {
+    fn read_dependency_graph(&self) -> Result<DependencyGraph> {
         let graph_file = self.config.dirs.graph_filename();
         ensure!(
             graph_file.exists(),
@@ -398,9 +1455,15 @@ impl LiveServiceGraph {
                 path: graph_file.to_string_lossy()
             }
         );
-        let mut dep_graph: DependencyGraph =
+        Ok(
             serde_json::from_slice(&std::fs::read(graph_file).with_context(|_| ReadGraphSnafu)?)
-                .with_context(|_| JsonDeserializeSnafu)?;
+                .with_context(|_| JsonDeserializeSnafu)?,
+        )
+    }
+
+    pub async fn reload_dependency_graph(&mut self) -> Result<()> {
+        let mut dep_graph = self.read_dependency_graph()?;
+        self.aliases = dep_graph.aliases().clone();
 
         // Assume that the depedency graph only contains services that are needed
         // and that is correct. This way we can skip checking dependencies and other
@@ -433,37 +1496,21 @@ impl LiveServiceGraph {
             match service.1 {
                 // There is a new service, add it to the graph without starting it
                 (false, true) => {
-                    let new = LiveService::new(dep_graph.nodes.swap_remove(&name).unwrap());
+                    let new = LiveService::new(
+                        dep_graph.nodes.swap_remove(&name).unwrap(),
+                        self.events.clone(),
+                        self.reaper.clone(),
+                    );
                     self.live_services.insert(name, new);
                     index += index;
                 }
                 // This service is only the live state and not in the new dependency graph
                 // mark it for removal
-                (true, false) => {
-                    let state = *self.live_services[&name].state.borrow();
-                    // If a service is already down, just update it with the new one
-                    if matches!(state, ServiceState::Idle(IdleServiceState::Down)) {
-                        self.live_services.swap_remove(&name);
-                    } else {
-                        self.live_services[&name].remove = true;
-                    }
-                }
+                (true, false) => self.remove_service(&name),
                 // This service is in both graph, update it now/later
                 (true, true) => {
-                    let new_live_service =
-                        LiveService::new(dep_graph.nodes.swap_remove(&name).unwrap());
-                    let state = *self.live_services[&name].state.borrow();
-                    // If a service is already down, just update it with
-                    // the new one
-                    if state == ServiceState::Idle(IdleServiceState::Down) {
-                        new_live_service.update_state(state);
-                        self.live_services[&name] = new_live_service;
-                        // Keep the current state
-                    } else {
-                        // otherwise, mark it for update. It will be updated by
-                        // update_service_state
-                        self.live_services[&name].new = Some(Box::new(new_live_service));
-                    }
+                    let node = dep_graph.nodes.swap_remove(&name).unwrap();
+                    self.replace_service(&name, node);
                 }
                 (false, false) => unreachable!(),
             }
@@ -472,6 +1519,267 @@ impl LiveServiceGraph {
         Ok(())
     }
 
+    /// Drop the service name, which is not in the dependency graph anymore. A
+    /// service that is not down is only marked, it is dropped once it goes
+    /// down
+    fn remove_service(
+        &mut self,
+        name: &str,
+    ) {
+        let state = *self.live_services[name].state.borrow();
+        if matches!(state, ServiceState::Idle(state) if state.is_down()) {
+            self.live_services.swap_remove(name);
+        } else {
+            self.live_services[name].remove = true;
+        }
+    }
+
+    /// Stop managing the services in names that have been removed from the
+    /// graph: they keep running and are dropped from the live graph once they
+    /// go down
+    pub fn keep_running(
+        &mut self,
+        names: &[String],
+    ) {
+        for name in names {
+            if let Some(live_service) = self.live_services.get_mut(name) {
+                live_service.unmanaged = live_service.remove;
+            }
+        }
+    }
+
+    /// Read the dependency graph again, but only update the definition of
+    /// the service name. The services it now depends on are added as well,
+    /// the ones that are not in the graph anymore are removed
+    pub fn reload_service(
+        &mut self,
+        name: &str,
+    ) -> Result<()> {
+        self.get_service(name)?;
+        let mut dep_graph = self.read_dependency_graph()?;
+        self.aliases = dep_graph.aliases().clone();
+        let removed: Vec<String> = self
+            .live_services
+            .keys()
+            .filter(|name| !dep_graph.nodes.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in &removed {
+            self.remove_service(name);
+        }
+        let node = dep_graph.nodes.swap_remove(name);
+        for (name, node) in dep_graph.nodes {
+            match self.live_services.get_mut(&name) {
+                // The services depending on each service could have changed
+                Some(live_service) => live_service.node.dependents = node.dependents,
+                None => {
+                    let live_service =
+                        LiveService::new(node, self.events.clone(), self.reaper.clone());
+                    self.live_services.insert(name, live_service);
+                }
+            }
+        }
+        // It has been disabled
+        if let Some(node) = node {
+            self.replace_service(name, node);
+        }
+        Ok(())
+    }
+
+    /// Parse the file of the service name again and apply it like
+    /// reload_service. The dependency graph on disk is updated first, so that
+    /// the new definition survives a reload
+    pub fn reparse_service(
+        &mut self,
+        name: &str,
+    ) -> Result<()> {
+        self.get_service(name)?;
+        let mut dep_graph = self.read_dependency_graph()?;
+        let system = unsafe { libc::getuid() } == 0;
+        parse_services(vec![name.to_string()], &self.config.dirs, system)
+            .map_err(|err| error_chain(&err))
+            .and_then(|services| {
+                dep_graph
+                    .update_services(services)
+                    .map_err(|err| error_chain(&err))
+            })
+            .map_err(|err| ServiceFileRejectedSnafu { service: name, err }.build())?;
+        write_graph(&dep_graph, &self.config.dirs.graph_filename()).context(WriteGraphSnafu)?;
+        self.reload_service(name)
+    }
+
+    /// Replace the definition of a service. It is replaced right away when the
+    /// service is down or when the change doesn't need a restart; otherwise
+    /// the new definition is applied by update_service once it goes down
+    fn replace_service(
+        &mut self,
+        name: &str,
+        node: Node,
+    ) {
+        let live_service = &mut self.live_services[name];
+        // The service has been enabled again
+        live_service.remove = false;
+        live_service.unmanaged = false;
+        if live_service.node == node {
+            // Nothing changed, e.g. on a full reload. A definition waiting for
+            // the service to go down has been reverted
+            live_service.new = None;
+            return;
+        }
+        let state = *live_service.state.borrow();
+        if matches!(state, ServiceState::Idle(idle) if idle.is_down()) {
+            let new_live_service = LiveService::new(node, self.events.clone(), self.reaper.clone());
+            // Keep the current state, the service didn't go through a transition
+            new_live_service.state.replace(state);
+            new_live_service.since.replace(*live_service.since.borrow());
+            *live_service = new_live_service;
+        } else if !needs_restart(&live_service.node.service, &node.service) {
+            live_service.node = node;
+            live_service.new = None;
+        } else {
+            live_service.new = Some(Box::new(LiveService::new(
+                node,
+                self.events.clone(),
+                self.reaper.clone(),
+            )));
+        }
+    }
+
+    /// Save the services that are up or listening on their socket, along with
+    /// the processes of the longruns, for the rsvc that is about to be executed. The file
+    /// descriptors it needs are duplicated without close-on-exec, so that
+    /// they are inherited
+    pub fn save_reexec_state(&self) -> Result<ReexecState> {
+        let inherit = |name: &str, fd: Option<RawFd>| {
+            let fd = fd?;
+            match dup(fd) {
+                Ok(fd) => Some(fd),
+                Err(err) => {
+                    warn!("unable to hand over file descriptor {fd} of service {name}: {err}");
+                    None
+                }
+            }
+        };
+        let services = self
+            .live_services
+            .values()
+            .filter(|live_service| live_service.is_available_now())
+            .map(|live_service| {
+                let name = live_service.node.name();
+                let process_info = &live_service.process_info;
+                let [stdout, stderr] = process_info.output_fds.get();
+                SavedService {
+                    name: name.to_string(),
+                    pid: process_info.pid.get(),
+                    stdout: inherit(name, stdout),
+                    stderr: inherit(name, stderr),
+                    listen: live_service
+                        .is_socket_activated()
+                        .then(|| inherit(name, process_info.listen_fd.get()))
+                        .flatten(),
+                    last_run: live_service.last_run.get(),
+                }
+            })
+            .collect();
+        let state = ReexecState::new(services);
+        if let Err(err) = state.save(&self.config.dirs.rundir) {
+            state.discard(&self.config.dirs.rundir);
+            return Err(err).context(SaveReexecStateSnafu)?;
+        }
+        Ok(state)
+    }
+
+    /// Drop the state saved by save_reexec_state, rsvc could not be executed
+    pub fn discard_reexec_state(
+        &self,
+        state: ReexecState,
+    ) {
+        state.discard(&self.config.dirs.rundir);
+    }
+
+    /// Take over the services that were up in the rsvc that re-executed into
+    /// this one. The processes of the longruns are adopted and the timers of
+    /// the scheduled oneshots are armed again; the services that can't be are
+    /// started again
+    pub async fn resume(
+        &self,
+        state: ReexecState,
+    ) -> StartSummary {
+        let mut to_start = Vec::new();
+        for mut saved in state.services {
+            let fds = saved.take_fds();
+            let Some(live_service) = self.live_services.get(&saved.name) else {
+                warn!(
+                    "service {} is not in the dependency graph anymore, it is left as it is",
+                    saved.name
+                );
+                continue;
+            };
+            live_service.update_state(ServiceState::Transitioning(
+                TransitioningServiceState::Starting,
+            ));
+            let adopted = match &live_service.node.service {
+                Service::Longrun(_) => {
+                    match self.service_pipes(live_service) {
+                        Ok(pipes) => {
+                            live_service.adopt_process(
+                                saved.pid,
+                                fds,
+                                &self.config.dirs,
+                                self.config.cgroup.as_deref(),
+                                pipes,
+                                self.send.clone(),
+                            )
+                        }
+                        Err(err) => {
+                            error!("{err}");
+                            false
+                        }
+                    }
+                }
+                Service::Oneshot(oneshot) => {
+                    // Keep it up without running it, only its timer is gone
+                    if let Some(schedule) = &oneshot.options.schedule {
+                        live_service.resume_timer(
+                            oneshot,
+                            schedule,
+                            &self.config.dirs,
+                            saved.last_run,
+                        );
+                    }
+                    true
+                }
+                Service::Bundle(_) | Service::Virtual(_) => true,
+            };
+            if adopted {
+                let state = if live_service.waits_connection() {
+                    IdleServiceState::Down
+                } else {
+                    IdleServiceState::Up
+                };
+                if let Err(err) = self.update_service_state(&saved.name, state) {
+                    error!("{err}");
+                }
+            } else {
+                live_service.update_state(ServiceState::Idle(IdleServiceState::Down));
+                to_start.push(live_service);
+            }
+        }
+        let mut summary = StartSummary::default();
+        for live_service in to_start {
+            summary.add(
+                live_service.node.name(),
+                self.start_service(live_service).await,
+            );
+        }
+        if summary.is_success() {
+            info!("services started again after re-executing: {summary}");
+        } else {
+            warn!("services started again after re-executing: {summary}");
+        }
+        summary
+    }
+
     pub fn update_service_state(
         &self,
         name: &str,
@@ -479,8 +1787,22 @@ impl LiveServiceGraph {
     ) -> Result<()> {
         info!("Service {name} is {state}");
         let live_service = self.get_service(name)?;
+        // A socket activated longrun waiting for a connection starts when one
+        // arrives
+        if state != IdleServiceState::Down
+            && live_service.is_socket_activated()
+            && *live_service.state.borrow() == ServiceState::Idle(IdleServiceState::Down)
+        {
+            live_service.update_state(ServiceState::Transitioning(
+                TransitioningServiceState::Starting,
+            ));
+        }
         live_service.update_state(ServiceState::Idle(state));
-        live_service.tx.send(state).unwrap();
+        // The waiters are notified even when the transition has been
+        // rejected, with the state the service is still in
+        if let ServiceState::Idle(state) = *live_service.state.borrow() {
+            live_service.tx.send(state).unwrap();
+        }
         Ok(())
     }
 
@@ -519,3 +1841,2112 @@ impl LiveServiceGraph {
         Ok(())
     }
 }
+
+/// Whether a running service has to be restarted to use its new definition,
+/// i.e. its scripts or its environment changed. The options are only used when
+/// starting the service, except the schedule, which is read by the timer armed
+/// then
+fn needs_restart(
+    old: &Service,
+    new: &Service,
+) -> bool {
+    match (old, new) {
+        (Service::Longrun(old), Service::Longrun(new)) => {
+            old.run != new.run || old.finish != new.finish || old.environment != new.environment
+        }
+        (Service::Oneshot(old), Service::Oneshot(new)) => {
+            old.start != new.start
+                || old.stop != new.stop
+                || old.up_check != new.up_check
+                || old.environment != new.environment
+                || old.options.schedule != new.options.schedule
+        }
+        (Service::Bundle(_), Service::Bundle(_)) | (Service::Virtual(_), Service::Virtual(_)) => {
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Message of err followed by the ones of its sources, as the messages of the
+/// parser don't include them
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashSet,
+        env,
+        os::{
+            fd::IntoRawFd,
+            unix::{
+                fs::PermissionsExt,
+                net::UnixStream,
+            },
+        },
+        process::Stdio,
+        sync::{
+            Arc,
+            Mutex,
+        },
+        time::SystemTime,
+    };
+
+    use futures::future::join;
+    use rinit_service::{
+        test_util::ServiceBuilder,
+        types::{
+            Credential,
+            ListenSocket,
+            Script,
+            ScriptPrefix,
+            Service,
+            Virtual,
+        },
+    };
+    use tokio::{
+        task,
+        time::sleep,
+    };
+    use tracing::{
+        instrument::WithSubscriber,
+        metadata::LevelFilter,
+    };
+    use tracing_subscriber::FmtSubscriber;
+
+    use super::*;
+    use crate::test_util::{
+        new_config,
+        new_config_in,
+        new_live_graph,
+        test_dir,
+        with_status_updates,
+    };
+
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for SharedWriter {
+        fn write(
+            &mut self,
+            buf: &[u8],
+        ) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn trace_service_start() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("foo")
+                    .dependencies(&["bar"])
+                    .build(),
+                ServiceBuilder::oneshot("bar").build(),
+            ],
+            new_config(),
+        );
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let writer = output.clone();
+        let subscriber = FmtSubscriber::builder()
+            .with_writer(move || SharedWriter(writer.clone()))
+            .with_max_level(LevelFilter::DEBUG)
+            .with_ansi(false)
+            .finish();
+
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("foo").unwrap()),
+        )
+        .with_subscriber(subscriber)
+        .await;
+        assert!(res.is_ok());
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.contains(
+            "start_service{service=\"foo\"}:start_dependencies{service=\"foo\"}:\
+             start_service{service=\"bar\"}"
+        ));
+        assert!(output.contains("state changed service=\"bar\" from=down to=starting"));
+        assert!(output.contains("state changed service=\"foo\" from=starting to=up"));
+    }
+
+    #[tokio::test]
+    async fn wait_idle_state_skips_missed_notifications() {
+        let (live_graph, _rx) =
+            new_live_graph(vec![ServiceBuilder::oneshot("foo").build()], new_config());
+        let live_service = live_graph.get_service("foo").unwrap();
+        live_service.update_state(ServiceState::Transitioning(
+            TransitioningServiceState::Starting,
+        ));
+        let state = live_service.wait_idle_state();
+        // The waiter wakes up after both have been sent, the first one is lost
+        live_service.tx.send(IdleServiceState::Failed).unwrap();
+        live_service.tx.send(IdleServiceState::Up).unwrap();
+        assert_eq!(state.await, IdleServiceState::Up);
+    }
+
+    #[test]
+    fn graph_referencing_deleted_service() {
+        let dir = test_dir("rinit-test-dangling");
+        let config = new_config_in(&dir);
+        let mut graph = DependencyGraph::new();
+        let services = vec![
+            ServiceBuilder::oneshot("parent")
+                .dependencies(&["db"])
+                .build(),
+            ServiceBuilder::oneshot("db").build(),
+            ServiceBuilder::oneshot("other").build(),
+        ];
+        graph
+            .add_services(vec!["parent".to_string(), "other".to_string()], services)
+            .unwrap();
+        // db has been deleted from the graph on disk
+        graph.nodes.shift_remove("db");
+        graph.enabled_services = HashSet::from([0, 1]);
+        std::fs::write(
+            config.dirs.graph_filename(),
+            serde_json::to_vec(&graph).unwrap(),
+        )
+        .unwrap();
+        let (tx, _rx) = mpsc::channel(100);
+
+        assert!(matches!(
+            LiveServiceGraph::new(config, tx.clone()),
+            Err(LiveGraphError::SystemError {
+                err: SystemError::BrokenGraph { .. }
+            })
+        ));
+
+        let mut config = new_config_in(&dir);
+        config.prune_dangling_services = true;
+        let live_graph = LiveServiceGraph::new(config, tx).unwrap();
+        assert!(live_graph.get_service("parent").is_err());
+        assert!(live_graph.get_service("other").is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn runlevel_survives_restart() {
+        let config = new_config_in(&test_dir("rinit-test-runlevel"));
+        let rundir = config.dirs.rundir.clone();
+        let (tx, _rx) = mpsc::channel(100);
+        let mut live_graph = LiveServiceGraph::new(config, tx.clone()).unwrap();
+        assert_eq!(live_graph.config.default_runlevel, RunLevel::Default);
+        live_graph.set_default_runlevel(RunLevel::Boot).unwrap();
+        drop(live_graph);
+
+        let live_graph =
+            LiveServiceGraph::new(new_config_in(&test_dir("rinit-test-runlevel")), tx).unwrap();
+        assert_eq!(live_graph.config.default_runlevel, RunLevel::Boot);
+        std::fs::remove_dir_all(rundir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn wanted_service_failure_ignored() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("foo")
+                    .wants(&["bar", "missing"])
+                    .build(),
+                ServiceBuilder::oneshot("bar").start("exit 1").build(),
+            ],
+            new_config(),
+        );
+
+        let res = with_status_updates(&live_graph, &mut rx, async {
+            live_graph
+                .start_service(live_graph.get_service("foo").unwrap())
+                .await?;
+            // bar is started separately, foo doesn't wait for it
+            let bar = live_graph.get_service("bar").unwrap();
+            timeout(Duration::from_secs(1), async {
+                while *bar.state.borrow() != ServiceState::Idle(IdleServiceState::Failed) {
+                    sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .unwrap();
+            Ok::<_, LiveGraphError>(())
+        })
+        .await;
+        assert!(res.is_ok());
+        assert_eq!(
+            *live_graph.get_service("foo").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+        );
+    }
+
+    #[tokio::test]
+    async fn wanted_service_does_not_delay_start() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("foo").wants(&["bar"]).build(),
+                ServiceBuilder::oneshot("bar").start("sleep 2").build(),
+            ],
+            new_config(),
+        );
+
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            timeout(
+                Duration::from_secs(1),
+                live_graph.start_service(live_graph.get_service("foo").unwrap()),
+            ),
+        )
+        .await;
+        res.unwrap().unwrap();
+        assert_eq!(
+            *live_graph.get_service("foo").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+        );
+    }
+
+    #[tokio::test]
+    async fn socket_activated_dependency_down_until_connection() {
+        let dir = test_dir("rinit-test-activated-dependency");
+        let socket = dir.join("bar.sock");
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("foo").dependencies(&["bar"]).build(),
+                ServiceBuilder::longrun("bar")
+                    .start_script(|script| {
+                        script.listen = Some(ListenSocket::Unix(socket.clone()));
+                    })
+                    .build(),
+            ],
+            new_config_in(&dir),
+        );
+        let foo = live_graph.get_service("foo").unwrap();
+        let bar = live_graph.get_service("bar").unwrap();
+
+        task::LocalSet::new()
+            .run_until(with_status_updates(&live_graph, &mut rx, async {
+                live_graph.start_service(foo).await.unwrap();
+                assert_eq!(*foo.state.borrow(), ServiceState::Idle(IdleServiceState::Up));
+                assert_eq!(*bar.state.borrow(), ServiceState::Idle(IdleServiceState::Down));
+                assert!(bar.waits_connection());
+                // Starting it again keeps the socket bound
+                live_graph.start_service(bar).await.unwrap();
+
+                let mut states = bar.tx.subscribe();
+                let _client = UnixStream::connect(&socket).unwrap();
+                assert_eq!(states.recv().await.unwrap(), IdleServiceState::Up);
+                assert!(bar.status().pid.is_some());
+
+                live_graph.stop_service(foo).await.unwrap();
+                live_graph.stop_service(bar).await.unwrap();
+                assert_eq!(bar.wait_idle_state().await, IdleServiceState::Down);
+                assert!(!bar.waits_connection());
+            }))
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn condition_not_met_skips_service() {
+        let marker = env::temp_dir().join(format!("rinit-test-skipped-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("foo").wants(&["bar"]).build(),
+                ServiceBuilder::oneshot("bar")
+                    .start(&format!("touch {}", marker.display()))
+                    .condition("exit 1")
+                    .build(),
+                ServiceBuilder::oneshot("baz").condition("exit 0").build(),
+            ],
+            new_config(),
+        );
+
+        let summary = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_all_services(RunLevel::Default),
+        )
+        .await;
+        assert!(summary.is_success());
+        assert_eq!(summary.skipped, vec!["bar"]);
+        assert!(summary.to_string().ends_with(", 1 skipped"));
+        // The start script never ran
+        assert!(!marker.exists());
+        assert_eq!(
+            *live_graph.get_service("bar").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Skipped)
+        );
+        // The services wanting it are not affected
+        for service in ["foo", "baz"] {
+            assert_eq!(
+                *live_graph.get_service(service).unwrap().state.borrow(),
+                ServiceState::Idle(IdleServiceState::Up)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn skipped_dependency_fails_dependent() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("foo")
+                    .dependencies(&["bar"])
+                    .build(),
+                ServiceBuilder::oneshot("bar").condition("exit 1").build(),
+            ],
+            new_config(),
+        );
+
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("foo").unwrap()),
+        )
+        .await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::DependencyFailedToStart { dependency, .. }
+            }) if dependency == "bar"
+        ));
+        assert_eq!(
+            *live_graph.get_service("bar").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Skipped)
+        );
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("bar").unwrap()),
+        )
+        .await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::ServiceSkipped { .. }
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn sync_point_orders_boot_phases() {
+        let order = env::temp_dir().join(format!("rinit-test-phases-{}", std::process::id()));
+        let _ = std::fs::remove_file(&order);
+        let record =
+            |name: &str, delay: &str| format!("sleep {delay}; echo {name} >> {}", order.display());
+        let mut services = Vec::new();
+        for (name, delay) in [("mount", "0.3"), ("udev", "0.1")] {
+            services.push(
+                ServiceBuilder::oneshot(name)
+                    .start(&record(name, delay))
+                    .before(&["sysinit"])
+                    .build(),
+            );
+        }
+        for name in ["network", "sshd"] {
+            services.push(
+                ServiceBuilder::oneshot(name)
+                    .start(&record(name, "0"))
+                    .dependencies(&["sysinit"])
+                    .build(),
+            );
+        }
+        services.push(Service::Virtual(Virtual::well_known("sysinit").unwrap()));
+        let (live_graph, mut rx) = new_live_graph(services, new_config());
+
+        let summary = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_all_services(RunLevel::Default),
+        )
+        .await;
+        assert!(summary.is_success());
+        // No service of the second phase ran before the first phase was up
+        let order = std::fs::read_to_string(&order).unwrap();
+        let mut phases: Vec<&str> = order.lines().collect();
+        phases[..2].sort();
+        phases[2..].sort();
+        assert_eq!(phases, ["mount", "udev", "network", "sshd"]);
+    }
+
+    #[tokio::test]
+    async fn dependency_chain_too_deep() {
+        let mut config = new_config();
+        config.max_dependency_depth = Some(3);
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("a").dependencies(&["b"]).build(),
+                ServiceBuilder::oneshot("b").dependencies(&["c"]).build(),
+                ServiceBuilder::oneshot("c").dependencies(&["d"]).build(),
+                ServiceBuilder::oneshot("d").build(),
+            ],
+            config,
+        );
+
+        let res = live_graph
+            .start_service(live_graph.get_service("a").unwrap())
+            .await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::DependencyTooDeep { path, max_depth: 3 }
+            }) if path == ["a", "b", "c", "d"]
+        ));
+        for service in ["a", "b", "c", "d"] {
+            assert_eq!(
+                *live_graph.get_service(service).unwrap().state.borrow(),
+                ServiceState::Idle(IdleServiceState::Down)
+            );
+        }
+
+        // A shorter chain is within the limit
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("b").unwrap()),
+        )
+        .await;
+        assert!(res.is_ok());
+    }
+
+    #[tokio::test]
+    async fn dependency_stuck_in_transition() {
+        let stuck = ServiceBuilder::oneshot("stuck")
+            .start_script(|start| {
+                start.timeout = 100;
+                start.max_deaths = 1;
+            })
+            .build();
+        let (live_graph, _rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("parent")
+                    .dependencies(&["stuck"])
+                    .build(),
+                stuck,
+            ],
+            new_config(),
+        );
+        // Nothing will ever move the service out of Starting
+        let stuck = live_graph.get_service("stuck").unwrap();
+        assert!(stuck.update_state(ServiceState::Transitioning(
+            TransitioningServiceState::Starting
+        )));
+
+        assert_eq!(
+            stuck
+                .wait_idle_state_timeout(Duration::from_millis(50))
+                .await,
+            None
+        );
+        let start = Instant::now();
+        let res = live_graph
+            .start_service(live_graph.get_service("parent").unwrap())
+            .await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::DependenciesTimedOut { .. }
+            })
+        ));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        // The dependency has not been started again
+        assert_eq!(
+            *stuck.state.borrow(),
+            ServiceState::Transitioning(TransitioningServiceState::Starting)
+        );
+    }
+
+    #[tokio::test]
+    async fn subtree_timeout_with_slow_dependency() {
+        let mut config = new_config();
+        config.subtree_timeout = Some(200);
+        let (live_graph, _rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("parent")
+                    .dependencies(&["middle"])
+                    .build(),
+                ServiceBuilder::oneshot("middle")
+                    .dependencies(&["leaf"])
+                    .build(),
+                ServiceBuilder::oneshot("leaf").start("sleep 5").build(),
+            ],
+            config,
+        );
+
+        let start = Instant::now();
+        let res = live_graph
+            .start_service(live_graph.get_service("parent").unwrap())
+            .await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::DependenciesTimedOut { .. }
+            })
+        ));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        for service in ["parent", "middle", "leaf"] {
+            assert_eq!(
+                *live_graph.get_service(service).unwrap().state.borrow(),
+                ServiceState::Idle(IdleServiceState::Down)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_to_status_changes() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::oneshot("service").build()],
+            new_config(),
+        );
+        let mut events = live_graph.subscribe();
+
+        let (res, _) = tokio::join!(
+            live_graph.start_service(live_graph.get_service("service").unwrap()),
+            async {
+                // Act as the RequestHandler for the status update
+                if let Some(Request::UpdateServiceStatus(name, state)) = rx.recv().await {
+                    live_graph.update_service_state(&name, state).unwrap();
+                }
+            }
+        );
+        res.unwrap();
+
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.service, "service");
+        assert_eq!(event.from, ServiceState::Idle(IdleServiceState::Down));
+        assert_eq!(
+            event.to,
+            ServiceState::Transitioning(TransitioningServiceState::Starting)
+        );
+        let event = events.recv().await.unwrap();
+        assert_eq!(
+            event.from,
+            ServiceState::Transitioning(TransitioningServiceState::Starting)
+        );
+        assert_eq!(event.to, ServiceState::Idle(IdleServiceState::Up));
+        assert!(events.try_recv().is_err());
+    }
+
+    async fn stop_failing_service(must_succeed: bool) -> (LiveServiceGraph, Result<()>) {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::oneshot("mount")
+                .stop("exit 1")
+                .stop_must_succeed(must_succeed)
+                .build()],
+            new_config(),
+        );
+        let live_service = live_graph.get_service("mount").unwrap();
+        live_service.update_state(ServiceState::Transitioning(
+            TransitioningServiceState::Starting,
+        ));
+        live_service.update_state(ServiceState::Idle(IdleServiceState::Up));
+        let res = live_graph.stop_service(live_service).await;
+        // Act as the RequestHandler for the status update
+        if let Ok(Request::UpdateServiceStatus(name, state)) = rx.try_recv() {
+            live_graph.update_service_state(&name, state).unwrap();
+        }
+        (live_graph, res)
+    }
+
+    #[tokio::test]
+    async fn failing_stop_script_must_succeed() {
+        let (live_graph, res) = stop_failing_service(true).await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::ServiceFailedToStop { .. }
+            })
+        ));
+        let live_service = live_graph.get_service("mount").unwrap();
+        assert_eq!(
+            *live_service.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+        );
+        assert_eq!(*live_service.stop_success.borrow(), Some(false));
+    }
+
+    #[tokio::test]
+    async fn failing_stop_script_ignored() {
+        let (live_graph, res) = stop_failing_service(false).await;
+        res.unwrap();
+        let live_service = live_graph.get_service("mount").unwrap();
+        assert_eq!(
+            *live_service.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Down)
+        );
+        assert_eq!(*live_service.stop_success.borrow(), Some(false));
+    }
+
+    #[tokio::test]
+    async fn stop_forced_down_after_timeout() {
+        // The stop script ignores the signals for longer than down_timeout
+        let service = ServiceBuilder::oneshot("mount")
+            .stop("trap '' TERM; sleep 5")
+            .stop_must_succeed(true)
+            .options(|options| options.down_timeout = Some(300))
+            .build();
+        let (live_graph, mut rx) = new_live_graph(vec![service], new_config());
+        let live_service = live_graph.get_service("mount").unwrap();
+        live_service.update_state(ServiceState::Transitioning(
+            TransitioningServiceState::Starting,
+        ));
+        live_service.update_state(ServiceState::Idle(IdleServiceState::Up));
+
+        let start = Instant::now();
+        let res = with_status_updates(&live_graph, &mut rx, async {
+            live_graph.stop_service(live_service).await?;
+            Ok::<_, LiveGraphError>(live_service.wait_idle_state().await)
+        })
+        .await;
+        assert!(matches!(res, Ok(IdleServiceState::Down)));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(*live_service.stop_success.borrow(), Some(false));
+    }
+
+    async fn start_default_runlevel(default_runlevel: RunLevel) -> LiveServiceGraph {
+        let mut config = new_config();
+        config.default_runlevel = default_runlevel;
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("boot")
+                    .runlevel(RunLevel::Boot)
+                    .build(),
+                ServiceBuilder::oneshot("default")
+                    .runlevel(RunLevel::Default)
+                    .build(),
+            ],
+            config,
+        );
+        let summary =
+            with_status_updates(&live_graph, &mut rx, live_graph.start_default_runlevel()).await;
+        assert!(summary.is_success());
+        live_graph
+    }
+
+    #[tokio::test]
+    async fn start_only_boot_runlevel() {
+        let live_graph = start_default_runlevel(RunLevel::Boot).await;
+        assert_eq!(
+            *live_graph.get_service("boot").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+        );
+        assert_eq!(
+            *live_graph.get_service("default").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Down)
+        );
+    }
+
+    #[tokio::test]
+    async fn start_default_runlevel_after_boot() {
+        let live_graph = start_default_runlevel(RunLevel::Default).await;
+        for service in ["boot", "default"] {
+            assert_eq!(
+                *live_graph.get_service(service).unwrap().state.borrow(),
+                ServiceState::Idle(IdleServiceState::Up)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn reload_config_changes_logdir() {
+        let dir = env::temp_dir().join(format!("rinit-reload-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_file = dir.join("rinit.conf");
+        let write_config = |logdir: &str| {
+            std::fs::write(
+                &config_file,
+                format!("logdir = {:?}\n", dir.join(logdir).to_str().unwrap()),
+            )
+            .unwrap();
+        };
+        write_config("old");
+        let (mut live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::oneshot("service")
+                .start("echo started")
+                .build()],
+            Config::new(Some(config_file.clone())).unwrap(),
+        );
+
+        write_config("new");
+        live_graph.reload_config(Some(config_file.clone())).unwrap();
+        let (res, _) = tokio::join!(
+            live_graph.start_service(live_graph.get_service("service").unwrap()),
+            async {
+                // Act as the RequestHandler for the status update
+                if let Some(Request::UpdateServiceStatus(name, state)) = rx.recv().await {
+                    live_graph.update_service_state(&name, state).unwrap();
+                }
+            }
+        );
+        res.unwrap();
+
+        assert!(dir.join("new").join("service").exists());
+        assert!(!dir.join("old").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reload_config_keeps_runlevel() {
+        let dir = test_dir("rinit-reload-config-runlevel");
+        let config_file = dir.join("rinit.conf");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            &config_file,
+            format!(
+                "rundir = {:?}\ndatadir = {:?}\ndefault_runlevel = \"default\"\n",
+                dir.to_str().unwrap(),
+                dir.to_str().unwrap()
+            ),
+        )
+        .unwrap();
+        let (tx, _rx) = mpsc::channel(100);
+        let mut live_graph =
+            LiveServiceGraph::new(Config::new(Some(config_file.clone())).unwrap(), tx).unwrap();
+        live_graph.set_default_runlevel(RunLevel::Boot).unwrap();
+
+        live_graph.reload_config(Some(config_file)).unwrap();
+        assert_eq!(live_graph.config.default_runlevel, RunLevel::Boot);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reload_edited_service() {
+        let dir = env::temp_dir().join(format!("rinit-reload-service-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("log");
+        let record = |version: &str| format!("echo {version} >> {}", log.display());
+        let write_graph = |services: Vec<Service>| {
+            let mut graph = DependencyGraph::new();
+            graph
+                .add_services(vec!["foo".to_string()], services)
+                .unwrap();
+            std::fs::write(dir.join("graph.data"), serde_json::to_vec(&graph).unwrap()).unwrap();
+        };
+        let mut config = new_config();
+        config.dirs.datadir = dir.clone();
+        let (mut live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::oneshot("foo").start(&record("v1")).build()],
+            config,
+        );
+        with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("foo").unwrap()),
+        )
+        .await
+        .unwrap();
+
+        // Only the options changed, it doesn't need to be restarted
+        write_graph(vec![
+            ServiceBuilder::oneshot("foo")
+                .start(&record("v1"))
+                .dependencies(&["bar"])
+                .build(),
+            ServiceBuilder::oneshot("bar").build(),
+        ]);
+        live_graph.reload_service("foo").unwrap();
+        let foo = live_graph.get_service("foo").unwrap();
+        assert_eq!(foo.node.service.dependencies(), ["bar".to_string()]);
+        assert!(foo.new.is_none());
+        assert_eq!(
+            *foo.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+        );
+        assert_eq!(
+            live_graph.get_service("bar").unwrap().node.dependents,
+            HashSet::from(["foo".to_string()])
+        );
+
+        // The new script is used once the service is restarted
+        write_graph(vec![
+            ServiceBuilder::oneshot("foo")
+                .start(&record("v2"))
+                .dependencies(&["bar"])
+                .build(),
+            ServiceBuilder::oneshot("bar").build(),
+        ]);
+        live_graph.reload_service("foo").unwrap();
+        let foo = live_graph.get_service("foo").unwrap();
+        assert!(foo.new.is_some());
+        with_status_updates(&live_graph, &mut rx, async {
+            live_graph.stop_service(foo).await.unwrap();
+            assert_eq!(foo.wait_idle_state().await, IdleServiceState::Down);
+        })
+        .await;
+        // As done by the RequestHandler once the service is down
+        live_graph.update_service("foo").unwrap();
+        with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("foo").unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "v1\nv2\n");
+
+        // bar is up, it is only dropped once it goes down
+        write_graph(vec![ServiceBuilder::oneshot("foo")
+            .start(&record("v2"))
+            .build()]);
+        live_graph.reload_service("foo").unwrap();
+        assert!(live_graph.get_service("bar").unwrap().remove);
+        assert!(live_graph.get_service("foo").unwrap().new.is_none());
+
+        // Reverting the change before a restart drops the pending definition
+        write_graph(vec![ServiceBuilder::oneshot("foo")
+            .start(&record("v3"))
+            .build()]);
+        live_graph.reload_service("foo").unwrap();
+        assert!(live_graph.get_service("foo").unwrap().new.is_some());
+        write_graph(vec![ServiceBuilder::oneshot("foo")
+            .start(&record("v2"))
+            .build()]);
+        live_graph.reload_dependency_graph().await.unwrap();
+        assert!(live_graph.get_service("foo").unwrap().new.is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reload_config_rejects_rundir_change() {
+        let dir = env::temp_dir().join(format!("rinit-reload-rundir-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_file = dir.join("rinit.conf");
+        std::fs::write(&config_file, "rundir = \"/run/rinit-old\"\n").unwrap();
+        let (mut live_graph, _rx) =
+            new_live_graph(Vec::new(), Config::new(Some(config_file.clone())).unwrap());
+
+        std::fs::write(&config_file, "rundir = \"/run/rinit-new\"\n").unwrap();
+        let res = live_graph.reload_config(Some(config_file));
+        std::fs::remove_dir_all(dir).unwrap();
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::ConfigChangeRequiresRestart { .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn rejected_transition_notifies_waiters() {
+        let (live_graph, _rx) =
+            new_live_graph(vec![ServiceBuilder::oneshot("foo").build()], new_config());
+        let mut rx = live_graph.get_service("foo").unwrap().tx.subscribe();
+
+        // A service can't become up without starting
+        live_graph
+            .update_service_state("foo", IdleServiceState::Up)
+            .unwrap();
+        assert_eq!(rx.try_recv(), Ok(IdleServiceState::Down));
+    }
+
+    #[tokio::test]
+    async fn dependency_failures_wait_for_other_dependencies() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("parent")
+                    .dependencies(&["slow", "broken", "crashing"])
+                    .build(),
+                ServiceBuilder::oneshot("slow").start("sleep 0.5").build(),
+                ServiceBuilder::oneshot("broken").start("exit 1").build(),
+                ServiceBuilder::oneshot("crashing").start("exit 2").build(),
+            ],
+            new_config(),
+        );
+
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("parent").unwrap()),
+        )
+        .await;
+        let Err(LiveGraphError::LogicError {
+            err:
+                LogicError::DependenciesFailedToStart {
+                    service,
+                    mut dependencies,
+                },
+        }) = res
+        else {
+            panic!("expected the dependencies to fail, got {res:?}");
+        };
+        dependencies.sort();
+        assert_eq!(service, "parent");
+        assert_eq!(dependencies, ["broken", "crashing"]);
+        // slow has not been cancelled by the failures
+        let state = |service| *live_graph.get_service(service).unwrap().state.borrow();
+        assert_eq!(state("slow"), ServiceState::Idle(IdleServiceState::Up));
+        assert_eq!(state("parent"), ServiceState::Idle(IdleServiceState::Down));
+        assert_eq!(
+            state("broken"),
+            ServiceState::Idle(IdleServiceState::Failed)
+        );
+    }
+
+    #[tokio::test]
+    async fn shared_dependency_survives_sibling_failure() {
+        let dir = test_dir("rinit-test-shared-dependency");
+        let config = new_config_in(&dir);
+        let started = dir.join("started");
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("parent")
+                    .dependencies(&["db", "broken"])
+                    .build(),
+                ServiceBuilder::oneshot("web").dependencies(&["db"]).build(),
+                ServiceBuilder::oneshot("db")
+                    .start(&format!("echo db >> {}; sleep 0.5", started.display()))
+                    .build(),
+                ServiceBuilder::oneshot("broken").start("exit 1").build(),
+            ],
+            config,
+        );
+
+        // broken fails while db is still starting for both parent and web
+        let (parent, web) = with_status_updates(
+            &live_graph,
+            &mut rx,
+            join(
+                live_graph.start_service(live_graph.get_service("parent").unwrap()),
+                live_graph.start_service(live_graph.get_service("web").unwrap()),
+            ),
+        )
+        .await;
+        assert!(parent.is_err());
+        web.unwrap();
+        for service in ["db", "web"] {
+            assert_eq!(
+                *live_graph.get_service(service).unwrap().state.borrow(),
+                ServiceState::Idle(IdleServiceState::Up)
+            );
+        }
+        // db has not been killed and started again
+        assert_eq!(std::fs::read_to_string(&started).unwrap(), "db\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn serial_start_order() {
+        let log = env::temp_dir().join("rinit-test-serial-start");
+        let _ = std::fs::remove_file(&log);
+        let record = |name: &str| format!("echo {name} >> {}", log.display());
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("network")
+                    .start(&record("network"))
+                    .dependencies(&["modules", "hostname"])
+                    .build(),
+                ServiceBuilder::oneshot("hostname")
+                    .start(&record("hostname"))
+                    .build(),
+                ServiceBuilder::oneshot("modules")
+                    .start(&record("modules"))
+                    .build(),
+                ServiceBuilder::oneshot("mount")
+                    .start(&record("mount"))
+                    .dependencies(&["modules"])
+                    .build(),
+            ],
+            Config {
+                serial_start: true,
+                ..new_config()
+            },
+        );
+
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_all_services(RunLevel::Default),
+        )
+        .await;
+        assert!(res.is_success());
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            "modules\nhostname\nnetwork\nmount\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn start_summary_lists_failures() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("network").build(),
+                ServiceBuilder::oneshot("mount").start("exit 1").build(),
+                ServiceBuilder::oneshot("sshd")
+                    .dependencies(&["network"])
+                    .build(),
+                ServiceBuilder::oneshot("swap").start("exit 2").build(),
+            ],
+            new_config(),
+        );
+
+        let mut summary = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_all_services(RunLevel::Default),
+        )
+        .await;
+        assert!(!summary.is_success());
+        summary.started.sort();
+        assert_eq!(summary.started, vec!["network", "sshd"]);
+        summary.failed.sort();
+        assert_eq!(
+            summary
+                .failed
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["mount", "swap"]
+        );
+        let message = summary.to_string();
+        assert!(message.starts_with("2 started, 2 failed"));
+        assert!(message.contains("mount: "));
+    }
+
+    #[tokio::test]
+    async fn arm_scheduled_oneshot() {
+        let output = env::temp_dir().join(format!("rinit-test-scheduled-{}", std::process::id()));
+        let service = ServiceBuilder::oneshot("backup")
+            .start(&format!("touch {}", output.display()))
+            .options(|options| options.schedule = Some("* * * * *".parse().unwrap()))
+            .build();
+        let (live_graph, mut rx) = new_live_graph(vec![service], new_config());
+
+        task::LocalSet::new()
+            .run_until(async {
+                let live_service = live_graph.get_service("backup").unwrap();
+                with_status_updates(&live_graph, &mut rx, live_graph.start_service(live_service))
+                    .await
+                    .unwrap();
+                assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Up);
+                // Let the timer compute the next run
+                sleep(Duration::from_millis(50)).await;
+                let status = live_service.status();
+                let next_run = status.next_run.unwrap();
+                assert!(next_run > SystemTime::now());
+                assert!(next_run <= SystemTime::now() + Duration::from_secs(60));
+                // The start script waits for the schedule to fire
+                assert_eq!(status.last_run, None);
+                assert!(!output.exists());
+
+                with_status_updates(&live_graph, &mut rx, live_graph.stop_service(live_service))
+                    .await
+                    .unwrap();
+                assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Down);
+                sleep(Duration::from_millis(50)).await;
+                assert_eq!(live_service.status().next_run, None);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn start_by_priority() {
+        let output = env::temp_dir().join(format!("rinit-test-priority-{}", std::process::id()));
+        let new_service = |name: &str, priority, dependencies: &[&str]| {
+            // Written twice to catch two scripts running at the same time
+            let execute = format!(
+                "echo {name} >> {0}; sleep 0.05; echo {name} >> {0}",
+                output.display()
+            );
+            ServiceBuilder::oneshot(name)
+                .start(&execute)
+                .dependencies(dependencies)
+                .options(|options| options.priority = priority)
+                .build()
+        };
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                new_service("low", -1, &[]),
+                new_service("mid", 0, &[]),
+                new_service("high", 10, &[]),
+            ],
+            Config {
+                max_parallel_starts: Some(1),
+                ..new_config()
+            },
+        );
+        let starting_order = |events: &mut broadcast::Receiver<StatusChanged>| {
+            let mut order = Vec::new();
+            while let Ok(event) = events.try_recv() {
+                if event.to == ServiceState::Transitioning(TransitioningServiceState::Starting) {
+                    order.push(event.service);
+                }
+            }
+            order
+        };
+
+        let mut events = live_graph.subscribe();
+        let summary = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_all_services(RunLevel::Default),
+        )
+        .await;
+        assert!(summary.is_success());
+        assert_eq!(starting_order(&mut events), vec!["high", "mid", "low"]);
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "high\nhigh\nmid\nmid\nlow\nlow\n"
+        );
+        std::fs::remove_file(&output).unwrap();
+
+        // The dependencies of a service are ordered too
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                new_service("app", 0, &["cache", "db"]),
+                new_service("cache", 0, &[]),
+                new_service("db", 5, &[]),
+            ],
+            Config {
+                max_parallel_starts: Some(1),
+                ..new_config()
+            },
+        );
+        let mut events = live_graph.subscribe();
+        with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("app").unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(starting_order(&mut events), vec!["app", "db", "cache"]);
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "db\ndb\ncache\ncache\napp\napp\n"
+        );
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn status_waiting_on_dependencies() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("parent")
+                    .dependencies(&["db", "network"])
+                    .build(),
+                ServiceBuilder::oneshot("db").start("sleep 1").build(),
+                ServiceBuilder::oneshot("network").build(),
+            ],
+            new_config(),
+        );
+        let parent = live_graph.get_service("parent").unwrap();
+
+        let check = async {
+            sleep(Duration::from_millis(300)).await;
+            let status = live_graph.status(parent);
+            assert_eq!(
+                status.status,
+                ServiceState::Transitioning(TransitioningServiceState::Starting)
+            );
+            assert_eq!(status.waiting_on, vec!["db".to_string()]);
+        };
+        let (res, _) = with_status_updates(
+            &live_graph,
+            &mut rx,
+            futures::future::join(live_graph.start_service(parent), check),
+        )
+        .await;
+        res.unwrap();
+        assert!(live_graph.status(parent).waiting_on.is_empty());
+    }
+
+    #[tokio::test]
+    async fn dump_all_services() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("parent")
+                    .dependencies(&["child"])
+                    .build(),
+                ServiceBuilder::oneshot("child").build(),
+                ServiceBuilder::oneshot("broken").start("exit 1").build(),
+                ServiceBuilder::oneshot("idle").build(),
+            ],
+            new_config(),
+        );
+        with_status_updates(&live_graph, &mut rx, async {
+            live_graph
+                .start_service(live_graph.get_service("parent").unwrap())
+                .await
+                .unwrap();
+            assert!(live_graph
+                .start_service(live_graph.get_service("broken").unwrap())
+                .await
+                .is_err());
+        })
+        .await;
+
+        let mut dump = live_graph.dump();
+        dump.sort_by(|a, b| a.status.name.cmp(&b.status.name));
+        let summary: Vec<_> = dump
+            .iter()
+            .map(|snapshot| {
+                (
+                    snapshot.status.name.as_str(),
+                    snapshot.service_type.as_str(),
+                    snapshot.status.status,
+                    snapshot.dependencies.clone(),
+                    snapshot.dependents.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "broken",
+                    "oneshot",
+                    ServiceState::Idle(IdleServiceState::Failed),
+                    vec![],
+                    vec![]
+                ),
+                (
+                    "child",
+                    "oneshot",
+                    ServiceState::Idle(IdleServiceState::Up),
+                    vec![],
+                    vec!["parent".to_string()]
+                ),
+                (
+                    "idle",
+                    "oneshot",
+                    ServiceState::Idle(IdleServiceState::Down),
+                    vec![],
+                    vec![]
+                ),
+                (
+                    "parent",
+                    "oneshot",
+                    ServiceState::Idle(IdleServiceState::Up),
+                    vec!["child".to_string()],
+                    vec![]
+                ),
+            ]
+        );
+        let parent = &dump[3];
+        assert!(parent.status.since.is_some());
+        assert!(parent.status.up_since.is_some());
+        assert!(dump[2].status.since.is_none());
+    }
+
+    fn new_conflicting_oneshots() -> Vec<Service> {
+        // dhclient doesn't declare the conflict, it is implied by dhcpcd
+        vec![
+            ServiceBuilder::oneshot("dhcpcd")
+                .conflicts(&["dhclient"])
+                .build(),
+            ServiceBuilder::oneshot("dhclient").build(),
+        ]
+    }
+
+    #[tokio::test]
+    async fn refuse_to_start_conflicting_service() {
+        let (live_graph, mut rx) = new_live_graph(new_conflicting_oneshots(), new_config());
+        let dhcpcd = live_graph.get_service("dhcpcd").unwrap();
+        let dhclient = live_graph.get_service("dhclient").unwrap();
+
+        let res = with_status_updates(&live_graph, &mut rx, async {
+            live_graph.start_service(dhclient).await.unwrap();
+            live_graph.start_service(dhcpcd).await
+        })
+        .await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::ConflictingServiceRunning { service, conflict }
+            }) if service == "dhcpcd" && conflict == "dhclient"
+        ));
+        assert_eq!(
+            *dhcpcd.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Down)
+        );
+        assert_eq!(
+            *dhclient.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+        );
+    }
+
+    #[tokio::test]
+    async fn stop_conflicting_service() {
+        let (live_graph, mut rx) = new_live_graph(new_conflicting_oneshots(), new_config());
+        let dhcpcd = live_graph.get_service("dhcpcd").unwrap();
+        let dhclient = live_graph.get_service("dhclient").unwrap();
+
+        with_status_updates(&live_graph, &mut rx, async {
+            live_graph.start_service(dhcpcd).await.unwrap();
+            live_graph.stop_conflicts(dhclient).await.unwrap();
+            live_graph.start_service(dhclient).await.unwrap();
+        })
+        .await;
+        assert_eq!(
+            *dhcpcd.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Down)
+        );
+        assert_eq!(
+            *dhclient.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+        );
+    }
+
+    #[tokio::test]
+    async fn credential_passed_to_script() {
+        let path = env::temp_dir().join(format!("rinit-test-db-password-{}", std::process::id()));
+        std::fs::write(&path, "hunter2\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        // The secret must not appear in the script either
+        let execute = format!("test \"$DB_PASSWORD\" = \"$(cat {})\"", path.display());
+        let service = ServiceBuilder::oneshot("db")
+            .start(&execute)
+            .options(|options| {
+                options.credentials = vec![Credential {
+                    name: "DB_PASSWORD".to_string(),
+                    path: path.clone(),
+                }]
+            })
+            .build();
+        let (live_graph, mut rx) = new_live_graph(vec![service], new_config());
+        let live_service = live_graph.get_service("db").unwrap();
+
+        let res =
+            with_status_updates(&live_graph, &mut rx, live_graph.start_service(live_service)).await;
+        assert!(res.is_ok());
+        assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Up);
+        // Only the path is part of the graph written to disk
+        let node = serde_json::to_string(&live_service.node).unwrap();
+        assert!(node.contains(path.to_str().unwrap()));
+        assert!(!node.contains("hunter2"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn target_unblocks_dependent() {
+        let online = env::temp_dir().join(format!("rinit-test-online-{}", std::process::id()));
+        let _ = std::fs::remove_file(&online);
+        let mut condition = Script::new(ScriptPrefix::Sh, format!("test -e {}", online.display()));
+        condition.timeout = 5000;
+        let target = Service::Virtual(Virtual {
+            condition: Some(condition),
+            ..Virtual::new("network-online".to_string())
+        });
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                target,
+                ServiceBuilder::oneshot("ntpd")
+                    .dependencies(&["network-online"])
+                    .build(),
+            ],
+            new_config(),
+        );
+        let target = live_graph.get_service("network-online").unwrap();
+        let ntpd = live_graph.get_service("ntpd").unwrap();
+
+        let res = with_status_updates(&live_graph, &mut rx, async {
+            let start = live_graph.start_service(ntpd);
+            let go_online = async {
+                sleep(Duration::from_millis(700)).await;
+                // The dependent waits for the target
+                assert_eq!(
+                    *target.state.borrow(),
+                    ServiceState::Transitioning(TransitioningServiceState::Starting)
+                );
+                assert_eq!(
+                    *ntpd.state.borrow(),
+                    ServiceState::Transitioning(TransitioningServiceState::Starting)
+                );
+                std::fs::write(&online, "").unwrap();
+            };
+            let (res, _) = tokio::join!(start, go_online);
+            res?;
+            Ok::<_, LiveGraphError>(ntpd.wait_idle_state().await)
+        })
+        .await;
+        assert!(matches!(res, Ok(IdleServiceState::Up)));
+        assert_eq!(
+            *target.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+        );
+        std::fs::remove_file(online).unwrap();
+    }
+
+    #[tokio::test]
+    async fn target_condition_timed_out() {
+        let mut condition = Script::new(ScriptPrefix::Sh, "exit 1".to_string());
+        condition.timeout = 600;
+        let target = Service::Virtual(Virtual {
+            condition: Some(condition),
+            ..Virtual::new("local-fs".to_string())
+        });
+        let (live_graph, mut rx) = new_live_graph(vec![target], new_config());
+        let target = live_graph.get_service("local-fs").unwrap();
+
+        let res = with_status_updates(&live_graph, &mut rx, live_graph.start_service(target)).await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::ServiceFailedToStart { service }
+            }) if service == "local-fs"
+        ));
+        assert_eq!(
+            *target.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Failed)
+        );
+    }
+
+    #[test]
+    fn connect_logger_service() {
+        let (live_graph, _rx) = new_live_graph(
+            vec![
+                ServiceBuilder::longrun("daemon")
+                    .start_script(|run| {
+                        run.stdout = ScriptOutput::Service("daemon-log".to_string())
+                    })
+                    .build(),
+                ServiceBuilder::longrun("daemon-log")
+                    .start_script(|run| run.stdout = ScriptOutput::Log)
+                    .build(),
+                ServiceBuilder::longrun("other")
+                    .start_script(|run| run.stdout = ScriptOutput::Null)
+                    .build(),
+            ],
+            new_config(),
+        );
+        let pipes = |name| {
+            live_graph
+                .service_pipes(live_graph.get_service(name).unwrap())
+                .unwrap()
+        };
+
+        let daemon = pipes("daemon");
+        assert!(daemon.stdin.is_none() && daemon.stderr.is_none());
+        let logger = pipes("daemon-log");
+        assert!(logger.stdout.is_none() && logger.stderr.is_none());
+        let other = pipes("other");
+        assert!(other.stdin.is_none() && other.stdout.is_none() && other.stderr.is_none());
+
+        let mut writer = std::fs::File::from(daemon.stdout.unwrap());
+        io::Write::write_all(&mut writer, b"hello\n").unwrap();
+        // A restarted logger reads from the same pipe
+        drop(logger);
+        let mut reader = std::fs::File::from(pipes("daemon-log").stdin.unwrap());
+        let mut buf = [0; 6];
+        io::Read::read_exact(&mut reader, &mut buf).unwrap();
+        assert_eq!(&buf, b"hello\n");
+    }
+
+    #[tokio::test]
+    async fn dangling_dependency() {
+        let (mut live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("parent")
+                    .dependencies(&["db"])
+                    .build(),
+                ServiceBuilder::oneshot("db").build(),
+            ],
+            new_config(),
+        );
+        // As if the graph on disk had been edited by hand
+        live_graph.live_services.swap_remove("db");
+
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("parent").unwrap()),
+        )
+        .await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::DependencyNotFound { service, dependency }
+            }) if service == "parent" && dependency == "db"
+        ));
+        assert_eq!(
+            *live_graph.get_service("parent").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Down)
+        );
+    }
+
+    #[tokio::test]
+    async fn dependency_failed_to_start() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("parent")
+                    .dependencies(&["broken"])
+                    .build(),
+                ServiceBuilder::oneshot("broken").start("exit 1").build(),
+            ],
+            new_config(),
+        );
+
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("parent").unwrap()),
+        )
+        .await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::DependencyFailedToStart { service, dependency }
+            }) if service == "parent" && dependency == "broken"
+        ));
+        // Only the service that failed by itself is marked as such
+        assert_eq!(
+            *live_graph.get_service("parent").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Down)
+        );
+        assert_eq!(
+            *live_graph.get_service("broken").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Failed)
+        );
+    }
+
+    #[tokio::test]
+    async fn dependency_started_again_after_failure() {
+        let marker = env::temp_dir().join(format!("rinit-test-retry-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        // Fails the first time only
+        let flaky = format!("test -f {0} || {{ touch {0}; exit 1; }}", marker.display());
+        let mut config = new_config();
+        config.dependency_start_retries = Some(2);
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("parent")
+                    .dependencies(&["flaky"])
+                    .build(),
+                ServiceBuilder::oneshot("flaky").start(&flaky).build(),
+            ],
+            config,
+        );
+
+        with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("parent").unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            *live_graph.get_service("flaky").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+        );
+        std::fs::remove_file(&marker).unwrap();
+    }
+
+    #[tokio::test]
+    async fn needs_path_created_later() {
+        let path = env::temp_dir().join("rinit-test-needs-path-created");
+        let _ = std::fs::remove_file(&path);
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::oneshot("foo")
+                .options(|options| {
+                    options.needs_path = vec![path.clone()];
+                    options.needs_path_timeout = 5000;
+                })
+                .build()],
+            new_config(),
+        );
+
+        let create_path = async {
+            sleep(Duration::from_millis(300)).await;
+            std::fs::write(&path, "").unwrap();
+        };
+        let (res, _) = tokio::join!(
+            with_status_updates(
+                &live_graph,
+                &mut rx,
+                live_graph.start_service(live_graph.get_service("foo").unwrap()),
+            ),
+            create_path
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(res.is_ok());
+        assert_eq!(
+            *live_graph.get_service("foo").unwrap().state.borrow(),
+            ServiceState::Idle(IdleServiceState::Up)
+        );
+    }
+
+    #[tokio::test]
+    async fn needs_path_timed_out() {
+        let path = env::temp_dir().join("rinit-test-needs-path-missing");
+        let _ = std::fs::remove_file(&path);
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::oneshot("foo")
+                .options(|options| {
+                    options.needs_path = vec![path];
+                    options.needs_path_timeout = 200;
+                })
+                .build()],
+            new_config(),
+        );
+
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("foo").unwrap()),
+        )
+        .await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::ServiceFailedToStart { service }
+            }) if service == "foo"
+        ));
+    }
+
+    #[tokio::test]
+    async fn start_dependency_through_alias() {
+        let (live_graph, mut rx) = new_live_graph(
+            vec![
+                ServiceBuilder::oneshot("backup")
+                    .dependencies(&["cron"])
+                    .build(),
+                ServiceBuilder::oneshot("cronie")
+                    .provides(&["cron"])
+                    .build(),
+            ],
+            new_config(),
+        );
+
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("backup").unwrap()),
+        )
+        .await;
+        assert!(res.is_ok());
+        for service in ["backup", "cronie"] {
+            assert_eq!(
+                *live_graph.get_service(service).unwrap().state.borrow(),
+                ServiceState::Idle(IdleServiceState::Up)
+            );
+        }
+    }
+
+    async fn start_modules(
+        live_graph: &LiveServiceGraph,
+        rx: &mut mpsc::Receiver<Request>,
+    ) -> IdleServiceState {
+        let live_service = live_graph.get_service("modules").unwrap();
+        let res = with_status_updates(live_graph, rx, live_graph.start_service(live_service)).await;
+        assert!(res.is_ok());
+        live_service.wait_idle_state().await
+    }
+
+    #[tokio::test]
+    async fn up_check_uncached() {
+        let config = new_config_in(&test_dir("rinit-test-up-check-uncached"));
+        let rundir = config.dirs.rundir.clone();
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::oneshot("modules")
+                .start("exit 0")
+                .up_check("exit 1")
+                .build()],
+            config,
+        );
+
+        assert_eq!(
+            start_modules(&live_graph, &mut rx).await,
+            IdleServiceState::Up
+        );
+        let done_marker = rundir.join("done").join("modules");
+        assert!(done_marker.exists());
+
+        let live_service = live_graph.get_service("modules").unwrap();
+        let res =
+            with_status_updates(&live_graph, &mut rx, live_graph.stop_service(live_service)).await;
+        assert!(res.is_ok());
+        assert!(!done_marker.exists());
+        std::fs::remove_dir_all(rundir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn up_check_already_done() {
+        let config = new_config_in(&test_dir("rinit-test-up-check-done"));
+        let rundir = config.dirs.rundir.clone();
+        // The start script would fail if it was run
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::oneshot("modules")
+                .start("exit 1")
+                .up_check("exit 0")
+                .build()],
+            config,
+        );
+
+        assert_eq!(
+            start_modules(&live_graph, &mut rx).await,
+            IdleServiceState::Up
+        );
+        assert!(rundir.join("done").join("modules").exists());
+        std::fs::remove_dir_all(rundir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn up_check_start_not_spawned() {
+        let config = new_config_in(&test_dir("rinit-test-up-check-not-spawned"));
+        let rundir = config.dirs.rundir.clone();
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::oneshot("modules")
+                .start_script(|start| {
+                    start.prefix = ScriptPrefix::Path;
+                    start.execute = "/nonexistent/rinit-modules".to_string();
+                })
+                .up_check("exit 1")
+                .build()],
+            config,
+        );
+
+        let res = with_status_updates(
+            &live_graph,
+            &mut rx,
+            live_graph.start_service(live_graph.get_service("modules").unwrap()),
+        )
+        .await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::ServiceFailedToStart { service }
+            }) if service == "modules"
+        ));
+        assert!(!rundir.join("done").join("modules").exists());
+        std::fs::remove_dir_all(rundir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn up_check_cached() {
+        let config = new_config_in(&test_dir("rinit-test-up-check-cached"));
+        let rundir = config.dirs.rundir.clone();
+        std::fs::create_dir_all(rundir.join("done")).unwrap();
+        std::fs::write(rundir.join("done").join("modules"), "").unwrap();
+        // Neither up_check nor the start script are run
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::oneshot("modules")
+                .start("exit 1")
+                .up_check("exit 1")
+                .build()],
+            config,
+        );
+
+        assert_eq!(
+            start_modules(&live_graph, &mut rx).await,
+            IdleServiceState::Up
+        );
+        std::fs::remove_dir_all(rundir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn hooks_run_in_order() {
+        let config = new_config_in(&test_dir("rinit-test-hooks-order"));
+        let rundir = config.dirs.rundir.clone();
+        // Every script appends its name to $RUNDIR/order
+        let append_order = |name: &str| format!("echo {name} >> \"$RUNDIR/order\"");
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::longrun("daemon")
+                .start(&format!("{}; sleep 10", append_order("run")))
+                .start_script(|run| run.timeout = 200)
+                .pre_start(&append_order("pre_start"))
+                .post_stop(&append_order("post_stop"))
+                .build()],
+            config,
+        );
+        let live_service = live_graph.get_service("daemon").unwrap();
+
+        // The supervisor of the longrun is spawned locally
+        task::LocalSet::new()
+            .run_until(with_status_updates(&live_graph, &mut rx, async {
+                live_graph.start_service(live_service).await?;
+                assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Up);
+                live_graph.stop_service(live_service).await?;
+                assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Down);
+                // The supervisor kills the process after the service is marked down
+                let order = rundir.join("order");
+                for _ in 0..50 {
+                    if std::fs::read_to_string(&order)
+                        .unwrap()
+                        .contains("post_stop")
+                    {
+                        break;
+                    }
+                    sleep(Duration::from_millis(20)).await;
+                }
+                Ok::<_, LiveGraphError>(())
+            }))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(rundir.join("order")).unwrap(),
+            "pre_start\nrun\npost_stop\n"
+        );
+        std::fs::remove_dir_all(rundir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn signal_running_longrun() {
+        let config = new_config_in(&test_dir("rinit-test-signal"));
+        let rundir = config.dirs.rundir.clone();
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::longrun("daemon")
+                .start(
+                    "trap 'echo usr1 >> \"$RUNDIR/signals\"' USR1; while true; do sleep 0.1; done",
+                )
+                .start_script(|run| run.timeout = 200)
+                .build()],
+            config,
+        );
+        let live_service = live_graph.get_service("daemon").unwrap();
+
+        task::LocalSet::new()
+            .run_until(with_status_updates(&live_graph, &mut rx, async {
+                assert!(matches!(
+                    live_graph.signal_service(live_service, libc::SIGUSR1),
+                    Err(LiveGraphError::LogicError {
+                        err: LogicError::ServiceNotRunning { .. }
+                    })
+                ));
+                live_graph.start_service(live_service).await?;
+                assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Up);
+                assert!(matches!(
+                    live_graph.signal_service(live_service, 1000),
+                    Err(LiveGraphError::LogicError {
+                        err: LogicError::InvalidSignal { signal: 1000 }
+                    })
+                ));
+                live_graph.signal_service(live_service, libc::SIGUSR1)?;
+                let signals = rundir.join("signals");
+                for _ in 0..50 {
+                    if signals.exists() {
+                        break;
+                    }
+                    sleep(Duration::from_millis(20)).await;
+                }
+                assert_eq!(std::fs::read_to_string(&signals).unwrap(), "usr1\n");
+                // The trap handled the signal, the service is still up
+                assert_eq!(
+                    *live_service.state.borrow(),
+                    ServiceState::Idle(IdleServiceState::Up)
+                );
+                live_graph.stop_service(live_service).await?;
+                assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Down);
+                Ok::<_, LiveGraphError>(())
+            }))
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(rundir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reload_with_notify_handshake() {
+        if !Path::new("/usr/bin/python3").exists() {
+            return;
+        }
+        let config = new_config_in(&test_dir("rinit-test-notify-reload"));
+        let rundir = config.dirs.rundir.clone();
+        // Takes a while to reload, after telling rsvc that it started
+        let daemon = ServiceBuilder::longrun("daemon").start(
+            "exec python3 -c \"import os, signal, socket, time; s = socket.socket(socket.AF_UNIX, \
+             socket.SOCK_DGRAM); notify = lambda msg: s.sendto(msg, os.environ['NOTIFY_SOCKET']); \
+             signal.signal(signal.SIGUSR1, lambda *_: (notify(b'RELOADING=1'), time.sleep(0.3), \
+             notify(b'STATUS=reloaded\\nREADY=1'))); notify(b'READY=1'); [time.sleep(1) for _ in \
+             iter(int, 1)]\"",
+        );
+        let daemon = daemon.start_script(|run| {
+            run.timeout = 2000;
+            run.notify_socket = true;
+            run.notify_reload = true;
+            run.reload_signal = libc::SIGUSR1;
+        });
+        let (live_graph, mut rx) = new_live_graph(vec![daemon.build()], config);
+        let live_service = live_graph.get_service("daemon").unwrap();
+
+        task::LocalSet::new()
+            .run_until(with_status_updates(&live_graph, &mut rx, async {
+                live_graph.start_service(live_service).await?;
+                assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Up);
+                let start = Instant::now();
+                live_graph.reload_process(live_service).await?;
+                // Only done once the process has sent READY=1 again
+                assert!(start.elapsed() >= Duration::from_millis(300));
+                assert_eq!(
+                    live_service.status_message.borrow().as_deref(),
+                    Some("reloaded")
+                );
+                assert_eq!(
+                    *live_service.state.borrow(),
+                    ServiceState::Idle(IdleServiceState::Up)
+                );
+                live_graph.stop_service(live_service).await?;
+                assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Down);
+                Ok::<_, LiveGraphError>(())
+            }))
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(rundir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn adopt_longrun_after_reexec() {
+        let mut config = new_config_in(&test_dir("rinit-test-reexec"));
+        let rundir = config.dirs.rundir.clone();
+        let logdir = env::temp_dir().join(format!("rinit-test-reexec-logs-{}", std::process::id()));
+        config.dirs.logdir = logdir.clone();
+        // Spawned by the rsvc that executed itself again, the pipe of its
+        // stdout is inherited
+        let mut process = std::process::Command::new("bash")
+            .args(["-c", "sleep 0.2; echo adopted; exec sleep 30"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let pid = process.id();
+        let stdout = OwnedFd::from(process.stdout.take().unwrap());
+        ReexecState::new(vec![SavedService {
+            name: "daemon".to_string(),
+            pid: Some(pid),
+            stdout: Some(stdout.into_raw_fd()),
+            stderr: None,
+            listen: None,
+            last_run: None,
+        }])
+        .save(&rundir)
+        .unwrap();
+        // Dropping it doesn't wait on the process
+        drop(process);
+        let state = ReexecState::take(&rundir).unwrap().unwrap();
+        let (live_graph, mut rx) = new_live_graph(
+            vec![ServiceBuilder::longrun("daemon")
+                .start_script(|run| run.stdout = ScriptOutput::Log)
+                .build()],
+            config,
+        );
+        let live_service = live_graph.get_service("daemon").unwrap();
+
+        task::LocalSet::new()
+            .run_until(with_status_updates(&live_graph, &mut rx, async {
+                let summary = live_graph.resume(state).await;
+                assert!(summary.is_success() && summary.started.is_empty());
+                assert_eq!(
+                    *live_service.state.borrow(),
+                    ServiceState::Idle(IdleServiceState::Up)
+                );
+                // The same process is supervised, its output is still logged
+                assert_eq!(live_service.status().pid, Some(pid));
+                let mut logged = false;
+                for _ in 0..50 {
+                    sleep(Duration::from_millis(20)).await;
+                    logged = std::fs::read_dir(logdir.join("daemon"))
+                        .into_iter()
+                        .flatten()
+                        .flatten()
+                        .any(|entry| {
+                            std::fs::read_to_string(entry.path())
+                                .is_ok_and(|log| log.contains("adopted"))
+                        });
+                    if logged {
+                        break;
+                    }
+                }
+                assert!(logged);
+                live_graph.stop_service(live_service).await?;
+                assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Down);
+                // The supervisor kills and reaps the process after the service
+                // is marked down
+                let proc = Path::new("/proc").join(pid.to_string());
+                for _ in 0..50 {
+                    if !proc.exists() {
+                        break;
+                    }
+                    sleep(Duration::from_millis(20)).await;
+                }
+                assert!(!proc.exists());
+                Ok::<_, LiveGraphError>(())
+            }))
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(rundir).unwrap();
+        std::fs::remove_dir_all(logdir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resume_scheduled_oneshot_after_reexec() {
+        let config = new_config_in(&test_dir("rinit-test-reexec-scheduled"));
+        let rundir = config.dirs.rundir.clone();
+        let output = rundir.join("backup");
+        let last_run = SystemTime::now() - Duration::from_secs(30);
+        ReexecState::new(vec![SavedService {
+            name: "backup".to_string(),
+            pid: None,
+            stdout: None,
+            stderr: None,
+            listen: None,
+            last_run: Some(last_run),
+        }])
+        .save(&rundir)
+        .unwrap();
+        let state = ReexecState::take(&rundir).unwrap().unwrap();
+        let service = ServiceBuilder::oneshot("backup")
+            .start(&format!("touch {}", output.display()))
+            .options(|options| options.schedule = Some("* * * * *".parse().unwrap()))
+            .build();
+        let (live_graph, mut rx) = new_live_graph(vec![service], config);
+        let live_service = live_graph.get_service("backup").unwrap();
+
+        task::LocalSet::new()
+            .run_until(with_status_updates(&live_graph, &mut rx, async {
+                let summary = live_graph.resume(state).await;
+                assert!(summary.is_success() && summary.started.is_empty());
+                assert_eq!(
+                    *live_service.state.borrow(),
+                    ServiceState::Idle(IdleServiceState::Up)
+                );
+                // Let the timer compute the next run
+                sleep(Duration::from_millis(50)).await;
+                let status = live_service.status();
+                assert_eq!(status.last_run, Some(last_run));
+                assert!(status.next_run.unwrap() > SystemTime::now());
+                // It has not been run again
+                assert!(!output.exists());
+                live_graph.stop_service(live_service).await?;
+                assert_eq!(live_service.wait_idle_state().await, IdleServiceState::Down);
+                Ok::<_, LiveGraphError>(())
+            }))
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(rundir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failing_pre_start_aborts_start() {
+        let config = new_config_in(&test_dir("rinit-test-pre-start-failed"));
+        let rundir = config.dirs.rundir.clone();
+        std::fs::create_dir_all(&rundir).unwrap();
+        let service = ServiceBuilder::oneshot("mount")
+            .start("echo start >> \"$RUNDIR/order\"")
+            .pre_start("exit 1")
+            .build();
+        let (live_graph, mut rx) = new_live_graph(vec![service], config);
+        let live_service = live_graph.get_service("mount").unwrap();
+
+        let res =
+            with_status_updates(&live_graph, &mut rx, live_graph.start_service(live_service)).await;
+        assert!(matches!(
+            res,
+            Err(LiveGraphError::LogicError {
+                err: LogicError::ServiceFailedToStart { service }
+            }) if service == "mount"
+        ));
+        assert_eq!(
+            *live_service.state.borrow(),
+            ServiceState::Idle(IdleServiceState::Failed)
+        );
+        assert!(!rundir.join("order").exists());
+        std::fs::remove_dir_all(rundir).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{
+    Context,
+    Result,
+};
+use rinit_service::config::LogFormat;
+use tracing::Subscriber;
+use tracing_subscriber::{
+    fmt::{
+        self,
+        MakeWriter,
+    },
+    layer::SubscriberExt,
+    reload,
+    EnvFilter,
+    Layer,
+    Registry,
+};
+
+/// Used when neither the command line nor the configuration set a filter
+pub const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Filter of the rsvc log, that can be changed while running
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogFilter {
+    /// Replace the filter with directives, in the EnvFilter syntax, e.g.
+    /// debug or warn,rsvc::live_service=trace
+    pub fn set(
+        &self,
+        directives: &str,
+    ) -> Result<()> {
+        let filter = EnvFilter::try_new(directives)
+            .with_context(|| format!("invalid log filter {directives}"))?;
+        self.handle
+            .reload(filter)
+            .context("unable to change the log filter")
+    }
+}
+
+/// Subscriber writing the events allowed by directives to make_writer, in the
+/// given format
+pub fn new_subscriber<W>(
+    make_writer: W,
+    directives: &str,
+    format: LogFormat,
+) -> Result<(impl Subscriber + Send + Sync, LogFilter)>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let filter = EnvFilter::try_new(directives)
+        .with_context(|| format!("invalid log filter {directives}"))?;
+    let (filter, handle) = reload::Layer::new(filter);
+    let output = match format {
+        LogFormat::Plain => fmt::layer().with_writer(make_writer).boxed(),
+        LogFormat::Json => fmt::layer().json().with_writer(make_writer).boxed(),
+    };
+    let subscriber = Registry::default().with(filter).with(output);
+    Ok((subscriber, LogFilter { handle }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io,
+        sync::{
+            Arc,
+            Mutex,
+        },
+    };
+
+    use tracing::{
+        debug,
+        info,
+    };
+
+    use super::*;
+
+    /// Keep everything written by the subscriber
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(
+            &mut self,
+            buf: &[u8],
+        ) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn take(&self) -> String {
+            String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap()
+        }
+    }
+
+    #[test]
+    fn change_log_level() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let (subscriber, filter) =
+            new_subscriber(move || writer.clone(), "info", LogFormat::Plain).unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            info!("starting");
+            debug!("hidden");
+            let log = buffer.take();
+            assert!(log.contains("starting"));
+            assert!(!log.contains("hidden"));
+
+            filter.set("debug").unwrap();
+            debug!("shown");
+            assert!(buffer.take().contains("shown"));
+
+            // Only the events of this module are logged
+            filter.set("error,rsvc::logging=warn").unwrap();
+            info!("dropped");
+            tracing::warn!("kept");
+            let log = buffer.take();
+            assert!(!log.contains("dropped"));
+            assert!(log.contains("kept"));
+
+            assert!(filter.set("rsvc=loud").is_err());
+        });
+    }
+
+    #[test]
+    fn json_format() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let (subscriber, _filter) =
+            new_subscriber(move || writer.clone(), "info", LogFormat::Json).unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            info!(service = "sshd", "started");
+        });
+        let line: serde_json::Value = serde_json::from_str(buffer.take().trim()).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["fields"]["message"], "started");
+        assert_eq!(line["fields"]["service"], "sshd");
+    }
+}
@@ -1,10 +1,3 @@
-#![feature(async_closure)]
-
-pub mod live_service;
-pub mod live_service_graph;
-pub mod request_handler;
-pub mod supervision;
-
 use std::{
     cell::RefCell,
     path::{
@@ -12,6 +5,7 @@ use std::{
         PathBuf,
     },
     rc::Rc,
+    time::Duration,
 };
 
 use anyhow::{
@@ -29,32 +23,35 @@ use flexi_logger::{
 use lexopt::prelude::{
     Long,
     Short,
+    Value,
+};
+use nix::unistd::{
+    setpgid,
+    Pid,
 };
-use live_service_graph::LiveServiceGraph;
-use nix::{
-    sys::signal::Signal,
-    unistd::{
-        setpgid,
-        Pid,
+use rinit_service::{
+    config::Config,
+    types::RunLevel,
+};
+use rsvc::{
+    logging::{
+        new_subscriber,
+        DEFAULT_LOG_FILTER,
+    },
+    reexec_state::ReexecState,
+    request_handler::RequestHandler,
+    supervision::{
+        set_subreaper,
+        signal_wait,
+        DEFAULT_REAP_INTERVAL,
     },
+    Supervisor,
 };
-use request_handler::RequestHandler;
-use rinit_ipc::Request;
-use rinit_service::config::Config;
 use tokio::{
     fs,
     join,
     net::UnixListener,
     select,
-    signal::unix::{
-        signal,
-        SignalKind,
-    },
-    sync::{
-        mpsc,
-        watch,
-        Mutex,
-    },
     task::{
         self,
         spawn_local,
@@ -66,72 +63,72 @@ use tracing::{
     error,
     info,
 };
-use tracing_subscriber::{
-    filter::LevelFilter,
-    FmtSubscriber,
-};
-
-#[macro_use]
-extern crate lazy_static;
 
 struct Args {
     config: Option<PathBuf>,
-    verbosity: u8,
+    verbosity: Option<u8>,
+    runlevel: Option<RunLevel>,
 }
 
 fn parse_args() -> Result<Args, lexopt::Error> {
     let mut config: Option<PathBuf> = None;
+    let mut runlevel: Option<RunLevel> = None;
     let mut parser = lexopt::Parser::from_env();
     // 0 => Error
     // 1 => Warn
     // 2 => Info
     // 3 => Debug
     // 4 => Trace
-    let mut verbosity = 2;
+    // None => log_level in the configuration
+    let mut verbosity = None;
     while let Some(arg) = parser.next()? {
         match arg {
             Short('c') | Long("config") => {
                 config = Some(PathBuf::from(parser.value()?));
             }
             Long("help") => {
-                println!("Usage: rsvc [-c|--config=CONFIG]");
+                println!("Usage: rsvc [-c|--config=CONFIG] [rinit.runlevel=RUNLEVEL]");
                 std::process::exit(0);
             }
             Short('q') | Long("quiet") => {
                 // quiet set it to Warn
-                verbosity = 1;
+                verbosity = Some(1);
             }
             Short('v') | Long("verbose") => {
                 // verbose set it to Debug
-                verbosity = 3;
+                verbosity = Some(3);
+            }
+            // Kernel command line style argument, e.g. rinit.runlevel=boot
+            Value(value) => {
+                let value = value.into_string()?;
+                let Some(level) = value.strip_prefix("rinit.runlevel=") else {
+                    return Err(lexopt::Error::UnexpectedArgument(value.into()));
+                };
+                runlevel = Some(level.parse().map_err(|_| {
+                    lexopt::Error::UnexpectedValue {
+                        option: "rinit.runlevel".to_string(),
+                        value: level.into(),
+                    }
+                })?);
             }
             _ => return Err(arg.unexpected()),
         }
     }
 
-    Ok(Args { config, verbosity })
-}
-
-lazy_static! {
-    static ref SIGINT: Mutex<tokio::signal::unix::Signal> =
-        Mutex::new(signal(SignalKind::interrupt()).unwrap());
-    static ref SIGTERM: Mutex<tokio::signal::unix::Signal> =
-        Mutex::new(signal(SignalKind::terminate()).unwrap());
-}
-
-pub async fn signal_wait() -> Signal {
-    let mut sigint = SIGINT.lock().await;
-    let mut sigterm = SIGTERM.lock().await;
-    select! {
-        _ = sigint.recv() => Signal::SIGINT,
-        _ = sigterm.recv() => Signal::SIGTERM,
-    }
+    Ok(Args {
+        config,
+        verbosity,
+        runlevel,
+    })
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     let args = parse_args()?;
-    let config = Config::new(args.config)?;
+    let mut config = Config::new(args.config.clone())?;
+    if let Some(runlevel) = args.runlevel {
+        config.default_runlevel = runlevel;
+    }
 
     // Setup logging
     let (file_writer, _fw_handle) = FileLogWriter::builder(
@@ -149,30 +146,55 @@ async fn main() -> Result<()> {
     .try_build_with_handle()
     .unwrap();
 
-    let subscriber_builder = FmtSubscriber::builder()
-        .with_writer(move || file_writer.clone())
-        .with_max_level(match args.verbosity {
-            0 => LevelFilter::ERROR,
-            1 => LevelFilter::WARN,
-            2 => LevelFilter::INFO,
-            3.. => LevelFilter::DEBUG,
-        });
+    let log_filter = match args.verbosity {
+        Some(0) => "error",
+        Some(1) => "warn",
+        Some(2) => "info",
+        Some(3..) => "debug",
+        None => config.log_level.as_deref().unwrap_or(DEFAULT_LOG_FILTER),
+    };
+    let (subscriber, log_filter) =
+        new_subscriber(move || file_writer.clone(), log_filter, config.log_format)?;
 
     // Get ready to trace
-    tracing::subscriber::set_global_default(subscriber_builder.finish())
+    tracing::subscriber::set_global_default(subscriber)
         .expect("setting default subscriber failed");
 
     // Create its own process group
     setpgid(Pid::from_raw(0), Pid::from_raw(0))?;
+    // The processes left behind by the services must not become zombies
+    set_subreaper()?;
+    let reap_interval = config
+        .reap_interval
+        .map_or(DEFAULT_REAP_INTERVAL, Duration::from_millis);
+    let cgroup_parent = config.cgroup.clone();
+
+    // rsvc has executed itself again, the services are still running
+    let reexec_state = ReexecState::take(&config.dirs.rundir)?;
 
-    let (tx, mut rx) = mpsc::channel::<Request>(20);
     let local = task::LocalSet::new();
-    let live_graph = LiveServiceGraph::new(config, tx.clone())?;
+    let supervisor = Rc::new(Supervisor::from_config(config)?.with_config_file(args.config));
+    let reaper = supervisor.reaper();
+    if let Some(reexec_state) = &reexec_state {
+        // The reaper must not steal the exit status of the processes that are
+        // going to be adopted
+        for pid in reexec_state
+            .services
+            .iter()
+            .filter_map(|service| service.pid)
+        {
+            reaper.track_child(pid);
+        }
+    }
 
     // Setup socket listener
     fs::create_dir_all(Path::new(rinit_ipc::get_host_address()).parent().unwrap())
         .await
         .unwrap();
+    // The socket has been left behind by the previous rsvc when executing this one
+    if reexec_state.is_some() {
+        let _ = fs::remove_file(rinit_ipc::get_host_address()).await;
+    }
 
     let listener = UnixListener::bind(rinit_ipc::get_host_address()).with_context(|| {
         format!(
@@ -181,14 +203,16 @@ async fn main() -> Result<()> {
         )
     })?;
 
-    let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
-    let mut shutdown = shutdown_tx.subscribe();
-    let handler = Rc::new(RequestHandler::new(live_graph, shutdown_tx));
+    let mut shutdown = supervisor.subscribe_shutdown();
+    let mut shutdown_rx = supervisor.subscribe_shutdown();
+    let handler = Rc::new(RequestHandler::new(supervisor.clone()).with_log_filter(log_filter));
     let handles = Rc::new(RefCell::new(Vec::new()));
     local
         .run_until(async move {
             info!("Starting rinit.");
 
+            spawn_local(async move { reaper.reap_loop(reap_interval, cgroup_parent).await });
+
             let handler_clone = handler.clone();
             let handles_clone = handles.clone();
             let ipc_handler_future = spawn_local(async move {
@@ -220,41 +244,20 @@ async fn main() -> Result<()> {
                 }
             });
 
-            let handler_clone = handler.clone();
-            let handles_clone = handles.clone();
-            let events_future = spawn_local(async move {
-                let handler = handler_clone;
-                let handles = handles_clone;
-                loop {
-                    let request = match rx.recv().await {
-                        Some(req) => req,
-                        None => break,
-                    };
-                    let handler = handler.clone();
-                    if let Request::StopAllServices = request {
-                        if let Err(err) = handler.handle_request(request).await {
-                            error!("{err}");
-                        }
-                        break;
-                    }
-                    handles.borrow_mut().push(task::spawn_local(async move {
-                        if let Err(err) = handler.handle_request(request).await {
-                            error!("{err}");
-                        }
-                    }));
-                }
-            });
-
             // Starting rinit consists of 2 different phases
             // The first one is starting the boot services, the second one starts all the
             // other services
-            if let Err(err) = tx.send(Request::StartAllServices).await {
-                error!("{err}");
-            }
+            let supervisor_clone = supervisor.clone();
+            handles.borrow_mut().push(task::spawn_local(async move {
+                match reexec_state {
+                    Some(reexec_state) => supervisor_clone.resume(reexec_state).await,
+                    None => supervisor_clone.start_all().await,
+                }
+            }));
 
-            let (res1, res2, _) = join! {
+            let (res, (), ()) = join! {
                 ipc_handler_future,
-                events_future,
+                supervisor.run(),
                 async {
                     let signal = select! {
                         signal = signal_wait() => {
@@ -266,14 +269,11 @@ async fn main() -> Result<()> {
                     };
                     if let Some(signal) = signal  {
                         debug!("received signal {signal}");
-                        if let Err(err) = tx.send(Request::StopAllServices).await {
-                            error!("{err}");
-                        }
+                        supervisor.stop_all().await;
                     }
                 }
             };
-            res1.unwrap();
-            res2.unwrap();
+            res.unwrap();
 
             while let Some(handle) = handles.borrow_mut().pop() {
                 let res: Result<(), JoinError> = handle.await;
@@ -0,0 +1,183 @@
+use std::{
+    fs,
+    io,
+    os::fd::{
+        FromRawFd,
+        OwnedFd,
+        RawFd,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    process,
+    time::SystemTime,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use snafu::{
+    ResultExt,
+    Snafu,
+};
+use tracing::warn;
+
+use crate::supervision::InheritedFds;
+
+#[derive(Snafu, Debug)]
+pub enum ReexecStateError {
+    #[snafu(display("error when accessing the state of rsvc in {path:?}: {source}"))]
+    StateFileError { path: PathBuf, source: io::Error },
+    #[snafu(display("invalid state of rsvc in {path:?}: {source}"))]
+    InvalidState {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+type Result<T, E = ReexecStateError> = std::result::Result<T, E>;
+
+/// What rsvc hands over to itself when it re-executes its binary, e.g. after
+/// an upgrade, so that the services keep running. It is written in the rundir
+/// right before exec and read back by the new rsvc when it starts
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ReexecState {
+    /// The pid of rsvc, which doesn't change on exec. A file left behind by
+    /// another process is ignored
+    pub pid: u32,
+    /// The services that were up
+    pub services: Vec<SavedService>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct SavedService {
+    pub name: String,
+    /// The running process of a longrun, adopted by the new rsvc
+    pub pid: Option<u32>,
+    /// Read ends of the pipes of stdout and stderr of the process. They are
+    /// inherited across exec, so that its output is still logged
+    pub stdout: Option<RawFd>,
+    pub stderr: Option<RawFd>,
+    /// Listening socket of a socket activated longrun, inherited as well
+    pub listen: Option<RawFd>,
+    /// When the schedule of a scheduled oneshot last fired, its timer is
+    /// armed again by the new rsvc
+    #[serde(default)]
+    pub last_run: Option<SystemTime>,
+}
+
+impl SavedService {
+    /// Take ownership of the file descriptors inherited from the previous
+    /// rsvc. They are only returned once
+    pub fn take_fds(&mut self) -> InheritedFds {
+        // Nothing else refers to them, the previous rsvc has duplicated them
+        // for this one before exec
+        let take = |fd: &mut Option<RawFd>| fd.take().map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+        InheritedFds {
+            stdout: take(&mut self.stdout),
+            stderr: take(&mut self.stderr),
+            listen: take(&mut self.listen),
+        }
+    }
+}
+
+impl ReexecState {
+    pub fn new(services: Vec<SavedService>) -> Self {
+        Self {
+            pid: process::id(),
+            services,
+        }
+    }
+
+    fn path(rundir: &Path) -> PathBuf {
+        rundir.join("reexec.json")
+    }
+
+    pub fn save(
+        &self,
+        rundir: &Path,
+    ) -> Result<()> {
+        let path = Self::path(rundir);
+        let contents =
+            serde_json::to_vec(self).with_context(|_| InvalidStateSnafu { path: path.clone() })?;
+        fs::create_dir_all(rundir)
+            .and_then(|()| fs::write(&path, contents))
+            .with_context(|_| StateFileSnafu { path })
+    }
+
+    /// Close the file descriptors that were going to be inherited and remove
+    /// the saved state, when the exec failed
+    pub fn discard(
+        mut self,
+        rundir: &Path,
+    ) {
+        self.services
+            .iter_mut()
+            .for_each(|service| drop(service.take_fds()));
+        match fs::remove_file(Self::path(rundir)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                warn!("unable to remove the state of rsvc: {err}");
+            }
+            _ => {}
+        }
+    }
+
+    /// Read the state saved by the rsvc that re-executed into this one and
+    /// remove it, so that it is only used once
+    pub fn take(rundir: &Path) -> Result<Option<Self>> {
+        let path = Self::path(rundir);
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => return Err(source).with_context(|_| StateFileSnafu { path }),
+        };
+        fs::remove_file(&path).with_context(|_| StateFileSnafu { path: path.clone() })?;
+        let state: Self = serde_json::from_slice(&contents)
+            .with_context(|_| InvalidStateSnafu { path: path.clone() })?;
+        if state.pid != process::id() {
+            warn!(
+                "ignoring {path:?}, it has been left by process {} instead of this one",
+                state.pid
+            );
+            return Ok(None);
+        }
+        Ok(Some(state))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn take_state_once() {
+        let rundir = env::temp_dir().join(format!("rinit-reexec-{}", process::id()));
+        assert_eq!(ReexecState::take(&rundir).unwrap(), None);
+
+        let state = ReexecState::new(vec![SavedService {
+            name: "sshd".to_string(),
+            pid: Some(42),
+            stdout: Some(7),
+            stderr: None,
+            listen: None,
+            last_run: None,
+        }]);
+        state.save(&rundir).unwrap();
+        assert_eq!(ReexecState::take(&rundir).unwrap(), Some(state));
+        assert_eq!(ReexecState::take(&rundir).unwrap(), None);
+
+        // Left by another rsvc, e.g. one that failed to exec and then died
+        ReexecState {
+            pid: process::id() + 1,
+            services: Vec::new(),
+        }
+        .save(&rundir)
+        .unwrap();
+        assert_eq!(ReexecState::take(&rundir).unwrap(), None);
+        fs::remove_dir_all(rundir).unwrap();
+    }
+}
@@ -1,64 +1,92 @@
-use futures::{
-    prelude::*,
-    stream::StreamExt,
+use std::{
+    rc::Rc,
+    time::Instant,
 };
+
 use remoc::rch;
 use rinit_ipc::{
-    request_error::RequestError,
+    request_error::{
+        LogicError,
+        RequestError,
+    },
     ConnectionError as ConnectionErrorGeneric,
+    Envelope,
     Reply,
+    ReplyEnvelope,
     Request,
 };
-use rinit_service::service_state::{
-    IdleServiceState,
-    ServiceState,
-};
 use tokio::{
     net::UnixStream,
-    sync::{
-        watch,
-        RwLock,
-    },
+    select,
+    sync::broadcast::error::RecvError,
     task,
 };
-use tracing::error;
+use tracing::{
+    debug,
+    info,
+    warn,
+};
 
 use crate::{
-    live_service::LiveService,
-    live_service_graph::{
-        LiveGraphError,
-        LiveServiceGraph,
-    },
+    logging::LogFilter,
+    supervisor::Supervisor,
 };
 
-type ConnectionError = ConnectionErrorGeneric<Result<Reply, RequestError>>;
+type ConnectionError = ConnectionErrorGeneric<ReplyEnvelope>;
 
+/// Serve the IPC requests by calling the matching method of the supervisor
 pub struct RequestHandler {
-    graph: RwLock<LiveServiceGraph>,
-    stop_ipc: watch::Sender<bool>,
+    supervisor: Rc<Supervisor>,
+    // When rsvc started, for the uptime replied to Ping
+    started: Instant,
+    // Changed by SetLogLevel, None when the log is not set up by rsvc
+    log_filter: Option<LogFilter>,
 }
 
 impl RequestHandler {
-    pub fn new(
-        graph: LiveServiceGraph,
-        stop_ipc: watch::Sender<bool>,
-    ) -> Self {
+    pub fn new(supervisor: Rc<Supervisor>) -> Self {
         Self {
-            graph: RwLock::new(graph),
-            stop_ipc,
+            supervisor,
+            started: Instant::now(),
+            log_filter: None,
         }
     }
 
+    /// Allow SetLogLevel to change the filter of the log
+    pub fn with_log_filter(
+        mut self,
+        log_filter: LogFilter,
+    ) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
     // Read IPC messages (i.e. rctl)
+    /// Serve the requests coming from stream until the client closes it. A
+    /// client going away in the middle of a reply or of a subscription is not
+    /// an error
     pub async fn handle_ipc_stream(
         &self,
         stream: UnixStream,
+    ) -> Result<(), ConnectionError> {
+        match self.serve_ipc_stream(stream).await {
+            Err(err) if err.is_disconnect() => {
+                debug!("client disconnected: {err}");
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    async fn serve_ipc_stream(
+        &self,
+        stream: UnixStream,
     ) -> Result<(), ConnectionError> {
         let (socket_rx, socket_tx) = stream.into_split();
         let (conn, mut tx, mut rx): (
             _,
-            rch::base::Sender<Result<Reply, RequestError>>,
-            rch::base::Receiver<Request>,
+            rch::base::Sender<ReplyEnvelope>,
+            rch::base::Receiver<Envelope<Request>>,
         ) = remoc::Connect::io(remoc::Cfg::default(), socket_rx, socket_tx).await?;
         // This has to be spawned in a different task, otherwise everything blocks
         task::spawn_local(conn);
@@ -83,8 +111,56 @@ impl RequestHandler {
                     }
                 }
             };
-            let reply = self.handle_request(request).await;
-            tx.send(reply).await?;
+            if let Request::Subscribe = request.payload {
+                return self.handle_subscription(request.id, tx, rx).await;
+            }
+            let reply = self.handle_request(request.payload).await;
+            tx.send(Envelope {
+                id: request.id,
+                payload: reply,
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    // Send every state transition to the connection until it gets closed
+    // All the replies carry the id of the Subscribe request
+    async fn handle_subscription(
+        &self,
+        id: u64,
+        mut tx: rch::base::Sender<ReplyEnvelope>,
+        mut rx: rch::base::Receiver<Envelope<Request>>,
+    ) -> Result<(), ConnectionError> {
+        let mut events = self.supervisor.subscribe().await;
+        tx.send(Envelope {
+            id,
+            payload: Ok(Reply::Empty),
+        })
+        .await?;
+        loop {
+            select! {
+                event = events.recv() => {
+                    match event {
+                        Ok(event) => {
+                            // Fails when the subscriber closed the connection,
+                            // handle_ipc_stream takes care of that
+                            let reply = Envelope {
+                                id,
+                                payload: Ok(Reply::StatusChanged(event)),
+                            };
+                            tx.send(reply).await?;
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("subscriber lagged behind, {skipped} events were skipped");
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+                // No other request is accepted on this connection, stop when it gets closed
+                _ = rx.recv() => break,
+            }
         }
 
         Ok(())
@@ -94,90 +170,302 @@ impl RequestHandler {
         &self,
         request: Request,
     ) -> Result<Reply, RequestError> {
-        let graph = self.graph.read().await;
+        let supervisor = &self.supervisor;
         Ok(match request {
-            Request::ServicesStatus => {
-                let services: Vec<Result<&LiveService, LiveGraphError>> = graph
-                    .live_services
-                    .iter()
-                    .map(|(_, live_service)| live_service)
-                    .map(Result::Ok)
-                    .collect();
-                let states = stream::iter(services)
-                    .then(async move |res| {
-                        match res {
-                            Ok(live_service) => {
-                                Ok((
-                                    live_service.node.name().to_owned(),
-                                    *live_service.state.borrow(),
-                                ))
-                            }
-                            Err(err) => Err(err),
-                        }
-                    })
-                    .collect::<Vec<_>>()
-                    .await;
-                Reply::ServicesStates(states.into_iter().collect::<Result<Vec<_>, _>>()?)
-            }
+            Request::ServicesStatus => Reply::ServicesStatus(supervisor.statuses().await),
             Request::ServiceStatus(service) => {
-                let state = graph.get_service(&service)?.wait_idle_state();
-                drop(graph);
-                Reply::ServiceState(service.clone(), ServiceState::Idle(state.await))
+                Reply::ServiceStatus(supervisor.status(&service).await?)
+            }
+            Request::DumpGraph => Reply::GraphDump(supervisor.dump().await),
+            Request::Usage => Reply::Usage(supervisor.usage().await),
+            Request::Signal { service, signal } => {
+                supervisor.signal(&service, signal).await?;
+                Reply::Empty
+            }
+            Request::ReloadProcess { service } => {
+                supervisor.reload_process(&service).await?;
+                Reply::Empty
             }
-            Request::StartService { service, runlevel } => {
-                graph.check_runlevel(&service, runlevel)?;
-                graph.start_service(graph.get_service(&service)?).await?;
-                let state = graph.get_service(&service)?.wait_idle_state();
-                drop(graph);
-                Reply::Success(state.await == IdleServiceState::Up)
+            Request::Reexec => {
+                supervisor.reexec().await?;
+                Reply::Empty
             }
+            Request::StartService {
+                service,
+                runlevel,
+                stop_conflicts,
+            } => Reply::Success(supervisor.start(&service, runlevel, stop_conflicts).await?),
             Request::StopService { service, runlevel } => {
-                graph.check_runlevel(&service, runlevel)?;
-                graph.stop_service(graph.get_service(&service)?).await?;
-                let state = graph.get_service(&service)?.wait_idle_state();
-                drop(graph);
-                Reply::Success(state.await == IdleServiceState::Down)
+                Reply::Success(supervisor.stop(&service, runlevel).await?)
             }
             Request::StartAllServices => {
-                graph
-                    .start_all_services(rinit_service::types::RunLevel::Boot)
-                    .await;
-                graph
-                    .start_all_services(rinit_service::types::RunLevel::Default)
-                    .await;
+                supervisor.start_all().await;
+                Reply::Empty
+            }
+            Request::SetRunLevel(runlevel) => {
+                supervisor.set_runlevel(runlevel).await?;
                 Reply::Empty
             }
             // This request can be generated by rctl or by sending a SIGTERM/SIGINT
             Request::StopAllServices => {
-                // Stop listening to IPC requests
-                if let Err(err) = self.stop_ipc.send(true) {
-                    error!("could not stop listening on IPC socket: {err}");
-                }
-                graph
-                    .stop_all_services(rinit_service::types::RunLevel::Default)
-                    .await;
-                graph
-                    .stop_all_services(rinit_service::types::RunLevel::Boot)
-                    .await;
+                supervisor.stop_all().await;
                 Reply::Empty
             }
-            Request::ReloadGraph => {
-                drop(graph);
-                let mut graph = self.graph.write().await;
-                graph.reload_dependency_graph().await?;
+            Request::Shutdown { action } => {
+                supervisor.shutdown(action).await?;
                 Reply::Empty
             }
-            Request::UpdateServiceStatus(name, state) => {
-                graph.update_service_state(&name, state)?;
-                // To update the service, we need the get a write lock
-                // Only get it if needed
-                if state == IdleServiceState::Down {
-                    drop(graph);
-                    let mut graph = self.graph.write().await;
-                    graph.update_service(&name)?;
+            Request::ReloadGraph { keep_running } => {
+                supervisor.reload(&keep_running).await?;
+                Reply::Empty
+            }
+            Request::ReloadService(service) => {
+                supervisor.reload_service(&service).await?;
+                Reply::Empty
+            }
+            Request::ReparseService { service } => {
+                supervisor.reparse_service(&service).await?;
+                Reply::Empty
+            }
+            Request::ReloadConfig => {
+                supervisor.reload_config().await?;
+                Reply::Empty
+            }
+            // Subscriptions need the connection and are handled in handle_ipc_stream
+            Request::Subscribe => unreachable!(),
+            Request::Ping => {
+                Reply::Pong {
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    uptime: self.started.elapsed(),
                 }
+            }
+            Request::GetConfig => Reply::Config(supervisor.config().await),
+            Request::SetLogLevel(level) => {
+                let Some(log_filter) = &self.log_filter else {
+                    return Err(RequestError::SystemError {
+                        err: "the log is not handled by rsvc".to_string(),
+                    });
+                };
+                log_filter.set(&level).map_err(|err| {
+                    RequestError::LogicError {
+                        err: LogicError::InvalidLogLevel {
+                            level,
+                            err: err.root_cause().to_string(),
+                        },
+                    }
+                })?;
+                info!("log level changed");
+                Reply::Empty
+            }
+            Request::UpdateServiceStatus(name, state) => {
+                supervisor.update_service_status(&name, state).await?;
+                Reply::Empty
+            }
+            Request::StartWantedService { service, wanted } => {
+                supervisor.start_wanted(&service, &wanted).await;
                 Reply::Empty
             }
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        path::Path,
+        rc::Rc,
+        time::Duration,
+    };
+
+    use rinit_ipc::AsyncConnection;
+    use rinit_service::{
+        test_util::{
+            new_graph,
+            ServiceBuilder,
+        },
+        types::{
+            RunLevel,
+            Service,
+        },
+    };
+    use tokio::{
+        net::UnixListener,
+        sync::mpsc,
+        time::{
+            sleep,
+            timeout,
+        },
+    };
+
+    use super::*;
+    use crate::test_util::{
+        new_config_in,
+        new_supervisor,
+    };
+
+    /// Handler of a supervisor using the graph in dir
+    fn new_handler(
+        dir: &Path,
+        services: Vec<Service>,
+    ) -> Rc<RequestHandler> {
+        Rc::new(RequestHandler::new(Rc::new(new_supervisor(dir, services))))
+    }
+
+    /// Handle the status updates like rsvc does
+    fn handle_status_updates(handler: &RequestHandler) {
+        let supervisor = handler.supervisor.clone();
+        task::spawn_local(async move { supervisor.run().await });
+    }
+
+    async fn start_services(
+        handler: &RequestHandler,
+        services: &[&str],
+    ) {
+        for service in services {
+            let request = Request::StartService {
+                service: service.to_string(),
+                runlevel: RunLevel::Default,
+                stop_conflicts: false,
+            };
+            assert!(matches!(
+                handler.handle_request(request).await,
+                Ok(Reply::Success(true))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn ping_round_trip() {
+        let dir = env::temp_dir().join(format!("rinit-ping-{}", std::process::id()));
+        let handler = new_handler(&dir, Vec::new());
+        let socket = dir.join("rinit.socket");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        task::LocalSet::new()
+            .run_until(async {
+                let server_handler = handler.clone();
+                task::spawn_local(async move {
+                    let (stream, _addr) = listener.accept().await.unwrap();
+                    server_handler.handle_ipc_stream(stream).await.unwrap();
+                });
+                sleep(Duration::from_millis(100)).await;
+
+                let mut conn = AsyncConnection::new(socket.to_str().unwrap()).await.unwrap();
+                let reply = conn.send_request(Request::Ping).await.unwrap().unwrap();
+                let Reply::Pong { version, uptime } = reply else {
+                    panic!("expected a pong, got {reply:?}");
+                };
+                assert_eq!(version, env!("CARGO_PKG_VERSION"));
+                assert!(uptime >= Duration::from_millis(100));
+            })
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_config_round_trip() {
+        let dir = env::temp_dir().join(format!("rinit-get-config-{}", std::process::id()));
+        let mut config = new_config_in(&dir);
+        config.default_runlevel = RunLevel::Boot;
+        config.subtree_timeout = Some(5000);
+        let supervisor = Supervisor::new(new_graph(Vec::new()), config.clone())
+            .with_config_file(Some(dir.join("rinit.conf")));
+        let handler = Rc::new(RequestHandler::new(Rc::new(supervisor)));
+        let socket = dir.join("rinit.socket");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        task::LocalSet::new()
+            .run_until(async {
+                let server_handler = handler.clone();
+                task::spawn_local(async move {
+                    let (stream, _addr) = listener.accept().await.unwrap();
+                    server_handler.handle_ipc_stream(stream).await.unwrap();
+                });
+                sleep(Duration::from_millis(100)).await;
+
+                let mut conn = AsyncConnection::new(socket.to_str().unwrap()).await.unwrap();
+                let reply = conn.send_request(Request::GetConfig).await.unwrap().unwrap();
+                let Reply::Config(effective) = reply else {
+                    panic!("expected the config, got {reply:?}");
+                };
+                assert_eq!(effective.config_file, Some(dir.join("rinit.conf")));
+                assert_eq!(effective.config.dirs.rundir, config.dirs.rundir);
+                assert_eq!(effective.config.dirs.datadir, config.dirs.datadir);
+                assert_eq!(effective.config.default_runlevel, RunLevel::Boot);
+                assert_eq!(effective.config.subtree_timeout, Some(5000));
+                assert_eq!(
+                    effective.service_directories,
+                    config.dirs.service_directories()
+                );
+                assert_eq!(effective.socket, rinit_ipc::get_host_address());
+            })
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn subscriber_disconnects_mid_stream() {
+        let dir = env::temp_dir().join(format!("rinit-disconnect-{}", std::process::id()));
+        let handler = new_handler(
+            &dir,
+            vec![
+                ServiceBuilder::oneshot("foo").build(),
+                ServiceBuilder::oneshot("bar").build(),
+            ],
+        );
+        let socket = dir.join("rinit.socket");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        task::LocalSet::new()
+            .run_until(async {
+                handle_status_updates(&handler);
+                let (done_tx, mut done_rx) = mpsc::channel(2);
+                let server_handler = handler.clone();
+                task::spawn_local(async move {
+                    loop {
+                        let (stream, _addr) = listener.accept().await.unwrap();
+                        let handler = server_handler.clone();
+                        let done_tx = done_tx.clone();
+                        task::spawn_local(async move {
+                            let res = handler.handle_ipc_stream(stream).await;
+                            done_tx.send(res.map_err(|err| err.to_string())).await.unwrap();
+                        });
+                    }
+                });
+
+                let socket = socket.to_str().unwrap();
+                let mut leaving = AsyncConnection::new(socket).await.unwrap();
+                leaving.send_request(Request::Subscribe).await.unwrap().unwrap();
+                let mut staying = AsyncConnection::new(socket).await.unwrap();
+                staying.send_request(Request::Subscribe).await.unwrap().unwrap();
+                start_services(&handler, &["foo"]).await;
+                assert!(matches!(
+                    leaving.recv_reply().await,
+                    Ok(Some(Ok(Reply::StatusChanged(_))))
+                ));
+                // Go away while the events of foo and bar are still being sent
+                drop(leaving);
+                start_services(&handler, &["bar"]).await;
+
+                let res = timeout(Duration::from_secs(1), done_rx.recv()).await.unwrap();
+                assert_eq!(res, Some(Ok(())));
+                // The other subscriber still gets the events
+                timeout(Duration::from_secs(1), async {
+                    loop {
+                        let reply = staying.recv_reply().await.unwrap().unwrap().unwrap();
+                        let Reply::StatusChanged(event) = reply else {
+                            panic!("expected a status change, got {reply:?}");
+                        };
+                        if event.service == "bar" {
+                            break;
+                        }
+                    }
+                })
+                .await
+                .unwrap();
+            })
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    fs,
+    net::TcpListener,
+    os::{
+        fd::{
+            AsRawFd,
+            OwnedFd,
+            RawFd,
+        },
+        unix::net::UnixListener,
+    },
+    path::PathBuf,
+};
+
+use anyhow::{
+    Context,
+    Result,
+};
+use nix::poll::{
+    poll,
+    PollFd,
+    PollFlags,
+};
+use rinit_service::types::ListenSocket;
+use tokio::io::{
+    unix::AsyncFd,
+    Interest,
+};
+
+/// Listening socket of a socket activated longrun. rsvc binds it and waits for
+/// a connection, the process is then spawned and accepts it by itself
+pub struct ActivationSocket {
+    socket: AsyncFd<OwnedFd>,
+    // Path of the unix socket, removed when the socket is closed
+    path: Option<PathBuf>,
+}
+
+impl ActivationSocket {
+    pub fn bind(listen: &ListenSocket) -> Result<Self> {
+        let (fd, path) = match listen {
+            ListenSocket::Unix(path) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("unable to create directory {:?}", parent))?;
+                }
+                // Remove the socket left by a previous instance of the service
+                if path.exists() {
+                    fs::remove_file(path)
+                        .with_context(|| format!("unable to remove stale socket {:?}", path))?;
+                }
+                let listener = UnixListener::bind(path)
+                    .with_context(|| format!("unable to bind socket {:?}", path))?;
+                (OwnedFd::from(listener), Some(path.clone()))
+            }
+            ListenSocket::Tcp(address) => {
+                let listener = TcpListener::bind(address)
+                    .with_context(|| format!("unable to listen on {address}"))?;
+                (OwnedFd::from(listener), None)
+            }
+        };
+        Self::new(fd, path)
+    }
+
+    /// Take over the socket bound by the rsvc that re-executed into this one,
+    /// instead of binding it again while the process still listens on it
+    pub fn inherit(
+        fd: OwnedFd,
+        listen: &ListenSocket,
+    ) -> Result<Self> {
+        let path = match listen {
+            ListenSocket::Unix(path) => Some(path.clone()),
+            ListenSocket::Tcp(_) => None,
+        };
+        Self::new(fd, path)
+    }
+
+    fn new(
+        fd: OwnedFd,
+        path: Option<PathBuf>,
+    ) -> Result<Self> {
+        // The socket is left blocking, as the process expects it
+        let socket = AsyncFd::with_interest(fd, Interest::READABLE)
+            .context("unable to register the activation socket")?;
+        Ok(Self { socket, path })
+    }
+
+    /// Wait until a connection is pending, without accepting it
+    pub async fn wait_connection(&self) -> Result<()> {
+        loop {
+            let mut guard = self
+                .socket
+                .readable()
+                .await
+                .context("unable to wait on the activation socket")?;
+            // The readiness is kept from the last activation, the connections
+            // might have been accepted since then
+            let mut fds = [PollFd::new(self.socket.as_raw_fd(), PollFlags::POLLIN)];
+            if poll(&mut fds, 0).context("unable to poll the activation socket")? > 0 {
+                return Ok(());
+            }
+            guard.clear_ready();
+        }
+    }
+
+    /// Copy of the socket, passed to the process
+    pub fn fd(&self) -> Result<OwnedFd> {
+        self.socket
+            .get_ref()
+            .try_clone()
+            .context("unable to duplicate the activation socket")
+    }
+}
+
+impl AsRawFd for ActivationSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+impl Drop for ActivationSocket {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    future::Future,
+    io,
+    os::{
+        fd::{
+            FromRawFd,
+            OwnedFd,
+            RawFd,
+        },
+        unix::process::ExitStatusExt,
+    },
+    process::ExitStatus,
+};
+
+use tokio::{
+    io::{
+        unix::AsyncFd,
+        Interest,
+    },
+    process::Child,
+};
+
+use crate::supervision::Reaper;
+
+/// A child of rsvc that can be waited on, whether it has been spawned by tokio
+/// or adopted after rsvc re-executed itself
+pub trait ChildProcess {
+    /// None once the process has been reaped
+    fn id(&self) -> Option<u32>;
+
+    /// Wait for the process to exit and reap it
+    fn wait(&mut self) -> impl Future<Output = io::Result<ExitStatus>>;
+}
+
+impl ChildProcess for Child {
+    fn id(&self) -> Option<u32> {
+        Child::id(self)
+    }
+
+    fn wait(&mut self) -> impl Future<Output = io::Result<ExitStatus>> {
+        Child::wait(self)
+    }
+}
+
+/// A process spawned by the rsvc that was running before re-executing itself.
+/// The pid of rsvc doesn't change on exec, so it is still one of its children,
+/// but tokio doesn't know about it: its exit is watched through a pidfd
+pub struct AdoptedProcess {
+    pid: u32,
+    pidfd: AsyncFd<OwnedFd>,
+    exit_status: Option<ExitStatus>,
+}
+
+impl AdoptedProcess {
+    pub fn new(
+        pid: u32,
+        reaper: &Reaper,
+    ) -> io::Result<Self> {
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let pidfd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+        // Its exit status is read here, the reaper must leave it alone
+        reaper.track_child(pid);
+        Ok(Self {
+            pid,
+            pidfd: AsyncFd::with_interest(pidfd, Interest::READABLE)?,
+            exit_status: None,
+        })
+    }
+}
+
+impl ChildProcess for AdoptedProcess {
+    fn id(&self) -> Option<u32> {
+        self.exit_status.is_none().then_some(self.pid)
+    }
+
+    async fn wait(&mut self) -> io::Result<ExitStatus> {
+        if let Some(exit_status) = self.exit_status {
+            return Ok(exit_status);
+        }
+        loop {
+            // The pidfd is readable once the process has exited
+            let mut guard = self.pidfd.readable().await?;
+            let mut status = 0;
+            match unsafe { libc::waitpid(self.pid as libc::pid_t, &mut status, libc::WNOHANG) } {
+                -1 => return Err(io::Error::last_os_error()),
+                0 => guard.clear_ready(),
+                _ => {
+                    let exit_status = ExitStatus::from_raw(status);
+                    self.exit_status = Some(exit_status);
+                    return Ok(exit_status);
+                }
+            }
+        }
+    }
+}
+
+/// File descriptors of a longrun inherited from the rsvc that re-executed
+/// itself
+#[derive(Default)]
+pub struct InheritedFds {
+    /// Read ends of the pipes of stdout and stderr of the process
+    pub stdout: Option<OwnedFd>,
+    pub stderr: Option<OwnedFd>,
+    /// Listening socket of a socket activated longrun
+    pub listen: Option<OwnedFd>,
+}
+
+/// The process run by a Supervisor
+pub enum SupervisedProcess {
+    Spawned(Child),
+    Adopted(AdoptedProcess),
+}
+
+impl ChildProcess for SupervisedProcess {
+    fn id(&self) -> Option<u32> {
+        match self {
+            Self::Spawned(child) => ChildProcess::id(child),
+            Self::Adopted(process) => process.id(),
+        }
+    }
+
+    async fn wait(&mut self) -> io::Result<ExitStatus> {
+        match self {
+            Self::Spawned(child) => child.wait().await,
+            Self::Adopted(process) => process.wait().await,
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    fs::{
+        self,
+        OpenOptions,
+    },
+    io::{
+        self,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::Duration,
+};
+
+use anyhow::{
+    bail,
+    Context,
+    Result,
+};
+use nix::{
+    errno::Errno,
+    sys::signal::{
+        kill,
+        Signal,
+    },
+    unistd::Pid,
+};
+use tokio::time::{
+    sleep,
+    Instant,
+};
+
+/// How long to wait for the processes to die after writing to cgroup.kill
+const KILL_TIMEOUT: Duration = Duration::from_secs(1);
+const KILL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// cgroup v2 of a service. The script and every process it forks are placed
+/// inside it, so that none of them is left behind when the service stops
+#[derive(Clone)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Create the cgroup name inside parent, reusing it if it already exists
+    pub fn create(
+        parent: &Path,
+        name: &str,
+    ) -> Result<Self> {
+        let path = parent.join(name);
+        fs::create_dir_all(&path)
+            .with_context(|| format!("unable to create cgroup {:?}", path))?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Pids of the processes in the cgroup
+    pub fn pids(&self) -> Result<Vec<u32>> {
+        let procs = self.path.join("cgroup.procs");
+        fs::read_to_string(&procs)
+            .with_context(|| format!("unable to read {:?}", procs))?
+            .lines()
+            .map(|pid| {
+                pid.parse()
+                    .with_context(|| format!("invalid pid {pid} in {:?}", procs))
+            })
+            .collect()
+    }
+
+    /// Send SIGKILL to all the processes in the cgroup, without waiting for
+    /// them to die. cgroup.kill needs Linux 5.14, on older kernels every
+    /// process in cgroup.procs is signaled instead
+    pub fn signal_kill(&self) -> Result<()> {
+        let kill = self.path.join("cgroup.kill");
+        match OpenOptions::new().write(true).open(&kill) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => self.kill_procs(),
+            file => {
+                file.and_then(|mut file| file.write_all(b"1"))
+                    .with_context(|| format!("unable to write to {:?}", kill))
+            }
+        }
+    }
+
+    /// Send SIGKILL to every process in cgroup.procs. The processes forked in
+    /// the meantime are missed, they are signaled by the next call
+    fn kill_procs(&self) -> Result<()> {
+        for pid in self.pids()? {
+            match kill(Pid::from_raw(pid as i32), Signal::SIGKILL) {
+                Err(Errno::ESRCH) | Ok(_) => {}
+                err => err.with_context(|| format!("unable to send signal SIGKILL to {pid}"))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Kill all the processes still in the cgroup and then remove it
+    pub async fn kill(&self) -> Result<()> {
+        self.signal_kill()?;
+        let deadline = Instant::now() + KILL_TIMEOUT;
+        while !self.pids()?.is_empty() {
+            if Instant::now() >= deadline {
+                bail!("processes in cgroup {:?} are still alive", self.path);
+            }
+            sleep(KILL_POLL_INTERVAL).await;
+            // Without cgroup.kill, the processes forked while killing the
+            // others are still alive
+            self.signal_kill()?;
+        }
+        match fs::remove_dir(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("unable to remove cgroup {:?}", self.path))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process;
+
+    use rinit_service::types::{
+        KillMode,
+        Script,
+        ScriptEnvironment,
+        ScriptPrefix,
+    };
+
+    use super::*;
+    use crate::supervision::{
+        exec_supervised_script,
+        kill_process,
+        Reaper,
+        ServicePipes,
+    };
+
+    /// Mountpoint of the cgroup v2 hierarchy, if any
+    fn cgroup2_mountpoint() -> Option<PathBuf> {
+        fs::read_to_string("/proc/self/mounts")
+            .ok()?
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .find(|fields| fields.get(2) == Some(&"cgroup2"))
+            .map(|fields| PathBuf::from(fields[1]))
+    }
+
+    #[tokio::test]
+    async fn kill_all_descendants() {
+        let Some(mountpoint) = cgroup2_mountpoint() else {
+            return;
+        };
+        if unsafe { libc::getuid() } != 0 {
+            return;
+        }
+        let cgroup = Cgroup::create(&mountpoint, &format!("rinit-test-{}", process::id())).unwrap();
+        // The subshell escapes the process group of the script
+        let script = Script::new(
+            ScriptPrefix::Bash,
+            "(setsid sleep 100 &) ; sleep 100 & wait".to_string(),
+        );
+        let mut child = exec_supervised_script(
+            &script,
+            &ScriptEnvironment::new(),
+            Some(cgroup.path()),
+            &ServicePipes::default(),
+            &Reaper::default(),
+        )
+        .await
+        .unwrap();
+        sleep(Duration::from_millis(200)).await;
+        let pids = cgroup.pids().unwrap();
+        assert!(pids.contains(&child.id().unwrap()));
+        assert!(pids.len() >= 3);
+
+        cgroup.kill().await.unwrap();
+        child.wait().await.unwrap();
+        assert!(!cgroup.path().exists());
+    }
+
+    #[tokio::test]
+    async fn kill_procs_without_cgroup_kill() {
+        let Some(mountpoint) = cgroup2_mountpoint() else {
+            return;
+        };
+        if unsafe { libc::getuid() } != 0 {
+            return;
+        }
+        let cgroup =
+            Cgroup::create(&mountpoint, &format!("rinit-test-procs-{}", process::id())).unwrap();
+        let script = Script::new(
+            ScriptPrefix::Bash,
+            "(setsid sleep 100 &) ; sleep 100 & wait".to_string(),
+        );
+        let mut child = exec_supervised_script(
+            &script,
+            &ScriptEnvironment::new(),
+            Some(cgroup.path()),
+            &ServicePipes::default(),
+            &Reaper::default(),
+        )
+        .await
+        .unwrap();
+        sleep(Duration::from_millis(200)).await;
+
+        // As done on kernels older than 5.14
+        cgroup.kill_procs().unwrap();
+        child.wait().await.unwrap();
+        let deadline = Instant::now() + KILL_TIMEOUT;
+        while !cgroup.pids().unwrap().is_empty() {
+            assert!(Instant::now() < deadline, "processes are still alive");
+            sleep(KILL_POLL_INTERVAL).await;
+        }
+        fs::remove_dir(cgroup.path()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn signal_whole_cgroup() {
+        let Some(mountpoint) = cgroup2_mountpoint() else {
+            return;
+        };
+        if unsafe { libc::getuid() } != 0 {
+            return;
+        }
+        let cgroup =
+            Cgroup::create(&mountpoint, &format!("rinit-test-signal-{}", process::id())).unwrap();
+        let marker = std::env::temp_dir().join(format!("rinit-cgroup-stopped-{}", process::id()));
+        // The forked child leaves the process group, only the cgroup still has it
+        let execute = format!(
+            "(setsid bash -c \"trap 'touch {}; exit 0' TERM; \
+             while true; do sleep 0.1; done\" &) ; \
+             trap 'exit 0' TERM; sleep 100 & wait",
+            marker.display(),
+        );
+        let mut script = Script::new(ScriptPrefix::Bash, execute);
+        script.down_signal = libc::SIGTERM;
+        script.kill_mode = KillMode::Cgroup;
+        let mut child = exec_supervised_script(
+            &script,
+            &ScriptEnvironment::new(),
+            Some(cgroup.path()),
+            &ServicePipes::default(),
+            &Reaper::default(),
+        )
+        .await
+        .unwrap();
+        sleep(Duration::from_millis(200)).await;
+
+        kill_process(&mut child, &script, Some(&cgroup))
+            .await
+            .unwrap();
+        sleep(Duration::from_millis(200)).await;
+        assert!(marker.exists());
+        fs::remove_file(marker).unwrap();
+        cgroup.kill().await.unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    fs::File,
+    io::Read,
+    os::unix::fs::PermissionsExt,
+};
+
+use anyhow::{
+    ensure,
+    Context,
+    Result,
+};
+use rinit_service::types::{
+    Credential,
+    ScriptEnvironment,
+};
+
+/// Read the credentials and add them to the environment. The files must not be
+/// readable by everyone, otherwise the secret has already leaked
+pub fn add_credentials(
+    environment: &mut ScriptEnvironment,
+    credentials: &[Credential],
+) -> Result<()> {
+    for credential in credentials {
+        let path = &credential.path;
+        // Check the file that is read, it could be replaced after checking the
+        // path
+        let mut file =
+            File::open(path).with_context(|| format!("unable to read credential {:?}", path))?;
+        let metadata = file
+            .metadata()
+            .with_context(|| format!("unable to read credential {:?}", path))?;
+        ensure!(
+            metadata.permissions().mode() & 0o004 == 0,
+            "credential {:?} must not be readable by everyone",
+            path
+        );
+        let mut secret = String::new();
+        file.read_to_string(&mut secret)
+            .with_context(|| format!("unable to read credential {:?}", path))?;
+        // Files usually end with a newline that is not part of the secret
+        let secret = secret.strip_suffix('\n').unwrap_or(&secret);
+        environment.add(&credential.name, secret.to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs,
+        path::Path,
+    };
+
+    use super::*;
+
+    fn write_credential(
+        path: &Path,
+        mode: u32,
+    ) {
+        fs::write(path, "hunter2\n").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn refuse_world_readable_credential() {
+        let path = env::temp_dir().join(format!("rinit-test-credential-{}", std::process::id()));
+        let credentials = [Credential {
+            name: "DB_PASSWORD".to_string(),
+            path: path.clone(),
+        }];
+
+        write_credential(&path, 0o644);
+        let mut environment = ScriptEnvironment::new();
+        assert!(add_credentials(&mut environment, &credentials).is_err());
+        assert!(environment.contents.is_empty());
+
+        write_credential(&path, 0o600);
+        add_credentials(&mut environment, &credentials).unwrap();
+        assert_eq!(
+            environment.contents,
+            vec![("DB_PASSWORD".to_string(), "hunter2".to_string())]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,27 +1,72 @@
 use std::{
     collections::HashMap,
     env,
+    ffi::CString,
+    fs::{
+        File,
+        OpenOptions,
+    },
+    io,
+    os::{
+        fd::{
+            AsRawFd,
+            FromRawFd,
+            OwnedFd,
+            RawFd,
+        },
+        raw::c_char,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
     process::Stdio,
+    ptr,
+    str::FromStr,
 };
 
 use anyhow::{
+    bail,
+    ensure,
     Context,
     Result,
 };
+use caps::{
+    CapSet,
+    Capability,
+};
 use nix::{
+    fcntl::{
+        fcntl,
+        FcntlArg,
+        FdFlag,
+    },
+    mount::{
+        mount,
+        MsFlags,
+    },
+    sched::{
+        unshare,
+        CloneFlags,
+    },
     sys::signal::{
         SigSet,
         SigmaskHow,
     },
     unistd::{
+        Gid,
         Group,
         Pid,
+        Uid,
         User,
     },
 };
 use rinit_service::types::{
+    BindMount,
     Script,
     ScriptEnvironment,
+    ScriptInput,
+    ScriptOutput,
     ScriptPrefix,
 };
 use tokio::process::{
@@ -30,14 +75,212 @@ use tokio::process::{
 };
 use tracing::warn;
 
+use crate::supervision::{
+    Reaper,
+    SeccompFilter,
+    ServicePipes,
+};
+
+/// File descriptor of the listening socket passed to socket activated services
+const LISTEN_FDS_START: RawFd = 3;
+
+extern "C" {
+    static mut environ: *const *const c_char;
+}
+
+/// Environment of a socket activated process, along with a LISTEN_PID set to
+/// its pid. It is built before forking, the child only writes its pid in
+/// place, as it can't allocate
+struct ListenEnvironment {
+    // Owns the strings envp points to
+    _variables: Vec<CString>,
+    _listen_pid: Vec<u8>,
+    // The value of LISTEN_PID, in _listen_pid
+    pid_slot: *mut u8,
+    envp: Vec<*const c_char>,
+}
+
+// The pointers are only used by the child, after forking
+unsafe impl Send for ListenEnvironment {}
+unsafe impl Sync for ListenEnvironment {}
+
+impl ListenEnvironment {
+    const LISTEN_PID: &'static [u8] = b"LISTEN_PID=";
+    /// Digits of the largest pid
+    const PID_DIGITS: usize = 10;
+
+    fn new(variables: &HashMap<String, String>) -> Result<Self> {
+        let variables = variables
+            .iter()
+            .filter(|(key, _)| key.as_str() != "LISTEN_PID")
+            .map(|(key, value)| CString::new(format!("{key}={value}")))
+            .collect::<Result<Vec<_>, _>>()
+            .context("the environment of the script contains a nul byte")?;
+        let mut listen_pid = Self::LISTEN_PID.to_vec();
+        listen_pid.resize(Self::LISTEN_PID.len() + Self::PID_DIGITS + 1, 0);
+        let listen_pid_ptr = listen_pid.as_mut_ptr();
+        let envp = variables
+            .iter()
+            .map(|variable| variable.as_ptr())
+            .chain([listen_pid_ptr as *const c_char, ptr::null()])
+            .collect();
+        Ok(Self {
+            _variables: variables,
+            _listen_pid: listen_pid,
+            pid_slot: unsafe { listen_pid_ptr.add(Self::LISTEN_PID.len()) },
+            envp,
+        })
+    }
+
+    /// Write the pid of the process in LISTEN_PID and make it the environment
+    /// the script is executed with. Only safe in the child, after forking
+    unsafe fn apply(&mut self) {
+        let mut pid = nix::unistd::getpid().as_raw() as u32;
+        let mut digits = [0; Self::PID_DIGITS];
+        let mut len = 0;
+        loop {
+            digits[len] = b'0' + (pid % 10) as u8;
+            len += 1;
+            pid /= 10;
+            if pid == 0 {
+                break;
+            }
+        }
+        for (index, digit) in digits[..len].iter().rev().enumerate() {
+            *self.pid_slot.add(index) = *digit;
+        }
+        *self.pid_slot.add(len) = 0;
+        environ = self.envp.as_ptr();
+    }
+}
+
+/// Replace ${VAR} with the value of VAR in env. Variables not in env, the
+/// ones without braces and $$ are left as they are, so that the shell can
+/// still expand them
+fn expand_variables(
+    value: &str,
+    env: &HashMap<String, String>,
+) -> String {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index..];
+        // An escaped variable, passed to the script as it is
+        if let Some(stripped) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = stripped;
+            continue;
+        }
+        // The pid of the shell, not the start of a variable
+        if let Some(stripped) = rest.strip_prefix("$$") {
+            expanded.push_str("$$");
+            rest = stripped;
+            continue;
+        }
+        let variable = rest
+            .strip_prefix("${")
+            .and_then(|stripped| stripped.split_once('}'))
+            .and_then(|(name, after)| env.get(name).map(|value| (value, after)));
+        if let Some((value, after)) = variable {
+            expanded.push_str(value);
+            rest = after;
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Where the output of the script goes. pipe is the logger pipe for
+/// ScriptOutput::Service, only available to longruns
+fn output_stdio(
+    output: &ScriptOutput,
+    pipe: Option<&OwnedFd>,
+) -> Result<Stdio> {
+    Ok(match output {
+        ScriptOutput::Log => Stdio::piped(),
+        ScriptOutput::Null => Stdio::null(),
+        ScriptOutput::File(path) => {
+            Stdio::from(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("unable to open output file {:?}", path))?,
+            )
+        }
+        ScriptOutput::Service(service) => {
+            let Some(pipe) = pipe else {
+                bail!("only longruns can send their output to the logger service {service}");
+            };
+            Stdio::from(pipe.try_clone().context("unable to duplicate logger pipe")?)
+        }
+    })
+}
+
+/// Where the input of the script comes from. The logger services read from
+/// their pipe instead
+fn input_stdio(input: &ScriptInput) -> Result<Stdio> {
+    Ok(match input {
+        ScriptInput::Null => Stdio::null(),
+        ScriptInput::File(path) => {
+            Stdio::from(
+                File::open(path).with_context(|| format!("unable to open input file {:?}", path))?,
+            )
+        }
+        ScriptInput::Fd(fd) => {
+            // The fd stays open in rsvc, the script gets its own copy. fcntl
+            // fails if it is not open
+            let fd = fcntl(*fd, FcntlArg::F_DUPFD_CLOEXEC(0))
+                .with_context(|| format!("unable to duplicate file descriptor {fd}"))?;
+            Stdio::from(unsafe { OwnedFd::from_raw_fd(fd) })
+        }
+    })
+}
+
+/// The variables of rsvc passed to the scripts of services with
+/// clean_environment set
+const CLEAN_ENVIRONMENT_VARIABLES: [&str; 4] = ["PATH", "HOME", "USER", "TERM"];
+
 pub async fn exec_script(
     script: &Script,
     env: &ScriptEnvironment,
+    reaper: &Reaper,
+) -> Result<Child> {
+    exec_supervised_script(script, env, None, &ServicePipes::default(), reaper).await
+}
+
+/// Execute the script inside the cgroup, so that every process it forks can be
+/// tracked and killed, and connect it to the pipes of the logger services
+pub async fn exec_supervised_script(
+    script: &Script,
+    env: &ScriptEnvironment,
+    cgroup: Option<&Path>,
+    pipes: &ServicePipes,
+    reaper: &Reaper,
 ) -> Result<Child> {
+    // Every value can reference the variables defined before it
+    let mut merged_env: HashMap<String, String> = env::vars()
+        .filter(|(key, _)| !env.clean || CLEAN_ENVIRONMENT_VARIABLES.contains(&key.as_str()))
+        .collect();
+    // Before the variables of the service, so that they can use it. The
+    // executable of a path script is looked up in it as well
+    if let Some(path) = &env.path {
+        ensure!(!path.is_empty(), "the PATH of the service must not be empty");
+        merged_env.insert("PATH".to_string(), path.to_owned());
+    }
+    for (key, value) in &env.contents {
+        let value = expand_variables(value, &merged_env);
+        merged_env.insert(key.to_owned(), value);
+    }
+    let execute = expand_variables(&script.execute, &merged_env);
     let (exe, args) = match &script.prefix {
-        ScriptPrefix::Bash => ("bash", vec!["-c", &script.execute]),
+        ScriptPrefix::Bash => ("bash", vec!["-c", execute.as_str()]),
         ScriptPrefix::Path => {
-            let mut split = script.execute.split_whitespace().peekable();
+            let mut split = execute.split_whitespace().peekable();
             (
                 split
                     .next()
@@ -46,32 +289,80 @@ pub async fn exec_script(
                 split.collect(),
             )
         }
-        ScriptPrefix::Sh => ("sh", vec!["-c", &script.execute]),
+        ScriptPrefix::Sh => ("sh", vec!["-c", execute.as_str()]),
+        // -P: the script takes no positional parameters, so that the ones of
+        // execlineb are not substituted into it
+        ScriptPrefix::Execline => ("execlineb", vec!["-P", "-c", execute.as_str()]),
     };
     let mut cmd = Command::new(exe);
     // TODO: Use a proper splitting function
     cmd.args(args);
-    if let Some(user) = &script.user {
-        cmd.uid(
+    let uid = if let Some(user) = &script.user {
+        Some(
             User::from_name(user)
                 .with_context(|| format!("unable to get UID for user {}", user))?
                 .with_context(|| format!("unable to find UID for user {}", user))?
-                .uid
-                .as_raw(),
-        );
-    }
-    if let Some(group) = &script.group {
-        cmd.gid(
+                .uid,
+        )
+    } else {
+        None
+    };
+    let gid = if let Some(group) = &script.group {
+        Some(
             Group::from_name(group)
                 .with_context(|| format!("unable to get GID for group {}", group))?
                 .with_context(|| format!("unable to find GID for group {}", group))?
-                .gid
-                .as_raw(),
-        );
+                .gid,
+        )
+    } else {
+        None
+    };
+    let capabilities = RetainedCapabilities::new(&script.capabilities)?;
+    let seccomp_filter = script
+        .syscall_filter
+        .as_deref()
+        .map(SeccompFilter::new)
+        .transpose()?;
+    // Lowering oom_score_adj needs privileges
+    let oom_score_adj = script.oom_score_adj.map(|value| value.to_string());
+    let cgroup_procs = cgroup.map(|cgroup| cgroup.join("cgroup.procs"));
+    let listen_fd = pipes.listen.as_ref().map(AsRawFd::as_raw_fd);
+    // LISTEN_PID is only known by the child, which sets the whole environment
+    // itself. std does not change it if no variable has been set on cmd
+    let mut listen_environment = listen_fd
+        .map(|_| ListenEnvironment::new(&merged_env))
+        .transpose()?;
+    let environment_set_by_child = listen_environment.is_some();
+    let bind_mounts = script.bind_mounts.clone();
+    let read_only_paths = script.read_only_paths.clone();
+    let private_tmp = script.private_tmp;
+    let mount_namespace = !bind_mounts.is_empty() || !read_only_paths.is_empty() || private_tmp;
+    // The capabilities and the privileges are lost when the uid changes, which
+    // std does before running pre_exec; in that case the credentials are
+    // changed in pre_exec
+    let change_credentials_in_pre_exec = !capabilities.is_empty()
+        || oom_score_adj.is_some()
+        || cgroup_procs.is_some()
+        || mount_namespace;
+    if !change_credentials_in_pre_exec {
+        if let Some(uid) = uid {
+            cmd.uid(uid.as_raw());
+        }
+        if let Some(gid) = gid {
+            cmd.gid(gid.as_raw());
+        }
     }
-    cmd.stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+    let stdin = match &pipes.stdin {
+        Some(stdin) => Stdio::from(stdin.try_clone().context("unable to duplicate logger pipe")?),
+        None => input_stdio(&script.stdin)?,
+    };
+    cmd.stdin(stdin)
+        .stdout(output_stdio(&script.stdout, pipes.stdout.as_ref())?)
+        .stderr(output_stdio(&script.stderr, pipes.stderr.as_ref())?)
+        // Do not leave the process behind when rsvc stops waiting for it and
+        // drops the Child: a start abandoned after subtree_timeout, or a stop
+        // past its down_timeout. The script is killed with SIGKILL
+        .kill_on_drop(true);
     unsafe {
         cmd.pre_exec(move || -> Result<(), std::io::Error> {
             let mask = SigSet::empty();
@@ -82,15 +373,665 @@ pub async fn exec_script(
             if let Err(err) = nix::unistd::setpgid(Pid::from_raw(0), Pid::from_raw(0)) {
                 warn!("failed to create new process group: {:#?}", err);
             }
+            // The listening socket of socket activated services goes in the
+            // first fd after stdio, as sd_listen_fds expects
+            if let Some(listen_fd) = listen_fd {
+                if listen_fd == LISTEN_FDS_START {
+                    fcntl(listen_fd, FcntlArg::F_SETFD(FdFlag::empty()))?;
+                } else {
+                    // dup2 clears FD_CLOEXEC on the new fd
+                    nix::unistd::dup2(listen_fd, LISTEN_FDS_START)?;
+                }
+            }
+            if let Some(listen_environment) = &mut listen_environment {
+                listen_environment.apply();
+            }
+            // 0 stands for the process writing
+            if let Some(cgroup_procs) = &cgroup_procs {
+                std::fs::write(cgroup_procs, "0")?;
+            }
+            if let Some(oom_score_adj) = &oom_score_adj {
+                std::fs::write("/proc/self/oom_score_adj", oom_score_adj)?;
+            }
+            // Mounting needs the privileges dropped below
+            if mount_namespace {
+                setup_mount_namespace(&bind_mounts, &read_only_paths, private_tmp)?;
+            }
+            if !capabilities.is_empty() {
+                capabilities.apply(uid, gid)?;
+            } else if change_credentials_in_pre_exec {
+                change_credentials(uid, gid)?;
+            }
+            // Last, the syscalls above could be denied by the filter
+            if let Some(seccomp_filter) = &seccomp_filter {
+                seccomp_filter.apply()?;
+            }
             Ok(())
         })
     };
 
-    let merged_env: HashMap<String, String> = env::vars()
-        .chain(env.contents.clone().into_iter())
-        .collect();
-    cmd.envs(merged_env);
-    let child = cmd.spawn().context("unable to spawn script")?;
+    if !environment_set_by_child {
+        if env.clean {
+            cmd.env_clear();
+        }
+        cmd.envs(merged_env);
+    }
+    let child = match cmd.spawn() {
+        Err(err)
+            if err.kind() == io::ErrorKind::NotFound && script.prefix == ScriptPrefix::Execline =>
+        {
+            bail!("execlineb is not in PATH, install execline to run execline scripts")
+        }
+        res => res.context("unable to spawn script")?,
+    };
+    if let Some(pid) = child.id() {
+        reaper.track_child(pid);
+    }
 
     Ok(child)
 }
+
+/// Move the process to its own mount namespace, then mount the private tmpfs
+/// and bind_mounts and make read_only_paths read-only. The read-only paths come
+/// last, so that the mounts below them keep their own flags
+fn setup_mount_namespace(
+    bind_mounts: &[BindMount],
+    read_only_paths: &[PathBuf],
+    private_tmp: bool,
+) -> io::Result<()> {
+    unshare(CloneFlags::CLONE_NEWNS)?;
+    // Keep receiving the mounts done on the host, without sending ours there
+    mount(
+        None::<&str>,
+        "/",
+        None::<&str>,
+        MsFlags::MS_REC | MsFlags::MS_SLAVE,
+        None::<&str>,
+    )?;
+    if private_tmp {
+        for tmp in ["/tmp", "/var/tmp"] {
+            // /var/tmp could be missing on minimal systems
+            if !Path::new(tmp).is_dir() {
+                continue;
+            }
+            // Writable by every user, as the host /tmp, since the uid changes later
+            mount(
+                Some("tmpfs"),
+                tmp,
+                Some("tmpfs"),
+                MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+                Some("mode=1777"),
+            )?;
+        }
+    }
+    for bind_mount in bind_mounts {
+        bind(&bind_mount.source, &bind_mount.target, bind_mount.read_only)?;
+    }
+    for path in read_only_paths {
+        bind(path, path, true)?;
+    }
+
+    Ok(())
+}
+
+/// Recursively bind mount source on target, only the top mount is made
+/// read-only
+fn bind(
+    source: &Path,
+    target: &Path,
+    read_only: bool,
+) -> io::Result<()> {
+    mount(
+        Some(source),
+        target,
+        None::<&str>,
+        MsFlags::MS_BIND | MsFlags::MS_REC,
+        None::<&str>,
+    )?;
+    if read_only {
+        mount(
+            None::<&str>,
+            target,
+            None::<&str>,
+            MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY,
+            None::<&str>,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn change_credentials(
+    uid: Option<Uid>,
+    gid: Option<Gid>,
+) -> io::Result<()> {
+    if let Some(gid) = gid {
+        nix::unistd::setgid(gid)?;
+        nix::unistd::setgroups(&[gid])?;
+    }
+    if let Some(uid) = uid {
+        nix::unistd::setuid(uid)?;
+    }
+
+    Ok(())
+}
+
+/// Capabilities kept by a script when its credentials change. Everything is
+/// computed before forking, so that only raw syscalls are done in the child:
+/// the caps crate allocates, which is not async-signal-safe
+struct RetainedCapabilities {
+    capabilities: Vec<Capability>,
+    // Capabilities of the bounding set to drop
+    dropped: Vec<Capability>,
+    // Permitted, effective and inheritable sets, as passed to capset
+    data: [CapUserData; 2],
+}
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+impl RetainedCapabilities {
+    // 64-bit capability sets, split in two CapUserData
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+    fn new(capabilities: &[String]) -> Result<Self> {
+        let capabilities = capabilities
+            .iter()
+            .map(|capability| {
+                Capability::from_str(capability)
+                    .with_context(|| format!("invalid capability {}", capability))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // The child inherits the bounding set of rsvc
+        let dropped = if capabilities.is_empty() {
+            Vec::new()
+        } else {
+            caps::read(None, CapSet::Bounding)
+                .context("unable to read the bounding set")?
+                .into_iter()
+                .filter(|capability| !capabilities.contains(capability))
+                .collect()
+        };
+        let mask = capabilities
+            .iter()
+            .fold(0, |mask, capability| mask | capability.bitmask());
+        Ok(Self {
+            capabilities,
+            dropped,
+            data: [0, 32].map(|shift| {
+                let set = (mask >> shift) as u32;
+                CapUserData {
+                    effective: set,
+                    permitted: set,
+                    inheritable: set,
+                }
+            }),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.capabilities.is_empty()
+    }
+
+    /// Change the credentials of the current process while keeping the
+    /// capabilities. Every other capability is removed from the bounding set
+    fn apply(
+        &self,
+        uid: Option<Uid>,
+        gid: Option<Gid>,
+    ) -> io::Result<()> {
+        prctl(libc::PR_SET_KEEPCAPS, 1, 0)?;
+        for capability in &self.dropped {
+            prctl(libc::PR_CAPBSET_DROP, capability.index().into(), 0)?;
+        }
+        change_credentials(uid, gid)?;
+        let header = CapUserHeader {
+            version: Self::LINUX_CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        if unsafe { libc::syscall(libc::SYS_capset, &header, self.data.as_ptr()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        for capability in &self.capabilities {
+            prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_RAISE as libc::c_ulong,
+                capability.index().into(),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn prctl(
+    option: libc::c_int,
+    arg2: libc::c_ulong,
+    arg3: libc::c_ulong,
+) -> io::Result<()> {
+    if unsafe { libc::prctl(option, arg2, arg3, 0, 0) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::Path,
+        time::Duration,
+    };
+
+    use rinit_service::types::SyscallFilter;
+    use tokio::time::{
+        sleep,
+        timeout,
+    };
+
+    use super::*;
+    use crate::supervision::LoggerPipe;
+
+    #[tokio::test]
+    async fn denied_syscall_fails() {
+        let run = |execute: &str, syscalls: &[&str]| {
+            let mut script = Script::new(ScriptPrefix::Bash, execute.to_string());
+            script.stdout = ScriptOutput::Null;
+            script.stderr = ScriptOutput::Null;
+            script.syscall_filter = Some(Box::new(SyscallFilter::Deny(
+                syscalls.iter().map(|syscall| syscall.to_string()).collect(),
+            )));
+            async move {
+                exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+                    .await
+                    .unwrap()
+                    .wait()
+                    .await
+                    .unwrap()
+            }
+        };
+
+        assert!(run("uname", &["@mount", "@reboot"]).await.success());
+        // The shell itself still works
+        assert!(run("true", &["uname"]).await.success());
+        assert!(!run("uname", &["uname"]).await.success());
+    }
+
+    #[tokio::test]
+    async fn kill_script_on_drop() {
+        let script = Script::new(ScriptPrefix::Bash, "sleep 10".to_string());
+        let child = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap();
+        let stat = format!("/proc/{}/stat", child.id().unwrap());
+
+        drop(child);
+        // Zombies are not running anymore, they are just waiting to be reaped
+        timeout(Duration::from_secs(1), async {
+            while std::fs::read_to_string(&stat).is_ok_and(|stat| !stat.contains(") Z ")) {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs root and /usr/bin/python3"]
+    async fn keep_capability_as_unprivileged_user() {
+        let mut script = Script::new(
+            ScriptPrefix::Bash,
+            "/usr/bin/python3 -c \"import socket; socket.socket().bind(('127.0.0.1', 999))\""
+                .to_string(),
+        );
+        script.user = Some("nobody".to_string());
+        script.capabilities = vec!["CAP_NET_BIND_SERVICE".to_string()];
+
+        let status = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[tokio::test]
+    async fn bind_mount_in_namespace() {
+        if !Uid::effective().is_root() {
+            return;
+        }
+        let dir = env::temp_dir().join(format!("rinit-test-mounts-{}", std::process::id()));
+        for subdir in ["source", "target", "writable"] {
+            std::fs::create_dir_all(dir.join(subdir)).unwrap();
+        }
+        std::fs::write(dir.join("source").join("marker"), "").unwrap();
+        let execute = format!(
+            "test -f {0}/target/marker && ! touch {0}/denied 2>/dev/null && \
+             touch {0}/writable/allowed",
+            dir.display()
+        );
+        let mut script = Script::new(ScriptPrefix::Bash, execute);
+        script.bind_mounts = vec![
+            BindMount {
+                source: dir.join("source"),
+                target: dir.join("target"),
+                read_only: true,
+            },
+            BindMount {
+                source: dir.join("writable"),
+                target: dir.join("writable"),
+                read_only: false,
+            },
+        ];
+        script.read_only_paths = vec![dir.clone()];
+
+        let status = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+        assert!(status.success());
+        assert!(dir.join("writable").join("allowed").exists());
+        // The mounts only exist in the namespace of the script
+        assert!(!dir.join("target").join("marker").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn private_tmp_in_namespace() {
+        if !Uid::effective().is_root() {
+            return;
+        }
+        let file = format!("rinit-test-private-tmp-{}", std::process::id());
+        // The tmpfs has to be mounted before dropping to nobody
+        let mut script = Script::new(
+            ScriptPrefix::Bash,
+            format!("test -z \"$(ls -A /tmp)\" && touch /tmp/{file} && test -f /tmp/{file}"),
+        );
+        script.user = Some("nobody".to_string());
+        script.private_tmp = true;
+
+        let status = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+        assert!(status.success());
+        assert!(!Path::new("/tmp").join(file).exists());
+    }
+
+    #[tokio::test]
+    async fn set_oom_score_adj() {
+        let mut script = Script::new(
+            ScriptPrefix::Bash,
+            "test \"$(cat /proc/self/oom_score_adj)\" = 500".to_string(),
+        );
+        // Raising the score doesn't need any privilege
+        script.oom_score_adj = Some(500);
+        if Uid::effective().is_root() {
+            script.user = Some("nobody".to_string());
+        }
+
+        let status = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[tokio::test]
+    async fn output_to_file_and_null() {
+        let path = env::temp_dir().join(format!("rinit-test-output-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut script = Script::new(ScriptPrefix::Bash, "echo out; echo err >&2".to_string());
+        script.stdout = ScriptOutput::File(path.clone());
+        script.stderr = ScriptOutput::Null;
+
+        let mut child = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap();
+        // Neither of them is logged
+        assert!(child.stdout.is_none() && child.stderr.is_none());
+        assert!(child.wait().await.unwrap().success());
+        // The file is appended to
+        exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "out\nout\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn input_from_file_and_fd() {
+        let path = env::temp_dir().join(format!("rinit-test-input-{}", std::process::id()));
+        std::fs::write(&path, "hello\n").unwrap();
+        let mut script = Script::new(
+            ScriptPrefix::Bash,
+            "read line; test \"$line\" = hello".to_string(),
+        );
+        script.stdin = ScriptInput::File(path.clone());
+        let status = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+        assert!(status.success());
+
+        let file = File::open(&path).unwrap();
+        script.stdin = ScriptInput::Fd(file.as_raw_fd());
+        let status = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+        assert!(status.success());
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+
+        // Not open in rsvc
+        script.stdin = ScriptInput::Fd(1000);
+        assert!(
+            exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn output_to_logger_service() {
+        let pipe = LoggerPipe::new().unwrap();
+        let mut producer = Script::new(ScriptPrefix::Bash, "echo hello".to_string());
+        producer.stdout = ScriptOutput::Service("logger".to_string());
+        let consumer = Script::new(
+            ScriptPrefix::Bash,
+            "read line; test \"$line\" = hello".to_string(),
+        );
+
+        // Without the pipe, i.e. in a oneshot
+        assert!(
+            exec_script(&producer, &ScriptEnvironment::new(), &Reaper::default())
+                .await
+                .is_err()
+        );
+
+        let env = ScriptEnvironment::new();
+        let producer_pipes = ServicePipes {
+            stdout: Some(pipe.writer().unwrap()),
+            ..Default::default()
+        };
+        let status =
+            exec_supervised_script(&producer, &env, None, &producer_pipes, &Reaper::default())
+                .await
+                .unwrap()
+                .wait()
+                .await
+                .unwrap();
+        assert!(status.success());
+        let consumer_pipes = ServicePipes {
+            stdin: Some(pipe.reader().unwrap()),
+            ..Default::default()
+        };
+        let status =
+            exec_supervised_script(&consumer, &env, None, &consumer_pipes, &Reaper::default())
+                .await
+                .unwrap()
+                .wait()
+                .await
+                .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn expand() {
+        let env = HashMap::from([
+            ("SERVICE_NAME".to_string(), "foo".to_string()),
+            ("RUNDIR".to_string(), "/run/rinit".to_string()),
+        ]);
+        assert_eq!(
+            expand_variables("${RUNDIR}/${SERVICE_NAME}.pid", &env),
+            "/run/rinit/foo.pid"
+        );
+        // Variables without braces are left to the shell
+        assert_eq!(expand_variables("echo $RUNDIR", &env), "echo $RUNDIR");
+    }
+
+    #[test]
+    fn expand_missing_variable() {
+        let env = HashMap::new();
+        assert_eq!(expand_variables("echo ${MISSING}", &env), "echo ${MISSING}");
+        assert_eq!(expand_variables("echo ${UNCLOSED", &env), "echo ${UNCLOSED");
+        assert_eq!(expand_variables("trailing $", &env), "trailing $");
+    }
+
+    #[test]
+    fn expand_keeps_shell_pid() {
+        let env = HashMap::from([("FOO".to_string(), "bar".to_string())]);
+        assert_eq!(expand_variables("echo $$ > ${FOO}", &env), "echo $$ > bar");
+        assert_eq!(expand_variables("kill $$$$", &env), "kill $$$$");
+        assert_eq!(expand_variables("$FOO", &env), "$FOO");
+    }
+
+    #[test]
+    fn expand_escaped_variable() {
+        let env = HashMap::from([("FOO".to_string(), "bar".to_string())]);
+        assert_eq!(expand_variables("$${FOO}", &env), "${FOO}");
+        assert_eq!(expand_variables("$${UNSET} ${FOO}", &env), "${UNSET} bar");
+        assert_eq!(expand_variables("$$${FOO}", &env), "$$bar");
+    }
+
+    #[tokio::test]
+    async fn clean_environment() {
+        env::set_var("RINIT_TEST_SUPERVISOR_VAR", "leaked");
+        let run = |clean| {
+            async move {
+                let mut env = ScriptEnvironment::new();
+                env.clean = clean;
+                env.add("DECLARED", "yes".to_string());
+                let script = Script::new(
+                    ScriptPrefix::Bash,
+                    "test -z \"$RINIT_TEST_SUPERVISOR_VAR\" && test \"$DECLARED\" = yes \
+                     && test -n \"$PATH\""
+                        .to_string(),
+                );
+                exec_script(&script, &env, &Reaper::default())
+                    .await
+                    .unwrap()
+                    .wait()
+                    .await
+                    .unwrap()
+                    .success()
+            }
+        };
+
+        assert!(run(true).await);
+        // Inherited by default
+        assert!(!run(false).await);
+        env::remove_var("RINIT_TEST_SUPERVISOR_VAR");
+    }
+
+    #[tokio::test]
+    async fn run_execline_script() {
+        let script = Script::new(
+            ScriptPrefix::Execline,
+            "foreground { true } exit 3".to_string(),
+        );
+        let installed = env::var_os("PATH").is_some_and(|path| {
+            env::split_paths(&path).any(|dir| dir.join("execlineb").exists())
+        });
+        let res = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default()).await;
+        if installed {
+            let status = res.unwrap().wait().await.unwrap();
+            assert_eq!(status.code(), Some(3));
+        } else {
+            assert!(res.unwrap_err().to_string().contains("execlineb is not in PATH"));
+        }
+    }
+
+    #[tokio::test]
+    async fn expand_in_environment() {
+        let mut env = ScriptEnvironment::new();
+        env.add("RUNDIR", "/run/rinit".to_string());
+        env.add("PIDFILE", "${RUNDIR}/foo.pid".to_string());
+        let script = Script::new(
+            ScriptPrefix::Bash,
+            "test \"$PIDFILE\" = /run/rinit/foo.pid".to_string(),
+        );
+        let status = exec_script(&script, &env, &Reaper::default())
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[tokio::test]
+    async fn path_override() {
+        let dir = env::temp_dir().join(format!("rinit-test-path-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Only found in the overridden PATH
+        let true_exe = env::split_paths(&env::var_os("PATH").unwrap())
+            .map(|dir| dir.join("true"))
+            .find(|exe| exe.exists())
+            .unwrap();
+        std::os::unix::fs::symlink(true_exe, dir.join("rinitpathtest")).unwrap();
+        let script = Script::new(ScriptPrefix::Path, "rinitpathtest".to_string());
+        let mut env = ScriptEnvironment::new();
+        assert!(exec_script(&script, &env, &Reaper::default())
+            .await
+            .is_err());
+
+        env.path = Some(dir.to_string_lossy().into_owned());
+        let status = exec_script(&script, &env, &Reaper::default())
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+        assert!(status.success());
+        env.path = Some(String::new());
+        assert!(exec_script(&script, &env, &Reaper::default())
+            .await
+            .is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
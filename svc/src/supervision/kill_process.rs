@@ -5,52 +5,116 @@ use anyhow::{
     Result,
 };
 use nix::{
+    errno::Errno,
     sys::signal::{
         kill,
+        killpg,
         Signal,
     },
-    unistd::Pid,
+    unistd::{
+        getpgid,
+        getpgrp,
+        Pid,
+    },
 };
-use tokio::{
-    process::Child,
-    time::timeout,
+use rinit_service::types::{
+    KillMode,
+    Script,
 };
+use tokio::time::timeout;
 use tracing::warn;
 
+use crate::supervision::{
+    Cgroup,
+    ChildProcess,
+};
+
+/// Return the process group of the child, if it's safe to signal it as a
+/// whole, i.e. the child is the leader of its own group and it's not ours
+pub fn process_group(child_pid: Pid) -> Option<Pid> {
+    getpgid(Some(child_pid))
+        .ok()
+        .filter(|pgid| *pgid == child_pid && *pgid != getpgrp() && pgid.as_raw() > 1)
+}
+
+/// Send down_signal to every process in the cgroup. The ones that exit in the
+/// meantime are skipped
+fn signal_cgroup(
+    cgroup: &Cgroup,
+    signal: Signal,
+) -> Result<()> {
+    for pid in cgroup.pids()? {
+        match kill(Pid::from_raw(pid as i32), signal) {
+            Err(Errno::ESRCH) | Ok(_) => {}
+            err => err.with_context(|| format!("unable to send signal {signal} to {pid}"))?,
+        }
+    }
+    Ok(())
+}
+
+/// Stop the process executing script, along with the processes selected by
+/// its kill_mode. cgroup is the cgroup of the service, if any
 pub async fn kill_process(
-    child: &mut Child,
-    down_signal: i32,
-    timeout_kill: u32,
+    child: &mut impl ChildProcess,
+    script: &Script,
+    cgroup: Option<&Cgroup>,
 ) -> Result<()> {
-    let child_id = child.id().unwrap() as i32;
-    let child_pid = Pid::from_raw(child_id);
-    kill(
-        child_pid,
-        // Safe, down_signal is always parsed from Signal
-        Signal::try_from(down_signal).unwrap(),
-    )
-    .with_context(|| format!("unable to send signal {:?}", down_signal))?;
+    // The process has already been reaped
+    let Some(child_id) = child.id() else {
+        return Ok(());
+    };
+    let child_pid = Pid::from_raw(child_id as i32);
+    let cgroup = cgroup.filter(|_| script.kill_mode == KillMode::Cgroup);
+    if script.kill_mode == KillMode::Cgroup && cgroup.is_none() {
+        warn!("the service is not in a cgroup, signaling the process group instead");
+    }
+    // exec_script creates a new process group, signal it so that the processes forked by the
+    // script are stopped as well
+    let group = if script.kill_mode == KillMode::Main || cgroup.is_some() {
+        None
+    } else {
+        let group = process_group(child_pid);
+        if group.is_none() {
+            warn!("process {child_pid} is not the leader of its own process group");
+        }
+        group
+    };
+    // Safe, down_signal is always parsed from Signal
+    let signal = Signal::try_from(script.down_signal).unwrap();
+    if let Some(cgroup) = cgroup {
+        signal_cgroup(cgroup, signal)?;
+    } else {
+        if let Some(group) = group {
+            killpg(group, signal)
+        } else {
+            kill(child_pid, signal)
+        }
+        .with_context(|| format!("unable to send signal {:?}", script.down_signal))?;
+    }
+    let timeout_kill = script.timeout_kill;
     let timeout_res = timeout(Duration::from_millis(timeout_kill as u64), child.wait()).await;
     if let Ok(exit_status) = timeout_res {
         exit_status.context("unable to call wait")?;
     } else {
         warn!(
             "the process didn't exit after signal {} and waiting {}ms. Sending SIGKILL",
-            // This is always valid as it's parsed from the name
-            Signal::try_from(down_signal).unwrap(),
-            timeout_kill
+            signal, timeout_kill
         );
         kill(child_pid, Signal::SIGKILL).context("unable to send signal SIGKILL")?;
     }
 
-    // The process might have spawned other processes, if it didn't cleanup it's a
-    // bug. Kill them with SIGKILL. This isn't a lot of overhead, kill_process
-    // shouldn't be called in normal circumstances, and
-    let res = kill(Pid::from_raw(-child_id), Signal::SIGKILL);
+    // The process might have spawned other processes that ignored down_signal, if they
+    // didn't exit it's a bug. Kill them with SIGKILL. This isn't a lot of overhead,
+    // kill_process shouldn't be called in normal circumstances. The lingering processes
+    // in a cgroup are killed together with the cgroup
+    let Some(group) = group else {
+        return Ok(());
+    };
+    let res = killpg(group, Signal::SIGKILL);
     match res {
         Ok(_) => warn!("The were lingering children of the process. Killing them with SIGKILL."),
         Err(errno) => {
-            if !matches!(errno, nix::errno::Errno::ESRCH) {
+            if !matches!(errno, Errno::ESRCH) {
                 res.context("unable to send signal SIGKILL to process group")?
             }
         }
@@ -58,3 +122,97 @@ pub async fn kill_process(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs,
+        path::Path,
+    };
+
+    use rinit_service::types::{
+        ScriptEnvironment,
+        ScriptPrefix,
+    };
+    use tokio::time::sleep;
+
+    use super::*;
+    use crate::supervision::{
+        exec_script,
+        Reaper,
+    };
+
+    fn is_running(pid: &str) -> bool {
+        // Zombies are not running anymore, they are just waiting to be reaped
+        fs::read_to_string(format!("/proc/{pid}/stat"))
+            .is_ok_and(|stat| !stat.contains(") Z "))
+    }
+
+    #[tokio::test]
+    async fn stop_forked_children() {
+        let dir = env::temp_dir().join(format!("rinit-kill-process-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pidfile = dir.join("pid");
+        let marker = dir.join("stopped");
+        // The forked child needs to receive down_signal to create the marker, the parent
+        // waits for it before exiting
+        let execute = format!(
+            "trap 'wait $child; exit 0' TERM; \
+             bash -c \"trap 'touch {}; exit 0' TERM; echo \\$\\$ > {}; \
+             while true; do sleep 0.1; done\" & \
+             child=$!; wait",
+            marker.display(),
+            pidfile.display(),
+        );
+        let mut script = Script::new(ScriptPrefix::Bash, execute);
+        script.down_signal = libc::SIGTERM;
+        let mut child = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap();
+        while !Path::new(&pidfile).exists() || fs::read_to_string(&pidfile).unwrap().is_empty() {
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        kill_process(&mut child, &script, None).await.unwrap();
+
+        assert!(marker.exists());
+        assert!(!is_running(fs::read_to_string(&pidfile).unwrap().trim()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stop_only_main_process() {
+        let dir = env::temp_dir().join(format!("rinit-kill-main-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pidfile = dir.join("pid");
+        let marker = dir.join("stopped");
+        let execute = format!(
+            "trap 'exit 0' TERM; \
+             bash -c \"trap 'touch {}; exit 0' TERM; echo \\$\\$ > {}; \
+             while true; do sleep 0.1; done\" & \
+             wait",
+            marker.display(),
+            pidfile.display(),
+        );
+        let mut script = Script::new(ScriptPrefix::Bash, execute);
+        script.down_signal = libc::SIGTERM;
+        script.kill_mode = KillMode::Main;
+        let mut child = exec_script(&script, &ScriptEnvironment::new(), &Reaper::default())
+            .await
+            .unwrap();
+        while !Path::new(&pidfile).exists() || fs::read_to_string(&pidfile).unwrap().is_empty() {
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        kill_process(&mut child, &script, None).await.unwrap();
+
+        // The forked child has been left alone
+        sleep(Duration::from_millis(200)).await;
+        assert!(!marker.exists());
+        let pid = fs::read_to_string(&pidfile).unwrap();
+        assert!(is_running(pid.trim()));
+        kill(Pid::from_raw(pid.trim().parse().unwrap()), Signal::SIGKILL).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
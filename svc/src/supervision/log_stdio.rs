@@ -1,4 +1,13 @@
-use std::future;
+use std::{
+    collections::VecDeque,
+    future,
+    sync::{
+        Arc,
+        Condvar,
+        Mutex,
+    },
+    thread,
+};
 
 use anyhow::Result;
 use tokio::{
@@ -8,10 +17,13 @@ use tokio::{
         ChildStdout,
     },
     select,
+    sync::oneshot,
 };
 use tracing::{
+    dispatcher,
     info,
     warn,
+    Dispatch,
 };
 
 pub enum StdioType {
@@ -28,58 +40,189 @@ impl From<StdioType> for &'static str {
     }
 }
 
-pub fn log_buf(
-    previous_line: &mut String,
+/// Lines read from the script waiting to be logged. When the logger can't keep
+/// up, the oldest lines are dropped instead of growing without limit
+struct LineBuffer {
+    lines: VecDeque<String>,
+    capacity: usize,
+    dropped: usize,
+    closed: bool,
+}
+
+impl LineBuffer {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+            dropped: 0,
+            closed: false,
+        }
+    }
+
+    fn push(
+        &mut self,
+        line: String,
+    ) {
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Return the next line to log, preceded by a marker if lines were dropped
+    fn pop(&mut self) -> Option<String> {
+        if self.dropped > 0 {
+            let dropped = std::mem::take(&mut self.dropped);
+            return Some(format!("{dropped} lines dropped"));
+        }
+        self.lines.pop_front()
+    }
+
+    /// Return all the lines to log, emptying the buffer
+    fn take(&mut self) -> Vec<String> {
+        std::iter::from_fn(|| self.pop()).collect()
+    }
+}
+
+/// Log the lines pushed in the buffer until it is closed
+fn write_lines(
+    lines: &(Mutex<LineBuffer>, Condvar),
+    dispatch: &Dispatch,
+) {
+    let (lines, new_lines) = lines;
+    dispatcher::with_default(dispatch, || {
+        loop {
+            let mut buffer = new_lines
+                .wait_while(lines.lock().unwrap(), |buffer| {
+                    buffer.lines.is_empty() && buffer.dropped == 0 && !buffer.closed
+                })
+                .unwrap();
+            let closed = buffer.closed;
+            let queued = buffer.take();
+            drop(buffer);
+            queued.iter().for_each(|line| info!("{line}"));
+            if closed {
+                break;
+            }
+        }
+    });
+}
+
+/// Output of the script that has been read but not logged yet, because its
+/// newline hasn't been read
+struct PartialLine {
+    line: String,
+    /// Lines longer than this are truncated instead of being split
+    max_length: Option<usize>,
+    /// How many bytes have been dropped from the end of the line
+    truncated: usize,
+}
+
+impl PartialLine {
+    fn new(max_length: Option<usize>) -> Self {
+        Self {
+            line: String::new(),
+            max_length,
+            truncated: 0,
+        }
+    }
+
+    /// Append text without newlines, dropping what goes beyond max_length
+    fn push_str(
+        &mut self,
+        text: &str,
+    ) {
+        let Some(max_length) = self.max_length else {
+            self.line.push_str(text);
+            return;
+        };
+        let mut end = text.len().min(max_length - self.line.len());
+        // Don't cut a character in half
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.line.push_str(&text[..end]);
+        self.truncated += text.len() - end;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.line.is_empty() && self.truncated == 0
+    }
+
+    /// Return the line to log, followed by a marker if it has been truncated
+    fn take(&mut self) -> String {
+        let line = std::mem::take(&mut self.line);
+        match std::mem::take(&mut self.truncated) {
+            0 => line,
+            truncated => format!("{line}…(truncated {truncated} bytes)"),
+        }
+    }
+}
+
+fn log_buf(
+    previous_line: &mut PartialLine,
     buf: &[u8],
     stdio: &'static str,
+    lines: &mut LineBuffer,
 ) -> Result<()> {
     let buf: &str = std::str::from_utf8(buf)?;
-    let mut last_newline = 0;
-    // If there is output remaining from the latest read call
-    // and there is a newline in buf
-    if let Some(index) = buf.find('\n').filter(|_| !previous_line.is_empty()) {
-        // print the resulting line
-        info!("[{stdio}] {previous_line}{}", &buf[..index]);
-        previous_line.clear();
-        last_newline = index + 1;
-    }
-
-    // iterate all other lines and stop when either the newline found
-    // was the last character or the there are no more newlines
-    while let Some(index) = buf.get(last_newline..).and_then(|b| b.find('\n')) {
-        // the index returned from find refers to new &str buf[last_newline..]
-        // so we need to port it back for buf, by adding last_new_line to it
-        let index = last_newline + index;
-        // print every line
-        info!("[{stdio}] {}", &buf[last_newline..index]);
-        last_newline = index + 1;
+    // Every newline ends the line, including the output remaining from the
+    // previous read calls
+    let mut rest = buf;
+    while let Some(index) = rest.find('\n') {
+        previous_line.push_str(&rest[..index]);
+        lines.push(format!("[{stdio}] {}", previous_line.take()));
+        rest = &rest[index + 1..];
     }
-
-    // if there are characters after the last newline, store them
-    // so that they can be print in the next loop
-    if last_newline != buf.len() {
-        previous_line.push_str(&buf[last_newline..]);
+    // Store the characters after the last newline, so that they can be
+    // printed in the next call
+    previous_line.push_str(rest);
+    // The script has exited without ending its last line
+    if buf.is_empty() && !previous_line.is_empty() {
+        lines.push(format!("[{stdio}] {}", previous_line.take()));
     }
 
     Ok(())
 }
 
 /// We need the handle open, otherwise the tracing subscriber won't work
+/// At most buffer_size lines are kept in memory while waiting to be logged
+/// stdout and stderr are None when they are not logged
+/// Lines longer than max_line_length, if set, are truncated
 pub async fn log_output(
-    mut stdout: ChildStdout,
-    mut stderr: ChildStderr,
-    mut rx: tokio::sync::oneshot::Receiver<()>,
+    mut stdout: Option<ChildStdout>,
+    mut stderr: Option<ChildStderr>,
+    mut rx: oneshot::Receiver<()>,
+    buffer_size: usize,
+    max_line_length: Option<usize>,
 ) -> Result<()> {
-    let mut stdout_line = String::new();
-    let mut stderr_line = String::new();
-    let mut stdout_open = true;
-    let mut stderr_open = true;
-    loop {
+    let lines = Arc::new((Mutex::new(LineBuffer::new(buffer_size)), Condvar::new()));
+    // Logging can block, e.g. on a full pipe, so it is done in its own thread
+    // and never stops the pipes of the script from being emptied
+    let (written, all_written) = oneshot::channel();
+    {
+        let lines = lines.clone();
+        let dispatch = dispatcher::get_default(Dispatch::clone);
+        thread::spawn(move || {
+            write_lines(&lines, &dispatch);
+            let _ = written.send(());
+        });
+    }
+    let (buffer, new_lines) = &*lines;
+
+    let mut stdout_line = PartialLine::new(max_line_length);
+    let mut stderr_line = PartialLine::new(max_line_length);
+    let mut stdout_open = stdout.is_some();
+    let mut stderr_open = stderr.is_some();
+    // If both ends are closed, exit out of the loop
+    while stdout_open || stderr_open {
         let mut stdout_buf = [0; 512];
         let mut stderr_buf = [0; 512];
         select! {
             read = async {
-                if stdout_open {
+                if let (true, Some(stdout)) = (stdout_open, &mut stdout) {
                     stdout.read(&mut stdout_buf[..]).await
                 } else {
                     future::pending::<()>().await;
@@ -92,7 +235,13 @@ pub async fn log_output(
                         stdout_open = false;
                     }
                     Ok(n) => {
-                        if let Err(err) = log_buf(&mut stdout_line, &stdout_buf[..n], "stdout") {
+                        let res = log_buf(
+                            &mut stdout_line,
+                            &stdout_buf[..n],
+                            "stdout",
+                            &mut buffer.lock().unwrap(),
+                        );
+                        if let Err(err) = res {
                             warn!("{err}");
                         }
                     }
@@ -100,7 +249,7 @@ pub async fn log_output(
                 }
             },
             read = async {
-                if stderr_open {
+                if let (true, Some(stderr)) = (stderr_open, &mut stderr) {
                     stderr.read(&mut stderr_buf[..]).await
                 } else {
                     future::pending::<()>().await;
@@ -113,7 +262,13 @@ pub async fn log_output(
                         stderr_open = false;
                     }
                     Ok(n) => {
-                        if let Err(err) = log_buf(&mut stderr_line, &stderr_buf[..n], "stderr") {
+                        let res = log_buf(
+                            &mut stderr_line,
+                            &stderr_buf[..n],
+                            "stderr",
+                            &mut buffer.lock().unwrap(),
+                        );
+                        if let Err(err) = res {
                             warn!("{err}");
                         }
                     }
@@ -124,20 +279,187 @@ pub async fn log_output(
                 break;
             }
         }
+        new_lines.notify_one();
+    }
 
-        // If both ends are closed, exit out of the loop
-        if !stdout_open && !stderr_open {
-            break;
+    {
+        let mut lines = buffer.lock().unwrap();
+        if let Err(err) = log_buf(&mut stdout_line, &[], "stdout", &mut lines) {
+            warn!("{err}");
         }
+        if let Err(err) = log_buf(&mut stderr_line, &[], "stderr", &mut lines) {
+            warn!("{err}");
+        }
+        lines.closed = true;
     }
+    new_lines.notify_one();
+    // The thread exits once every line has been logged
+    let _ = all_written.await;
+
+    Ok(())
+}
 
-    if let Err(err) = log_buf(&mut stdout_line, &[], "stdout") {
-        warn!("{err}");
+#[cfg(test)]
+mod test {
+    use std::{
+        io,
+        process::Stdio,
+    };
+
+    use rinit_service::types::Script;
+    use tokio::process::Command;
+
+    use super::*;
+
+    /// Keep everything logged by the subscriber. Logging blocks while it is
+    /// stalled
+    #[derive(Clone, Default)]
+    struct Buffer {
+        log: Arc<Mutex<Vec<u8>>>,
+        stalled: Arc<(Mutex<bool>, Condvar)>,
     }
 
-    if let Err(err) = log_buf(&mut stderr_line, &[], "stderr") {
-        warn!("{err}");
+    impl Buffer {
+        fn stall(
+            &self,
+            stalled: bool,
+        ) {
+            let (lock, cvar) = &*self.stalled;
+            *lock.lock().unwrap() = stalled;
+            cvar.notify_all();
+        }
+
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.log.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|line| line.rsplit_once("log_stdio: ").unwrap().1.to_string())
+                .collect()
+        }
     }
 
-    Ok(())
+    impl io::Write for Buffer {
+        fn write(
+            &mut self,
+            buf: &[u8],
+        ) -> io::Result<usize> {
+            let (lock, cvar) = &*self.stalled;
+            let stalled = lock.lock().unwrap();
+            drop(cvar.wait_while(stalled, |stalled| *stalled).unwrap());
+            self.log.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn set_buffer_subscriber(buffer: &Buffer) -> tracing::subscriber::DefaultGuard {
+        let writer = buffer.clone();
+        tracing::subscriber::set_default(
+            tracing_subscriber::fmt()
+                .with_writer(move || writer.clone())
+                .finish(),
+        )
+    }
+
+    fn seq(count: usize) -> tokio::process::Child {
+        Command::new("seq")
+            .arg(count.to_string())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn drop_oldest_lines() {
+        let mut lines = LineBuffer::new(4);
+        let mut previous_line = PartialLine::new(None);
+        let output: String = (0..100).map(|i| format!("line {i}\n")).collect();
+        log_buf(&mut previous_line, output.as_bytes(), "stdout", &mut lines).unwrap();
+        assert_eq!(lines.lines.len(), 4);
+        assert_eq!(lines.pop().unwrap(), "96 lines dropped");
+        assert_eq!(lines.pop().unwrap(), "[stdout] line 96");
+        lines.push("[stdout] line 100".to_string());
+        assert_eq!(
+            std::iter::from_fn(|| lines.pop()).collect::<Vec<_>>(),
+            vec![
+                "[stdout] line 97",
+                "[stdout] line 98",
+                "[stdout] line 99",
+                "[stdout] line 100"
+            ]
+        );
+    }
+
+    #[test]
+    fn truncate_long_lines() {
+        let mut lines = LineBuffer::new(16);
+        let mut previous_line = PartialLine::new(Some(8));
+        // The line is read in several calls, the truncated part included
+        log_buf(&mut previous_line, b"0123456789", "stdout", &mut lines).unwrap();
+        log_buf(&mut previous_line, b"abcdef\nshort\n", "stdout", &mut lines).unwrap();
+        assert_eq!(
+            std::iter::from_fn(|| lines.pop()).collect::<Vec<_>>(),
+            vec!["[stdout] 01234567…(truncated 8 bytes)", "[stdout] short"]
+        );
+        // Characters are not cut in half, é is 2 bytes long
+        log_buf(&mut previous_line, "abcdefgé".as_bytes(), "stderr", &mut lines).unwrap();
+        log_buf(&mut previous_line, &[], "stderr", &mut lines).unwrap();
+        assert_eq!(lines.pop().unwrap(), "[stderr] abcdefg…(truncated 2 bytes)");
+    }
+
+    #[tokio::test]
+    async fn log_every_line_when_the_logger_keeps_up() {
+        let buffer = Buffer::default();
+        let _guard = set_buffer_subscriber(&buffer);
+
+        let mut child = seq(20000);
+        let (_tx, rx) = oneshot::channel();
+        log_output(child.stdout.take(), None, rx, 20000, None)
+            .await
+            .unwrap();
+        child.wait().await.unwrap();
+
+        let expected: Vec<_> = (1..=20000).map(|i| format!("[stdout] {i}")).collect();
+        assert_eq!(buffer.lines(), expected);
+    }
+
+    #[tokio::test]
+    async fn stalled_logger_drops_the_oldest_lines_of_a_flood() {
+        let buffer = Buffer::default();
+        let _guard = set_buffer_subscriber(&buffer);
+
+        buffer.stall(true);
+        // Far more than the pipe can hold, seq can only exit if the pipe is
+        // emptied while nothing can be logged
+        let mut child = seq(100000);
+        let (_tx, rx) = oneshot::channel();
+        let logger = tokio::spawn(log_output(
+            child.stdout.take(),
+            None,
+            rx,
+            Script::DEFAULT_LOG_BUFFER,
+            None,
+        ));
+        tokio::time::timeout(std::time::Duration::from_secs(5), child.wait())
+            .await
+            .unwrap()
+            .unwrap();
+        buffer.stall(false);
+        logger.await.unwrap().unwrap();
+
+        let lines = buffer.lines();
+        let dropped: usize = lines
+            .iter()
+            .filter_map(|line| line.strip_suffix(" lines dropped"))
+            .map(|dropped| dropped.parse::<usize>().unwrap())
+            .sum();
+        assert!(dropped > 0);
+        // Every line is either logged or counted by a marker
+        let logged = lines.iter().filter(|line| line.starts_with("[stdout]"));
+        assert_eq!(logged.count() + dropped, 100000);
+        assert_eq!(lines.last().unwrap(), "[stdout] 100000");
+    }
 }
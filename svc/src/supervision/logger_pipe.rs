@@ -0,0 +1,53 @@
+use std::{
+    io,
+    os::fd::{
+        FromRawFd,
+        OwnedFd,
+    },
+};
+
+use nix::{
+    fcntl::OFlag,
+    unistd::pipe2,
+};
+
+/// Pipe between the longruns writing their output to a logger service and the
+/// logger reading it on stdin. It is kept open by rsvc, so that no output is
+/// lost while either side is being restarted
+pub struct LoggerPipe {
+    read: OwnedFd,
+    write: OwnedFd,
+}
+
+impl LoggerPipe {
+    pub fn new() -> io::Result<Self> {
+        let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+        // pipe2 returned two new file descriptors that nothing else owns
+        Ok(unsafe {
+            Self {
+                read: OwnedFd::from_raw_fd(read),
+                write: OwnedFd::from_raw_fd(write),
+            }
+        })
+    }
+
+    pub fn reader(&self) -> io::Result<OwnedFd> {
+        self.read.try_clone()
+    }
+
+    pub fn writer(&self) -> io::Result<OwnedFd> {
+        self.write.try_clone()
+    }
+}
+
+/// Ends of the logger pipes used by a script instead of what it has in stdout
+/// and stderr
+#[derive(Default)]
+pub struct ServicePipes {
+    /// Set for logger services, the output of the services logging to it
+    pub stdin: Option<OwnedFd>,
+    pub stdout: Option<OwnedFd>,
+    pub stderr: Option<OwnedFd>,
+    /// Set for socket activated services, passed to the script as fd 3
+    pub listen: Option<OwnedFd>,
+}
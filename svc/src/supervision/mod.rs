@@ -1,17 +1,56 @@
+mod activation_socket;
+pub use activation_socket::ActivationSocket;
+mod adopted_process;
+pub use adopted_process::{
+    AdoptedProcess,
+    ChildProcess,
+    InheritedFds,
+    SupervisedProcess,
+};
+mod cgroup;
+pub use cgroup::Cgroup;
+mod credentials;
+pub use credentials::add_credentials;
 mod exec_script;
-pub use exec_script::exec_script;
+pub use exec_script::{
+    exec_script,
+    exec_supervised_script,
+};
 mod kill_process;
-pub use kill_process::kill_process;
+pub use kill_process::{
+    kill_process,
+    process_group,
+};
 mod log_stdio;
 pub use log_stdio::log_output;
+mod logger_pipe;
+pub use logger_pipe::{
+    LoggerPipe,
+    ServicePipes,
+};
+mod notify_socket;
+pub use notify_socket::NotifySocket;
 mod pidfd_send_signal;
 pub use pidfd_send_signal::pidfd_send_signal;
+mod reaper;
+pub use reaper::{
+    set_subreaper,
+    Reaper,
+    DEFAULT_REAP_INTERVAL,
+};
+mod resource_usage;
+pub use resource_usage::ResourceUsage;
 mod run_short_lived_script;
 pub use run_short_lived_script::run_short_lived_script;
+mod seccomp;
+pub use seccomp::SeccompFilter;
 mod signal_wait;
 pub use signal_wait::{
     signal_wait,
     signal_wait_fun,
 };
 mod supervisor;
-pub use supervisor::Supervisor;
+pub use supervisor::{
+    ProcessInfo,
+    Supervisor,
+};
@@ -0,0 +1,154 @@
+use std::{
+    cell::RefCell,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{
+        Path,
+        PathBuf,
+    },
+    rc::Rc,
+};
+
+use anyhow::{
+    Context,
+    Result,
+};
+use tokio::net::UnixDatagram;
+use tracing::warn;
+
+use crate::supervision::ProcessInfo;
+
+/// Datagram socket implementing the sd_notify protocol, so that daemons
+/// supporting systemd readiness notification can be used as they are
+pub struct NotifySocket {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl NotifySocket {
+    pub fn bind(path: PathBuf) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("unable to create directory {:?}", parent))?;
+        }
+        // Remove the socket left by a previous instance of the service
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("unable to remove stale socket {:?}", path))?;
+        }
+        let socket = UnixDatagram::bind(&path)
+            .with_context(|| format!("unable to bind notify socket {:?}", path))?;
+        // The script could be running as a different user
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666))
+            .with_context(|| format!("unable to set permissions of {:?}", path))?;
+        Ok(Self { socket, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read the notifications until the service sends READY=1. Return
+    /// whether it sent RELOADING=1 before, i.e. it is ready again after
+    /// reloading
+    pub async fn wait_ready(
+        &self,
+        status_message: &RefCell<Option<String>>,
+    ) -> Result<bool> {
+        let mut buf = vec![0; 4096];
+        let mut reloading = false;
+        loop {
+            let len = self
+                .socket
+                .recv(&mut buf)
+                .await
+                .context("unable to receive from notify socket")?;
+            let notification = handle_notification(&buf[..len], status_message);
+            reloading |= notification.reloading;
+            if notification.ready {
+                break Ok(reloading);
+            }
+        }
+    }
+
+    /// Keep reading the status updates after the service is ready, waking up
+    /// whoever waits on process_info.reloaded when a reload is done
+    pub async fn listen(
+        self,
+        status_message: Rc<RefCell<Option<String>>>,
+        process_info: Rc<ProcessInfo>,
+    ) {
+        loop {
+            match self.wait_ready(&status_message).await {
+                Ok(true) => process_info.reloaded.notify_waiters(),
+                Ok(false) => {}
+                Err(err) => {
+                    warn!("{err:?}");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for NotifySocket {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("unable to remove notify socket {:?}: {err}", self.path);
+        }
+    }
+}
+
+/// The state changes sent by the service in a notification
+#[derive(Default, Debug, PartialEq, Eq)]
+struct Notification {
+    ready: bool,
+    reloading: bool,
+}
+
+/// Parse the newline-separated assignments of a notification
+fn handle_notification(
+    buf: &[u8],
+    status_message: &RefCell<Option<String>>,
+) -> Notification {
+    let mut notification = Notification::default();
+    let Ok(assignments) = std::str::from_utf8(buf) else {
+        warn!("received invalid UTF-8 on the notify socket");
+        return notification;
+    };
+    for line in assignments.lines() {
+        if line == "READY=1" {
+            notification.ready = true;
+        } else if line == "RELOADING=1" {
+            notification.reloading = true;
+        } else if let Some(status) = line.strip_prefix("STATUS=") {
+            status_message.replace(Some(status.to_string()));
+        }
+    }
+    notification
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_notification() {
+        let status_message = RefCell::new(None);
+        assert_eq!(
+            handle_notification(b"STATUS=loading", &status_message),
+            Notification::default()
+        );
+        assert_eq!(status_message.borrow().as_deref(), Some("loading"));
+        assert!(handle_notification(b"STATUS=listening\nREADY=1\n", &status_message).ready);
+        assert_eq!(status_message.borrow().as_deref(), Some("listening"));
+        assert_eq!(
+            handle_notification(b"RELOADING=1\nSTATUS=reloading", &status_message),
+            Notification {
+                ready: false,
+                reloading: true,
+            }
+        );
+        assert_eq!(status_message.borrow().as_deref(), Some("reloading"));
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+    process,
+    sync::Mutex,
+    time::Duration,
+};
+
+use anyhow::{
+    Context,
+    Result,
+};
+use nix::{
+    sys::wait::{
+        waitpid,
+        WaitPidFlag,
+        WaitStatus,
+    },
+    unistd::Pid,
+};
+use tokio::time::interval;
+use tracing::{
+    debug,
+    warn,
+};
+
+/// Used when reap_interval is not set in the configuration
+pub const DEFAULT_REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Become the parent of the processes orphaned by the services. PID 1 already
+/// is, being the reaper of the whole system
+pub fn set_subreaper() -> Result<()> {
+    if process::id() == 1 {
+        return Ok(());
+    }
+    if unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("unable to become a subreaper");
+    }
+    Ok(())
+}
+
+/// A process that was reaped without being spawned by rsvc
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReapedOrphan {
+    pub pid: u32,
+    /// Service whose cgroup contained the process, if known
+    pub service: Option<String>,
+}
+
+/// Reaps the processes orphaned by the services. It keeps track of the
+/// children spawned by rsvc itself: tokio waits on them, reaping them here
+/// would steal their exit status
+#[derive(Default)]
+pub struct Reaper {
+    tracked_children: Mutex<BTreeSet<u32>>,
+}
+
+impl Reaper {
+    /// Mark pid as a child spawned by rsvc, so that it is never reaped here
+    pub fn track_child(
+        &self,
+        pid: u32,
+    ) {
+        self.tracked_children.lock().unwrap().insert(pid);
+    }
+
+    /// Reap the zombies that are children of rsvc but are not tracked, i.e. the
+    /// processes orphaned by the services and reparented to rsvc
+    pub fn reap_orphans(
+        &self,
+        cgroup_parent: Option<&Path>,
+    ) -> Vec<ReapedOrphan> {
+        let zombies = match zombie_children() {
+            Ok(zombies) => zombies,
+            Err(err) => {
+                warn!("{err:?}");
+                return Vec::new();
+            }
+        };
+        let mut tracked = self.tracked_children.lock().unwrap();
+        // The children reaped by tokio are gone
+        tracked.retain(|pid| Path::new("/proc").join(pid.to_string()).exists());
+        zombies
+            .into_iter()
+            .filter(|pid| !tracked.contains(pid))
+            .filter_map(|pid| {
+                // The cgroup must be read before the zombie disappears
+                let service = cgroup_parent.and_then(|parent| service_of(pid, parent));
+                match waitpid(Pid::from_raw(pid as i32), Some(WaitPidFlag::WNOHANG)) {
+                    Ok(WaitStatus::StillAlive) => None,
+                    Ok(status) => {
+                        debug!(
+                            "reaped orphaned process {pid} of service {}: {status:?}",
+                            service.as_deref().unwrap_or("unknown")
+                        );
+                        Some(ReapedOrphan { pid, service })
+                    }
+                    Err(err) => {
+                        warn!("unable to reap process {pid}: {err}");
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Reap the orphans every reap_interval, until rsvc exits
+    pub async fn reap_loop(
+        &self,
+        reap_interval: Duration,
+        cgroup_parent: Option<PathBuf>,
+    ) {
+        let mut interval = interval(reap_interval);
+        loop {
+            interval.tick().await;
+            self.reap_orphans(cgroup_parent.as_deref());
+        }
+    }
+}
+
+/// Pids of the children of rsvc that are zombies
+fn zombie_children() -> Result<Vec<u32>> {
+    let own_pid = process::id();
+    Ok(fs::read_dir("/proc")
+        .context("unable to read /proc")?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            // The process could have exited in the meantime
+            let Ok(stat) = fs::read_to_string(format!("/proc/{pid}/stat")) else {
+                return false;
+            };
+            // The command name can contain spaces and parentheses, the other
+            // fields come after the last parenthesis
+            let mut fields = stat
+                .rsplit_once(')')
+                .map(|(_, fields)| fields)
+                .unwrap_or_default()
+                .split_whitespace();
+            let state = fields.next();
+            let ppid = fields.next().and_then(|ppid| ppid.parse::<u32>().ok());
+            state == Some("Z") && ppid == Some(own_pid)
+        })
+        .collect())
+}
+
+/// Name of the service whose cgroup, inside cgroup_parent, contains pid
+fn service_of(
+    pid: u32,
+    cgroup_parent: &Path,
+) -> Option<String> {
+    let cgroups = fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+    // cgroup v2 has a single hierarchy, e.g. 0::/rinit/sshd
+    let cgroup = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let cgroup = Path::new(cgroup.trim_start_matches('/'));
+    let parent = cgroup.parent().filter(|parent| !parent.as_os_str().is_empty())?;
+    if !cgroup_parent.ends_with(parent) {
+        return None;
+    }
+    Some(cgroup.file_name()?.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        process::Command,
+    };
+
+    use rinit_service::types::{
+        Script,
+        ScriptEnvironment,
+        ScriptPrefix,
+    };
+    use tokio::{
+        io::AsyncReadExt,
+        runtime,
+        time::sleep,
+    };
+
+    use super::*;
+    use crate::supervision::exec_script;
+
+    /// Set in the test binary re-executed by reap_orphaned_grandchild
+    const SUBPROCESS_VAR: &str = "RINIT_TEST_REAPER_SUBPROCESS";
+
+    async fn reap_grandchild() {
+        set_subreaper().unwrap();
+        let reaper = Reaper::default();
+        // The script exits right away, leaving the background sleep orphaned
+        let script = Script::new(ScriptPrefix::Bash, "sleep 0.2 & echo $!".to_string());
+        let mut child = exec_script(&script, &ScriptEnvironment::new(), &reaper)
+            .await
+            .unwrap();
+        let mut stdout = String::new();
+        child
+            .stdout
+            .take()
+            .unwrap()
+            .read_to_string(&mut stdout)
+            .await
+            .unwrap();
+        let grandchild: u32 = stdout.trim().parse().unwrap();
+
+        sleep(Duration::from_millis(500)).await;
+        assert!(zombie_children().unwrap().contains(&grandchild));
+        // The script is tracked, its exit status is left to tokio
+        let reaped = reaper.reap_orphans(None);
+        assert_eq!(
+            reaped,
+            vec![ReapedOrphan {
+                pid: grandchild,
+                service: None,
+            }]
+        );
+        assert!(!zombie_children().unwrap().contains(&grandchild));
+        assert!(child.wait().await.unwrap().success());
+    }
+
+    /// Becoming a subreaper and reaping every untracked child affects the
+    /// whole process, so it is done in a copy of the test binary running only
+    /// this test
+    #[test]
+    fn reap_orphaned_grandchild() {
+        if env::var_os(SUBPROCESS_VAR).is_some() {
+            runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(reap_grandchild());
+            return;
+        }
+        let status = Command::new(env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "supervision::reaper::test::reap_orphaned_grandchild",
+                "--nocapture",
+            ])
+            .env(SUBPROCESS_VAR, "1")
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    fs,
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{
+    Context,
+    Result,
+};
+use nix::unistd::{
+    sysconf,
+    SysconfVar,
+};
+
+/// Memory and CPU time used by a service
+#[derive(Debug, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// Bytes of memory in use
+    pub memory: u64,
+    pub cpu_time: Duration,
+}
+
+impl ResourceUsage {
+    /// Usage of every process in the cgroup v2 at path, read from
+    /// memory.current and cpu.stat
+    pub fn of_cgroup(path: &Path) -> Result<Self> {
+        let memory = read_file(&path.join("memory.current"))?;
+        let cpu_stat = read_file(&path.join("cpu.stat"))?;
+        Ok(Self {
+            memory: memory
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid memory.current in {:?}", path))?,
+            cpu_time: parse_cpu_stat(&cpu_stat)
+                .with_context(|| format!("usage_usec not found in cpu.stat of {:?}", path))?,
+        })
+    }
+
+    /// Usage of the process pid alone, for when the services are not placed
+    /// in cgroups: its resident set size and its user and system time
+    pub fn of_process(pid: u32) -> Result<Self> {
+        let proc = Path::new("/proc").join(pid.to_string());
+        let status = read_file(&proc.join("status"))?;
+        let stat = read_file(&proc.join("stat"))?;
+        let ticks_per_second = sysconf(SysconfVar::CLK_TCK)
+            .context("unable to get the clock ticks per second")?
+            .context("the clock ticks per second are unknown")?;
+        let ticks = parse_proc_stat_ticks(&stat)
+            .with_context(|| format!("invalid stat of process {pid}"))?;
+        Ok(Self {
+            memory: parse_proc_status_rss(&status)
+                .with_context(|| format!("VmRSS not found in the status of process {pid}"))?,
+            cpu_time: Duration::from_secs_f64(ticks as f64 / ticks_per_second as f64),
+        })
+    }
+}
+
+fn read_file(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("unable to read {:?}", path))
+}
+
+/// CPU time in the usage_usec line of cpu.stat
+fn parse_cpu_stat(cpu_stat: &str) -> Option<Duration> {
+    cpu_stat
+        .lines()
+        .find_map(|line| line.strip_prefix("usage_usec "))
+        .and_then(|usec| usec.trim().parse().ok())
+        .map(Duration::from_micros)
+}
+
+/// Bytes in the VmRSS line of /proc/<pid>/status, e.g. "VmRSS:  1024 kB"
+fn parse_proc_status_rss(status: &str) -> Option<u64> {
+    let rss = status.lines().find_map(|line| line.strip_prefix("VmRSS:"))?;
+    let kilobytes: u64 = rss.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Sum of utime and stime, in clock ticks, of /proc/<pid>/stat
+fn parse_proc_stat_ticks(stat: &str) -> Option<u64> {
+    // The command name can contain spaces and parentheses, the other fields
+    // come after the last parenthesis, starting from the state (3rd field)
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(utime + stime)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        process,
+    };
+
+    use super::*;
+
+    #[test]
+    fn parse_cgroup_stat_files() {
+        let dir = env::temp_dir().join(format!("rinit-test-usage-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("memory.current"), "4702208\n").unwrap();
+        fs::write(
+            dir.join("cpu.stat"),
+            "usage_usec 1503000\nuser_usec 1002000\nsystem_usec 501000\n\
+             nr_periods 0\nnr_throttled 0\nthrottled_usec 0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            ResourceUsage::of_cgroup(&dir).unwrap(),
+            ResourceUsage {
+                memory: 4702208,
+                cpu_time: Duration::from_micros(1503000),
+            }
+        );
+
+        fs::write(dir.join("cpu.stat"), "user_usec 1002000\n").unwrap();
+        assert!(ResourceUsage::of_cgroup(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(ResourceUsage::of_cgroup(&dir).is_err());
+    }
+
+    #[test]
+    fn parse_proc_files() {
+        let status = "Name:\tsshd\nVmPeak:\t   10000 kB\nVmRSS:\t    5120 kB\nThreads:\t1\n";
+        assert_eq!(parse_proc_status_rss(status), Some(5120 * 1024));
+        // Kernel threads have no memory
+        assert_eq!(parse_proc_status_rss("Name:\tkthreadd\n"), None);
+
+        let stat = "42 (my (odd) daemon) S 1 42 42 0 -1 4194560 300 0 0 0 250 50 0 0 20 0 1 0";
+        assert_eq!(parse_proc_stat_ticks(stat), Some(300));
+
+        let usage = ResourceUsage::of_process(process::id()).unwrap();
+        assert!(usage.memory > 0);
+    }
+}
@@ -27,6 +27,7 @@ use crate::supervision::{
     kill_process,
     log_output,
     signal_wait::WaitFn,
+    Reaper,
 };
 
 #[derive(Debug, PartialEq, Eq)]
@@ -39,6 +40,7 @@ enum ScriptResult {
 pub async fn run_short_lived_script<F>(
     script: &Script,
     env: &ScriptEnvironment,
+    reaper: &Reaper,
     mut wait: F,
 ) -> Result<bool>
 where
@@ -48,16 +50,18 @@ where
 
     let mut time_tried = 0;
     let success = loop {
-        let mut child = exec_script(script, env)
+        let mut child = exec_script(script, env, reaper)
             .await
             .context("unable to execute script")?;
         let (tx, rx) = oneshot::channel();
         // TODO
         let logger = task::spawn(
             log_output(
-                child.stdout.take().unwrap(),
-                child.stderr.take().unwrap(),
+                child.stdout.take(),
+                child.stderr.take(),
                 rx,
+                script.log_buffer,
+                script.max_line_length,
             )
             .with_current_subscriber(),
         );
@@ -86,20 +90,20 @@ where
             // The process exited on its own within timeout
             ScriptResult::Exited(exit_status) => {
                 // We want the process to exit successfully to consider it "up"
-                if exit_status.success() {
+                if script.exited_successfully(exit_status.code()) {
                     break true;
                 }
             }
             // The supervisor received a signal while waiting and interuppted the wait
             ScriptResult::SignalReceived => {
                 // Kill the process before exiting
-                kill_process(&mut child, script.down_signal, script.timeout_kill).await?;
+                kill_process(&mut child, script, None).await?;
                 break false;
             }
             // The script didn't exit within timeout
             ScriptResult::TimedOut => {
                 // Kill it and try again
-                kill_process(&mut child, script.down_signal, script.timeout_kill).await?;
+                kill_process(&mut child, script, None).await?;
             }
         }
 
@@ -148,32 +152,65 @@ mod tests {
     #[tokio::test]
     async fn test_run_script_success() {
         let script = Script::new(ScriptPrefix::Bash, "exit 0".to_string());
-        assert!(
-            run_short_lived_script(&script, &ScriptEnvironment::default(), wait!(100))
-                .await
-                .unwrap()
-        );
+        assert!(run_short_lived_script(
+            &script,
+            &ScriptEnvironment::default(),
+            &Reaper::default(),
+            wait!(100)
+        )
+        .await
+        .unwrap());
     }
 
     #[tokio::test]
     async fn test_run_script_failure() {
         let script = Script::new(ScriptPrefix::Bash, "exit 1".to_string());
-        assert!(
-            !run_short_lived_script(&script, &ScriptEnvironment::default(), wait!(100))
-                .await
-                .unwrap()
-        );
+        assert!(!run_short_lived_script(
+            &script,
+            &ScriptEnvironment::default(),
+            &Reaper::default(),
+            wait!(100)
+        )
+        .await
+        .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_run_script_success_exit_codes() {
+        let mut script = Script::new(ScriptPrefix::Bash, "exit 1".to_string());
+        script.success_exit_codes = vec![0, 1];
+        assert!(run_short_lived_script(
+            &script,
+            &ScriptEnvironment::default(),
+            &Reaper::default(),
+            wait!(100)
+        )
+        .await
+        .unwrap());
+        script.execute = "exit 2".to_string();
+        script.max_deaths = 1;
+        assert!(!run_short_lived_script(
+            &script,
+            &ScriptEnvironment::default(),
+            &Reaper::default(),
+            wait!(100)
+        )
+        .await
+        .unwrap());
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_run_script_timeout() {
         let mut script = Script::new(ScriptPrefix::Bash, "sleep 15".to_string());
         script.timeout = 10;
-        assert!(
-            !run_short_lived_script(&script, &ScriptEnvironment::default(), wait!(100))
-                .await
-                .unwrap()
-        );
+        assert!(!run_short_lived_script(
+            &script,
+            &ScriptEnvironment::default(),
+            &Reaper::default(),
+            wait!(100)
+        )
+        .await
+        .unwrap());
     }
 
     #[tokio::test]
@@ -186,22 +223,28 @@ mod tests {
         // 10 is SIGUSR1. Send a signal that won't terminate the program
         script.down_signal = 10;
         script.max_deaths = 1;
-        assert!(
-            !run_short_lived_script(&script, &ScriptEnvironment::default(), wait!(100))
-                .await
-                .unwrap()
-        );
+        assert!(!run_short_lived_script(
+            &script,
+            &ScriptEnvironment::default(),
+            &Reaper::default(),
+            wait!(100)
+        )
+        .await
+        .unwrap());
     }
 
     #[tokio::test]
     async fn test_run_script_side_effects() {
         let filename = "test_run_script_side_effects";
         let script = Script::new(ScriptPrefix::Bash, format!("touch {filename}"));
-        assert!(
-            run_short_lived_script(&script, &ScriptEnvironment::default(), wait!(100))
-                .await
-                .unwrap()
-        );
+        assert!(run_short_lived_script(
+            &script,
+            &ScriptEnvironment::default(),
+            &Reaper::default(),
+            wait!(100)
+        )
+        .await
+        .unwrap());
         assert!(Path::new(filename).exists());
         // cleanup
         remove_file(filename).await.unwrap();
@@ -214,7 +257,7 @@ mod tests {
         let mut env = ScriptEnvironment::new();
         env.add("filename", filename.to_string());
         assert!(
-            run_short_lived_script(&script, &env, wait!(100))
+            run_short_lived_script(&script, &env, &Reaper::default(), wait!(100))
                 .await
                 .unwrap()
         );
@@ -232,11 +275,14 @@ mod tests {
         let mut script = Script::new(ScriptPrefix::Path, execute);
         script.timeout = 100000;
         // Wait 50 milliseconds to give time for the file to be created
-        assert!(
-            !run_short_lived_script(&script, &ScriptEnvironment::default(), wait!(1))
-                .await
-                .unwrap()
-        );
+        assert!(!run_short_lived_script(
+            &script,
+            &ScriptEnvironment::default(),
+            &Reaper::default(),
+            wait!(1)
+        )
+        .await
+        .unwrap());
     }
 
     #[tokio::test]
@@ -248,10 +294,13 @@ mod tests {
         let mut script = Script::new(ScriptPrefix::Bash, execute);
         script.timeout = 100000;
         // Wait 50 milliseconds to give time for the file to be created
-        assert!(
-            !run_short_lived_script(&script, &ScriptEnvironment::default(), wait!(1))
-                .await
-                .unwrap()
-        );
+        assert!(!run_short_lived_script(
+            &script,
+            &ScriptEnvironment::default(),
+            &Reaper::default(),
+            wait!(1)
+        )
+        .await
+        .unwrap());
     }
 }
@@ -0,0 +1,35 @@
+use std::io;
+
+use anyhow::{
+    Context,
+    Result,
+};
+use rinit_service::types::SyscallFilter;
+use seccompiler::BpfProgram;
+
+/// seccomp-bpf program built from the SyscallFilter of a script
+pub struct SeccompFilter {
+    program: BpfProgram,
+}
+
+impl SeccompFilter {
+    pub fn new(filter: &SyscallFilter) -> Result<Self> {
+        Ok(Self {
+            program: filter
+                .compile()
+                .context("unable to build the seccomp filter")?,
+        })
+    }
+
+    /// Install the filter in the current process, to be called in pre_exec.
+    /// no_new_privs is needed to install it without CAP_SYS_ADMIN; it also
+    /// stops setuid programs from gaining the privileges to remove it
+    pub fn apply(&self) -> io::Result<()> {
+        seccompiler::apply_filter(&self.program).map_err(|err| {
+            match err {
+                seccompiler::Error::Prctl(err) | seccompiler::Error::Seccomp(err) => err,
+                err => io::Error::other(err),
+            }
+        })
+    }
+}
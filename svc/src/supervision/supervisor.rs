@@ -1,17 +1,48 @@
 use std::{
+    cell::{
+        Cell,
+        RefCell,
+    },
+    collections::VecDeque,
+    future,
+    os::fd::{
+        AsRawFd,
+        RawFd,
+    },
+    path::PathBuf,
     process::ExitStatus,
+    rc::Rc,
     time::Duration,
 };
 
 use anyhow::{
+    ensure,
     Context,
     Result,
 };
 use flexi_logger::writers::FileLogWriterHandle;
+use nix::{
+    errno::Errno,
+    sys::signal::{
+        killpg,
+        Signal,
+    },
+    unistd::Pid,
+};
 use rinit_ipc::Request;
-use rinit_service::types::Longrun;
+use rinit_service::{
+    service_state::IdleServiceState,
+    types::{
+        KillMode,
+        Longrun,
+    },
+};
 use tokio::{
-    process::Child,
+    process::{
+        Child,
+        ChildStderr,
+        ChildStdout,
+    },
     select,
     sync::{
         mpsc,
@@ -20,44 +51,143 @@ use tokio::{
             Sender,
         },
         watch,
+        Notify,
     },
     task::{
         self,
         JoinHandle,
     },
-    time::timeout,
+    time::{
+        sleep,
+        timeout,
+        Instant,
+    },
 };
 use tracing::{
     error,
+    info,
     instrument::WithSubscriber,
     warn,
 };
 
 use crate::supervision::{
-    exec_script,
+    ActivationSocket,
+    exec_supervised_script,
     kill_process,
     log_output,
+    process_group,
     run_short_lived_script,
     signal_wait::signal_wait_fun,
+    AdoptedProcess,
+    Cgroup,
+    ChildProcess,
+    InheritedFds,
+    NotifySocket,
+    Reaper,
+    ServicePipes,
+    SupervisedProcess,
 };
 
+// Kill everything left behind when the start of the process fails or is
+// abandoned after it has been spawned, e.g. because of a timeout. kill_on_drop
+// only kills the process itself, not the ones it forked
+struct SpawnGuard {
+    process_group: Option<Pid>,
+    cgroup: Option<Cgroup>,
+}
+
+impl SpawnGuard {
+    fn new(
+        child: &Child,
+        cgroup: Option<Cgroup>,
+    ) -> Self {
+        Self {
+            process_group: child
+                .id()
+                .and_then(|pid| process_group(Pid::from_raw(pid as i32))),
+            cgroup,
+        }
+    }
+
+    fn disarm(mut self) {
+        self.process_group = None;
+        self.cgroup = None;
+    }
+}
+
+impl Drop for SpawnGuard {
+    fn drop(&mut self) {
+        if let Some(process_group) = self.process_group {
+            match killpg(process_group, Signal::SIGKILL) {
+                Ok(()) | Err(Errno::ESRCH) => {}
+                Err(err) => warn!("unable to kill process group {process_group}: {err}"),
+            }
+        }
+        if let Some(cgroup) = &self.cgroup {
+            if let Err(err) = cgroup.signal_kill() {
+                warn!("{err:?}");
+            }
+        }
+    }
+}
+
 struct RunningScript {
-    child: Child,
+    child: SupervisedProcess,
+    // Read ends of the pipes of stdout and stderr, owned by logger
+    output_fds: [Option<RawFd>; 2],
     logger: JoinHandle<Result<(), anyhow::Error>>,
     logger_stop: Sender<()>,
+    // Reads the status updates sent on the notify socket
+    notify_listener: Option<JoinHandle<()>>,
+}
+
+/// Information about the supervised process, updated by the Supervisor and read
+/// by LiveService
+#[derive(Default)]
+pub struct ProcessInfo {
+    pub pid: Cell<Option<u32>>,
+    /// How many times the process has been restarted after dying
+    pub restarts: Cell<u32>,
+    /// Exit code of the last process that died, None if it was killed by a signal
+    pub last_exit_code: Cell<Option<i32>>,
+    /// When the process has been restarted within the last restart_window
+    pub restart_times: RefCell<VecDeque<Instant>>,
+    /// Read ends of the pipes of stdout and stderr of the process, handed
+    /// over to the next rsvc when it re-executes itself
+    pub output_fds: Cell<[Option<RawFd>; 2]>,
+    /// Listening socket of a socket activated longrun, handed over as well
+    pub listen_fd: Cell<Option<RawFd>>,
+    /// Woken up when the process sends READY=1 after RELOADING=1 on the
+    /// notify socket
+    pub reloaded: Notify,
 }
 
 pub struct Supervisor {
     running_script: Option<RunningScript>,
     terminate: watch::Receiver<()>,
     longrun: Longrun,
+    // Directory containing the notify sockets
+    notify_dir: PathBuf,
+    // Last STATUS= sent on the notify socket, shared with LiveService
+    status_message: Rc<RefCell<Option<String>>>,
+    // Shared with LiveService
+    process_info: Rc<ProcessInfo>,
+    // Contains every process forked by the script
+    cgroup: Option<Cgroup>,
+    // Shared with LiveService, the spawned processes must not be reaped by it
+    reaper: Rc<Reaper>,
+    // Logger pipes connected to the process
+    pipes: ServicePipes,
+    // Bound when the run script listens on a socket, the process is spawned
+    // on the first connection
+    activation_socket: Option<ActivationSocket>,
     // Store the fds of the logger so that they will stay open
     _fw_handle: FileLogWriterHandle,
 }
 
 enum ScriptResult {
     Exited(ExitStatus),
-    Running(RunningScript),
+    Running(Box<RunningScript>),
     Terminated,
 }
 
@@ -69,18 +199,227 @@ impl Supervisor {
         longrun: Longrun,
         terminate: watch::Receiver<()>,
         fw_handle: FileLogWriterHandle,
+        notify_dir: PathBuf,
+        status_message: Rc<RefCell<Option<String>>>,
+        process_info: Rc<ProcessInfo>,
+        cgroup: Option<Cgroup>,
     ) -> Self {
         Self {
             longrun,
             running_script: None,
             terminate,
+            notify_dir,
+            status_message,
+            process_info,
+            cgroup,
+            reaper: Rc::new(Reaper::default()),
+            pipes: ServicePipes::default(),
+            activation_socket: None,
             _fw_handle: fw_handle,
         }
     }
 
+    /// Connect the process to the pipes of logger services
+    pub fn with_pipes(
+        mut self,
+        pipes: ServicePipes,
+    ) -> Self {
+        self.pipes = pipes;
+        self
+    }
+
+    /// Track the spawned processes in reaper, the one reaping the orphans of
+    /// the services
+    pub fn with_reaper(
+        mut self,
+        reaper: Rc<Reaper>,
+    ) -> Self {
+        self.reaper = reaper;
+        self
+    }
+
+    /// Kill the processes left behind by the script, e.g. daemons that escaped
+    /// its process group. Only done with KillMode::Cgroup, the other modes
+    /// leave alone the processes they don't signal
+    async fn kill_cgroup(&self) {
+        if self.longrun.run.kill_mode != KillMode::Cgroup {
+            return;
+        }
+        if let Some(cgroup) = &self.cgroup {
+            if let Err(err) = cgroup.kill().await {
+                warn!("{err:?}");
+            }
+        }
+    }
+
+    /// Kill whatever is left in the cgroup after a failed start, whatever the
+    /// kill_mode, and remove it
+    async fn remove_cgroup(&self) {
+        if let Some(cgroup) = &self.cgroup {
+            if let Err(err) = cgroup.kill().await {
+                warn!("{err:?}");
+            }
+        }
+    }
+
+    /// Run post_stop once the process and its children are gone
+    async fn run_post_stop(&self) {
+        if let Some(post_stop) = &self.longrun.post_stop {
+            match run_short_lived_script(
+                post_stop,
+                &self.longrun.environment,
+                &self.reaper,
+                signal_wait_fun(),
+            )
+            .await
+            {
+                Ok(true) => {}
+                Ok(false) => warn!("post_stop script of service {} failed", self.longrun.name),
+                Err(err) => warn!("{err:?}"),
+            }
+        }
+    }
+
+    /// Record a restart. Return false if the process has already been restarted
+    /// restart_limit times within restart_window, so that a process crashing
+    /// in a loop is left failed. Without restart_limit, it is always restarted
+    fn allow_restart(&self) -> bool {
+        let window = Duration::from_millis(self.longrun.run.restart_window as u64);
+        let now = Instant::now();
+        let mut restart_times = self.process_info.restart_times.borrow_mut();
+        restart_times.retain(|time| now.duration_since(*time) < window);
+        if self
+            .longrun
+            .run
+            .restart_limit
+            .is_some_and(|restart_limit| restart_times.len() >= restart_limit as usize)
+        {
+            return false;
+        }
+        restart_times.push_back(now);
+        true
+    }
+
+    /// Wait restart_delay before restarting the process. Return false if the
+    /// service has been stopped in the meantime
+    async fn wait_restart_delay(&mut self) -> bool {
+        let delay = Duration::from_millis(self.longrun.run.restart_delay as u64);
+        select! {
+            _ = sleep(delay) => true,
+            _ = self.terminate.changed() => false,
+        }
+    }
+
     pub async fn start(&mut self) -> Result<bool> {
+        // Starting the service by hand gives it a new chance
+        self.process_info.restart_times.borrow_mut().clear();
+        if let Some(listen) = &self.longrun.run.listen {
+            // The process is spawned by supervise, once a connection arrives;
+            // the service is down until then
+            self.set_activation_socket(ActivationSocket::bind(listen)?)?;
+            return Ok(true);
+        }
+        let res = self.spawn_process().await;
+        // No process of a service that failed to start is left running
+        if !matches!(res, Ok(ScriptResult::Running(_))) {
+            self.remove_cgroup().await;
+        }
+        Ok(match res? {
+            ScriptResult::Running(running_script) => {
+                self.set_running(*running_script);
+                true
+            }
+            ScriptResult::Exited(_) | ScriptResult::Terminated => false,
+        })
+    }
+
+    /// Supervise the process spawned by the rsvc running before it
+    /// re-executed itself, if any, logging what it writes on the pipes it
+    /// inherited. A socket activated longrun keeps listening on its socket
+    pub fn adopt(
+        &mut self,
+        pid: Option<u32>,
+        fds: InheritedFds,
+    ) -> Result<()> {
+        if let Some(listen) = &self.longrun.run.listen {
+            let fd = fds
+                .listen
+                .context("the activation socket has not been inherited")?;
+            self.set_activation_socket(ActivationSocket::inherit(fd, listen)?)?;
+        }
+        let Some(pid) = pid else {
+            ensure!(
+                self.activation_socket.is_some(),
+                "only a socket activated longrun can be without a process"
+            );
+            return Ok(());
+        };
+        let process = AdoptedProcess::new(pid, &self.reaper)
+            .with_context(|| format!("unable to adopt process {pid}"))?;
+        let output_fds = [
+            fds.stdout.as_ref().map(AsRawFd::as_raw_fd),
+            fds.stderr.as_ref().map(AsRawFd::as_raw_fd),
+        ];
+        let stdout = fds
+            .stdout
+            .map(|fd| ChildStdout::from_std(std::process::ChildStdout::from(fd)))
+            .transpose()?;
+        let stderr = fds
+            .stderr
+            .map(|fd| ChildStderr::from_std(std::process::ChildStderr::from(fd)))
+            .transpose()?;
+        let (tx, rx) = oneshot::channel();
+        let logger = task::spawn_local(
+            log_output(
+                stdout,
+                stderr,
+                rx,
+                self.longrun.run.log_buffer,
+                self.longrun.run.max_line_length,
+            )
+            .with_current_subscriber(),
+        );
+        self.set_running(RunningScript {
+            child: SupervisedProcess::Adopted(process),
+            output_fds,
+            logger,
+            logger_stop: tx,
+            notify_listener: None,
+        });
+        Ok(())
+    }
+
+    fn set_activation_socket(
+        &mut self,
+        socket: ActivationSocket,
+    ) -> Result<()> {
+        self.pipes.listen = Some(socket.fd()?);
+        self.process_info.listen_fd.set(Some(socket.as_raw_fd()));
+        self.activation_socket = Some(socket);
+        Ok(())
+    }
+
+    /// Stop listening, before sending the last state of the service so that it
+    /// is not taken for one waiting for a connection anymore
+    fn close_activation_socket(&mut self) {
+        self.activation_socket = None;
+        self.pipes.listen = None;
+        self.process_info.listen_fd.set(None);
+    }
+
+    fn set_running(
+        &mut self,
+        running_script: RunningScript,
+    ) {
+        self.process_info.pid.set(running_script.child.id());
+        self.process_info.output_fds.set(running_script.output_fds);
+        self.running_script = Some(running_script);
+    }
+
+    /// Start the process, trying again until it dies max_deaths times
+    async fn spawn_process(&mut self) -> Result<ScriptResult> {
         let mut time_tried = 0;
-        Ok(loop {
+        loop {
             let script_res = self.start_process().await?;
 
             match script_res {
@@ -92,41 +431,104 @@ impl Supervisor {
                         let _ = run_short_lived_script(
                             finish_script,
                             &self.longrun.environment,
+                            &self.reaper,
                             signal_wait_fun(),
                         )
                         .await;
                     }
                     if time_tried == self.longrun.run.max_deaths {
-                        break false;
+                        break Ok(ScriptResult::Exited(status));
                     }
                 }
-                ScriptResult::Running(running_script) => {
-                    self.running_script = Some(running_script);
-                    break true;
-                }
-                ScriptResult::Terminated => break false,
+                ScriptResult::Running(_) | ScriptResult::Terminated => break Ok(script_res),
             }
-        })
+        }
+    }
+
+    /// Wait for a connection on the activation socket and spawn the process
+    async fn activate(&mut self) -> Result<ScriptResult> {
+        let socket = self
+            .activation_socket
+            .as_ref()
+            .context("the service is not socket activated")?;
+        select! {
+            res = socket.wait_connection() => res?,
+            _ = self.terminate.changed() => return Ok(ScriptResult::Terminated),
+        }
+        info!("connection received, starting the process");
+        self.spawn_process().await
+    }
+
+    async fn send_state(
+        &self,
+        send: &mpsc::Sender<Request>,
+        state: IdleServiceState,
+    ) {
+        if let Err(err) = send
+            .send(Request::UpdateServiceStatus(
+                self.longrun.name.to_owned(),
+                state,
+            ))
+            .await
+        {
+            error!("Could not notify the main thread: {err}");
+        }
     }
 
     async fn start_process(&mut self) -> Result<ScriptResult> {
         let script = &self.longrun.run;
         let script_timeout = Duration::from_millis(script.timeout as u64);
 
-        let mut child = exec_script(script, &self.longrun.environment)
-            .await
-            .context("unable to execute script")?;
+        let notify_socket = if script.notify_socket {
+            Some(NotifySocket::bind(
+                self.notify_dir
+                    .join(format!("{}.notify", self.longrun.name)),
+            )?)
+        } else {
+            None
+        };
+        let mut environment = self.longrun.environment.clone();
+        if self.pipes.listen.is_some() {
+            environment.add("LISTEN_FDS", "1".to_string());
+        }
+        if let Some(notify_socket) = &notify_socket {
+            environment.add(
+                "NOTIFY_SOCKET",
+                notify_socket.path().to_string_lossy().into_owned(),
+            );
+        }
+
+        let cgroup = self.cgroup.as_ref().map(Cgroup::path);
+        let mut child =
+            exec_supervised_script(script, &environment, cgroup, &self.pipes, &self.reaper)
+                .await
+                .context("unable to execute script")?;
+        let guard = SpawnGuard::new(&child, self.cgroup.clone());
+        let output_fds = [
+            child.stdout.as_ref().map(AsRawFd::as_raw_fd),
+            child.stderr.as_ref().map(AsRawFd::as_raw_fd),
+        ];
         let (tx, rx) = oneshot::channel();
         // let (fw_handle, subscriber) = self.logger_subscriber();
         let logger = task::spawn_local(
             log_output(
-                child.stdout.take().unwrap(),
-                child.stderr.take().unwrap(),
+                child.stdout.take(),
+                child.stderr.take(),
                 rx,
+                script.log_buffer,
+                script.max_line_length,
             )
             .with_current_subscriber(),
         );
-        Ok(select! {
+        // Without a notify socket, the script is up when it's still running after timeout
+        let status_message = &self.status_message;
+        let ready = async {
+            match &notify_socket {
+                Some(notify_socket) => notify_socket.wait_ready(status_message).await,
+                None => future::pending().await,
+            }
+        };
+        let res = select! {
             timeout_res = timeout(script_timeout, child.wait()) => {
                 if let Ok(exit_status) = timeout_res {
                     let status = exit_status.context("unable to call wait on child")?;
@@ -135,30 +537,80 @@ impl Supervisor {
                     }
                     logger.await??;
                     ScriptResult::Exited(status)
+                } else if notify_socket.is_some() {
+                    warn!("process didn't send READY=1 before timeout");
+                    kill_process(&mut child, script, self.cgroup.as_ref()).await?;
+                    let status = child.wait().await.context("unable to call wait on child")?;
+                    if !tx.is_closed() {
+                        tx.send(()).unwrap();
+                    }
+                    logger.await??;
+                    ScriptResult::Exited(status)
                 } else {
-                    ScriptResult::Running(RunningScript {child, logger, logger_stop: tx})
+                    ScriptResult::Running(Box::new(RunningScript {
+                        child: SupervisedProcess::Spawned(child),
+                        output_fds,
+                        logger,
+                        logger_stop: tx,
+                        notify_listener: None,
+                    }))
                 }
             }
+            res = ready => {
+                res?;
+                let status_message = self.status_message.clone();
+                let process_info = self.process_info.clone();
+                ScriptResult::Running(Box::new(RunningScript {
+                    child: SupervisedProcess::Spawned(child),
+                    output_fds,
+                    logger,
+                    logger_stop: tx,
+                    notify_listener: notify_socket.map(|notify_socket| {
+                        task::spawn_local(notify_socket.listen(status_message, process_info))
+                    }),
+                }))
+            }
             _ = self.terminate.changed() => {
-                kill_process(&mut child, script.down_signal, script.timeout_kill).await?;
+                kill_process(&mut child, script, self.cgroup.as_ref()).await?;
+                self.kill_cgroup().await;
+                self.run_post_stop().await;
                 if !tx.is_closed() {
                     tx.send(()).unwrap();
                 }
                 logger.await??;
                 ScriptResult::Terminated
             }
-        })
+        };
+        guard.disarm();
+        Ok(res)
     }
 
     pub async fn supervise(
         &mut self,
         send: mpsc::Sender<Request>,
     ) -> Result<()> {
-        debug_assert!(self.running_script.is_some());
         loop {
-            // This is never empty. Move out the value so that we can use logger and
-            // logger_stop
-            let mut running_script = self.running_script.take().unwrap();
+            // Move out the value so that we can use logger and logger_stop. It
+            // is only empty for socket activated services waiting for a
+            // connection
+            let Some(mut running_script) = self.running_script.take() else {
+                match self.activate().await? {
+                    ScriptResult::Running(running_script) => {
+                        self.set_running(*running_script);
+                        self.send_state(&send, IdleServiceState::Up).await;
+                        continue;
+                    }
+                    ScriptResult::Exited(_) => {
+                        self.close_activation_socket();
+                        self.send_state(&send, IdleServiceState::Failed).await;
+                    }
+                    ScriptResult::Terminated => {
+                        self.close_activation_socket();
+                        self.send_state(&send, IdleServiceState::Down).await;
+                    }
+                }
+                break;
+            };
             let res = select! {
                 exit_status = running_script.child.wait() => {
                     ScriptResult::Exited(exit_status.context("unable to wait on child process")?)
@@ -172,23 +624,38 @@ impl Supervisor {
                     // stop running
                     kill_process(
                         &mut running_script.child,
-                        self.longrun.run.down_signal,
-                        self.longrun.run.timeout_kill,
+                        &self.longrun.run,
+                        self.cgroup.as_ref(),
                     )
                     .await?;
+                    self.kill_cgroup().await;
+                    self.run_post_stop().await;
+                }
+                ScriptResult::Exited(status) => {
+                    warn!("process exited with {status}");
+                    self.process_info.last_exit_code.set(status.code());
                 }
-                ScriptResult::Exited(status) => warn!("process exited with {status}"),
                 ScriptResult::Running(_) => unreachable!(),
             }
-            if let Err(err) = send
-                .send(Request::UpdateServiceStatus(
-                    self.longrun.name.to_owned(),
-                    rinit_service::service_state::IdleServiceState::Down,
-                ))
-                .await
-            {
-                error!("Could not notify the main thread: {err}");
-            };
+            if let Some(notify_listener) = &running_script.notify_listener {
+                notify_listener.abort();
+            }
+            self.status_message.replace(None);
+            self.process_info.pid.set(None);
+            self.process_info.output_fds.set([None, None]);
+            // A socket activated process exiting successfully, e.g. after being
+            // idle, is down until the next connection spawns it again
+            let idle = self.activation_socket.is_some()
+                && matches!(res, ScriptResult::Exited(status) if status.success());
+            // Only a process that has been asked to stop is down, otherwise it crashed
+            if let ScriptResult::Terminated = res {
+                self.close_activation_socket();
+                self.send_state(&send, IdleServiceState::Down).await;
+            } else if idle {
+                self.send_state(&send, IdleServiceState::Down).await;
+            } else {
+                self.send_state(&send, IdleServiceState::Failed).await;
+            }
             if !running_script.logger_stop.is_closed() {
                 if let Err(_err) = running_script.logger_stop.send(()) {
                     warn!("logger was not working properly");
@@ -198,19 +665,30 @@ impl Supervisor {
             if let ScriptResult::Terminated = res {
                 break;
             }
+            if idle {
+                continue;
+            }
+            if !self.allow_restart() {
+                error!(
+                    "process restarted {} times within {}ms, not restarting it anymore",
+                    self.longrun.run.restart_limit.unwrap_or_default(),
+                    self.longrun.run.restart_window
+                );
+                break;
+            }
+            if !self.wait_restart_delay().await {
+                self.close_activation_socket();
+                self.send_state(&send, IdleServiceState::Down).await;
+                break;
+            }
+            self.process_info
+                .restarts
+                .set(self.process_info.restarts.get() + 1);
             match self.start_process().await? {
                 ScriptResult::Exited(_) | ScriptResult::Terminated => break,
                 ScriptResult::Running(running_script) => {
-                    if let Err(err) = send
-                        .send(Request::UpdateServiceStatus(
-                            self.longrun.name.to_owned(),
-                            rinit_service::service_state::IdleServiceState::Up,
-                        ))
-                        .await
-                    {
-                        error!("Could not notify the main thread: {err}");
-                    }
-                    self.running_script = Some(running_script);
+                    self.set_running(*running_script);
+                    self.send_state(&send, IdleServiceState::Up).await;
                 }
             }
         }
@@ -226,11 +704,18 @@ mod test {
         FileSpec,
     };
     use rinit_service::types::{
+        ListenSocket,
         Script,
         ScriptEnvironment,
         ScriptPrefix,
         ServiceOptions,
     };
+    use std::{
+        os::unix::net::UnixStream,
+        path::Path,
+        time::Instant,
+    };
+
     use tokio::join;
 
     use super::*;
@@ -248,7 +733,15 @@ mod test {
             let (_file_writer, fw_handle) = FileLogWriter::builder(FileSpec::default())
                 .try_build_with_handle()
                 .unwrap();
-            let mut $supervisor = Supervisor::new($longrun, rx, fw_handle);
+            let mut $supervisor = Supervisor::new(
+                $longrun,
+                rx,
+                fw_handle,
+                std::env::temp_dir().join("rinit-test-notify"),
+                Rc::new(RefCell::new(None)),
+                Rc::new(ProcessInfo::default()),
+                None,
+            );
         };
     }
 
@@ -262,6 +755,9 @@ mod test {
             name: "test".to_string(),
             run: script,
             finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
             options: ServiceOptions::new(),
             environment: ScriptEnvironment::new(),
         };
@@ -279,6 +775,9 @@ mod test {
             name: "test".to_string(),
             run: script,
             finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
             options: ServiceOptions::new(),
             environment: ScriptEnvironment::new(),
         };
@@ -296,6 +795,9 @@ mod test {
             name: "test".to_string(),
             run: script,
             finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
             options: ServiceOptions::new(),
             environment: ScriptEnvironment::new(),
         };
@@ -312,4 +814,344 @@ mod test {
             res1.unwrap().unwrap();
         });
     }
+
+    #[tokio::test]
+    async fn test_start_process_notify_socket() {
+        if !Path::new("/usr/bin/python3").exists() {
+            return;
+        }
+        let mut script = Script::new(
+            ScriptPrefix::Bash,
+            "python3 -c \"import os, socket; \
+             s = socket.socket(socket.AF_UNIX, socket.SOCK_DGRAM); \
+             s.sendto(b'STATUS=listening\\nREADY=1', os.environ['NOTIFY_SOCKET'])\"; \
+             sleep 10"
+                .to_string(),
+        );
+        script.timeout = 5000;
+        script.notify_socket = true;
+        let longrun = Longrun {
+            name: "notify".to_string(),
+            run: script,
+            finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
+            options: ServiceOptions::new(),
+            environment: ScriptEnvironment::new(),
+        };
+        new_supervisor!(supervisor, _tx, longrun);
+        task::LocalSet::new()
+            .run_until(async move {
+                let start = Instant::now();
+                assert!(supervisor.start().await.unwrap());
+                // The service is up as soon as it sends READY=1, without waiting for timeout
+                assert!(start.elapsed() < Duration::from_millis(5000));
+                assert_eq!(
+                    supervisor.status_message.borrow().as_deref(),
+                    Some("listening")
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_start_process_notify_socket_not_ready() {
+        let mut script = Script::new(ScriptPrefix::Bash, "sleep 10".to_string());
+        script.timeout = 50;
+        script.timeout_kill = 50;
+        script.max_deaths = 1;
+        script.notify_socket = true;
+        let longrun = Longrun {
+            name: "notify-not-ready".to_string(),
+            run: script,
+            finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
+            options: ServiceOptions::new(),
+            environment: ScriptEnvironment::new(),
+        };
+        new_supervisor!(supervisor, _tx, longrun);
+        task::LocalSet::new()
+            .run_until(async move {
+                assert!(!supervisor.start().await.unwrap());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_supervise_restarts() {
+        let mut script = Script::new(ScriptPrefix::Bash, "sleep 0.2; exit 3".to_string());
+        script.timeout = 50;
+        let longrun = Longrun {
+            name: "test-restarts".to_string(),
+            run: script,
+            finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
+            options: ServiceOptions::new(),
+            environment: ScriptEnvironment::new(),
+        };
+        new_supervisor!(supervisor, tx, longrun);
+        let process_info = supervisor.process_info.clone();
+        task::LocalSet::new()
+            .run_until(async move {
+                assert!(supervisor.start().await.unwrap());
+                let (send, mut recv) = mpsc::channel(10);
+                let supervise = task::spawn_local(async move { supervisor.supervise(send).await });
+                // Every restart sends a Down and then an Up
+                for _ in 0..4 {
+                    recv.recv().await.unwrap();
+                }
+                tx.send(()).unwrap();
+                supervise.await.unwrap().unwrap();
+            })
+            .await;
+        assert_eq!(process_info.restarts.get(), 2);
+        assert_eq!(process_info.last_exit_code.get(), Some(3));
+        assert_eq!(process_info.pid.get(), None);
+    }
+
+    #[tokio::test]
+    async fn restart_rate_limit() {
+        let mut script = Script::new(ScriptPrefix::Bash, "sleep 0.1; exit 3".to_string());
+        script.timeout = 50;
+        script.restart_limit = Some(2);
+        let longrun = Longrun {
+            name: "test-rate-limit".to_string(),
+            run: script,
+            finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
+            options: ServiceOptions::new(),
+            environment: ScriptEnvironment::new(),
+        };
+        new_supervisor!(supervisor, _tx, longrun);
+        let process_info = supervisor.process_info.clone();
+        task::LocalSet::new()
+            .run_until(async move {
+                assert!(supervisor.start().await.unwrap());
+                let (send, mut recv) = mpsc::channel(10);
+                // The supervisor gives up by itself, without being terminated
+                timeout(Duration::from_secs(5), supervisor.supervise(send))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let mut states = Vec::new();
+                while let Ok(Request::UpdateServiceStatus(_, state)) = recv.try_recv() {
+                    states.push(state);
+                }
+                assert_eq!(
+                    states,
+                    vec![
+                        IdleServiceState::Failed,
+                        IdleServiceState::Up,
+                        IdleServiceState::Failed,
+                        IdleServiceState::Up,
+                        IdleServiceState::Failed,
+                    ]
+                );
+            })
+            .await;
+        assert_eq!(process_info.restarts.get(), 2);
+        assert_eq!(process_info.pid.get(), None);
+    }
+
+    #[tokio::test]
+    async fn crash_and_clean_stop() {
+        let mut script = Script::new(ScriptPrefix::Bash, "sleep 0.2; exit 3".to_string());
+        script.timeout = 50;
+        let longrun = Longrun {
+            name: "test-crash".to_string(),
+            run: script,
+            finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
+            options: ServiceOptions::new(),
+            environment: ScriptEnvironment::new(),
+        };
+        new_supervisor!(supervisor, tx, longrun);
+        task::LocalSet::new()
+            .run_until(async move {
+                assert!(supervisor.start().await.unwrap());
+                let (send, mut recv) = mpsc::channel(10);
+                let supervise = task::spawn_local(async move { supervisor.supervise(send).await });
+                // The process exited on its own
+                assert!(matches!(
+                    recv.recv().await.unwrap(),
+                    Request::UpdateServiceStatus(_, IdleServiceState::Failed)
+                ));
+                // It has been restarted, now stop it
+                assert!(matches!(
+                    recv.recv().await.unwrap(),
+                    Request::UpdateServiceStatus(_, IdleServiceState::Up)
+                ));
+                tx.send(()).unwrap();
+                assert!(matches!(
+                    recv.recv().await.unwrap(),
+                    Request::UpdateServiceStatus(_, IdleServiceState::Down)
+                ));
+                supervise.await.unwrap().unwrap();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn restart_delay_before_respawn() {
+        let mut script = Script::new(ScriptPrefix::Bash, "sleep 0.1; exit 3".to_string());
+        script.timeout = 50;
+        script.restart_delay = 300;
+        let longrun = Longrun {
+            name: "test-restart-delay".to_string(),
+            run: script,
+            finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
+            options: ServiceOptions::new(),
+            environment: ScriptEnvironment::new(),
+        };
+        new_supervisor!(supervisor, tx, longrun);
+        task::LocalSet::new()
+            .run_until(async move {
+                assert!(supervisor.start().await.unwrap());
+                let (send, mut recv) = mpsc::channel(10);
+                let supervise = task::spawn_local(async move { supervisor.supervise(send).await });
+                assert!(matches!(
+                    recv.recv().await.unwrap(),
+                    Request::UpdateServiceStatus(_, IdleServiceState::Failed)
+                ));
+                let died = Instant::now();
+                assert!(matches!(
+                    recv.recv().await.unwrap(),
+                    Request::UpdateServiceStatus(_, IdleServiceState::Up)
+                ));
+                assert!(died.elapsed() >= Duration::from_millis(300));
+
+                // Stopping the service cancels the restart waiting for the delay
+                assert!(matches!(
+                    recv.recv().await.unwrap(),
+                    Request::UpdateServiceStatus(_, IdleServiceState::Failed)
+                ));
+                let died = Instant::now();
+                tx.send(()).unwrap();
+                assert!(matches!(
+                    recv.recv().await.unwrap(),
+                    Request::UpdateServiceStatus(_, IdleServiceState::Down)
+                ));
+                assert!(died.elapsed() < Duration::from_millis(300));
+                supervise.await.unwrap().unwrap();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn socket_activation_on_first_connection() {
+        let dir =
+            std::env::temp_dir().join(format!("rinit-test-activation-{}", std::process::id()));
+        let socket = dir.join("echo.sock");
+        let marker = dir.join("activated");
+        let mut script = Script::new(
+            ScriptPrefix::Bash,
+            format!(
+                "test -S /dev/fd/3 && echo $LISTEN_FDS $LISTEN_PID > {}; exec sleep 10",
+                marker.display()
+            ),
+        );
+        script.timeout = 50;
+        script.listen = Some(ListenSocket::Unix(socket.clone()));
+        let longrun = Longrun {
+            name: "test-activation".to_string(),
+            run: script,
+            finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
+            options: ServiceOptions::new(),
+            environment: ScriptEnvironment::new(),
+        };
+        new_supervisor!(supervisor, tx, longrun);
+        let process_info = supervisor.process_info.clone();
+        task::LocalSet::new()
+            .run_until(async move {
+                assert!(supervisor.start().await.unwrap());
+                let (send, mut recv) = mpsc::channel(10);
+                let supervise = task::spawn_local(async move { supervisor.supervise(send).await });
+                // Nothing runs until a client connects, the service stays down
+                sleep(Duration::from_millis(200)).await;
+                assert!(socket.exists());
+                assert!(!marker.exists());
+                assert_eq!(process_info.pid.get(), None);
+                assert!(recv.try_recv().is_err());
+
+                let _client = UnixStream::connect(&socket).unwrap();
+                assert!(matches!(
+                    recv.recv().await.unwrap(),
+                    Request::UpdateServiceStatus(_, IdleServiceState::Up)
+                ));
+                sleep(Duration::from_millis(300)).await;
+                let pid = process_info.pid.get().unwrap();
+                assert_eq!(std::fs::read_to_string(&marker).unwrap(), format!("1 {pid}\n"));
+
+                tx.send(()).unwrap();
+                assert!(matches!(
+                    recv.recv().await.unwrap(),
+                    Request::UpdateServiceStatus(_, IdleServiceState::Down)
+                ));
+                supervise.await.unwrap().unwrap();
+                assert!(!socket.exists());
+                assert_eq!(process_info.listen_fd.get(), None);
+            })
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn abandoned_start_leaves_no_orphan() {
+        let pidfile =
+            std::env::temp_dir().join(format!("rinit-test-abandoned-{}", std::process::id()));
+        let _ = std::fs::remove_file(&pidfile);
+        let mut script = Script::new(
+            ScriptPrefix::Bash,
+            format!("sleep 100 & echo $! > {}; sleep 100", pidfile.display()),
+        );
+        script.timeout = 5000;
+        let longrun = Longrun {
+            name: "test-abandoned".to_string(),
+            run: script,
+            finish: None,
+            condition: None,
+            pre_start: None,
+            post_stop: None,
+            options: ServiceOptions::new(),
+            environment: ScriptEnvironment::new(),
+        };
+        new_supervisor!(supervisor, _tx, longrun);
+        let start = async {
+            // The start is abandoned after spawning the process, while waiting
+            // for it to be up
+            let wait_pidfile = async {
+                while std::fs::read_to_string(&pidfile).map_or(true, |pid| pid.is_empty()) {
+                    sleep(Duration::from_millis(10)).await;
+                }
+            };
+            select! {
+                _ = supervisor.start() => unreachable!(),
+                _ = wait_pidfile => {}
+            }
+        };
+        task::LocalSet::new().run_until(start).await;
+
+        let orphan = std::fs::read_to_string(&pidfile).unwrap();
+        sleep(Duration::from_millis(100)).await;
+        // Killed, at most a zombie waiting to be reaped
+        let running = std::fs::read_to_string(format!("/proc/{}/stat", orphan.trim()))
+            .is_ok_and(|stat| !stat.contains(") Z "));
+        assert!(!running);
+        std::fs::remove_file(&pidfile).unwrap();
+    }
 }
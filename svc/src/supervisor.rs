@@ -0,0 +1,1057 @@
+use std::{
+    cell::RefCell,
+    env,
+    io,
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::Command,
+    rc::Rc,
+};
+
+use futures::{
+    future::join_all,
+    stream::{
+        FuturesUnordered,
+        StreamExt,
+    },
+};
+use nix::{
+    sys::reboot::{
+        reboot,
+        RebootMode,
+    },
+    unistd::sync,
+};
+use rinit_ipc::{
+    request_error::{
+        LogicError,
+        RequestError,
+    },
+    EffectiveConfig,
+    Request,
+    ServiceSnapshot,
+    ServiceStatus,
+    ServiceUsage,
+    ShutdownAction,
+    StatusChanged,
+};
+use rinit_service::{
+    config::Config,
+    graph::DependencyGraph,
+    service_state::{
+        IdleServiceState,
+        ServiceState,
+        TransitioningServiceState,
+    },
+    types::RunLevel,
+};
+use tokio::{
+    select,
+    sync::{
+        broadcast,
+        mpsc,
+        watch,
+        RwLock,
+    },
+};
+use tracing::{
+    error,
+    info,
+    warn,
+};
+
+use crate::{
+    live_service::LiveService,
+    live_service_graph::{
+        LiveGraphError,
+        LiveServiceGraph,
+    },
+    reexec_state::ReexecState,
+    supervision::Reaper,
+};
+
+/// Supervise the services of a dependency graph. rsvc serves it on the IPC
+/// socket through RequestHandler, each request having its own method, so that
+/// it can be driven directly by tests and other programs as well.
+/// The services report their status changes to the supervisor, which are only
+/// handled while run is being awaited; everything has to run in the same
+/// tokio LocalSet
+pub struct Supervisor {
+    graph: RwLock<LiveServiceGraph>,
+    // The status updates sent by the services, taken by run
+    updates: RefCell<Option<mpsc::Receiver<Request>>>,
+    // Set when stop_all starts stopping the services
+    shutdown: watch::Sender<bool>,
+    // Set when stop_all has stopped every service, run returns then
+    stopped: watch::Sender<bool>,
+    // The configuration file, read again by reload_config
+    config_file: Option<PathBuf>,
+    // Powers off, reboots or halts the system once shutdown has stopped the
+    // services
+    power_control: Box<dyn Fn(ShutdownAction) -> nix::Result<()>>,
+    // Executes rsvc again once reexec has saved the state of the services
+    reexec_control: Box<dyn Fn() -> io::Result<()>>,
+    // Shared with the services, reaps the processes they leave behind
+    reaper: Rc<Reaper>,
+}
+
+impl Supervisor {
+    const UPDATES_CAPACITY: usize = 20;
+
+    /// Supervise the services in graph, none of them is started
+    pub fn new(
+        graph: DependencyGraph,
+        config: Config,
+    ) -> Self {
+        let (send, updates) = mpsc::channel(Self::UPDATES_CAPACITY);
+        Self::with_live_graph(LiveServiceGraph::new_with_graph(graph, config, send), updates)
+    }
+
+    /// Supervise the services enabled in the dependency graph of config, in
+    /// the runlevel that was saved by a previous supervisor if any
+    pub fn from_config(config: Config) -> Result<Self, LiveGraphError> {
+        let (send, updates) = mpsc::channel(Self::UPDATES_CAPACITY);
+        Ok(Self::with_live_graph(
+            LiveServiceGraph::new(config, send)?,
+            updates,
+        ))
+    }
+
+    fn with_live_graph(
+        graph: LiveServiceGraph,
+        updates: mpsc::Receiver<Request>,
+    ) -> Self {
+        Self {
+            reaper: graph.reaper(),
+            graph: RwLock::new(graph),
+            updates: RefCell::new(Some(updates)),
+            shutdown: watch::channel(false).0,
+            stopped: watch::channel(false).0,
+            config_file: None,
+            power_control: Box::new(power_control),
+            reexec_control: Box::new(reexec_control),
+        }
+    }
+
+    /// Reaper of the processes orphaned by the services, which knows about
+    /// the processes spawned by their scripts
+    pub fn reaper(&self) -> Rc<Reaper> {
+        self.reaper.clone()
+    }
+
+    /// Read the configuration from config_file on reload_config, instead of
+    /// the default one
+    pub fn with_config_file(
+        mut self,
+        config_file: Option<PathBuf>,
+    ) -> Self {
+        self.config_file = config_file;
+        self
+    }
+
+    /// Call power_control instead of the reboot syscall on shutdown, e.g. to
+    /// run as a service manager that is not pid 1
+    pub fn with_power_control(
+        mut self,
+        power_control: impl Fn(ShutdownAction) -> nix::Result<()> + 'static,
+    ) -> Self {
+        self.power_control = Box::new(power_control);
+        self
+    }
+
+    /// Call reexec_control instead of executing the binary of rsvc on reexec
+    pub fn with_reexec_control(
+        mut self,
+        reexec_control: impl Fn() -> io::Result<()> + 'static,
+    ) -> Self {
+        self.reexec_control = Box::new(reexec_control);
+        self
+    }
+
+    /// Handle the status updates sent by the services until stop_all has
+    /// stopped all of them. Only the first call does anything
+    pub async fn run(&self) {
+        let Some(mut updates) = self.updates.borrow_mut().take() else {
+            warn!("the supervisor is already running");
+            return;
+        };
+        let mut stopped = self.stopped.subscribe();
+        let mut pending = FuturesUnordered::new();
+        loop {
+            select! {
+                Some(update) = updates.recv() => pending.push(self.handle_update(update)),
+                Some(()) = pending.next() => {}
+                _ = stopped.changed() => break,
+            }
+        }
+    }
+
+    async fn handle_update(
+        &self,
+        update: Request,
+    ) {
+        match update {
+            Request::UpdateServiceStatus(name, state) => {
+                if let Err(err) = self.update_service_status(&name, state).await {
+                    error!("{err}");
+                }
+            }
+            Request::StartWantedService { service, wanted } => {
+                self.start_wanted(&service, &wanted).await;
+            }
+            update => warn!("unexpected request {update:?} sent by a service"),
+        }
+    }
+
+    /// Changed to true once stop_all starts stopping the services, e.g. to
+    /// stop accepting new requests
+    pub fn subscribe_shutdown(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    /// Every state transition of the services
+    pub async fn subscribe(&self) -> broadcast::Receiver<StatusChanged> {
+        self.graph.read().await.subscribe()
+    }
+
+    pub async fn statuses(&self) -> Vec<ServiceStatus> {
+        let graph = self.graph.read().await;
+        graph
+            .live_services
+            .values()
+            .map(|live_service| graph.status(live_service))
+            .collect()
+    }
+
+    /// Don't wait for the service to be idle, a service stuck starting
+    /// reports the dependencies it is waiting on
+    pub async fn status(
+        &self,
+        service: &str,
+    ) -> Result<ServiceStatus, RequestError> {
+        let graph = self.graph.read().await;
+        Ok(graph.status(graph.get_service(service)?))
+    }
+
+    pub async fn dump(&self) -> Vec<ServiceSnapshot> {
+        self.graph.read().await.dump()
+    }
+
+    pub async fn config(&self) -> EffectiveConfig {
+        let config = self.graph.read().await.config().clone();
+        EffectiveConfig {
+            config_file: self.config_file.clone(),
+            service_directories: config.dirs.service_directories(),
+            socket: rinit_ipc::get_host_address().to_string(),
+            config,
+        }
+    }
+
+    pub async fn usage(&self) -> Vec<ServiceUsage> {
+        self.graph.read().await.usage()
+    }
+
+    pub async fn signal(
+        &self,
+        service: &str,
+        signal: i32,
+    ) -> Result<(), RequestError> {
+        let graph = self.graph.read().await;
+        graph.signal_service(graph.get_service(service)?, signal)?;
+        Ok(())
+    }
+
+    pub async fn reload_process(
+        &self,
+        service: &str,
+    ) -> Result<(), RequestError> {
+        let graph = self.graph.read().await;
+        graph.reload_process(graph.get_service(service)?).await?;
+        Ok(())
+    }
+
+    /// Start the service and its dependencies, return whether it is up, or
+    /// listening on its socket when it is socket activated
+    pub async fn start(
+        &self,
+        service: &str,
+        runlevel: RunLevel,
+        stop_conflicts: bool,
+    ) -> Result<bool, RequestError> {
+        let graph = self.graph.read().await;
+        graph.check_runlevel(service, runlevel)?;
+        if stop_conflicts {
+            graph.stop_conflicts(graph.get_service(service)?).await?;
+        }
+        graph.start_service(graph.get_service(service)?).await?;
+        let state = graph.get_service(service)?.wait_idle_state();
+        drop(graph);
+        let state = state.await;
+        Ok(self
+            .graph
+            .read()
+            .await
+            .get_service(service)?
+            .is_available(state))
+    }
+
+    /// Stop the service and its dependents, return whether it is down
+    pub async fn stop(
+        &self,
+        service: &str,
+        runlevel: RunLevel,
+    ) -> Result<bool, RequestError> {
+        let graph = self.graph.read().await;
+        graph.check_runlevel(service, runlevel)?;
+        graph.stop_service(graph.get_service(service)?).await?;
+        let state = graph.get_service(service)?.wait_idle_state();
+        drop(graph);
+        Ok(state.await == IdleServiceState::Down)
+    }
+
+    /// Start the services of the boot runlevel, then the ones of the default
+    /// runlevel
+    pub async fn start_all(&self) {
+        self.graph.read().await.start_default_runlevel().await;
+    }
+
+    pub async fn set_runlevel(
+        &self,
+        runlevel: RunLevel,
+    ) -> Result<(), RequestError> {
+        self.graph.write().await.set_default_runlevel(runlevel)?;
+        self.graph
+            .read()
+            .await
+            .start_all_services(runlevel)
+            .await;
+        Ok(())
+    }
+
+    /// Stop every service, the boot runlevel last. run returns afterwards
+    pub async fn stop_all(&self) {
+        self.shutdown.send_replace(true);
+        let graph = self.graph.read().await;
+        graph.stop_all_services(RunLevel::Default).await;
+        graph.stop_all_services(RunLevel::Boot).await;
+        // Some services could still be stopping, their updates have to be
+        // handled before run returns. Don't hold the lock while waiting, the
+        // status updates need it
+        let stopping: Vec<_> = graph
+            .live_services
+            .values()
+            .filter(|live_service| {
+                *live_service.state.borrow()
+                    == ServiceState::Transitioning(TransitioningServiceState::Stopping)
+            })
+            .map(LiveService::wait_idle_state)
+            .collect();
+        drop(graph);
+        join_all(stopping).await;
+        self.stopped.send_replace(true);
+    }
+
+    /// Stop every service, then power off, reboot or halt the system. The
+    /// services stopping get the whole time they need, including timeout_kill,
+    /// as stop_all waits for them. Only returns if the action failed
+    pub async fn shutdown(
+        &self,
+        action: ShutdownAction,
+    ) -> Result<(), RequestError> {
+        info!("stopping every service to {action} the system");
+        self.stop_all().await;
+        (self.power_control)(action).map_err(|err| {
+            RequestError::SystemError {
+                err: format!("unable to {action} the system: {err}"),
+            }
+        })
+    }
+
+    /// Hand the services over to a new rsvc by executing its binary again,
+    /// e.g. once it has been upgraded. The new rsvc adopts the running
+    /// processes instead of restarting them. Only returns if it failed
+    pub async fn reexec(&self) -> Result<(), RequestError> {
+        // The services can't change state while they are being saved
+        let graph = self.graph.write().await;
+        let state = graph.save_reexec_state()?;
+        info!(
+            "executing rsvc again, handing over {} services",
+            state.services.len()
+        );
+        if let Err(err) = (self.reexec_control)() {
+            graph.discard_reexec_state(state);
+            return Err(RequestError::SystemError {
+                err: format!("unable to execute rsvc again: {err}"),
+            });
+        }
+        Ok(())
+    }
+
+    /// Take over the services handed over by the rsvc that executed this one,
+    /// instead of starting them
+    pub async fn resume(
+        &self,
+        state: ReexecState,
+    ) {
+        self.graph.read().await.resume(state).await;
+    }
+
+    /// Read the dependency graph again and stop the services that are not
+    /// in it anymore, except the ones in keep_running, which become unmanaged
+    pub async fn reload(
+        &self,
+        keep_running: &[String],
+    ) -> Result<(), RequestError> {
+        let mut graph = self.graph.write().await;
+        graph.reload_dependency_graph().await?;
+        graph.keep_running(keep_running);
+        drop(graph);
+        self.stop_removed_services().await;
+        Ok(())
+    }
+
+    /// Stop the services that are not in the dependency graph anymore, except
+    /// the unmanaged ones. They are dropped from the live graph once they are
+    /// down
+    async fn stop_removed_services(&self) {
+        let removed = self.graph.read().await.removed_services();
+        for service in removed {
+            // Don't hold the lock while waiting, the status updates need it
+            let graph = self.graph.read().await;
+            let Ok(live_service) = graph.get_service(&service) else {
+                continue;
+            };
+            if let Err(err) = graph.stop_service(live_service).await {
+                warn!("unable to stop removed service {service}: {err}");
+                continue;
+            }
+            let state = live_service.wait_idle_state();
+            drop(graph);
+            state.await;
+        }
+    }
+
+    pub async fn reload_service(
+        &self,
+        service: &str,
+    ) -> Result<(), RequestError> {
+        self.graph.write().await.reload_service(service)?;
+        Ok(())
+    }
+
+    /// Parse the file of the service again and apply it. A service that is
+    /// up and needs a restart for its new definition is restarted, unless
+    /// its dependents are running; then the new definition is used once it
+    /// stops, as with reload_service
+    pub async fn reparse_service(
+        &self,
+        service: &str,
+    ) -> Result<(), RequestError> {
+        self.graph.write().await.reparse_service(service)?;
+        let graph = self.graph.read().await;
+        let live_service = graph.get_service(service)?;
+        if live_service.new.is_none() {
+            return Ok(());
+        }
+        info!("restarting service {service} to apply its new definition");
+        match graph.stop_service(live_service).await {
+            Ok(()) => {}
+            Err(LiveGraphError::LogicError {
+                err: LogicError::DependentsStillRunning { dependents, .. },
+            }) => {
+                warn!(
+                    "service {service} will use its new definition once stopped, its dependents \
+                     {dependents:?} are running"
+                );
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        }
+        let state = live_service.wait_idle_state();
+        drop(graph);
+        state.await;
+        // Apply the new definition before starting, the status update could
+        // still be waiting for the lock
+        self.graph.write().await.update_service(service)?;
+        let graph = self.graph.read().await;
+        graph.start_service(graph.get_service(service)?).await?;
+        Ok(())
+    }
+
+    pub async fn reload_config(&self) -> Result<(), RequestError> {
+        self.graph
+            .write()
+            .await
+            .reload_config(self.config_file.clone())?;
+        Ok(())
+    }
+
+    /// Start wanted as a service wanted by service, only logging its failure
+    pub async fn start_wanted(
+        &self,
+        service: &str,
+        wanted: &str,
+    ) {
+        self.graph.read().await.start_wanted(service, wanted).await;
+    }
+
+    pub async fn update_service_status(
+        &self,
+        service: &str,
+        state: IdleServiceState,
+    ) -> Result<(), RequestError> {
+        let graph = self.graph.read().await;
+        graph.update_service_state(service, state)?;
+        // To update the service, we need the get a write lock
+        // Only get it if needed: a start holds the read lock until its
+        // dependencies are up, and the writer waiting would block the status
+        // updates of the other services, which need a read lock
+        let live_service = graph.get_service(service)?;
+        if state.is_down() && (live_service.remove || live_service.new.is_some()) {
+            drop(graph);
+            let mut graph = self.graph.write().await;
+            graph.update_service(service)?;
+        }
+        Ok(())
+    }
+}
+
+/// Flush the filesystems and run action with the reboot syscall, which does
+/// not return when it succeeds
+fn power_control(action: ShutdownAction) -> nix::Result<()> {
+    sync();
+    let mode = match action {
+        ShutdownAction::Poweroff => RebootMode::RB_POWER_OFF,
+        ShutdownAction::Reboot => RebootMode::RB_AUTOBOOT,
+        ShutdownAction::Halt => RebootMode::RB_HALT_SYSTEM,
+    };
+    reboot(mode)?;
+    Ok(())
+}
+
+/// Execute the binary of rsvc again with the same arguments. It keeps its pid
+/// and so its children. Only returns if it failed
+fn reexec_control() -> io::Result<()> {
+    let exe = env::current_exe()?;
+    // The binary has been replaced by the upgrade
+    let exe = match exe.to_str().and_then(|exe| exe.strip_suffix(" (deleted)")) {
+        Some(exe) => PathBuf::from(exe),
+        None => exe,
+    };
+    Err(Command::new(exe).args(env::args_os().skip(1)).exec())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        rc::Rc,
+        time::Duration,
+    };
+
+    use futures::future::join;
+    use rinit_service::{
+        test_util::{
+            new_graph,
+            ServiceBuilder,
+        },
+        types::Service,
+    };
+    use tokio::{
+        task,
+        time::{
+            sleep,
+            timeout,
+            Instant,
+        },
+    };
+
+    use super::*;
+    use crate::test_util::{
+        new_config_in,
+        new_supervisor,
+        test_dir,
+    };
+
+    fn run_in_background(supervisor: &Rc<Supervisor>) {
+        let supervisor = supervisor.clone();
+        task::spawn_local(async move { supervisor.run().await });
+    }
+
+    async fn state_of(
+        supervisor: &Supervisor,
+        service: &str,
+    ) -> ServiceState {
+        supervisor.status(service).await.unwrap().status
+    }
+
+    #[tokio::test]
+    async fn drive_supervisor_directly() {
+        let dir = env::temp_dir().join(format!("rinit-supervisor-{}", std::process::id()));
+        let supervisor = new_supervisor(
+            &dir,
+            vec![
+                ServiceBuilder::oneshot("foo").build(),
+                ServiceBuilder::oneshot("bar")
+                    .dependencies(&["foo"])
+                    .build(),
+            ],
+        );
+
+        task::LocalSet::new()
+            .run_until(async {
+                let ((), ()) = join(supervisor.run(), async {
+                    assert!(supervisor.start("bar", RunLevel::Default, false).await.unwrap());
+                    assert_eq!(
+                        state_of(&supervisor, "foo").await,
+                        ServiceState::Idle(IdleServiceState::Up)
+                    );
+                    // bar depends on foo
+                    assert!(supervisor.stop("foo", RunLevel::Default).await.is_err());
+                    assert!(supervisor.stop("bar", RunLevel::Default).await.unwrap());
+                    assert_eq!(
+                        state_of(&supervisor, "foo").await,
+                        ServiceState::Idle(IdleServiceState::Up)
+                    );
+                    assert!(supervisor.status("missing").await.is_err());
+                    assert_eq!(supervisor.statuses().await.len(), 2);
+
+                    let mut shutdown = supervisor.subscribe_shutdown();
+                    supervisor.start("foo", RunLevel::Default, false).await.unwrap();
+                    supervisor.stop_all().await;
+                    assert!(*shutdown.borrow_and_update());
+                    assert_eq!(
+                        state_of(&supervisor, "foo").await,
+                        ServiceState::Idle(IdleServiceState::Down)
+                    );
+                })
+                .await;
+            })
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reload_stops_removed_services() {
+        let dir = env::temp_dir().join(format!("rinit-reload-remove-{}", std::process::id()));
+        let supervisor = Rc::new(new_supervisor(
+            &dir,
+            vec![
+                ServiceBuilder::oneshot("foo").build(),
+                ServiceBuilder::oneshot("bar")
+                    .dependencies(&["foo"])
+                    .build(),
+                ServiceBuilder::oneshot("qux").build(),
+            ],
+        ));
+
+        task::LocalSet::new()
+            .run_until(async {
+                run_in_background(&supervisor);
+                for service in ["bar", "qux"] {
+                    assert!(supervisor.start(service, RunLevel::Default, false).await.unwrap());
+                }
+
+                // foo and bar have been disabled
+                let graph = new_graph(vec![ServiceBuilder::oneshot("qux").build()]);
+                std::fs::write(dir.join("graph.data"), serde_json::to_vec(&graph).unwrap())
+                    .unwrap();
+                supervisor.reload(&[]).await.unwrap();
+                timeout(Duration::from_secs(1), async {
+                    while supervisor.graph.read().await.live_services.len() != 1 {
+                        sleep(Duration::from_millis(10)).await;
+                    }
+                })
+                .await
+                .unwrap();
+                let graph = supervisor.graph.read().await;
+                assert_eq!(
+                    *graph.get_service("qux").unwrap().state.borrow(),
+                    ServiceState::Idle(IdleServiceState::Up)
+                );
+            })
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_running_services() {
+        let dir = env::temp_dir().join(format!("rinit-reload-keep-{}", std::process::id()));
+        let supervisor = Rc::new(new_supervisor(
+            &dir,
+            vec![ServiceBuilder::oneshot("foo").build()],
+        ));
+
+        task::LocalSet::new()
+            .run_until(async {
+                run_in_background(&supervisor);
+                assert!(supervisor.start("foo", RunLevel::Default, false).await.unwrap());
+
+                // foo has been disabled with --keep-running
+                std::fs::write(
+                    dir.join("graph.data"),
+                    serde_json::to_vec(&new_graph(Vec::new())).unwrap(),
+                )
+                .unwrap();
+                supervisor.reload(&["foo".to_string()]).await.unwrap();
+                sleep(Duration::from_millis(200)).await;
+                let graph = supervisor.graph.read().await;
+                let foo = graph.get_service("foo").unwrap();
+                assert!(foo.unmanaged);
+                assert_eq!(
+                    *foo.state.borrow(),
+                    ServiceState::Idle(IdleServiceState::Up)
+                );
+            })
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn later_reloads_keep_unmanaged_services_running() {
+        let dir = test_dir("rinit-reload-keep-twice");
+        let supervisor = Rc::new(new_supervisor(
+            &dir,
+            vec![ServiceBuilder::oneshot("foo").build()],
+        ));
+
+        task::LocalSet::new()
+            .run_until(async {
+                run_in_background(&supervisor);
+                assert!(supervisor.start("foo", RunLevel::Default, false).await.unwrap());
+
+                std::fs::write(
+                    dir.join("graph.data"),
+                    serde_json::to_vec(&new_graph(Vec::new())).unwrap(),
+                )
+                .unwrap();
+                supervisor.reload(&["foo".to_string()]).await.unwrap();
+                // e.g. another service has been enabled afterwards
+                supervisor.reload(&[]).await.unwrap();
+                sleep(Duration::from_millis(200)).await;
+                let graph = supervisor.graph.read().await;
+                let foo = graph.get_service("foo").unwrap();
+                assert!(foo.unmanaged);
+                assert_eq!(
+                    *foo.state.borrow(),
+                    ServiceState::Idle(IdleServiceState::Up)
+                );
+            })
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reparse_restarts_only_the_service() {
+        let dir = env::temp_dir().join(format!("rinit-reparse-{}", std::process::id()));
+        let record =
+            |name: &str, line: &str| format!("echo {line} >> {}", dir.join(name).display());
+        let services = vec![
+            ServiceBuilder::oneshot("foo")
+                .start(&record("foo", "v1"))
+                .build(),
+            ServiceBuilder::oneshot("bar")
+                .start(&record("bar", "v1"))
+                .build(),
+        ];
+        let supervisor = Rc::new(new_supervisor(&dir, services.clone()));
+        std::fs::write(
+            dir.join("graph.data"),
+            serde_json::to_vec(&new_graph(services)).unwrap(),
+        )
+        .unwrap();
+
+        task::LocalSet::new()
+            .run_until(async {
+                run_in_background(&supervisor);
+                for service in ["foo", "bar"] {
+                    assert!(supervisor.start(service, RunLevel::Default, false).await.unwrap());
+                }
+
+                let mode = if unsafe { libc::getuid() } == 0 {
+                    "system"
+                } else {
+                    "user"
+                };
+                let service_dir = dir.join(mode);
+                std::fs::create_dir_all(&service_dir).unwrap();
+                std::fs::write(
+                    service_dir.join(format!("foo.{mode}")),
+                    format!(
+                        "name = foo\ntype = oneshot\n[start]\nexecute = (\n    {}\n)\nprefix = \
+                         bash\n",
+                        record("foo", "v2")
+                    ),
+                )
+                .unwrap();
+                supervisor.reparse_service("foo").await.unwrap();
+
+                let graph = supervisor.graph.read().await;
+                for service in ["foo", "bar"] {
+                    let live_service = graph.get_service(service).unwrap();
+                    assert!(live_service.new.is_none());
+                    assert_eq!(
+                        *live_service.state.borrow(),
+                        ServiceState::Idle(IdleServiceState::Up)
+                    );
+                }
+            })
+            .await;
+        assert_eq!(
+            std::fs::read_to_string(dir.join("foo")).unwrap(),
+            "v1\nv2\n"
+        );
+        assert_eq!(std::fs::read_to_string(dir.join("bar")).unwrap(), "v1\n");
+        // The new definition has been saved in the graph
+        let graph: DependencyGraph =
+            serde_json::from_slice(&std::fs::read(dir.join("graph.data")).unwrap()).unwrap();
+        let Service::Oneshot(foo) = &graph.nodes["foo"].service else {
+            panic!("expected a oneshot");
+        };
+        assert!(foo.start.execute.contains("v2"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_dependents_first() {
+        let dir = env::temp_dir().join(format!("rinit-shutdown-{}", std::process::id()));
+        let order = dir.join("order");
+        let with_stop = |name: &str, dependencies: &[&str]| {
+            ServiceBuilder::oneshot(name)
+                .dependencies(dependencies)
+                .stop(&format!("echo {name} >> {}", order.display()))
+                .build()
+        };
+        let actions = Rc::new(RefCell::new(Vec::new()));
+        let actions_clone = actions.clone();
+        let order_clone = order.clone();
+        let supervisor = new_supervisor(
+            &dir,
+            vec![with_stop("foo", &[]), with_stop("bar", &["foo"])],
+        )
+        .with_power_control(move |action| {
+            // Every service has been stopped before the system goes down
+            let order = std::fs::read_to_string(&order_clone).unwrap();
+            actions_clone.borrow_mut().push((action, order));
+            Ok(())
+        });
+
+        task::LocalSet::new()
+            .run_until(async {
+                let ((), ()) = join(supervisor.run(), async {
+                    assert!(supervisor.start("bar", RunLevel::Default, false).await.unwrap());
+                    supervisor.shutdown(ShutdownAction::Reboot).await.unwrap();
+                })
+                .await;
+            })
+            .await;
+        assert_eq!(
+            *actions.borrow(),
+            vec![(ShutdownAction::Reboot, "bar\nfoo\n".to_string())]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_after_failed_stop() {
+        let dir = env::temp_dir().join(format!("rinit-shutdown-failed-{}", std::process::id()));
+        let order = dir.join("order");
+        let order_clone = order.clone();
+        let actions = Rc::new(RefCell::new(Vec::new()));
+        let actions_clone = actions.clone();
+        let supervisor = new_supervisor(
+            &dir,
+            vec![
+                ServiceBuilder::oneshot("foo")
+                    .stop(&format!("echo foo >> {}", order.display()))
+                    .build(),
+                ServiceBuilder::oneshot("bar")
+                    .dependencies(&["baz"])
+                    .stop("exit 1")
+                    .stop_must_succeed(true)
+                    .build(),
+                // Not stopped while bar is still up
+                ServiceBuilder::oneshot("baz")
+                    .stop(&format!("echo baz >> {}", order.display()))
+                    .build(),
+            ],
+        )
+        .with_power_control(move |action| {
+            actions_clone.borrow_mut().push(action);
+            Ok(())
+        });
+
+        task::LocalSet::new()
+            .run_until(async {
+                let ((), ()) = join(supervisor.run(), async {
+                    for service in ["foo", "bar"] {
+                        assert!(supervisor.start(service, RunLevel::Default, false).await.unwrap());
+                    }
+                    supervisor.shutdown(ShutdownAction::Reboot).await.unwrap();
+                })
+                .await;
+            })
+            .await;
+        // The other services are still stopped and the system goes down
+        assert_eq!(std::fs::read_to_string(&order_clone).unwrap(), "foo\n");
+        assert_eq!(*actions.borrow(), vec![ShutdownAction::Reboot]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_reexec_discards_state() {
+        let dir = env::temp_dir().join(format!("rinit-test-reexec-failed-{}", std::process::id()));
+        let config = new_config_in(&dir);
+        let rundir = config.dirs.rundir.clone();
+        let saved = Rc::new(RefCell::new(String::new()));
+        let saved_clone = saved.clone();
+        let rundir_clone = rundir.clone();
+        let graph = new_graph(vec![ServiceBuilder::oneshot("mount").build()]);
+        let supervisor = Supervisor::new(graph, config).with_reexec_control(move || {
+            // The state is saved before executing rsvc
+            saved_clone.replace(std::fs::read_to_string(rundir_clone.join("reexec.json"))?);
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        });
+
+        task::LocalSet::new()
+            .run_until(async {
+                let ((), ()) = join(supervisor.run(), async {
+                    assert!(supervisor.start("mount", RunLevel::Default, false).await.unwrap());
+                    assert!(matches!(
+                        supervisor.reexec().await,
+                        Err(RequestError::SystemError { .. })
+                    ));
+                    // The services are still supervised by this rsvc
+                    assert_eq!(
+                        supervisor.status("mount").await.unwrap().status,
+                        ServiceState::Idle(IdleServiceState::Up)
+                    );
+                    supervisor.stop_all().await;
+                })
+                .await;
+            })
+            .await;
+        assert!(saved.borrow().contains("mount"));
+        assert_eq!(ReexecState::take(&rundir).unwrap(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failure_during_boot_does_not_block_updates() {
+        let dir = env::temp_dir().join(format!("rinit-boot-failure-{}", std::process::id()));
+        let supervisor = Rc::new(new_supervisor(
+            &dir,
+            vec![
+                ServiceBuilder::oneshot("broken").start("exit 1").build(),
+                ServiceBuilder::oneshot("slow").start("sleep 0.5").build(),
+            ],
+        ));
+
+        task::LocalSet::new()
+            .run_until(async {
+                run_in_background(&supervisor);
+                // slow becomes up after broken has failed, while the boot still
+                // holds the lock
+                timeout(Duration::from_secs(5), supervisor.start_all())
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    state_of(&supervisor, "broken").await,
+                    ServiceState::Idle(IdleServiceState::Failed)
+                );
+                assert_eq!(
+                    state_of(&supervisor, "slow").await,
+                    ServiceState::Idle(IdleServiceState::Up)
+                );
+            })
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn start_with_empty_rundir() {
+        let dir = env::temp_dir().join(format!("rinit-empty-rundir-{}", std::process::id()));
+        let rundir = dir.join("run");
+        // Its marker is written in rundir once it is up
+        let mount = ServiceBuilder::oneshot("mount").up_check("exit 1").build();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("graph.data"),
+            serde_json::to_vec(&new_graph(vec![mount])).unwrap(),
+        )
+        .unwrap();
+        let new_config = || {
+            let mut config = new_config_in(&dir);
+            config.dirs.rundir = rundir.clone();
+            config
+        };
+
+        // rundir is a tmpfs, wiped at every reboot
+        for _ in 0..2 {
+            assert!(!rundir.exists());
+            let supervisor = Supervisor::from_config(new_config()).unwrap();
+            assert!(rundir.join(LiveService::NOTIFY_DIR).is_dir());
+            assert!(rundir.join(LiveService::DONE_DIR).is_dir());
+            task::LocalSet::new()
+                .run_until(async {
+                    let ((), ()) = join(supervisor.run(), async {
+                        supervisor.start_all().await;
+                        assert_eq!(
+                            state_of(&supervisor, "mount").await,
+                            ServiceState::Idle(IdleServiceState::Up)
+                        );
+                        assert!(rundir.join(LiveService::DONE_DIR).join("mount").exists());
+                        supervisor.set_runlevel(RunLevel::Default).await.unwrap();
+                        supervisor.stop_all().await;
+                    })
+                    .await;
+                })
+                .await;
+            assert!(rundir.join("runlevel").exists());
+            std::fs::remove_dir_all(&rundir).unwrap();
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn statuses_during_boot_are_not_blocked() {
+        let dir = test_dir("rinit-boot-contention");
+        let services = (0..100)
+            .map(|i| {
+                ServiceBuilder::oneshot(&format!("service{i}"))
+                    .start("sleep 0.5")
+                    .build()
+            })
+            .collect();
+        let supervisor = Rc::new(new_supervisor(&dir, services));
+
+        task::LocalSet::new()
+            .run_until(async {
+                run_in_background(&supervisor);
+                // The boot holds a read lock until every service is up, the
+                // requests only need one as well
+                let statuses = async {
+                    sleep(Duration::from_millis(100)).await;
+                    let start = Instant::now();
+                    let statuses = supervisor.statuses().await;
+                    assert!(start.elapsed() < Duration::from_millis(100));
+                    assert!(statuses.iter().any(|status| {
+                        status.status
+                            == ServiceState::Transitioning(TransitioningServiceState::Starting)
+                    }));
+                    let start = Instant::now();
+                    state_of(&supervisor, "service0").await;
+                    assert!(start.elapsed() < Duration::from_millis(100));
+                };
+                // The services are started at the same time
+                timeout(Duration::from_secs(10), join(supervisor.start_all(), statuses))
+                    .await
+                    .unwrap();
+                for status in supervisor.statuses().await {
+                    assert_eq!(status.status, ServiceState::Idle(IdleServiceState::Up));
+                }
+            })
+            .await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
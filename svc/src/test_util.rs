@@ -0,0 +1,109 @@
+use std::{
+    env,
+    future::Future,
+    path::{
+        Path,
+        PathBuf,
+    },
+    process,
+};
+
+use futures::stream::{
+    FuturesUnordered,
+    StreamExt,
+};
+use rinit_ipc::Request;
+use rinit_service::{
+    config::Config,
+    dirs::Dirs,
+    test_util::new_graph,
+    types::Service,
+};
+use tokio::sync::mpsc;
+
+use crate::{
+    live_service_graph::LiveServiceGraph,
+    Supervisor,
+};
+
+/// Temporary directory of the test called name
+pub fn test_dir(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("{name}-{}", process::id()))
+}
+
+/// Config of rsvc logging in a temporary directory shared by the tests
+pub fn new_config() -> Config {
+    Config {
+        dirs: Dirs {
+            logdir: env::temp_dir().join("rinit-test-logs"),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Config of rsvc keeping the graph, the logs and the runtime files in dir,
+/// which is created
+pub fn new_config_in(dir: &Path) -> Config {
+    std::fs::create_dir_all(dir).unwrap();
+    Config {
+        dirs: Dirs {
+            logdir: dir.join("logs"),
+            datadir: dir.to_path_buf(),
+            rundir: dir.to_path_buf(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+pub fn new_live_graph(
+    services: Vec<Service>,
+    config: Config,
+) -> (LiveServiceGraph, mpsc::Receiver<Request>) {
+    let (send, rx) = mpsc::channel(20);
+    (
+        LiveServiceGraph::new_with_graph(new_graph(services), config, send),
+        rx,
+    )
+}
+
+/// Run future while acting as the Supervisor for the requests sent by the
+/// services, then until the wanted services requested are started
+pub async fn with_status_updates<F: Future>(
+    live_graph: &LiveServiceGraph,
+    rx: &mut mpsc::Receiver<Request>,
+    future: F,
+) -> F::Output {
+    tokio::pin!(future);
+    let mut output = None;
+    let mut wanted = FuturesUnordered::new();
+    loop {
+        tokio::select! {
+            res = &mut future, if output.is_none() => output = Some(res),
+            Some(request) = rx.recv() => match request {
+                Request::UpdateServiceStatus(name, state) => {
+                    live_graph.update_service_state(&name, state).unwrap();
+                }
+                Request::StartWantedService { service, wanted: name } => {
+                    wanted.push(async move { live_graph.start_wanted(&service, &name).await });
+                }
+                request => panic!("unexpected request {request:?}"),
+            },
+            Some(()) = wanted.next() => {}
+        }
+        if wanted.is_empty() {
+            if let Some(output) = output.take() {
+                return output;
+            }
+        }
+    }
+}
+
+/// Supervisor of the services, enabled in a graph kept in dir
+pub fn new_supervisor(
+    dir: &Path,
+    services: Vec<Service>,
+) -> Supervisor {
+    Supervisor::new(new_graph(services), new_config_in(dir))
+}